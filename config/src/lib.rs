@@ -114,6 +114,14 @@ pub struct Parameters {
     pub max_header_delay: Duration,
     /// The depth of the garbage collection (Denominated in number of rounds).
     pub gc_depth: u64,
+    /// The consensus protocol ordering the DAG. Recorded in the store: a node cannot switch
+    /// protocols on an existing database.
+    #[serde(default)]
+    pub consensus_protocol: ConsensusProtocolKind,
+    /// How Bullshark swaps the authorities with a poor reputation out of the leader schedule.
+    /// Must be the same on every authority of the committee.
+    #[serde(default)]
+    pub leader_reputation: LeaderReputationParameters,
     /// The delay after which the synchronizer retries to send sync requests. Denominated in ms.
     #[serde(with = "duration_format")]
     pub sync_retry_delay: Duration,
@@ -139,6 +147,549 @@ pub struct Parameters {
     /// If empty, UdsExecutionState will not be used
     #[serde(default)]
     pub uds_block_path: String,
+    /// The protocol used to deliver committed blocks over `uds_block_path`
+    #[serde(default)]
+    pub uds_protocol: UdsProtocolParameters,
+    /// Unix Domain Socket path for getting the validator set from the executor (optional)
+    /// If empty, the committee and the workers only come from the `--committee` and `--workers` files
+    #[serde(default)]
+    pub uds_get_validators_path: String,
+    /// How the validator set is queried over `uds_get_validators_path`
+    #[serde(default)]
+    pub validator_source: ValidatorSourceParameters,
+    /// Retention of the persistent archive of committed blocks
+    #[serde(default)]
+    pub block_archive: BlockArchiveParameters,
+    /// How sequenced certificates are grouped into the blocks delivered to the executor
+    #[serde(default)]
+    pub block_policy: BlockPolicy,
+    /// The checks applied by the workers to client transactions before batching them
+    #[serde(default)]
+    pub transaction_validation: TransactionValidationParameters,
+    /// How long the workers remember submitted transactions to reject resubmissions
+    #[serde(default)]
+    pub transaction_deduplication: TransactionDeduplicationParameters,
+    /// The limits applied by the workers to client transactions
+    #[serde(default)]
+    pub admission_control: AdmissionControlParameters,
+    /// The exchange of block fingerprints between primaries to detect a fork of the executed chain
+    #[serde(default)]
+    pub fork_detection: ForkDetectionParameters,
+    /// Secondary outputs receiving a copy of the committed blocks (eg. a shadow executor or an
+    /// indexer), besides the executor on `uds_block_path`
+    #[serde(default)]
+    pub block_sinks: Vec<BlockSinkParameters>,
+    /// Slow down consensus delivery and proposing when the executor falls behind
+    #[serde(default)]
+    pub execution_backpressure: ExecutionBackpressureParameters,
+}
+
+/// The consensus protocol sequencing the certificates of the DAG.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusProtocolKind {
+    /// Bullshark: commits a leader every two rounds with the votes of the next round
+    /// (partially synchronous proposer).
+    Bullshark,
+    /// Tusk: commits the leader of a round elected by the random coin three rounds later
+    /// (asynchronous proposer).
+    Tusk,
+}
+
+impl Default for ConsensusProtocolKind {
+    fn default() -> Self {
+        ConsensusProtocolKind::Bullshark
+    }
+}
+
+/// The rule deciding where a block delivered to the executor ends.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockFormation {
+    /// A block groups `certificates_per_block` consecutive sequenced certificates.
+    Certificates,
+    /// A block ends with the certificate of a committed leader (one block per sub-DAG).
+    SubDag,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(default)]
+pub struct BlockPolicy {
+    /// Where a block ends.
+    #[serde(default = "BlockPolicy::default_formation")]
+    pub formation: BlockFormation,
+    /// The number of sequenced certificates per block when `formation` is `certificates`.
+    #[serde(default = "BlockPolicy::default_certificates_per_block")]
+    pub certificates_per_block: u64,
+    /// The maximum number of transactions in a block. Oversized blocks are split, in commit
+    /// order, into several consecutive heights. Zero disables the cap.
+    #[serde(default = "BlockPolicy::default_max_transactions")]
+    pub max_transactions: u64,
+    /// The maximum size (sum of the transaction sizes, in bytes) of a block. Oversized blocks
+    /// are split, in commit order, into several consecutive heights. Zero disables the cap.
+    #[serde(default = "BlockPolicy::default_max_bytes")]
+    pub max_bytes: u64,
+    /// The number of consensus indices for which processed batch digests and delivered transaction
+    /// hashes are remembered to drop re-committed batches and transactions already delivered in
+    /// another batch. Must be the same on every node.
+    #[serde(default = "BlockPolicy::default_processed_batches_gc_depth")]
+    pub processed_batches_gc_depth: u64,
+}
+
+impl BlockPolicy {
+    fn default_formation() -> BlockFormation {
+        BlockFormation::Certificates
+    }
+    fn default_certificates_per_block() -> u64 {
+        10
+    }
+    fn default_max_transactions() -> u64 {
+        0
+    }
+    fn default_max_bytes() -> u64 {
+        0
+    }
+    fn default_processed_batches_gc_depth() -> u64 {
+        1_000
+    }
+}
+
+impl Default for BlockPolicy {
+    fn default() -> Self {
+        Self {
+            formation: BlockPolicy::default_formation(),
+            certificates_per_block: BlockPolicy::default_certificates_per_block(),
+            max_transactions: BlockPolicy::default_max_transactions(),
+            max_bytes: BlockPolicy::default_max_bytes(),
+            processed_batches_gc_depth: BlockPolicy::default_processed_batches_gc_depth(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TransactionValidationParameters {
+    /// Reject the client transactions that are not valid `transaction.proto` transactions.
+    /// Opt-in: by default the workers batch any bytes (eg. the synthetic transactions of the
+    /// benchmarks and the tests).
+    #[serde(default = "TransactionValidationParameters::default_enabled")]
+    pub enabled: bool,
+    /// The chain id the transactions must be signed for. Zero accepts any chain id.
+    #[serde(default = "TransactionValidationParameters::default_chain_id")]
+    pub chain_id: u64,
+    /// Reject the transactions without a secp256k1 (R, S, V) signature.
+    #[serde(default = "TransactionValidationParameters::default_require_signature")]
+    pub require_signature: bool,
+}
+
+impl TransactionValidationParameters {
+    fn default_enabled() -> bool {
+        false
+    }
+    fn default_chain_id() -> u64 {
+        0
+    }
+    fn default_require_signature() -> bool {
+        false
+    }
+}
+
+impl Default for TransactionValidationParameters {
+    fn default() -> Self {
+        Self {
+            enabled: TransactionValidationParameters::default_enabled(),
+            chain_id: TransactionValidationParameters::default_chain_id(),
+            require_signature: TransactionValidationParameters::default_require_signature(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TransactionDeduplicationParameters {
+    /// Reject the client transactions whose hash was already submitted to this worker.
+    #[serde(default = "TransactionDeduplicationParameters::default_enabled")]
+    pub enabled: bool,
+    /// How long a submitted transaction hash is remembered.
+    #[serde(
+        with = "duration_format",
+        default = "TransactionDeduplicationParameters::default_window"
+    )]
+    pub window: Duration,
+    /// The maximum number of remembered transaction hashes. The oldest are forgotten first.
+    #[serde(default = "TransactionDeduplicationParameters::default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl TransactionDeduplicationParameters {
+    fn default_enabled() -> bool {
+        true
+    }
+    fn default_window() -> Duration {
+        Duration::from_secs(60)
+    }
+    fn default_max_entries() -> usize {
+        1_000_000
+    }
+}
+
+impl Default for TransactionDeduplicationParameters {
+    fn default() -> Self {
+        Self {
+            enabled: TransactionDeduplicationParameters::default_enabled(),
+            window: TransactionDeduplicationParameters::default_window(),
+            max_entries: TransactionDeduplicationParameters::default_max_entries(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdmissionControlParameters {
+    /// The maximum size of a client transaction (in bytes). Zero disables the limit.
+    #[serde(default = "AdmissionControlParameters::default_max_transaction_size")]
+    pub max_transaction_size: usize,
+    /// The maximum size of the client transactions accepted by a worker and not yet sealed into
+    /// a batch (in bytes). Zero disables the limit.
+    #[serde(default = "AdmissionControlParameters::default_max_pending_bytes")]
+    pub max_pending_bytes: usize,
+    /// The transactions per second accepted from a source IP. Zero disables the limit.
+    #[serde(default = "AdmissionControlParameters::default_per_source_ip_rate")]
+    pub per_source_ip_rate: u32,
+    /// The transactions a source IP may submit at once. Zero uses the rate.
+    #[serde(default = "AdmissionControlParameters::default_per_source_ip_burst")]
+    pub per_source_ip_burst: u32,
+    /// The transactions per second accepted from a `FromAddress`. Zero disables the limit.
+    #[serde(default = "AdmissionControlParameters::default_per_sender_rate")]
+    pub per_sender_rate: u32,
+    /// The transactions a `FromAddress` may submit at once. Zero uses the rate.
+    #[serde(default = "AdmissionControlParameters::default_per_sender_burst")]
+    pub per_sender_burst: u32,
+    /// The maximum number of source IPs and senders tracked by the rate limiters.
+    #[serde(default = "AdmissionControlParameters::default_max_tracked_clients")]
+    pub max_tracked_clients: usize,
+}
+
+impl AdmissionControlParameters {
+    fn default_max_transaction_size() -> usize {
+        1_048_576
+    }
+    fn default_max_pending_bytes() -> usize {
+        134_217_728
+    }
+    fn default_per_source_ip_rate() -> u32 {
+        0
+    }
+    fn default_per_source_ip_burst() -> u32 {
+        0
+    }
+    fn default_per_sender_rate() -> u32 {
+        0
+    }
+    fn default_per_sender_burst() -> u32 {
+        0
+    }
+    fn default_max_tracked_clients() -> usize {
+        100_000
+    }
+}
+
+impl Default for AdmissionControlParameters {
+    fn default() -> Self {
+        Self {
+            max_transaction_size: AdmissionControlParameters::default_max_transaction_size(),
+            max_pending_bytes: AdmissionControlParameters::default_max_pending_bytes(),
+            per_source_ip_rate: AdmissionControlParameters::default_per_source_ip_rate(),
+            per_source_ip_burst: AdmissionControlParameters::default_per_source_ip_burst(),
+            per_sender_rate: AdmissionControlParameters::default_per_sender_rate(),
+            per_sender_burst: AdmissionControlParameters::default_per_sender_burst(),
+            max_tracked_clients: AdmissionControlParameters::default_max_tracked_clients(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ForkDetectionParameters {
+    /// Exchange the (height, block hash) of the blocks delivered to the executor with the other
+    /// primaries and compare them.
+    #[serde(default = "ForkDetectionParameters::default_enabled")]
+    pub enabled: bool,
+    /// How often the fingerprints of the most recent blocks are sent to the other primaries.
+    #[serde(
+        with = "duration_format",
+        default = "ForkDetectionParameters::default_gossip_interval"
+    )]
+    pub gossip_interval: Duration,
+    /// The number of most recent blocks whose fingerprints are remembered and sent.
+    #[serde(default = "ForkDetectionParameters::default_retained_heights")]
+    pub retained_heights: u64,
+    /// Stop delivering blocks to the executor once authorities with f+1 stake reported the same
+    /// different block hash.
+    #[serde(default = "ForkDetectionParameters::default_halt_on_fork")]
+    pub halt_on_fork: bool,
+}
+
+impl ForkDetectionParameters {
+    fn default_enabled() -> bool {
+        true
+    }
+    fn default_gossip_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+    fn default_retained_heights() -> u64 {
+        100
+    }
+    fn default_halt_on_fork() -> bool {
+        false
+    }
+}
+
+impl Default for ForkDetectionParameters {
+    fn default() -> Self {
+        Self {
+            enabled: ForkDetectionParameters::default_enabled(),
+            gossip_interval: ForkDetectionParameters::default_gossip_interval(),
+            retained_heights: ForkDetectionParameters::default_retained_heights(),
+            halt_on_fork: ForkDetectionParameters::default_halt_on_fork(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExecutionBackpressureParameters {
+    /// The number of blocks handed to the executor but not confirmed yet from which the delivery
+    /// of the committed certificates to the execution state is slowed down. Zero disables it.
+    #[serde(default = "ExecutionBackpressureParameters::default_throttle_lag_blocks")]
+    pub throttle_lag_blocks: u64,
+    /// The delay added before delivering each committed certificate while throttled.
+    #[serde(
+        with = "duration_format",
+        default = "ExecutionBackpressureParameters::default_throttle_delay"
+    )]
+    pub throttle_delay: Duration,
+    /// The number of unconfirmed blocks from which the primary proposes headers without new
+    /// batches (it keeps voting and certifying). Zero disables it.
+    #[serde(default = "ExecutionBackpressureParameters::default_pause_payload_lag_blocks")]
+    pub pause_payload_lag_blocks: u64,
+}
+
+impl ExecutionBackpressureParameters {
+    fn default_throttle_lag_blocks() -> u64 {
+        50
+    }
+    fn default_throttle_delay() -> Duration {
+        Duration::from_millis(50)
+    }
+    fn default_pause_payload_lag_blocks() -> u64 {
+        200
+    }
+}
+
+impl Default for ExecutionBackpressureParameters {
+    fn default() -> Self {
+        Self {
+            throttle_lag_blocks: ExecutionBackpressureParameters::default_throttle_lag_blocks(),
+            throttle_delay: ExecutionBackpressureParameters::default_throttle_delay(),
+            pause_payload_lag_blocks:
+                ExecutionBackpressureParameters::default_pause_payload_lag_blocks(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LeaderReputationParameters {
+    /// The number of committed rounds after which the reputation scores are turned into a new
+    /// leader schedule, and reset. Zero (the default) keeps the stake-weighted schedule.
+    #[serde(default = "LeaderReputationParameters::default_update_interval")]
+    pub schedule_update_interval: u64,
+    /// The percentage of the total stake held by the lowest-score authorities swapped out of the
+    /// leader schedule. Capped at the stake of f authorities, one less than the validity threshold.
+    #[serde(default = "LeaderReputationParameters::default_bad_nodes_stake_threshold")]
+    pub bad_nodes_stake_threshold: u64,
+}
+
+impl LeaderReputationParameters {
+    fn default_update_interval() -> u64 {
+        0
+    }
+    fn default_bad_nodes_stake_threshold() -> u64 {
+        20
+    }
+}
+
+impl Default for LeaderReputationParameters {
+    fn default() -> Self {
+        Self {
+            schedule_update_interval: LeaderReputationParameters::default_update_interval(),
+            bad_nodes_stake_threshold:
+                LeaderReputationParameters::default_bad_nodes_stake_threshold(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ValidatorSourceParameters {
+    /// How long to wait at startup for the executor to answer with the validator set before
+    /// falling back to the committee and workers files.
+    #[serde(
+        with = "duration_format",
+        default = "ValidatorSourceParameters::default_startup_timeout"
+    )]
+    pub startup_timeout: Duration,
+    /// How often the executor is asked for the validator set to detect a new epoch.
+    #[serde(
+        with = "duration_format",
+        default = "ValidatorSourceParameters::default_poll_interval"
+    )]
+    pub poll_interval: Duration,
+}
+
+impl ValidatorSourceParameters {
+    fn default_startup_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+    fn default_poll_interval() -> Duration {
+        Duration::from_secs(2)
+    }
+}
+
+impl Default for ValidatorSourceParameters {
+    fn default() -> Self {
+        Self {
+            startup_timeout: ValidatorSourceParameters::default_startup_timeout(),
+            poll_interval: ValidatorSourceParameters::default_poll_interval(),
+        }
+    }
+}
+
+/// The transport of a secondary block output.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSinkKind {
+    /// A Unix domain socket, `address` is the path of the socket.
+    Uds,
+    /// A TCP connection on the loopback interface, `address` is `ip:port`.
+    Tcp,
+    /// Rotating append-only segment files, `address` is the directory of the segments.
+    File,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockSinkParameters {
+    /// The name of the sink, used in the metrics and to persist its cursor.
+    pub name: String,
+    /// The transport of the sink.
+    pub kind: BlockSinkKind,
+    /// The socket path, `ip:port` or segments directory, depending on `kind`.
+    pub address: String,
+    /// The delay before retrying a failed write. Doubled after every failure, up to
+    /// `max_retry_delay`.
+    #[serde(
+        with = "duration_format",
+        default = "BlockSinkParameters::default_retry_delay"
+    )]
+    pub retry_delay: Duration,
+    /// The maximum delay between two attempts to write to the sink.
+    #[serde(
+        with = "duration_format",
+        default = "BlockSinkParameters::default_max_retry_delay"
+    )]
+    pub max_retry_delay: Duration,
+    /// The size from which a file sink starts a new segment file.
+    #[serde(default = "BlockSinkParameters::default_segment_max_bytes")]
+    pub segment_max_bytes: u64,
+}
+
+impl BlockSinkParameters {
+    fn default_retry_delay() -> Duration {
+        Duration::from_millis(100)
+    }
+    fn default_max_retry_delay() -> Duration {
+        Duration::from_secs(10)
+    }
+    fn default_segment_max_bytes() -> u64 {
+        64 * 1024 * 1024
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BlockArchiveParameters {
+    /// The number of most recent committed blocks kept in the archive. Zero keeps every block.
+    #[serde(default = "BlockArchiveParameters::default_retention_blocks")]
+    pub retention_blocks: u64,
+    /// How often (in blocks) the archive is pruned down to `retention_blocks`.
+    #[serde(default = "BlockArchiveParameters::default_prune_interval_blocks")]
+    pub prune_interval_blocks: u64,
+}
+
+impl BlockArchiveParameters {
+    fn default_retention_blocks() -> u64 {
+        0
+    }
+    fn default_prune_interval_blocks() -> u64 {
+        100
+    }
+}
+
+impl Default for BlockArchiveParameters {
+    fn default() -> Self {
+        Self {
+            retention_blocks: BlockArchiveParameters::default_retention_blocks(),
+            prune_interval_blocks: BlockArchiveParameters::default_prune_interval_blocks(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UdsProtocolParameters {
+    /// The protocol version spoken on the block socket. Version 1 is the legacy write-only
+    /// framing (u16 length + CommittedEpochData). Version 2 uses versioned request/response
+    /// frames where the executor acknowledges every block height.
+    #[serde(default = "UdsProtocolParameters::default_version")]
+    pub version: u32,
+    /// How long to wait for the executor to acknowledge a block before the connection is
+    /// considered broken. Unacknowledged blocks are retransmitted after reconnecting.
+    #[serde(
+        with = "duration_format",
+        default = "UdsProtocolParameters::default_ack_timeout"
+    )]
+    pub ack_timeout: Duration,
+    /// The encoding of the transactions of a block. Format 1 packs all the transactions into one
+    /// wrapper in the first transaction of the block. Format 2 sends one typed entry per
+    /// transaction, with its hash and provenance. Every block carries its format. With protocol
+    /// version 2 the executor advertises the formats it reads when it connects, and the node
+    /// falls back to the highest common format (or does not start without one). Format 2 needs
+    /// protocol version 2.
+    #[serde(default = "UdsProtocolParameters::default_block_format")]
+    pub block_format: u32,
+}
+
+impl UdsProtocolParameters {
+    fn default_version() -> u32 {
+        1
+    }
+    fn default_ack_timeout() -> Duration {
+        Duration::from_secs(5)
+    }
+    fn default_block_format() -> u32 {
+        1
+    }
+}
+
+impl Default for UdsProtocolParameters {
+    fn default() -> Self {
+        Self {
+            version: UdsProtocolParameters::default_version(),
+            ack_timeout: UdsProtocolParameters::default_ack_timeout(),
+            block_format: UdsProtocolParameters::default_block_format(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -268,6 +819,8 @@ impl Default for Parameters {
             // Điều này giúp giảm tỉ lệ skip batches do thiếu linked path
             max_header_delay: Duration::from_millis(300),
             gc_depth: 50,
+            consensus_protocol: ConsensusProtocolKind::default(),
+            leader_reputation: LeaderReputationParameters::default(),
             // Giảm sync_retry_delay để retry nhanh hơn, đảm bảo DAG được sync đầy đủ
             // Giảm từ 5s xuống 200ms để sync nhanh hơn cho localhost
             sync_retry_delay: Duration::from_millis(200),
@@ -280,6 +833,17 @@ impl Default for Parameters {
             max_concurrent_requests: 500_000,
             prometheus_metrics: PrometheusMetricsParameters::default(),
             uds_block_path: String::new(),
+            uds_protocol: UdsProtocolParameters::default(),
+            uds_get_validators_path: String::new(),
+            validator_source: ValidatorSourceParameters::default(),
+            block_archive: BlockArchiveParameters::default(),
+            block_policy: BlockPolicy::default(),
+            transaction_validation: TransactionValidationParameters::default(),
+            transaction_deduplication: TransactionDeduplicationParameters::default(),
+            admission_control: AdmissionControlParameters::default(),
+            fork_detection: ForkDetectionParameters::default(),
+            block_sinks: Vec::new(),
+            execution_backpressure: ExecutionBackpressureParameters::default(),
         }
     }
 }
@@ -292,6 +856,12 @@ impl Parameters {
             self.max_header_delay.as_millis()
         );
         info!("Garbage collection depth set to {} rounds", self.gc_depth);
+        info!("Consensus protocol set to {:?}", self.consensus_protocol);
+        info!(
+            "Leader schedule updated every {} committed rounds, swapping up to {}% of the stake",
+            self.leader_reputation.schedule_update_interval,
+            self.leader_reputation.bad_nodes_stake_threshold
+        );
         info!(
             "Sync retry delay set to {} ms",
             self.sync_retry_delay.as_millis()
@@ -352,6 +922,76 @@ impl Parameters {
             "Prometheus metrics server will run on {}",
             self.prometheus_metrics.socket_addr
         );
+        info!(
+            "UDS block protocol set to version {}",
+            self.uds_protocol.version
+        );
+        info!(
+            "UDS block ack timeout set to {} ms",
+            self.uds_protocol.ack_timeout.as_millis()
+        );
+        info!(
+            "UDS block format set to version {}",
+            self.uds_protocol.block_format
+        );
+        info!(
+            "Block archive retention set to {} blocks",
+            self.block_archive.retention_blocks
+        );
+        info!("Block formation set to {:?}", self.block_policy.formation);
+        info!(
+            "Block certificates per block set to {}",
+            self.block_policy.certificates_per_block
+        );
+        info!(
+            "Block max transactions set to {}",
+            self.block_policy.max_transactions
+        );
+        info!("Block max size set to {} B", self.block_policy.max_bytes);
+        info!(
+            "Transaction validation set to {} (chain id {})",
+            self.transaction_validation.enabled, self.transaction_validation.chain_id
+        );
+        info!(
+            "Transaction deduplication window set to {} ms",
+            self.transaction_deduplication.window.as_millis()
+        );
+        info!(
+            "Max transaction size set to {} B",
+            self.admission_control.max_transaction_size
+        );
+        info!(
+            "Max pending transaction bytes set to {} B",
+            self.admission_control.max_pending_bytes
+        );
+        info!(
+            "Transaction rate limits set to {} tx/s per source IP, {} tx/s per sender",
+            self.admission_control.per_source_ip_rate, self.admission_control.per_sender_rate
+        );
+        info!(
+            "Fork detection set to {} (gossip every {} ms, halt on fork {})",
+            self.fork_detection.enabled,
+            self.fork_detection.gossip_interval.as_millis(),
+            self.fork_detection.halt_on_fork
+        );
+        for sink in &self.block_sinks {
+            info!(
+                "Block sink {} set to {:?} {}",
+                sink.name, sink.kind, sink.address
+            );
+        }
+        info!(
+            "Execution backpressure set to throttle at {} and pause payload at {} unconfirmed blocks",
+            self.execution_backpressure.throttle_lag_blocks,
+            self.execution_backpressure.pause_payload_lag_blocks
+        );
+        if !self.uds_get_validators_path.trim().is_empty() {
+            info!(
+                "Validator set queried on {} every {} ms",
+                self.uds_get_validators_path,
+                self.validator_source.poll_interval.as_millis()
+            );
+        }
     }
 }
 
@@ -716,6 +1356,10 @@ pub struct PrimaryKeyConfig {
     /// If empty or missing, UdsExecutionState will not be used
     #[serde(default)]
     pub uds_block_path: String,
+    /// Unix Domain Socket path for getting the validator set from the executor (optional)
+    /// If empty or missing, the committee only comes from the `--committee` file
+    #[serde(default)]
+    pub uds_get_validators_path: String,
 }
 
 impl PrimaryKeyConfig {
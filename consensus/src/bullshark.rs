@@ -3,9 +3,9 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::{
     consensus::{ConsensusProtocol, ConsensusState, Dag},
-    utils, ConsensusOutput,
+    utils, CommittedSubDag, LeaderSchedule,
 };
-use config::{Committee, Stake};
+use config::{Committee, LeaderReputationParameters, Stake};
 use fastcrypto::{traits::EncodeDecodeBase64, hash::Hash};
 use std::{collections::HashMap, sync::Arc};
 use tracing::{debug, info, warn};
@@ -22,6 +22,8 @@ pub struct Bullshark {
     pub store: Arc<ConsensusStore>,
     /// The depth of the garbage collector.
     pub gc_depth: Round,
+    /// The leader schedule, updated from the reputation of the authorities.
+    pub leader_schedule: LeaderSchedule,
 }
 
 impl ConsensusProtocol for Bullshark {
@@ -30,7 +32,7 @@ impl ConsensusProtocol for Bullshark {
         state: &mut ConsensusState,
         consensus_index: SequenceNumber,
         certificate: Certificate,
-    ) -> StoreResult<Vec<ConsensusOutput>> {
+    ) -> StoreResult<Vec<CommittedSubDag>> {
         debug!("Processing {:?}", certificate);
         let round = certificate.round();
         let mut consensus_index = consensus_index;
@@ -59,8 +61,12 @@ impl ConsensusProtocol for Bullshark {
             debug!("[CONSENSUS] Leader round {} already committed (last_committed_round={})", leader_round, state.last_committed_round);
             return Ok(Vec::new());
         }
-        let (leader_digest, leader) = match Self::leader(&self.committee, leader_round, &state.dag)
-        {
+        let (leader_digest, leader) = match Self::leader(
+            &self.committee,
+            &self.leader_schedule,
+            leader_round,
+            &state.dag,
+        ) {
             Some(x) => {
                 info!("✅ [CONSENSUS] Found leader at round {}: LeaderDigest={:?}", leader_round, x.0);
                 x
@@ -111,35 +117,43 @@ impl ConsensusProtocol for Bullshark {
             leader_round, stake, self.committee.validity_threshold());
         info!("🔍 [CONSENSUS] Calling order_leaders() for leader round {} (last_committed_round={})", 
             leader_round, state.last_committed_round);
-        let leaders_to_commit = utils::order_leaders(&self.committee, leader, state, Self::leader);
+        let leaders_to_commit =
+            utils::order_leaders(&self.committee, leader, state, |committee, round, dag| {
+                Self::leader(committee, &self.leader_schedule, round, dag)
+            });
         info!("📋 [CONSENSUS] order_leaders() returned {} leaders to commit: rounds {:?}", 
             leaders_to_commit.len(), 
             leaders_to_commit.iter().map(|l| l.round()).collect::<Vec<_>>());
         let mut sequence = Vec::new();
         for leader in leaders_to_commit.iter().rev() {
             // Starting from the oldest leader, flatten the sub-dag referenced by the leader.
-            for x in utils::order_dag(self.gc_depth, leader, state) {
-                let digest = x.digest();
-
-                // Update and clean up internal state.
-                state.update(&x, self.gc_depth);
-
-                // Add the certificate to the sequence.
-                sequence.push(ConsensusOutput {
-                    certificate: x,
-                    consensus_index,
-                });
-
-                // Increase the global consensus index.
-                consensus_index += 1;
-
-                // Persist the update.
-                // TODO [issue #116]: Ensure this is not a performance bottleneck.
-                self.store.write_consensus_state(
-                    &state.last_committed,
-                    &consensus_index,
-                    &digest,
-                )?;
+            let sub_dag = utils::commit_sub_dag(
+                &self.store,
+                self.gc_depth,
+                leader,
+                state,
+                &mut consensus_index,
+                Some(&self.leader_schedule),
+            )?;
+            debug!(
+                "Committed sub-dag {} of leader round {}: {} certificate(s)",
+                sub_dag.sub_dag_index(),
+                sub_dag.leader_round(),
+                sub_dag.certificates.len()
+            );
+            let schedule_updated =
+                self.leader_schedule.reputation().schedule_round == sub_dag.leader_round();
+            sequence.push(sub_dag);
+
+            // The remaining leaders were elected with the previous schedule: they are elected
+            // again with the new one when the next certificates come in, as on every other node.
+            if schedule_updated {
+                debug!(
+                    "Leader schedule updated at round {}, {} linked leader(s) left for later",
+                    leader.round(),
+                    leaders_to_commit.len() - sequence.len()
+                );
+                break;
             }
         }
 
@@ -154,25 +168,49 @@ impl ConsensusProtocol for Bullshark {
     }
 
     fn update_committee(&mut self, new_committee: Committee) -> StoreResult<()> {
+        self.leader_schedule.update_committee(new_committee.clone());
         self.committee = new_committee;
         self.store.clear()
     }
 }
 
 impl Bullshark {
-    /// Create a new Bullshark consensus instance.
+    /// Create a new Bullshark consensus instance. The leader schedule is the stake-weighted one.
     pub fn new(committee: Committee, store: Arc<ConsensusStore>, gc_depth: Round) -> Self {
+        let leader_schedule = LeaderSchedule::new(
+            committee.clone(),
+            LeaderReputationParameters {
+                schedule_update_interval: 0,
+                ..LeaderReputationParameters::default()
+            },
+        );
         Self {
             committee,
             store,
             gc_depth,
+            leader_schedule,
+        }
+    }
+
+    /// Update the leader schedule from the reputation of the authorities. The schedule resumes
+    /// from the reputation persisted with the last committed sub-dag.
+    pub fn with_leader_schedule(mut self, leader_schedule: LeaderSchedule) -> Self {
+        if let Some(reputation) = self
+            .store
+            .read_leader_reputation()
+            .expect("Failed to load the leader reputation from store")
+        {
+            leader_schedule.restore(reputation);
         }
+        self.leader_schedule = leader_schedule;
+        self
     }
 
     /// Returns the certificate (and the certificate's digest) originated by the leader of the
     /// specified round (if any).
     fn leader<'a>(
         committee: &Committee,
+        leader_schedule: &LeaderSchedule,
         round: Round,
         dag: &'a Dag,
     ) -> Option<&'a (CertificateDigest, Certificate)> {
@@ -188,6 +226,8 @@ impl Bullshark {
                 let leader = &committee.leader(round);
             }
         }
+        // Authorities with a poor reputation lead through a replacement.
+        let leader = &leader_schedule.swap(leader.clone(), round);
 
         // Return its certificate and the certificate's digest.
         dag.get(&round).and_then(|x| x.get(leader))
@@ -4,7 +4,7 @@
 
 #![allow(clippy::mutable_key_type)]
 
-use crate::{metrics::ConsensusMetrics, ConsensusOutput, SequenceNumber};
+use crate::{metrics::ConsensusMetrics, CommittedSubDag, ConsensusOutput, SequenceNumber};
 use config::Committee;
 use crypto::PublicKey;
 use fastcrypto::hash::Hash;
@@ -34,6 +34,8 @@ pub struct ConsensusState {
     /// Keeps the latest committed certificate (and its parents) for every authority. Anything older
     /// must be regularly cleaned up through the function `update`.
     pub dag: Dag,
+    /// The commit sequence number of the next committed leader.
+    pub next_sub_dag_index: SequenceNumber,
     /// Metrics handler
     pub metrics: Arc<ConsensusMetrics>,
 }
@@ -55,6 +57,7 @@ impl ConsensusState {
                 .iter()
                 .cloned()
                 .collect::<HashMap<_, HashMap<_, _>>>(),
+            next_sub_dag_index: 0,
             metrics,
         }
     }
@@ -64,6 +67,7 @@ impl ConsensusState {
         genesis: Vec<Certificate>,
         metrics: Arc<ConsensusMetrics>,
         recover_last_committed: HashMap<PublicKey, Round>,
+        next_sub_dag_index: SequenceNumber,
         cert_store: CertificateStore,
         gc_depth: Round,
     ) -> Self {
@@ -85,6 +89,7 @@ impl ConsensusState {
             last_committed_round,
             last_committed: recover_last_committed,
             dag,
+            next_sub_dag_index,
             metrics,
         }
     }
@@ -170,7 +175,7 @@ pub trait ConsensusProtocol {
         consensus_index: SequenceNumber,
         // The new certificate.
         certificate: Certificate,
-    ) -> StoreResult<Vec<ConsensusOutput>>;
+    ) -> StoreResult<Vec<CommittedSubDag>>;
 
     fn update_committee(&mut self, new_committee: Committee) -> StoreResult<()>;
 }
@@ -227,9 +232,13 @@ where
         tokio::spawn(async move {
             // Load state từ global_state nếu có
             let mut consensus_index = store
-                .read_last_consensus_index()
+                .read_next_consensus_index()
                 .expect("Failed to load consensus index from store");
             let mut recovered_last_committed = store.read_last_committed();
+            let next_sub_dag_index = store
+                .read_last_committed_sub_dag()
+                .expect("Failed to load the last committed sub-dag from store")
+                .map_or(0, |sub_dag| sub_dag.sub_dag_index + 1);
             
             if let Some(ref gs) = global_state {
                 let state_snapshot = gs.get_state().await;
@@ -268,7 +277,7 @@ where
                 gc_depth,
                 global_state,
             }
-            .run(recovered_last_committed, cert_store, gc_depth)
+            .run(recovered_last_committed, next_sub_dag_index, cert_store, gc_depth)
             .await
             .expect("Failed to run consensus")
         })
@@ -288,6 +297,7 @@ where
     async fn run(
         &mut self,
         recover_last_committed: HashMap<PublicKey, Round>,
+        next_sub_dag_index: SequenceNumber,
         cert_store: CertificateStore,
         gc_depth: Round,
     ) -> StoreResult<()> {
@@ -297,6 +307,7 @@ where
             genesis,
             self.metrics.clone(),
             recover_last_committed,
+            next_sub_dag_index,
             cert_store,
             gc_depth,
         )
@@ -349,8 +360,9 @@ where
                     continue;
                 }
                 
-                let sequence = self.protocol
+                let sub_dags = self.protocol
                     .process_certificate(&mut state, self.consensus_index, certificate)?;
+                let sequence = Self::flatten(sub_dags);
                 
                 let old_consensus_index = self.consensus_index;
                 let old_last_committed_round = state.last_committed_round;
@@ -437,9 +449,10 @@ where
 
                     // Process the certificate using the selected consensus protocol.
                     let cert_round = certificate.round();
-                    let sequence =
+                    let sub_dags =
                         self.protocol
                             .process_certificate(&mut state, self.consensus_index, certificate)?;
+                    let sequence = Self::flatten(sub_dags);
 
                     // Update the consensus index.
                     let old_consensus_index = self.consensus_index;
@@ -536,6 +549,25 @@ where
         }
     }
     
+    /// Flatten the committed sub-dags into the sequence sent to the primary and the executor.
+    /// Every output keeps the metadata of its sub-dag so the commit boundaries can be recovered.
+    fn flatten(sub_dags: Vec<CommittedSubDag>) -> Vec<ConsensusOutput> {
+        sub_dags
+            .into_iter()
+            .flat_map(|sub_dag| {
+                tracing::debug!(
+                    "📦 [Consensus] Sub-dag {} committed: LeaderRound={}, Certificates={}, ConsensusIndex {}..={}",
+                    sub_dag.sub_dag_index(),
+                    sub_dag.leader_round(),
+                    sub_dag.certificates.len(),
+                    sub_dag.info.first_consensus_index,
+                    sub_dag.info.last_consensus_index
+                );
+                sub_dag.certificates
+            })
+            .collect()
+    }
+
     /// Re-send certificates từ DAG sau recovery để trigger consensus processing
     fn resend_certificates_from_dag(
         &self,
@@ -0,0 +1,153 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Leader reputation for Bullshark.
+//!
+//! The stake-weighted election keeps electing a crashed or slow authority, and each of its leader
+//! slots is a missed commit. The authorities are scored on the committed sub-dags (one point per
+//! committed certificate, one per committed leader slot) and, every `schedule_update_interval`
+//! committed rounds, the lowest scores are swapped out of the leader schedule for the highest
+//! ones. Only committed data feeds the scores, so every node derives the same schedule.
+
+use arc_swap::ArcSwap;
+use config::{Committee, LeaderReputationParameters, Stake};
+use crypto::PublicKey;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use tracing::info;
+use types::{Certificate, LeaderReputation, Round};
+
+#[cfg(test)]
+#[path = "tests/leader_schedule_tests.rs"]
+pub mod leader_schedule_tests;
+
+/// The leader schedule, shared by the consensus (which updates it on every commit) and the
+/// proposer (which waits for the leaders it elects).
+#[derive(Clone)]
+pub struct LeaderSchedule {
+    /// When the schedule is updated and how many authorities it swaps.
+    parameters: LeaderReputationParameters,
+    /// The committee of the current epoch.
+    committee: Arc<ArcSwap<Committee>>,
+    /// The reputation as of the last committed sub-dag.
+    reputation: Arc<ArcSwap<LeaderReputation>>,
+}
+
+impl LeaderSchedule {
+    pub fn new(committee: Committee, parameters: LeaderReputationParameters) -> Self {
+        Self {
+            parameters,
+            committee: Arc::new(ArcSwap::from_pointee(committee)),
+            reputation: Arc::new(ArcSwap::from_pointee(LeaderReputation::default())),
+        }
+    }
+
+    /// Whether the schedule is updated from the reputation of the authorities.
+    pub fn enabled(&self) -> bool {
+        self.parameters.schedule_update_interval > 0
+    }
+
+    /// Resume from the reputation persisted with the last committed sub-dag.
+    pub fn restore(&self, reputation: LeaderReputation) {
+        self.reputation.store(Arc::new(reputation));
+    }
+
+    /// Start over with the committee of a new epoch.
+    pub fn update_committee(&self, committee: Committee) {
+        self.committee.store(Arc::new(committee));
+        self.reputation.store(Arc::new(LeaderReputation::default()));
+    }
+
+    /// The reputation as of the last committed sub-dag.
+    pub fn reputation(&self) -> Arc<LeaderReputation> {
+        self.reputation.load_full()
+    }
+
+    /// The leader of `round`.
+    pub fn leader(&self, round: Round) -> PublicKey {
+        let elected = self.committee.load().leader(round);
+        self.swap(elected, round)
+    }
+
+    /// The authority leading `round` in place of `elected` (the stake-weighted choice): one of
+    /// the good nodes if `elected` is a bad node, `elected` otherwise.
+    pub fn swap(&self, elected: PublicKey, round: Round) -> PublicKey {
+        let reputation = self.reputation.load();
+        if reputation.good_nodes.is_empty() || !reputation.bad_nodes.contains(&elected) {
+            return elected;
+        }
+        // Leaders are elected on even rounds: spread them over all the good nodes.
+        let index = (round / 2) as usize % reputation.good_nodes.len();
+        reputation.good_nodes[index].clone()
+    }
+
+    /// Score the sub-dag committed by `leader` and, at the end of the window, turn the scores into
+    /// a new schedule for the rounds after `leader`. Returns the reputation to persist with the
+    /// commit, or None if the schedule is not updated from the reputation.
+    pub fn record_commit(
+        &self,
+        leader: &Certificate,
+        certificates: &[Certificate],
+    ) -> Option<LeaderReputation> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let mut reputation = (**self.reputation.load()).clone();
+        for certificate in certificates {
+            *reputation.scores.entry(certificate.origin()).or_default() += 1;
+        }
+        *reputation.scores.entry(leader.origin()).or_default() += 1;
+
+        if leader.round() >= reputation.schedule_round + self.parameters.schedule_update_interval {
+            reputation = self.next_schedule(&reputation.scores, leader.round());
+            info!(
+                "Leader schedule updated at round {}: {} authorities swapped out",
+                leader.round(),
+                reputation.bad_nodes.len()
+            );
+        }
+
+        self.reputation.store(Arc::new(reputation.clone()));
+        Some(reputation)
+    }
+
+    /// Pair the lowest scores with the highest ones, up to the stake threshold, as long as the
+    /// low score is strictly below the high one.
+    fn next_schedule(&self, scores: &BTreeMap<PublicKey, u64>, round: Round) -> LeaderReputation {
+        let committee = self.committee.load();
+        let total_stake: Stake = committee.authorities.values().map(|x| x.stake).sum();
+        // Swap out at most the stake of f authorities, one less than the validity threshold.
+        let max_bad_stake = (total_stake * self.parameters.bad_nodes_stake_threshold / 100)
+            .min(committee.validity_threshold() - 1);
+
+        // Lowest scores first, ties broken by name so every node ranks the same way.
+        let mut ranked: Vec<(u64, &PublicKey)> = committee
+            .authorities
+            .keys()
+            .map(|name| (scores.get(name).copied().unwrap_or_default(), name))
+            .collect();
+        ranked.sort();
+
+        let mut bad_nodes = BTreeSet::new();
+        let mut good_nodes = Vec::new();
+        let mut bad_stake = 0;
+        for ((bad_score, bad), (good_score, good)) in ranked.iter().zip(ranked.iter().rev()) {
+            bad_stake += committee.stake(bad);
+            if bad_score >= good_score || bad_stake > max_bad_stake {
+                break;
+            }
+            bad_nodes.insert((*bad).clone());
+            good_nodes.push((*good).clone());
+        }
+
+        LeaderReputation {
+            schedule_round: round,
+            scores: BTreeMap::new(),
+            bad_nodes,
+            good_nodes,
+        }
+    }
+}
@@ -10,14 +10,16 @@
 pub mod bullshark;
 pub mod consensus;
 pub mod dag;
+pub mod leader_schedule;
 pub mod metrics;
 pub mod tusk;
 mod utils;
 
-pub use crate::consensus::Consensus;
+pub use crate::{consensus::Consensus, leader_schedule::LeaderSchedule};
 
+use fastcrypto::hash::Hash;
 use serde::{Deserialize, Serialize};
-use types::{Certificate, SequenceNumber};
+use types::{Certificate, CommittedSubDagInfo, Round, SequenceNumber};
 
 /// The default channel size used in the consensus and subscriber logic.
 pub const DEFAULT_CHANNEL_SIZE: usize = 1_000;
@@ -29,4 +31,54 @@ pub struct ConsensusOutput {
     pub certificate: Certificate,
     /// The (global) index associated with this certificate.
     pub consensus_index: SequenceNumber,
+    /// The commit of the leader whose sub-dag contains this certificate.
+    pub sub_dag: CommittedSubDagInfo,
+}
+
+impl ConsensusOutput {
+    /// An output whose sub-dag is unknown (sequenced before the commits were persisted): the
+    /// certificate is treated as a sub-dag of its own.
+    pub fn standalone(certificate: Certificate, consensus_index: SequenceNumber) -> Self {
+        let sub_dag = CommittedSubDagInfo {
+            sub_dag_index: SequenceNumber::default(),
+            leader: certificate.digest(),
+            leader_round: certificate.round(),
+            first_consensus_index: consensus_index,
+            last_consensus_index: consensus_index,
+            commit_timestamp: 0,
+        };
+        Self {
+            certificate,
+            consensus_index,
+            sub_dag,
+        }
+    }
+}
+
+/// The certificates committed by a leader, in commit order (the leader is the last one).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CommittedSubDag {
+    /// The committed leader.
+    pub leader: Certificate,
+    /// The sequenced certificates of the sub-dag.
+    pub certificates: Vec<ConsensusOutput>,
+    /// The commit metadata shared by all the certificates of the sub-dag.
+    pub info: CommittedSubDagInfo,
+}
+
+impl CommittedSubDag {
+    /// The round of the committed leader.
+    pub fn leader_round(&self) -> Round {
+        self.info.leader_round
+    }
+
+    /// The commit sequence number.
+    pub fn sub_dag_index(&self) -> SequenceNumber {
+        self.info.sub_dag_index
+    }
+
+    /// The time of the commit on this node (ms since UNIX epoch).
+    pub fn commit_timestamp(&self) -> u64 {
+        self.info.commit_timestamp
+    }
 }
@@ -16,21 +16,39 @@ use test_utils::CommitteeFixture;
 #[allow(unused_imports)]
 use tokio::sync::mpsc::channel;
 use tokio::sync::watch;
-use types::{CertificateDigest, ReconfigureNotification};
+use types::{CertificateDigest, CommittedSubDagInfo, LeaderReputation, ReconfigureNotification};
 
 pub fn make_consensus_store(store_path: &std::path::Path) -> Arc<ConsensusStore> {
     const LAST_COMMITTED_CF: &str = "last_committed";
     const SEQUENCE_CF: &str = "sequence";
+    const SUB_DAGS_CF: &str = "sub_dags";
+    const LEADER_REPUTATION_CF: &str = "leader_reputation";
 
-    let rocksdb = rocks::open_cf(store_path, None, &[LAST_COMMITTED_CF, SEQUENCE_CF])
-        .expect("Failed to create database");
+    let rocksdb = rocks::open_cf(
+        store_path,
+        None,
+        &[
+            LAST_COMMITTED_CF,
+            SEQUENCE_CF,
+            SUB_DAGS_CF,
+            LEADER_REPUTATION_CF,
+        ],
+    )
+    .expect("Failed to create database");
 
-    let (last_committed_map, sequence_map) = reopen!(&rocksdb,
+    let (last_committed_map, sequence_map, sub_dags_map, leader_reputation_map) = reopen!(&rocksdb,
         LAST_COMMITTED_CF;<PublicKey, Round>,
-        SEQUENCE_CF;<SequenceNumber, CertificateDigest>
+        SEQUENCE_CF;<SequenceNumber, CertificateDigest>,
+        SUB_DAGS_CF;<SequenceNumber, CommittedSubDagInfo>,
+        LEADER_REPUTATION_CF;<u8, LeaderReputation>
     );
 
-    Arc::new(ConsensusStore::new(last_committed_map, sequence_map))
+    Arc::new(ConsensusStore::new(
+        last_committed_map,
+        sequence_map,
+        sub_dags_map,
+        leader_reputation_map,
+    ))
 }
 
 pub fn make_certificate_store(store_path: &std::path::Path) -> CertificateStore {
@@ -90,7 +108,7 @@ async fn commit_one() {
     let metrics = Arc::new(ConsensusMetrics::new(&Registry::new()));
     let _consensus_handle = Consensus::spawn(
         committee,
-        store,
+        store.clone(),
         cert_store,
         rx_reconfigure,
         rx_waiter,
@@ -113,9 +131,24 @@ async fn commit_one() {
     for _ in 1..=4 {
         let output = rx_output.recv().await.unwrap();
         assert_eq!(output.certificate.round(), 1);
+        assert!(!output.sub_dag.is_last(output.consensus_index));
     }
     let output = rx_output.recv().await.unwrap();
     assert_eq!(output.certificate.round(), 2);
+
+    // The leader ends the first committed sub-dag, which is persisted with the sequence.
+    assert_eq!(output.sub_dag.sub_dag_index, 0);
+    assert_eq!(output.sub_dag.leader, output.certificate.digest());
+    assert_eq!(output.sub_dag.first_consensus_index, 0);
+    assert!(output.sub_dag.is_last(output.consensus_index));
+    assert_eq!(
+        store.read_committed_sub_dag(1).unwrap(),
+        Some(output.sub_dag.clone())
+    );
+    assert_eq!(
+        store.read_last_committed_sub_dag().unwrap(),
+        Some(output.sub_dag)
+    );
 }
 
 // Run for 8 dag rounds with one dead node node (that is not a leader). We should commit the leaders of
@@ -560,3 +593,139 @@ async fn restart_with_new_committee() {
         handle.await.unwrap();
     }
 }
+
+/// Feed `certificates` to `bullshark` in order. Returns the sub-dags committed by each
+/// certificate that committed any.
+fn process_certificates(
+    bullshark: &mut Bullshark,
+    certificates: impl IntoIterator<Item = Certificate>,
+) -> Vec<Vec<CommittedSubDag>> {
+    let metrics = Arc::new(ConsensusMetrics::new(&Registry::new()));
+    let mut state = ConsensusState::new(Certificate::genesis(&bullshark.committee), metrics);
+    let mut consensus_index = 0;
+    let mut commits = Vec::new();
+    for certificate in certificates {
+        let sub_dags = bullshark
+            .process_certificate(&mut state, consensus_index, certificate)
+            .unwrap();
+        consensus_index += sub_dags
+            .iter()
+            .map(|sub_dag| sub_dag.certificates.len() as SequenceNumber)
+            .sum::<SequenceNumber>();
+        if !sub_dags.is_empty() {
+            commits.push(sub_dags);
+        }
+    }
+    commits
+}
+
+// Run for 5 dag rounds with a silent authority and a schedule updated every 2 rounds. The leader of
+// round 2 does not have enough support, and the leader of round 4 commits it: the schedule is
+// updated with the sub-dag of round 2, so the leader of round 4 is left for the next certificate.
+// Every arrival order commits the same sequence.
+#[test]
+fn commits_continue_across_a_schedule_update() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let mut keys: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+    keys.sort(); // The first key is the leader elected in the tests.
+    let silent = keys.pop().unwrap();
+
+    let genesis = Certificate::genesis(&committee)
+        .iter()
+        .map(|x| x.digest())
+        .collect::<BTreeSet<_>>();
+
+    // Rounds 1 and 2: fully connected graph (without the silent authority).
+    let (mut certificates, parents) =
+        test_utils::make_optimal_certificates(&committee, 1..=2, &genesis, &keys);
+    let leader_2_digest = certificates
+        .iter()
+        .find(|x| x.round() == 2 && x.origin() == keys[0])
+        .unwrap()
+        .digest();
+
+    // Round 3: only the author of the leader of round 2 links to it.
+    let mut next_parents = BTreeSet::new();
+    let mut weak_parents = parents.clone();
+    weak_parents.remove(&leader_2_digest);
+    for name in &keys[1..] {
+        let (digest, certificate) =
+            test_utils::mock_certificate(&committee, name.clone(), 3, weak_parents.clone());
+        certificates.push_back(certificate);
+        next_parents.insert(digest);
+    }
+    let (digest, certificate) =
+        test_utils::mock_certificate(&committee, keys[0].clone(), 3, parents);
+    certificates.push_back(certificate);
+    next_parents.insert(digest);
+
+    // Rounds 4 and 5: fully connected graph, the leader of round 4 is supported.
+    let (out, _) = test_utils::make_optimal_certificates(&committee, 4..=5, &next_parents, &keys);
+    certificates.extend(out);
+
+    let bullshark = || {
+        let schedule = LeaderSchedule::new(
+            committee.clone(),
+            LeaderReputationParameters {
+                schedule_update_interval: 2,
+                bad_nodes_stake_threshold: 34,
+            },
+        );
+        Bullshark::new(
+            committee.clone(),
+            make_consensus_store(&test_utils::temp_dir()),
+            50,
+        )
+        .with_leader_schedule(schedule)
+    };
+    let leader_rounds = |commits: &[Vec<CommittedSubDag>]| -> Vec<Vec<Round>> {
+        commits
+            .iter()
+            .map(|sub_dags| sub_dags.iter().map(|x| x.leader_round()).collect())
+            .collect()
+    };
+    let sequence = |commits: &[Vec<CommittedSubDag>]| -> Vec<(SequenceNumber, CertificateDigest)> {
+        commits
+            .iter()
+            .flatten()
+            .flat_map(|sub_dag| &sub_dag.certificates)
+            .map(|output| (output.consensus_index, output.certificate.digest()))
+            .collect()
+    };
+
+    // The schedule is updated by the sub-dag of round 2, and the next certificate commits the
+    // leader of round 4.
+    let mut in_order = bullshark();
+    let in_order_commits = process_certificates(&mut in_order, certificates.iter().cloned());
+    assert_eq!(leader_rounds(&in_order_commits), vec![vec![2], vec![4]]);
+    let reputation = in_order.leader_schedule.reputation();
+    assert_eq!(reputation.schedule_round, 4);
+    assert_eq!(reputation.bad_nodes, BTreeSet::from([silent]));
+    assert_eq!(
+        in_order.store.read_leader_reputation().unwrap(),
+        Some((*reputation).clone())
+    );
+
+    // Another node receives the certificates of each round in the reverse order.
+    let mut reordered = bullshark();
+    let mut by_round: Vec<Vec<Certificate>> = Vec::new();
+    for certificate in certificates {
+        match by_round.last_mut() {
+            Some(round) if round[0].round() == certificate.round() => round.push(certificate),
+            _ => by_round.push(vec![certificate]),
+        }
+    }
+    let reordered_commits = process_certificates(
+        &mut reordered,
+        by_round
+            .into_iter()
+            .flat_map(|round| round.into_iter().rev()),
+    );
+    assert_eq!(leader_rounds(&reordered_commits), vec![vec![2], vec![4]]);
+    assert_eq!(sequence(&reordered_commits), sequence(&in_order_commits));
+    assert_eq!(
+        reordered.leader_schedule.reputation(),
+        in_order.leader_schedule.reputation()
+    );
+}
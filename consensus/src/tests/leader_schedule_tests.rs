@@ -0,0 +1,158 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+
+use test_utils::CommitteeFixture;
+
+fn schedule(committee: &Committee, schedule_update_interval: u64) -> LeaderSchedule {
+    schedule_with_threshold(committee, schedule_update_interval, 34)
+}
+
+fn schedule_with_threshold(
+    committee: &Committee,
+    schedule_update_interval: u64,
+    bad_nodes_stake_threshold: u64,
+) -> LeaderSchedule {
+    LeaderSchedule::new(
+        committee.clone(),
+        LeaderReputationParameters {
+            schedule_update_interval,
+            bad_nodes_stake_threshold,
+        },
+    )
+}
+
+/// Commit a leader every two rounds up to `last_round`, with one certificate per round from each
+/// of `authors` in its sub-dag. The leader slots go round-robin over `authors`.
+fn commit_rounds(
+    schedule: &LeaderSchedule,
+    committee: &Committee,
+    authors: &[PublicKey],
+    last_round: Round,
+) -> Vec<Option<LeaderReputation>> {
+    (2..=last_round)
+        .step_by(2)
+        .map(|leader_round| {
+            let certificates: Vec<Certificate> = (leader_round - 1..=leader_round)
+                .flat_map(|round| {
+                    authors.iter().map(move |author| {
+                        test_utils::mock_certificate(
+                            committee,
+                            author.clone(),
+                            round,
+                            BTreeSet::new(),
+                        )
+                        .1
+                    })
+                })
+                .collect();
+            let leader_index =
+                certificates.len() - authors.len() + (leader_round / 2) as usize % authors.len();
+            let leader = certificates[leader_index].clone();
+            schedule.record_commit(&leader, &certificates)
+        })
+        .collect()
+}
+
+#[test]
+fn silent_authority_is_swapped_out_of_the_schedule() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let keys: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+    let silent = keys[3].clone();
+    assert!((0..100).any(|round| committee.leader(round) == silent));
+
+    let schedule = schedule(&committee, 10);
+    commit_rounds(&schedule, &committee, &keys[..3], 8);
+    // Before the end of the window, the schedule is the stake-weighted one.
+    assert_eq!(schedule.reputation().scores.get(&silent), None);
+    assert!(schedule.reputation().bad_nodes.is_empty());
+
+    let reputation = commit_rounds(&schedule, &committee, &keys[..3], 10)
+        .pop()
+        .unwrap()
+        .unwrap();
+    assert_eq!(reputation.schedule_round, 10);
+    assert_eq!(reputation.bad_nodes, BTreeSet::from([silent.clone()]));
+    assert_eq!(reputation.good_nodes.len(), 1);
+    assert!(reputation.scores.is_empty());
+    assert_eq!(*schedule.reputation(), reputation);
+
+    for round in 0..100 {
+        assert_ne!(schedule.leader(round), silent);
+    }
+}
+
+#[test]
+fn at_most_f_authorities_are_swapped_out() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let keys: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+    let f = committee.validity_threshold() - 1;
+
+    // Two authorities are silent but the stake of only f = 1 authority may be swapped out, even
+    // with a threshold of the whole stake.
+    let schedule = schedule_with_threshold(&committee, 10, 100);
+    let reputation = commit_rounds(&schedule, &committee, &keys[..2], 10)
+        .pop()
+        .unwrap()
+        .unwrap();
+    assert_eq!(reputation.bad_nodes.len() as Stake, f);
+    assert!(reputation
+        .bad_nodes
+        .is_subset(&BTreeSet::from([keys[2].clone(), keys[3].clone()])));
+}
+
+#[test]
+fn schedule_depends_only_on_the_commits() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let keys: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+
+    let first = schedule(&committee, 4);
+    let second = schedule(&committee, 4);
+    assert_eq!(
+        commit_rounds(&first, &committee, &keys[1..], 12),
+        commit_rounds(&second, &committee, &keys[1..], 12)
+    );
+
+    // A restarted node resumes from the persisted reputation.
+    let restarted = schedule(&committee, 4);
+    restarted.restore((*first.reputation()).clone());
+    for round in 0..100 {
+        assert_eq!(restarted.leader(round), first.leader(round));
+    }
+}
+
+#[test]
+fn balanced_scores_keep_the_schedule() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let keys: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+
+    // Every authority leads once in the window: all the scores are equal.
+    let schedule = schedule(&committee, 8);
+    commit_rounds(&schedule, &committee, &keys, 8);
+    let reputation = schedule.reputation();
+    assert_eq!(reputation.schedule_round, 8);
+    assert!(reputation.bad_nodes.is_empty());
+    for round in 0..100 {
+        assert_eq!(schedule.leader(round), committee.leader(round));
+    }
+}
+
+#[test]
+fn disabled_schedule_records_nothing() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let keys: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+
+    let schedule = schedule(&committee, 0);
+    assert!(commit_rounds(&schedule, &committee, &keys[..3], 20)
+        .iter()
+        .all(Option::is_none));
+    assert_eq!(*schedule.reputation(), LeaderReputation::default());
+    for round in 0..100 {
+        assert_eq!(schedule.leader(round), committee.leader(round));
+    }
+}
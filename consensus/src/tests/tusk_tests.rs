@@ -16,21 +16,39 @@ use test_utils::CommitteeFixture;
 #[allow(unused_imports)]
 use tokio::sync::mpsc::channel;
 use tokio::sync::watch;
-use types::{CertificateDigest, ReconfigureNotification};
+use types::{CertificateDigest, CommittedSubDagInfo, LeaderReputation, ReconfigureNotification};
 
 pub fn make_consensus_store(store_path: &std::path::Path) -> Arc<ConsensusStore> {
     const LAST_COMMITTED_CF: &str = "last_committed";
     const SEQUENCE_CF: &str = "sequence";
+    const SUB_DAGS_CF: &str = "sub_dags";
+    const LEADER_REPUTATION_CF: &str = "leader_reputation";
 
-    let rocksdb = rocks::open_cf(store_path, None, &[LAST_COMMITTED_CF, SEQUENCE_CF])
-        .expect("Failed to create database");
+    let rocksdb = rocks::open_cf(
+        store_path,
+        None,
+        &[
+            LAST_COMMITTED_CF,
+            SEQUENCE_CF,
+            SUB_DAGS_CF,
+            LEADER_REPUTATION_CF,
+        ],
+    )
+    .expect("Failed to create database");
 
-    let (last_committed_map, sequence_map) = reopen!(&rocksdb,
+    let (last_committed_map, sequence_map, sub_dags_map, leader_reputation_map) = reopen!(&rocksdb,
         LAST_COMMITTED_CF;<PublicKey, Round>,
-        SEQUENCE_CF;<SequenceNumber, CertificateDigest>
+        SEQUENCE_CF;<SequenceNumber, CertificateDigest>,
+        SUB_DAGS_CF;<SequenceNumber, CommittedSubDagInfo>,
+        LEADER_REPUTATION_CF;<u8, LeaderReputation>
     );
 
-    Arc::new(ConsensusStore::new(last_committed_map, sequence_map))
+    Arc::new(ConsensusStore::new(
+        last_committed_map,
+        sequence_map,
+        sub_dags_map,
+        leader_reputation_map,
+    ))
 }
 
 pub fn make_certificate_store(store_path: &std::path::Path) -> CertificateStore {
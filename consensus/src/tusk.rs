@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::{
     consensus::{ConsensusProtocol, ConsensusState, Dag},
-    utils, ConsensusOutput, SequenceNumber,
+    utils, CommittedSubDag, SequenceNumber,
 };
 use config::{Committee, Stake};
 use fastcrypto::{traits::EncodeDecodeBase64, hash::Hash};
@@ -30,7 +30,7 @@ impl ConsensusProtocol for Tusk {
         state: &mut ConsensusState,
         consensus_index: SequenceNumber,
         certificate: Certificate,
-    ) -> StoreResult<Vec<ConsensusOutput>> {
+    ) -> StoreResult<Vec<CommittedSubDag>> {
         debug!("Processing {:?}", certificate);
         let round = certificate.round();
         let mut consensus_index = consensus_index;
@@ -89,29 +89,14 @@ impl ConsensusProtocol for Tusk {
             .rev()
         {
             // Starting from the oldest leader, flatten the sub-dag referenced by the leader.
-            for x in utils::order_dag(self.gc_depth, leader, state) {
-                let digest = x.digest();
-
-                // Update and clean up internal state.
-                state.update(&x, self.gc_depth);
-
-                // Add the certificate to the sequence.
-                sequence.push(ConsensusOutput {
-                    certificate: x,
-                    consensus_index,
-                });
-
-                // Increase the global consensus index.
-                consensus_index += 1;
-
-                // Persist the update.
-                // TODO [issue #116]: Ensure this is not a performance bottleneck.
-                self.store.write_consensus_state(
-                    &state.last_committed,
-                    &consensus_index,
-                    &digest,
-                )?;
-            }
+            sequence.push(utils::commit_sub_dag(
+                &self.store,
+                self.gc_depth,
+                leader,
+                state,
+                &mut consensus_index,
+                /* leader_schedule */ None,
+            )?);
         }
 
         // Log the latest committed round of every authority (for debug).
@@ -1,11 +1,21 @@
 // Copyright (c) 2021, Facebook, Inc. and its affiliates
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
-use crate::consensus::{ConsensusState, Dag};
+use crate::{
+    consensus::{ConsensusState, Dag},
+    CommittedSubDag, ConsensusOutput, LeaderSchedule,
+};
 use config::Committee;
-use std::collections::HashSet;
+use fastcrypto::hash::Hash;
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tracing::{debug, info, warn};
-use types::{Certificate, CertificateDigest, Round};
+use types::{
+    Certificate, CertificateDigest, CommittedSubDagInfo, ConsensusStore, Round, SequenceNumber,
+    StoreResult,
+};
 
 /// Order the past leaders that we didn't already commit.
 pub fn order_leaders<'a, LeaderElector>(
@@ -153,3 +163,67 @@ pub fn order_dag(
     ordered.sort_by_key(|x| x.round());
     ordered
 }
+
+/// Commit the sub-dag of a leader: flatten it, assign the consensus indices starting at
+/// `consensus_index` (which is advanced past the sub-dag) and persist the sequence and the commit.
+/// The commit is scored in `leader_schedule` (if any), and the reputation persisted with it.
+pub fn commit_sub_dag(
+    store: &ConsensusStore,
+    gc_depth: Round,
+    leader: &Certificate,
+    state: &mut ConsensusState,
+    consensus_index: &mut SequenceNumber,
+    leader_schedule: Option<&LeaderSchedule>,
+) -> StoreResult<CommittedSubDag> {
+    let ordered = order_dag(gc_depth, leader, state);
+    let leader_reputation =
+        leader_schedule.and_then(|schedule| schedule.record_commit(leader, &ordered));
+    let info = CommittedSubDagInfo {
+        sub_dag_index: state.next_sub_dag_index,
+        leader: leader.digest(),
+        leader_round: leader.round(),
+        first_consensus_index: *consensus_index,
+        last_consensus_index: (*consensus_index + ordered.len() as SequenceNumber)
+            .saturating_sub(1),
+        commit_timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+    };
+
+    let mut certificates = Vec::with_capacity(ordered.len());
+    for x in ordered {
+        let digest = x.digest();
+
+        // Update and clean up internal state.
+        state.update(&x, gc_depth);
+
+        // Add the certificate to the sequence.
+        let is_last = info.is_last(*consensus_index);
+        certificates.push(ConsensusOutput {
+            certificate: x,
+            consensus_index: *consensus_index,
+            sub_dag: info.clone(),
+        });
+
+        // Persist the update, and the commit with its last certificate.
+        // TODO [issue #116]: Ensure this is not a performance bottleneck.
+        store.write_consensus_state(
+            &state.last_committed,
+            consensus_index,
+            &digest,
+            is_last.then_some(&info),
+            leader_reputation.as_ref().filter(|_| is_last),
+        )?;
+
+        // Increase the global consensus index.
+        *consensus_index += 1;
+    }
+    state.next_sub_dag_index += 1;
+
+    Ok(CommittedSubDag {
+        leader: leader.clone(),
+        certificates,
+        info,
+    })
+}
@@ -1,6 +1,6 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
-use config::WorkerId;
+use config::{ConsensusProtocolKind, WorkerId};
 use std::fmt::Debug;
 use store::StoreError;
 use thiserror::Error;
@@ -73,6 +73,12 @@ pub enum SubscriberError {
 
     #[error("Client transaction invalid: {0}")]
     ClientExecutionError(String),
+
+    #[error("The database was created with the {stored:?} consensus protocol, the parameters select {configured:?}")]
+    ConsensusProtocolMismatch {
+        stored: ConsensusProtocolKind,
+        configured: ConsensusProtocolKind,
+    },
 }
 
 impl From<Box<bincode::ErrorKind>> for SubscriberError {
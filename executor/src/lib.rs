@@ -18,6 +18,7 @@ use config::{Committee, SharedWorkerCache};
 use consensus::ConsensusOutput;
 use crypto::PublicKey;
 use network::P2pNetwork;
+use primary::{CommittedOutputFeed, ExecutionBackpressure};
 
 use prometheus::Registry;
 
@@ -27,9 +28,7 @@ use storage::CertificateStore;
 use crate::subscriber::spawn_subscriber;
 use tokio::sync::oneshot;
 use tokio::{sync::watch, task::JoinHandle};
-use types::{
-    metered_channel, CertificateDigest, ConsensusStore, ReconfigureNotification, SequenceNumber,
-};
+use types::{metered_channel, ConsensusStore, ReconfigureNotification};
 
 /// Convenience type representing a serialized transaction.
 pub type SerializedTransaction = Vec<u8>;
@@ -41,7 +40,8 @@ pub type SerializedTransactionDigest = u64;
 pub trait ExecutionState {
     /// Execute the transaction and atomically persist the consensus index. This function
     /// returns an execution outcome that will be output by the executor channel. It may
-    /// also return a new committee to reconfigure the system.
+    /// also return a new committee to reconfigure the system. `consensus_output.sub_dag` is the
+    /// commit of the leader whose sub-dag contains the certificate.
     async fn handle_consensus_transaction(
         &self,
         consensus_output: &ConsensusOutput,
@@ -51,6 +51,12 @@ pub trait ExecutionState {
 
     /// Load the last consensus index from storage.
     async fn load_execution_indices(&self) -> ExecutionIndices;
+
+    /// The lag of the executor behind this execution state, if it reports one. The committed
+    /// certificates are then delivered more slowly while the executor is behind.
+    fn execution_backpressure(&self) -> Option<Arc<ExecutionBackpressure>> {
+        None
+    }
 }
 
 /// A client subscribing to the consensus output and executing every transaction.
@@ -69,6 +75,7 @@ impl Executor {
         rx_consensus: metered_channel::Receiver<ConsensusOutput>,
         registry: &Registry,
         restored_consensus_output: Vec<ConsensusOutput>,
+        committed_output_feed: Option<Arc<CommittedOutputFeed>>,
     ) -> SubscriberResult<Vec<JoinHandle<()>>>
     where
         State: ExecutionState + Send + Sync + 'static,
@@ -94,7 +101,7 @@ impl Executor {
             restored_consensus_output,
         );
 
-        let notifier_handler = Notifier::spawn(rx_notifier, execution_state);
+        let notifier_handler = Notifier::spawn(rx_notifier, execution_state, committed_output_feed);
 
         // Return the handle.
         info!("Consensus subscriber successfully started");
@@ -110,7 +117,7 @@ pub async fn get_restored_consensus_output<State: ExecutionState>(
 ) -> Result<Vec<ConsensusOutput>, SubscriberError> {
     let mut restored_consensus_output = Vec::new();
     let consensus_next_index = consensus_store
-        .read_last_consensus_index()
+        .read_next_consensus_index()
         .map_err(SubscriberError::StoreError)?;
 
     let next_cert_index = execution_state
@@ -120,19 +127,21 @@ pub async fn get_restored_consensus_output<State: ExecutionState>(
 
     if next_cert_index < consensus_next_index {
         let missing = consensus_store
-            .read_sequenced_certificates(&(next_cert_index..=consensus_next_index - 1))?
-            .iter()
-            .zip(next_cert_index..consensus_next_index)
-            .filter_map(|(c, seq)| c.map(|digest| (digest, seq)))
-            .collect::<Vec<(CertificateDigest, SequenceNumber)>>();
+            .read_sequenced_certificates(&(next_cert_index..=consensus_next_index - 1))?;
 
-        for (cert_digest, seq) in missing {
+        for (seq, cert_digest) in missing {
             if let Some(cert) = certificate_store.read(cert_digest).unwrap() {
-                // Save the missing sequence / cert pair as ConsensusOutput to re-send to the executor.
-                restored_consensus_output.push(ConsensusOutput {
-                    certificate: cert,
-                    consensus_index: seq,
-                })
+                // Save the missing sequence / cert pair as ConsensusOutput to re-send to the executor,
+                // together with the commit of its sub-dag.
+                let output = match consensus_store.read_committed_sub_dag(seq)? {
+                    Some(sub_dag) => ConsensusOutput {
+                        certificate: cert,
+                        consensus_index: seq,
+                        sub_dag,
+                    },
+                    None => ConsensusOutput::standalone(cert, seq),
+                };
+                restored_consensus_output.push(output)
             }
         }
     }
@@ -155,4 +164,8 @@ impl<T: ExecutionState + 'static + Send + Sync> ExecutionState for Arc<T> {
     async fn load_execution_indices(&self) -> ExecutionIndices {
         self.as_ref().load_execution_indices().await
     }
+
+    fn execution_backpressure(&self) -> Option<Arc<ExecutionBackpressure>> {
+        self.as_ref().execution_backpressure()
+    }
 }
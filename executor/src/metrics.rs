@@ -22,6 +22,9 @@ pub struct ExecutorMetrics {
     pub pending_remote_request_batch: IntGauge,
     /// The number of pending payload downloads
     pub waiting_elements_subscriber: IntGauge,
+    /// The number of committed payloads unavailable for longer than the stuck delay. The
+    /// delivery of the committed certificates waits for them.
+    pub subscriber_stuck_payloads: IntGauge,
 }
 
 impl ExecutorMetrics {
@@ -75,6 +78,11 @@ impl ExecutorMetrics {
                 "The number of pending payload downloads",
                 registry
             ).unwrap(),
+            subscriber_stuck_payloads: register_int_gauge_with_registry!(
+                "subscriber_stuck_payloads",
+                "The number of committed payloads unavailable for longer than the stuck delay",
+                registry
+            ).unwrap(),
         }
     }
 }
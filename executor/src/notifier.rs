@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::{ExecutionIndices, ExecutionState};
 use consensus::ConsensusOutput;
-use tokio::task::JoinHandle;
+use primary::{CommittedOutputFeed, ExecutionBackpressure};
+use std::sync::Arc;
+use tokio::{task::JoinHandle, time::sleep};
 use tracing;
 
 use types::{metered_channel, Batch};
@@ -17,16 +19,27 @@ pub struct BatchIndex {
 pub struct Notifier<State: ExecutionState> {
     rx_notifier: metered_channel::Receiver<(BatchIndex, Batch)>,
     callback: State,
+    /// Publish the committed certificates with their batches to the streaming clients.
+    committed_output_feed: Option<Arc<CommittedOutputFeed>>,
+    /// The batches of the certificate being received, until its last batch.
+    pending_batches: Vec<Batch>,
+    /// Slows down the delivery of the certificates while the executor is behind.
+    execution_backpressure: Option<Arc<ExecutionBackpressure>>,
 }
 
 impl<State: ExecutionState + Send + Sync + 'static> Notifier<State> {
     pub fn spawn(
         rx_notifier: metered_channel::Receiver<(BatchIndex, Batch)>,
         callback: State,
+        committed_output_feed: Option<Arc<CommittedOutputFeed>>,
     ) -> JoinHandle<()> {
+        let execution_backpressure = callback.execution_backpressure();
         let notifier = Notifier {
             rx_notifier,
             callback,
+            committed_output_feed,
+            pending_batches: Vec::new(),
+            execution_backpressure,
         };
         tokio::spawn(notifier.run())
     }
@@ -38,7 +51,14 @@ impl<State: ExecutionState + Send + Sync + 'static> Notifier<State> {
             
             // Log để debug
             tracing::debug!("📦 [Notifier] Received batch for round {}: {} transaction(s)", round, tx_count);
-            
+
+            // Chậm lại trước mỗi certificate mới khi executor bị tụt lại phía sau.
+            if index.batch_index == 0 {
+                self.throttle(round).await;
+            }
+
+            self.publish(&index, &batch).await;
+
             // Nếu batch rỗng, vẫn gọi handle_consensus_transaction với empty transaction
             // để đảm bảo round chẵn luôn tạo block (ẩn log để giảm log)
             if batch.0.is_empty() {
@@ -73,4 +93,45 @@ impl<State: ExecutionState + Send + Sync + 'static> Notifier<State> {
             }
         }
     }
+
+    /// Wait before delivering a new certificate if the executor is behind. This slows down
+    /// consensus delivery without blocking it: the blocks still pending at the executor are
+    /// only confirmed (and retransmitted) as new blocks are delivered.
+    async fn throttle(&self, round: u64) {
+        let delay = match self
+            .execution_backpressure
+            .as_ref()
+            .and_then(|backpressure| backpressure.throttle_delay())
+        {
+            Some(delay) => delay,
+            None => return,
+        };
+        tracing::debug!(
+            "🐢 [Notifier] Executor is behind: delaying certificate of round {} by {:?}",
+            round,
+            delay
+        );
+        sleep(delay).await;
+    }
+
+    /// Publish the certificate once all its batches are received (the batches of a certificate
+    /// arrive in the order of its payload).
+    async fn publish(&mut self, index: &BatchIndex, batch: &Batch) {
+        let feed = match &self.committed_output_feed {
+            Some(feed) => feed,
+            None => return,
+        };
+        let payload_len = index.consensus_output.certificate.header.payload.len();
+        if index.batch_index == 0 {
+            self.pending_batches.clear();
+        }
+        // A certificate without payload comes with a single empty batch.
+        if payload_len > 0 {
+            self.pending_batches.push(batch.clone());
+        }
+        if index.batch_index + 1 >= payload_len as u64 {
+            let batches = std::mem::take(&mut self.pending_batches);
+            feed.publish(index.consensus_output.clone(), batches).await;
+        }
+    }
 }
@@ -30,7 +30,7 @@ use tokio::{
     sync::{oneshot, watch},
     task::JoinHandle,
 };
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 use tracing::{info, instrument};
 use types::{metered_channel, Batch, BatchDigest, Certificate, ReconfigureNotification};
 
@@ -166,6 +166,10 @@ impl<Network: SubscriberNetwork> Subscriber<Network> {
 }
 
 impl<Network: SubscriberNetwork> Fetcher<Network> {
+    /// The delay after which a payload still unavailable is reported as stuck. It only drives
+    /// the metric and the logs: the certificate is never delivered without its payload.
+    const STUCK_PAYLOAD_DELAY: Duration = Duration::from_secs(30);
+
     /// Returns ordered vector of futures for downloading individual payloads for certificate
     /// Order of futures returned follows order of payloads in the certificate
     /// See fetch_payload for more details
@@ -178,15 +182,16 @@ impl<Network: SubscriberNetwork> Fetcher<Network> {
         for (batch_index, (digest, worker_id)) in
             deliver.certificate.header.payload.iter().enumerate()
         {
-            let mut workers = self
-                .network
-                .workers_for_certificate(&deliver.certificate, worker_id);
+            let workers = payload_workers(
+                self.network
+                    .workers_for_certificate(&deliver.certificate, worker_id),
+                self.network.workers_for_committee(worker_id),
+            );
             let batch_index = BatchIndex {
                 consensus_output: deliver.clone(),
                 next_certificate_index: deliver.consensus_index,
                 batch_index: batch_index as u64,
             };
-            workers.shuffle(&mut ThreadRng::default());
             ret.push(
                 Box::pin(
                     self.fetch_payload(*digest, *worker_id, workers)
@@ -218,7 +223,8 @@ impl<Network: SubscriberNetwork> Fetcher<Network> {
     /// Fetches single payload from network
     /// This future performs infinite retries and blocks until Batch is available
     /// As an optimization it tries to download from local worker first, but then fans out
-    /// requests to remote worker if not found locally
+    /// requests to remote worker if not found locally: first the workers of the certificate's
+    /// signers, then any other worker of the committee
     #[instrument(level = "debug", skip_all, fields(digest = % digest, worker_id = % worker_id))]
     async fn fetch_payload(
         &self,
@@ -237,7 +243,21 @@ impl<Network: SubscriberNetwork> Fetcher<Network> {
             futures.push(future.boxed());
             stagger += Duration::from_secs(1);
         }
-        let (batch, _, _) = futures::future::select_all(futures).await;
+        let mut fetch = futures::future::select_all(futures);
+        let (batch, _, _) = tokio::select! {
+            result = &mut fetch => result,
+            () = tokio::time::sleep(Self::STUCK_PAYLOAD_DELAY) => {
+                warn!(
+                    "Payload {} still unavailable after {:?}: delivery of the committed certificates is waiting for it",
+                    digest,
+                    Self::STUCK_PAYLOAD_DELAY
+                );
+                let _stuck_guard = PendingGuard::make_inc(&self.metrics.subscriber_stuck_payloads);
+                let result = fetch.await;
+                debug!("Stuck payload {} fetched", digest);
+                result
+            }
+        };
         batch
     }
 
@@ -316,6 +336,23 @@ impl<Network: SubscriberNetwork> Fetcher<Network> {
     }
 }
 
+/// The workers to request a payload from, in order: the workers of the certificate's signers
+/// (which hold the payload), then the other workers of the committee (which may have synced it).
+fn payload_workers(
+    mut signers: Vec<NetworkPublicKey>,
+    committee: Vec<NetworkPublicKey>,
+) -> Vec<NetworkPublicKey> {
+    let mut rng = ThreadRng::default();
+    signers.shuffle(&mut rng);
+    let mut others: Vec<_> = committee
+        .into_iter()
+        .filter(|worker| !signers.contains(worker))
+        .collect();
+    others.shuffle(&mut rng);
+    signers.extend(others);
+    signers
+}
+
 // todo - make it generic so that other can reuse
 struct PendingGuard<'a> {
     metric: &'a IntGauge,
@@ -343,6 +380,7 @@ pub trait SubscriberNetwork: Send + Sync {
         certificate: &Certificate,
         worker_id: &WorkerId,
     ) -> Vec<NetworkPublicKey>;
+    fn workers_for_committee(&self, worker_id: &WorkerId) -> Vec<NetworkPublicKey>;
     async fn request_batch(
         &self,
         digest: BatchDigest,
@@ -391,6 +429,16 @@ impl SubscriberNetwork for SubscriberNetworkImpl {
             .collect()
     }
 
+    fn workers_for_committee(&self, worker_id: &WorkerId) -> Vec<NetworkPublicKey> {
+        let worker_cache = self.worker_cache.load();
+        self.committee
+            .authorities
+            .keys()
+            .filter_map(|authority| worker_cache.worker(authority, worker_id).ok())
+            .map(|worker| worker.name)
+            .collect()
+    }
+
     async fn request_batch(
         &self,
         digest: BatchDigest,
@@ -430,6 +478,17 @@ mod tests {
         assert_eq!(batch, batch2);
     }
 
+    #[test]
+    pub fn test_payload_workers() {
+        let workers = payload_workers(test_pks(&[2, 3]), test_pks(&[0, 1, 2, 3]));
+        assert_eq!(workers.len(), 4);
+        // The signers come first, then the rest of the committee.
+        assert!(workers[..2].contains(&test_pk(2)));
+        assert!(workers[..2].contains(&test_pk(3)));
+        assert!(workers[2..].contains(&test_pk(0)));
+        assert!(workers[2..].contains(&test_pk(1)));
+    }
+
     struct TestSubscriberNetwork {
         data: HashMap<BatchDigest, HashMap<NetworkPublicKey, Batch>>,
         my: NetworkPublicKey,
@@ -467,6 +526,10 @@ mod tests {
             self.data.get(digest).unwrap().keys().cloned().collect()
         }
 
+        fn workers_for_committee(&self, _worker_id: &WorkerId) -> Vec<NetworkPublicKey> {
+            test_pks(&[0, 1, 2, 3])
+        }
+
         async fn request_batch(
             &self,
             digest: BatchDigest,
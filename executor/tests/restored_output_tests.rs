@@ -0,0 +1,97 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use async_trait::async_trait;
+use consensus::ConsensusOutput;
+use executor::{get_restored_consensus_output, ExecutionIndices, ExecutionState};
+use fastcrypto::hash::Hash;
+use std::collections::{BTreeSet, HashMap};
+use storage::CertificateStore;
+use store::{reopen, rocks, rocks::DBMap};
+use test_utils::{make_consensus_store, temp_dir, CommitteeFixture};
+use types::{Certificate, CertificateDigest, Round};
+
+/// An execution state that executed the certificates up to `next_certificate_index`.
+struct ExecutedUpTo(u64);
+
+#[async_trait]
+impl ExecutionState for ExecutedUpTo {
+    async fn handle_consensus_transaction(
+        &self,
+        _consensus_output: &ConsensusOutput,
+        _execution_indices: ExecutionIndices,
+        _transaction: Vec<u8>,
+    ) {
+    }
+
+    async fn load_execution_indices(&self) -> ExecutionIndices {
+        ExecutionIndices {
+            next_certificate_index: self.0,
+            ..ExecutionIndices::default()
+        }
+    }
+}
+
+fn make_certificate_store(store_path: &std::path::Path) -> CertificateStore {
+    const CERTIFICATES_CF: &str = "certificates";
+    const CERTIFICATE_ID_BY_ROUND_CF: &str = "certificate_id_by_round";
+
+    let rocksdb = rocks::open_cf(
+        store_path,
+        None,
+        &[CERTIFICATES_CF, CERTIFICATE_ID_BY_ROUND_CF],
+    )
+    .expect("Failed creating database");
+
+    let (certificate_map, certificate_id_by_round_map) = reopen!(&rocksdb,
+        CERTIFICATES_CF;<CertificateDigest, Certificate>,
+        CERTIFICATE_ID_BY_ROUND_CF;<(Round, CertificateDigest), u8>
+    );
+
+    CertificateStore::new(certificate_map, certificate_id_by_round_map)
+}
+
+#[tokio::test]
+async fn restore_the_certificates_the_execution_state_missed() {
+    let fixture = CommitteeFixture::builder().build();
+    let committee = fixture.committee();
+    let author = fixture.authorities().next().unwrap().public_key();
+    let consensus_store = make_consensus_store(&temp_dir());
+    let certificate_store = make_certificate_store(&temp_dir());
+
+    // Sequence three certificates.
+    let certificates: Vec<Certificate> = (0..3)
+        .map(|consensus_index| {
+            let (_, certificate) = test_utils::mock_certificate(
+                &committee,
+                author.clone(),
+                consensus_index + 1,
+                BTreeSet::new(),
+            );
+            certificate_store.write(certificate.clone()).unwrap();
+            consensus_store
+                .write_consensus_state(
+                    &HashMap::new(),
+                    &consensus_index,
+                    &certificate.digest(),
+                    None,
+                    None,
+                )
+                .unwrap();
+            certificate
+        })
+        .collect();
+
+    // The execution state executed the certificate with consensus index 0 only.
+    let restored =
+        get_restored_consensus_output(consensus_store, certificate_store, &ExecutedUpTo(1))
+            .await
+            .unwrap();
+
+    assert_eq!(restored.len(), 2);
+    for (output, (consensus_index, certificate)) in
+        restored.iter().zip(certificates.iter().enumerate().skip(1))
+    {
+        assert_eq!(output.consensus_index, consensus_index as u64);
+        assert_eq!(output.certificate.digest(), certificate.digest());
+    }
+}
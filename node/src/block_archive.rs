@@ -0,0 +1,178 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent archive of the committed blocks delivered to the executor.
+//!
+//! Blocks are stored protobuf-encoded by height, together with an index from
+//! transaction hash to its (height, position) in the archived block.
+
+use crate::execution_state::comm::CommittedBlock;
+use prost::Message;
+use store::{
+    rocks::{
+        DBMap,
+        TypedStoreError::{self, SerializationError},
+    },
+    traits::Map,
+};
+
+/// Convenience type to propagate store errors.
+pub type ArchiveResult<T> = Result<T, TypedStoreError>;
+
+/// The location of a transaction inside the archive: (block height, position in block).
+pub type TxLocation = (u64, u32);
+
+pub struct BlockArchive {
+    /// Committed blocks (protobuf-encoded `CommittedBlock`) by height.
+    blocks: DBMap<u64, Vec<u8>>,
+    /// Transaction hash -> location of the transaction in the archive.
+    tx_index: DBMap<Vec<u8>, TxLocation>,
+}
+
+impl BlockArchive {
+    pub fn new(blocks: DBMap<u64, Vec<u8>>, tx_index: DBMap<Vec<u8>, TxLocation>) -> Self {
+        Self { blocks, tx_index }
+    }
+
+    /// Persist a block and the location of its transactions atomically.
+    /// Writing the same height twice overwrites the previous entry (blocks are deterministic).
+    pub fn write_block(&self, block: &CommittedBlock, tx_hashes: &[Vec<u8>]) -> ArchiveResult<()> {
+        let locations = tx_hashes
+            .iter()
+            .enumerate()
+            .map(|(position, hash)| (hash.clone(), (block.height, position as u32)));
+
+        let mut write_batch = self.blocks.batch();
+        write_batch = write_batch.insert_batch(
+            &self.blocks,
+            std::iter::once((block.height, block.encode_to_vec())),
+        )?;
+        write_batch = write_batch.insert_batch(&self.tx_index, locations)?;
+        write_batch.write()
+    }
+
+    /// Load the archived block at a specific height. An entry that does not decode is an error,
+    /// not a missing block.
+    pub fn read_block(&self, height: u64) -> ArchiveResult<Option<CommittedBlock>> {
+        self.blocks
+            .get(&height)?
+            .map(|bytes| {
+                CommittedBlock::decode(bytes.as_slice()).map_err(|e| {
+                    SerializationError(format!("Archived block {} does not decode: {}", height, e))
+                })
+            })
+            .transpose()
+    }
+
+    /// Find the block height and position of a transaction by its hash.
+    pub fn read_tx_location(&self, tx_hash: &[u8]) -> ArchiveResult<Option<TxLocation>> {
+        self.tx_index.get(&tx_hash.to_vec())
+    }
+
+    /// The lowest archived height, if any.
+    pub fn first_height(&self) -> Option<u64> {
+        self.blocks.keys().next()
+    }
+
+    /// The highest archived height, if any.
+    pub fn last_height(&self) -> ArchiveResult<Option<u64>> {
+        Ok(self.blocks.keys().skip_prior_to(&u64::MAX)?.next())
+    }
+
+    /// Delete every block with height < `height` together with its transaction index entries.
+    /// `tx_hashes_of` extracts the transaction hashes of a pruned block.
+    /// Returns the number of pruned blocks.
+    pub fn prune_below<F>(&self, height: u64, tx_hashes_of: F) -> ArchiveResult<usize>
+    where
+        F: Fn(&CommittedBlock) -> Vec<Vec<u8>>,
+    {
+        let mut pruned_heights = Vec::new();
+        let mut pruned_hashes = Vec::new();
+        for (block_height, bytes) in self.blocks.iter().take_while(|(h, _)| *h < height) {
+            if let Ok(block) = CommittedBlock::decode(bytes.as_slice()) {
+                for hash in tx_hashes_of(&block) {
+                    // Keep index entries that point to a newer (retained) block.
+                    if matches!(self.tx_index.get(&hash)?, Some((h, _)) if h < height) {
+                        pruned_hashes.push(hash);
+                    }
+                }
+            }
+            pruned_heights.push(block_height);
+        }
+        if pruned_heights.is_empty() {
+            return Ok(0);
+        }
+
+        let pruned = pruned_heights.len();
+        let mut write_batch = self.blocks.batch();
+        write_batch = write_batch.delete_batch(&self.blocks, pruned_heights)?;
+        write_batch = write_batch.delete_batch(&self.tx_index, pruned_hashes)?;
+        write_batch.write()?;
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{execution_state::comm::CommittedBlock, NodeStorage};
+    use store::traits::Map;
+    use tempfile::TempDir;
+
+    fn block(height: u64) -> CommittedBlock {
+        CommittedBlock {
+            epoch: 0,
+            height,
+            transactions: Vec::new(),
+            header: None,
+            format_version: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn tx_hash(height: u64, position: u8) -> Vec<u8> {
+        let mut hash = height.to_be_bytes().to_vec();
+        hash.push(position);
+        hash
+    }
+
+    #[test]
+    fn archive_index_and_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = NodeStorage::reopen(temp_dir.path()).block_archive;
+
+        for height in 0..5 {
+            archive
+                .write_block(&block(height), &[tx_hash(height, 0), tx_hash(height, 1)])
+                .unwrap();
+        }
+        assert_eq!(archive.read_block(3).unwrap(), Some(block(3)));
+        assert_eq!(
+            archive.read_tx_location(&tx_hash(3, 1)).unwrap(),
+            Some((3, 1))
+        );
+        assert_eq!(archive.first_height(), Some(0));
+        assert_eq!(archive.last_height().unwrap(), Some(4));
+
+        let pruned = archive
+            .prune_below(3, |b| vec![tx_hash(b.height, 0), tx_hash(b.height, 1)])
+            .unwrap();
+        assert_eq!(pruned, 3);
+        assert_eq!(archive.read_block(2).unwrap(), None);
+        assert_eq!(archive.read_tx_location(&tx_hash(2, 0)).unwrap(), None);
+        assert_eq!(archive.first_height(), Some(3));
+        assert_eq!(
+            archive.read_tx_location(&tx_hash(4, 0)).unwrap(),
+            Some((4, 0))
+        );
+    }
+
+    #[test]
+    fn undecodable_block_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = NodeStorage::reopen(temp_dir.path()).block_archive;
+
+        archive.blocks.insert(&7, &vec![0xff; 4]).unwrap();
+        assert!(archive.read_block(7).is_err());
+        assert_eq!(archive.read_block(8).unwrap(), None);
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Block headers: each block delivered to the executor carries the hash of its parent and a
+//! Merkle root over its ordered transaction hashes, so the executor can check that block N
+//! follows block N-1 and that two validators produced the same block.
+//!
+//! FORK-SAFE: the header only depends on the content of the block and of its parent.
+
+use crate::execution_state::comm::{BlockHeader, CommittedBlock};
+use bytes::Bytes;
+use sha3::{Digest, Keccak256};
+
+/// Length of the block hashes and Merkle roots (Keccak256).
+pub const HASH_LEN: usize = 32;
+
+/// The parent hash of the first block.
+pub const GENESIS_PARENT_HASH: [u8; HASH_LEN] = [0u8; HASH_LEN];
+
+/// Domain separation of the leaves and the inner nodes of the transactions Merkle tree.
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Merkle root over the transaction hashes, in block order. A node without sibling is promoted
+/// to the next level unchanged; the root of a block without transactions is all zeroes.
+pub fn transactions_root(tx_hashes: &[Vec<u8>]) -> Vec<u8> {
+    if tx_hashes.is_empty() {
+        return vec![0u8; HASH_LEN];
+    }
+
+    let mut level: Vec<Vec<u8>> = tx_hashes
+        .iter()
+        .map(|tx_hash| {
+            let mut hasher = Keccak256::new();
+            hasher.update([LEAF_PREFIX]);
+            hasher.update(tx_hash);
+            hasher.finalize().to_vec()
+        })
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut hasher = Keccak256::new();
+                    hasher.update([NODE_PREFIX]);
+                    hasher.update(left);
+                    hasher.update(right);
+                    hasher.finalize().to_vec()
+                }
+                [single] => single.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level.remove(0)
+}
+
+/// The hash identifying a block: Keccak256 over its epoch, height and header fields.
+pub fn block_hash(block: &CommittedBlock) -> Vec<u8> {
+    let header = block.header.clone().unwrap_or_default();
+    let mut hasher = Keccak256::new();
+    hasher.update(block.epoch.to_be_bytes());
+    hasher.update(block.height.to_be_bytes());
+    hasher.update(&header.parent_hash);
+    hasher.update(&header.transactions_root);
+    hasher.update(header.first_consensus_index.to_be_bytes());
+    hasher.update(header.end_consensus_index.to_be_bytes());
+    hasher.update(header.leader_round.to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// The last block of the chain, parent of the next block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u64,
+    pub hash: Vec<u8>,
+    pub end_consensus_index: u64,
+}
+
+impl ChainTip {
+    pub fn of(block: &CommittedBlock) -> Self {
+        Self {
+            height: block.height,
+            hash: block_hash(block),
+            end_consensus_index: block
+                .header
+                .as_ref()
+                .map_or(0, |header| header.end_consensus_index),
+        }
+    }
+}
+
+/// Link a block to its parent (`None` for the first block). Blocks built without a header
+/// (empty blocks filling a gap) get the header of a block without transactions nor certificates.
+pub fn link(block: &mut CommittedBlock, parent: Option<&ChainTip>) {
+    let header = block.header.get_or_insert_with(|| BlockHeader {
+        transactions_root: Bytes::from(transactions_root(&[])),
+        ..BlockHeader::default()
+    });
+    header.parent_hash = Bytes::from(parent.map_or_else(
+        || GENESIS_PARENT_HASH.to_vec(),
+        |parent| parent.hash.clone(),
+    ));
+    if header.first_consensus_index == header.end_consensus_index {
+        let end_consensus_index = parent.map_or(0, |parent| parent.end_consensus_index);
+        header.first_consensus_index = end_consensus_index;
+        header.end_consensus_index = end_consensus_index;
+    }
+}
+
+/// Whether `block` is the child of `parent`.
+pub fn follows(block: &CommittedBlock, parent: &CommittedBlock) -> bool {
+    block.height == parent.height + 1
+        && block.header.as_ref().map_or(false, |header| {
+            header.parent_hash.as_ref() == block_hash(parent).as_slice()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u64, tx_hashes: &[Vec<u8>], consensus_indices: (u64, u64)) -> CommittedBlock {
+        CommittedBlock {
+            epoch: 0,
+            height,
+            transactions: Vec::new(),
+            header: Some(BlockHeader {
+                transactions_root: Bytes::from(transactions_root(tx_hashes)),
+                first_consensus_index: consensus_indices.0,
+                end_consensus_index: consensus_indices.1,
+                leader_round: 2,
+                ..BlockHeader::default()
+            }),
+            format_version: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn transactions_root_depends_on_order() {
+        let hashes: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; HASH_LEN]).collect();
+        let root = transactions_root(&hashes);
+        assert_eq!(root.len(), HASH_LEN);
+        assert_eq!(root, transactions_root(&hashes));
+
+        let mut reordered = hashes.clone();
+        reordered.swap(0, 1);
+        assert_ne!(root, transactions_root(&reordered));
+        assert_ne!(root, transactions_root(&hashes[..4]));
+        // A single transaction is not its own root.
+        assert_ne!(transactions_root(&hashes[..1]), hashes[0]);
+        assert_eq!(transactions_root(&[]), vec![0u8; HASH_LEN]);
+    }
+
+    #[test]
+    fn blocks_are_chained_by_parent_hash() {
+        let mut first = block(0, &[vec![1; HASH_LEN]], (0, 3));
+        link(&mut first, None);
+        assert_eq!(
+            first.header.as_ref().unwrap().parent_hash.as_ref(),
+            GENESIS_PARENT_HASH.as_slice()
+        );
+
+        let mut second = block(1, &[vec![2; HASH_LEN]], (3, 6));
+        link(&mut second, Some(&ChainTip::of(&first)));
+        assert!(follows(&second, &first));
+
+        // Changing the content of the parent breaks the chain.
+        let mut forked = block(0, &[vec![3; HASH_LEN]], (0, 3));
+        link(&mut forked, None);
+        assert_ne!(block_hash(&forked), block_hash(&first));
+        assert!(!follows(&second, &forked));
+    }
+
+    #[test]
+    fn empty_blocks_continue_the_consensus_range() {
+        let mut first = block(0, &[vec![1; HASH_LEN]], (0, 3));
+        link(&mut first, None);
+
+        let mut gap = CommittedBlock {
+            epoch: 0,
+            height: 1,
+            transactions: Vec::new(),
+            header: None,
+            format_version: 0,
+            entries: Vec::new(),
+        };
+        link(&mut gap, Some(&ChainTip::of(&first)));
+        let header = gap.header.as_ref().unwrap();
+        assert_eq!(
+            (header.first_consensus_index, header.end_consensus_index),
+            (3, 3)
+        );
+        assert_eq!(
+            header.transactions_root.as_ref(),
+            vec![0u8; HASH_LEN].as_slice()
+        );
+        assert!(follows(&gap, &first));
+        assert_eq!(ChainTip::of(&gap).end_consensus_index, 3);
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Block formation: assigns the sequenced certificates (and their transactions) to the heights
+//! of the blocks delivered to the executor, according to the configured `BlockPolicy`.
+//!
+//! FORK-SAFE: heights only depend on the policy and on the consensus output sequence, so every
+//! node delivers the same blocks at the same heights.
+//!
+//! The consensus indices restart at 0 with every epoch: the heights of an epoch start after the
+//! last height of the previous one, so the executor sees one continuous chain.
+
+use config::{BlockFormation, BlockPolicy};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Position of the block being formed. Persisted with the execution state so a restarted node
+/// keeps numbering the blocks of a stateful policy from where it stopped.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BlockCursor {
+    /// Height of the block being formed.
+    pub height: u64,
+    /// Consensus index of the last certificate assigned to a block.
+    pub last_consensus_index: Option<u64>,
+    /// Certificates assigned to the block being formed.
+    pub certificates: u64,
+    /// Transactions assigned to the block being formed.
+    pub transactions: u64,
+    /// Size of the transactions assigned to the block being formed.
+    pub bytes: u64,
+    /// The last certificate ends the block (committed leader): the next certificate opens a new height.
+    pub closed: bool,
+}
+
+/// Returns true if switching from `stored` to `current` would assign different heights to the
+/// blocks that were already delivered.
+pub fn renumbers_heights(stored: &BlockPolicy, current: &BlockPolicy) -> bool {
+    stored.formation != current.formation
+        || (current.formation == BlockFormation::Certificates
+            && stored.certificates_per_block.max(1) != current.certificates_per_block.max(1))
+        || stored.max_transactions != current.max_transactions
+        || stored.max_bytes != current.max_bytes
+}
+
+pub struct BlockFormer {
+    policy: BlockPolicy,
+    cursor: BlockCursor,
+    /// Height of the first block of the current epoch.
+    epoch_start_height: u64,
+}
+
+impl BlockFormer {
+    pub fn new(policy: BlockPolicy) -> Self {
+        Self {
+            policy,
+            cursor: BlockCursor::default(),
+            epoch_start_height: 0,
+        }
+    }
+
+    pub fn policy(&self) -> &BlockPolicy {
+        &self.policy
+    }
+
+    pub fn cursor(&self) -> &BlockCursor {
+        &self.cursor
+    }
+
+    /// Resume numbering from a persisted cursor.
+    pub fn restore(&mut self, cursor: BlockCursor) {
+        self.cursor = cursor;
+    }
+
+    /// Number the blocks of a new epoch from `start_height`, its consensus indices from 0.
+    pub fn start_epoch(&mut self, start_height: u64) {
+        self.epoch_start_height = start_height;
+        self.cursor = BlockCursor {
+            height: start_height,
+            ..BlockCursor::default()
+        };
+    }
+
+    pub fn epoch_start_height(&self) -> u64 {
+        self.epoch_start_height
+    }
+
+    /// The first height no certificate of the current epoch was assigned to.
+    pub fn next_height(&self) -> u64 {
+        match self.cursor.last_consensus_index {
+            Some(_) => self.cursor.height + 1,
+            None => self.cursor.height,
+        }
+    }
+
+    /// Whether the height of a certificate is simply `consensus_index / certificates_per_block`.
+    /// Aligned policies need no state: blocks can be rebuilt from the consensus store alone.
+    pub fn is_index_aligned(&self) -> bool {
+        self.policy.formation == BlockFormation::Certificates
+            && self.policy.max_transactions == 0
+            && self.policy.max_bytes == 0
+    }
+
+    /// Whether certificates of committed leaders end a block.
+    pub fn closes_on_leader(&self) -> bool {
+        self.policy.formation == BlockFormation::SubDag
+    }
+
+    /// Whether the caps can split the transactions of a certificate over several blocks.
+    pub fn splits_certificates(&self) -> bool {
+        self.policy.max_transactions > 0 || self.policy.max_bytes > 0
+    }
+
+    pub fn certificates_per_block(&self) -> u64 {
+        self.policy.certificates_per_block.max(1)
+    }
+
+    /// Height of the last assigned certificate or transaction.
+    pub fn current_height(&self) -> u64 {
+        self.cursor.height
+    }
+
+    /// Consensus indices of an aligned height of the current epoch (None for stateful policies
+    /// and the heights of the previous epochs).
+    pub fn index_range(&self, height: u64) -> Option<RangeInclusive<u64>> {
+        if !self.is_index_aligned() || height < self.epoch_start_height {
+            return None;
+        }
+        let per_block = self.certificates_per_block();
+        let offset = height - self.epoch_start_height;
+        Some(offset * per_block..=(offset + 1) * per_block - 1)
+    }
+
+    /// Assign a certificate to a height. Calls with the consensus index of the last assigned
+    /// certificate (further batches or transactions of the same certificate) return the current height.
+    /// `is_committed_leader` ends the block after this certificate in `sub_dag` formation.
+    pub fn assign_certificate(&mut self, consensus_index: u64, is_committed_leader: bool) -> u64 {
+        if self.is_index_aligned() {
+            self.cursor.height =
+                self.epoch_start_height + consensus_index / self.certificates_per_block();
+            self.cursor.last_consensus_index = Some(consensus_index);
+            return self.cursor.height;
+        }
+        if self.cursor.last_consensus_index == Some(consensus_index) {
+            return self.cursor.height;
+        }
+
+        if self.cursor.last_consensus_index.is_some() && self.is_block_full() {
+            self.open_next_block(0);
+        }
+        self.cursor.certificates += 1;
+        self.cursor.last_consensus_index = Some(consensus_index);
+        if is_committed_leader && self.closes_on_leader() {
+            self.cursor.closed = true;
+        }
+        self.cursor.height
+    }
+
+    /// Assign a transaction of the last assigned certificate to a height. When the transaction
+    /// does not fit in the caps, the block is split and the transaction opens the next height.
+    /// A block always holds at least one transaction, whatever its size.
+    pub fn assign_transaction(&mut self, size: u64) -> u64 {
+        if self.is_index_aligned() {
+            return self.cursor.height;
+        }
+
+        let over_count = self.policy.max_transactions > 0
+            && self.cursor.transactions + 1 > self.policy.max_transactions;
+        let over_bytes =
+            self.policy.max_bytes > 0 && self.cursor.bytes + size > self.policy.max_bytes;
+        if self.cursor.transactions > 0 && (over_count || over_bytes) {
+            // The certificate continues in the next block, which still ends where this one would have.
+            let closed = self.cursor.closed;
+            self.open_next_block(1);
+            self.cursor.closed = closed;
+        }
+        self.cursor.transactions += 1;
+        self.cursor.bytes += size;
+        self.cursor.height
+    }
+
+    fn is_block_full(&self) -> bool {
+        self.cursor.closed
+            || (self.policy.formation == BlockFormation::Certificates
+                && self.cursor.certificates >= self.certificates_per_block())
+            || (self.policy.max_transactions > 0
+                && self.cursor.transactions >= self.policy.max_transactions)
+            || (self.policy.max_bytes > 0 && self.cursor.bytes >= self.policy.max_bytes)
+    }
+
+    fn open_next_block(&mut self, certificates: u64) {
+        self.cursor.height += 1;
+        self.cursor.certificates = certificates;
+        self.cursor.transactions = 0;
+        self.cursor.bytes = 0;
+        self.cursor.closed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(formation: BlockFormation, max_transactions: u64, max_bytes: u64) -> BlockPolicy {
+        BlockPolicy {
+            formation,
+            certificates_per_block: 3,
+            max_transactions,
+            max_bytes,
+            ..BlockPolicy::default()
+        }
+    }
+
+    #[test]
+    fn certificates_per_block_is_index_aligned() {
+        let mut former = BlockFormer::new(policy(BlockFormation::Certificates, 0, 0));
+        assert!(former.is_index_aligned());
+        let heights: Vec<u64> = (0..7)
+            .map(|i| former.assign_certificate(i, false))
+            .collect();
+        assert_eq!(heights, vec![0, 0, 0, 1, 1, 1, 2]);
+        assert_eq!(former.index_range(1), Some(3..=5));
+    }
+
+    #[test]
+    fn sub_dag_ends_blocks_on_committed_leaders() {
+        let mut former = BlockFormer::new(policy(BlockFormation::SubDag, 0, 0));
+        assert!(!former.is_index_aligned());
+        let leaders = [false, false, true, false, true, true, false];
+        let heights: Vec<u64> = leaders
+            .iter()
+            .enumerate()
+            .map(|(i, leader)| former.assign_certificate(i as u64, *leader))
+            .collect();
+        assert_eq!(heights, vec![0, 0, 0, 1, 1, 2, 3]);
+        // Further batches of the same certificate stay in its block.
+        assert_eq!(former.assign_certificate(6, false), 3);
+    }
+
+    #[test]
+    fn caps_split_blocks_in_commit_order() {
+        let mut former = BlockFormer::new(policy(BlockFormation::SubDag, 2, 100));
+        assert_eq!(former.assign_certificate(0, false), 0);
+        assert_eq!(former.assign_transaction(10), 0);
+        assert_eq!(former.assign_transaction(10), 0);
+        // Max transactions reached: split inside the certificate.
+        assert_eq!(former.assign_transaction(10), 1);
+        assert_eq!(former.assign_certificate(1, true), 1);
+        // Max bytes reached: split, an oversized transaction gets a block of its own.
+        assert_eq!(former.assign_transaction(500), 2);
+        assert_eq!(former.assign_transaction(1), 3);
+        // The split block still ends with the leader.
+        assert_eq!(former.assign_certificate(2, false), 4);
+
+        let mut restarted = BlockFormer::new(former.policy().clone());
+        restarted.restore(former.cursor().clone());
+        assert_eq!(restarted.assign_certificate(3, false), 4);
+    }
+
+    #[test]
+    fn epochs_continue_the_heights() {
+        let mut aligned = BlockFormer::new(policy(BlockFormation::Certificates, 0, 0));
+        assert_eq!(aligned.next_height(), 0);
+        aligned.assign_certificate(4, false);
+        assert_eq!(aligned.next_height(), 2);
+        // The consensus indices restart at 0 in the new epoch.
+        aligned.start_epoch(aligned.next_height());
+        assert_eq!(aligned.next_height(), 2);
+        assert_eq!(aligned.assign_certificate(0, false), 2);
+        assert_eq!(aligned.assign_certificate(3, false), 3);
+        assert_eq!(aligned.index_range(3), Some(3..=5));
+        assert_eq!(aligned.index_range(1), None);
+
+        let mut sub_dag = BlockFormer::new(policy(BlockFormation::SubDag, 0, 0));
+        sub_dag.assign_certificate(0, false);
+        sub_dag.assign_certificate(1, true);
+        sub_dag.start_epoch(sub_dag.next_height());
+        assert_eq!(sub_dag.assign_certificate(0, true), 1);
+        assert_eq!(sub_dag.assign_certificate(1, false), 2);
+    }
+
+    #[test]
+    fn policy_changes_that_renumber_heights() {
+        let current = policy(BlockFormation::Certificates, 0, 0);
+        let mut stored = current.clone();
+        stored.processed_batches_gc_depth = 10;
+        assert!(!renumbers_heights(&stored, &current));
+        stored.certificates_per_block = 10;
+        assert!(renumbers_heights(&stored, &current));
+        assert!(renumbers_heights(
+            &policy(BlockFormation::SubDag, 0, 0),
+            &current
+        ));
+        assert!(renumbers_heights(
+            &policy(BlockFormation::Certificates, 0, 1_000),
+            &current
+        ));
+    }
+}
@@ -0,0 +1,416 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Committed blocks stored in rotating segment files, for offline testing and forensics.
+//!
+//! A segment `<first height>.seg` holds the v2 frames (`u32 LE length + NodeMessage`) of one
+//! block each, byte for byte what an executor receives on the socket. Its index
+//! `<first height>.idx` holds one `(height, offset)` pair (two u64 LE) per frame. The index entry
+//! is written after its frame, so after a crash the segment is truncated to its last indexed
+//! frame and the writer resumes after the last indexed height.
+
+use crate::{
+    execution_state::comm::{node_message, CommittedBlock, NodeMessage},
+    uds_protocol,
+};
+use prost::Message;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const SEGMENT_EXTENSION: &str = "seg";
+const INDEX_EXTENSION: &str = "idx";
+/// The size of an index entry: height and offset, two u64 LE.
+const INDEX_ENTRY_LEN: usize = 16;
+
+fn segment_path(dir: &Path, first_height: u64, extension: &str) -> PathBuf {
+    dir.join(format!("{:020}.{}", first_height, extension))
+}
+
+/// The first heights of the segments in `dir`, in increasing order.
+fn list_segments(dir: &Path) -> Result<Vec<u64>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to list {:?}: {}", dir, e))?;
+    let mut segments = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("Failed to list {:?}: {}", dir, e))?
+            .path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(first_height) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse().ok())
+        {
+            segments.push(first_height);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// The `(height, offset)` entries of an index file; a torn last entry is ignored.
+fn read_index(path: &Path) -> Result<Vec<(u64, u64)>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {:?}: {}", path, e)),
+    };
+    Ok(bytes
+        .chunks_exact(INDEX_ENTRY_LEN)
+        .map(|entry| {
+            let mut height = [0u8; 8];
+            let mut offset = [0u8; 8];
+            height.copy_from_slice(&entry[..8]);
+            offset.copy_from_slice(&entry[8..]);
+            (u64::from_le_bytes(height), u64::from_le_bytes(offset))
+        })
+        .collect())
+}
+
+/// The length of the frame starting at `offset` (length prefix included).
+fn frame_len(data: &mut File, offset: u64) -> Result<u64, String> {
+    let mut len_buf = [0u8; 4];
+    data.seek(SeekFrom::Start(offset))
+        .and_then(|_| data.read_exact(&mut len_buf))
+        .map_err(|e| format!("Failed to read the frame at offset {}: {}", offset, e))?;
+    Ok(4 + u32::from_le_bytes(len_buf) as u64)
+}
+
+/// The segment being written.
+struct OpenSegment {
+    data: File,
+    index: File,
+    /// The size of the segment file.
+    len: u64,
+}
+
+/// Appends committed blocks to the segments of a directory.
+pub struct SegmentWriter {
+    dir: PathBuf,
+    /// The size from which a new segment is started.
+    segment_max_bytes: u64,
+    current: Option<OpenSegment>,
+    /// The height of the last block written.
+    last_height: Option<u64>,
+}
+
+impl SegmentWriter {
+    /// Open the segments of `dir`, repairing the last one if it was torn by a crash.
+    pub fn open<P: AsRef<Path>>(dir: P, segment_max_bytes: u64) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+
+        let mut writer = Self {
+            dir,
+            segment_max_bytes,
+            current: None,
+            last_height: None,
+        };
+        let mut segments = list_segments(&writer.dir)?;
+        while let Some(first_height) = segments.pop() {
+            if let Some(segment) = writer.repair(first_height)? {
+                writer.current = Some(segment);
+                break;
+            }
+        }
+        Ok(writer)
+    }
+
+    /// The height of the last block written to the segments.
+    pub fn last_height(&self) -> Option<u64> {
+        self.last_height
+    }
+
+    /// Append a block. Blocks at or below the last written height (eg. re-sent after a restart)
+    /// are ignored; returns whether the block was written.
+    pub fn append(&mut self, block: &CommittedBlock) -> Result<bool, String> {
+        if self.last_height.map_or(false, |last| block.height <= last) {
+            return Ok(false);
+        }
+        let frame = uds_protocol::encode_frame(&uds_protocol::blocks_message(vec![block.clone()]))?;
+
+        let rotate = self
+            .current
+            .as_ref()
+            .map_or(true, |segment| segment.len >= self.segment_max_bytes);
+        if rotate {
+            self.current = Some(self.create(block.height)?);
+        }
+        let segment = self.current.as_mut().unwrap();
+
+        let mut entry = Vec::with_capacity(INDEX_ENTRY_LEN);
+        entry.extend_from_slice(&block.height.to_le_bytes());
+        entry.extend_from_slice(&segment.len.to_le_bytes());
+        segment
+            .data
+            .write_all(&frame)
+            .and_then(|_| segment.index.write_all(&entry))
+            .map_err(|e| format!("Failed to append block {}: {}", block.height, e))?;
+        segment.len += frame.len() as u64;
+        self.last_height = Some(block.height);
+        Ok(true)
+    }
+
+    fn create(&self, first_height: u64) -> Result<OpenSegment, String> {
+        let open = |extension| {
+            let path = segment_path(&self.dir, first_height, extension);
+            OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to open {:?}: {}", path, e))
+        };
+        Ok(OpenSegment {
+            data: open(SEGMENT_EXTENSION)?,
+            index: open(INDEX_EXTENSION)?,
+            len: 0,
+        })
+    }
+
+    /// Truncate the segment to its last indexed frame and its index to whole entries. A segment
+    /// without indexed frame is removed (`None`).
+    fn repair(&mut self, first_height: u64) -> Result<Option<OpenSegment>, String> {
+        let index_path = segment_path(&self.dir, first_height, INDEX_EXTENSION);
+        let entries = read_index(&index_path)?;
+        let (height, offset) = match entries.last() {
+            Some(&entry) => entry,
+            None => {
+                let data_path = segment_path(&self.dir, first_height, SEGMENT_EXTENSION);
+                fs::remove_file(&data_path)
+                    .and_then(|_| fs::remove_file(&index_path).or(Ok(())))
+                    .map_err(|e| format!("Failed to remove {:?}: {}", data_path, e))?;
+                return Ok(None);
+            }
+        };
+
+        let mut segment = self.create(first_height)?;
+        let len = offset + frame_len(&mut segment.data, offset)?;
+        segment
+            .data
+            .set_len(len)
+            .and_then(|_| {
+                segment
+                    .index
+                    .set_len((entries.len() * INDEX_ENTRY_LEN) as u64)
+            })
+            .map_err(|e| format!("Failed to repair segment {}: {}", first_height, e))?;
+        segment.len = len;
+        self.last_height = Some(height);
+        Ok(Some(segment))
+    }
+}
+
+/// Reads the blocks of the segments written by a [`SegmentWriter`], by height.
+pub struct SegmentReader {
+    dir: PathBuf,
+    /// The first heights of the segments, in increasing order.
+    segments: Vec<u64>,
+}
+
+impl SegmentReader {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, String> {
+        let dir = dir.as_ref().to_path_buf();
+        let segments = list_segments(&dir)?;
+        Ok(Self { dir, segments })
+    }
+
+    /// The height of the first block of the segments.
+    pub fn first_height(&self) -> Option<u64> {
+        self.segments.first().copied()
+    }
+
+    /// The raw frames of the blocks from `height` on, as `(height, frame)` with the frame exactly
+    /// as written (length prefix included), eg. to replay them to an executor.
+    pub fn frames_from(&self, height: u64) -> SegmentFrames {
+        // The last segment starting at or below `height`.
+        let start = self
+            .segments
+            .iter()
+            .rposition(|first_height| *first_height <= height)
+            .unwrap_or(0);
+        SegmentFrames {
+            dir: self.dir.clone(),
+            segments: self.segments[start..].to_vec(),
+            from_height: height,
+            data: Vec::new(),
+            entries: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// The blocks from `height` on, in height order.
+    pub fn blocks_from(&self, height: u64) -> impl Iterator<Item = Result<CommittedBlock, String>> {
+        self.frames_from(height).flat_map(|frame| {
+            let blocks = frame.and_then(|(height, frame)| decode_blocks(height, &frame));
+            match blocks {
+                Ok(blocks) => blocks.into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            }
+        })
+    }
+}
+
+fn decode_blocks(height: u64, frame: &[u8]) -> Result<Vec<CommittedBlock>, String> {
+    let message = NodeMessage::decode(&frame[4..])
+        .map_err(|e| format!("Failed to decode block {}: {}", height, e))?;
+    match message.payload {
+        Some(node_message::Payload::Blocks(data)) => Ok(data.blocks),
+        _ => Err(format!("Frame of block {} carries no blocks", height)),
+    }
+}
+
+/// Iterator over the frames of the segments, see [`SegmentReader::frames_from`].
+pub struct SegmentFrames {
+    dir: PathBuf,
+    /// The segments not loaded yet.
+    segments: Vec<u64>,
+    from_height: u64,
+    /// The content and index of the loaded segment.
+    data: Vec<u8>,
+    entries: Vec<(u64, u64)>,
+    /// The next entry of the loaded segment.
+    position: usize,
+}
+
+impl SegmentFrames {
+    fn load_next_segment(&mut self) -> Result<bool, String> {
+        if self.segments.is_empty() {
+            return Ok(false);
+        }
+        let first_height = self.segments.remove(0);
+        let data_path = segment_path(&self.dir, first_height, SEGMENT_EXTENSION);
+        self.data =
+            fs::read(&data_path).map_err(|e| format!("Failed to read {:?}: {}", data_path, e))?;
+        self.entries = read_index(&segment_path(&self.dir, first_height, INDEX_EXTENSION))?;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl Iterator for SegmentFrames {
+    type Item = Result<(u64, Vec<u8>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some(&(height, offset)) = self.entries.get(self.position) {
+                self.position += 1;
+                if height < self.from_height {
+                    continue;
+                }
+                let start = offset as usize;
+                let frame = self
+                    .data
+                    .get(start..start + 4)
+                    .map(|len| {
+                        let mut len_buf = [0u8; 4];
+                        len_buf.copy_from_slice(len);
+                        start + 4 + u32::from_le_bytes(len_buf) as usize
+                    })
+                    .and_then(|end| self.data.get(start..end));
+                return Some(match frame {
+                    Some(frame) => Ok((height, frame.to_vec())),
+                    None => {
+                        // Stop at a torn segment.
+                        self.segments.clear();
+                        self.entries.clear();
+                        Err(format!("Frame of block {} is truncated", height))
+                    }
+                });
+            }
+            match self.load_next_segment() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => {
+                    self.segments.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution_state::comm::Transaction;
+    use tempfile::TempDir;
+
+    fn block(height: u64) -> CommittedBlock {
+        CommittedBlock {
+            epoch: 0,
+            height,
+            transactions: vec![Transaction {
+                digest: vec![height as u8; 100].into(),
+                worker_id: 0,
+            }],
+            header: None,
+            format_version: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn heights(reader: &SegmentReader, from: u64) -> Vec<u64> {
+        reader
+            .blocks_from(from)
+            .map(|block| block.unwrap().height)
+            .collect()
+    }
+
+    #[test]
+    fn segments_rotate_and_are_read_by_height() {
+        let temp_dir = TempDir::new().unwrap();
+        // About three blocks per segment.
+        let mut writer = SegmentWriter::open(temp_dir.path(), 300).unwrap();
+        for height in (0..10).chain(12..15) {
+            assert!(writer.append(&block(height)).unwrap());
+        }
+        assert!(!writer.append(&block(13)).unwrap());
+        assert_eq!(list_segments(temp_dir.path()).unwrap().len(), 5);
+
+        let reader = SegmentReader::open(temp_dir.path()).unwrap();
+        assert_eq!(reader.first_height(), Some(0));
+        assert_eq!(
+            heights(&reader, 0),
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 13, 14]
+        );
+        assert_eq!(heights(&reader, 7), vec![7, 8, 9, 12, 13, 14]);
+        assert_eq!(heights(&reader, 10), vec![12, 13, 14]);
+        assert_eq!(reader.blocks_from(4).next().unwrap().unwrap(), block(4));
+
+        // The frames are the bytes sent to the executor.
+        let (height, frame) = reader.frames_from(5).next().unwrap().unwrap();
+        assert_eq!(height, 5);
+        assert_eq!(
+            frame,
+            uds_protocol::encode_frame(&uds_protocol::blocks_message(vec![block(5)])).unwrap()
+        );
+    }
+
+    #[test]
+    fn torn_segment_is_repaired_on_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut writer = SegmentWriter::open(temp_dir.path(), u64::MAX).unwrap();
+        for height in 0..3 {
+            writer.append(&block(height)).unwrap();
+        }
+        drop(writer);
+
+        // A crash while writing block 3: half a frame, no index entry.
+        let data_path = segment_path(temp_dir.path(), 0, SEGMENT_EXTENSION);
+        let mut data = OpenOptions::new().append(true).open(&data_path).unwrap();
+        data.write_all(&[42u8; 10]).unwrap();
+
+        let mut writer = SegmentWriter::open(temp_dir.path(), u64::MAX).unwrap();
+        assert_eq!(writer.last_height(), Some(2));
+        assert!(!writer.append(&block(2)).unwrap());
+        assert!(writer.append(&block(3)).unwrap());
+
+        let reader = SegmentReader::open(temp_dir.path()).unwrap();
+        assert_eq!(heights(&reader, 0), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,426 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Secondary outputs of the committed blocks (eg. a shadow executor or an indexer).
+//!
+//! The executor on `uds_block_path` stays the only acknowledged consumer. Every other sink
+//! reads the blocks from the block archive with its own cursor, so a slow or unavailable sink
+//! never delays the executor nor the other sinks. The blocks are written as v2 frames
+//! (`u32 LE length + NodeMessage`); the sinks are write-only and their replies are ignored.
+//! File sinks write the same frames to rotating segment files (see `block_segments`).
+//!
+//! The cursor of a sink is persisted after every write: a block may be written twice to a socket
+//! after a crash (at-least-once), the consumers deduplicate by height. File sinks skip the blocks
+//! already in their segments.
+
+use crate::{
+    block_archive::BlockArchive, block_segments::SegmentWriter,
+    execution_state::comm::CommittedBlock, metrics::BlockSinkMetrics, uds_protocol,
+};
+use config::{BlockSinkKind, BlockSinkParameters};
+use prometheus::Registry;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWrite,
+    net::{TcpStream, UnixStream},
+    sync::watch,
+};
+use tracing::{info, warn};
+
+/// The connection or the segments of a sink. The segments are written with blocking file I/O,
+/// on the blocking thread pool.
+enum SinkWriter {
+    Stream(Box<dyn AsyncWrite + Send + Unpin>),
+    Segments(Arc<Mutex<SegmentWriter>>),
+}
+
+impl SinkWriter {
+    async fn write_block(&mut self, block: CommittedBlock) -> Result<(), String> {
+        match self {
+            Self::Stream(stream) => {
+                uds_protocol::write_frame(stream, &uds_protocol::blocks_message(vec![block])).await
+            }
+            Self::Segments(segments) => {
+                let segments = segments.clone();
+                tokio::task::spawn_blocking(move || {
+                    segments
+                        .lock()
+                        .map_err(|_| "The segment writer is poisoned".to_string())?
+                        .append(&block)
+                        .map(|_| ())
+                })
+                .await
+                .map_err(|e| format!("Failed to append to the segments: {}", e))?
+            }
+        }
+    }
+}
+
+/// Where a sink writes the blocks.
+#[derive(Clone, Debug)]
+enum SinkTarget {
+    Uds(PathBuf),
+    Tcp(SocketAddr),
+    File {
+        dir: PathBuf,
+        segment_max_bytes: u64,
+    },
+}
+
+impl SinkTarget {
+    fn new(parameters: &BlockSinkParameters) -> Result<Self, String> {
+        match parameters.kind {
+            BlockSinkKind::Uds => Ok(Self::Uds(PathBuf::from(&parameters.address))),
+            BlockSinkKind::Tcp => {
+                let address: SocketAddr = parameters.address.parse().map_err(|e| {
+                    format!(
+                        "Invalid address {} of block sink {}: {}",
+                        parameters.address, parameters.name, e
+                    )
+                })?;
+                if !address.ip().is_loopback() {
+                    return Err(format!(
+                        "Block sink {} must listen on the loopback interface, not {}",
+                        parameters.name, address
+                    ));
+                }
+                Ok(Self::Tcp(address))
+            }
+            BlockSinkKind::File => Ok(Self::File {
+                dir: PathBuf::from(&parameters.address),
+                segment_max_bytes: parameters.segment_max_bytes,
+            }),
+        }
+    }
+
+    async fn open(&self) -> Result<SinkWriter, String> {
+        let writer = match self {
+            Self::Uds(path) => UnixStream::connect(path)
+                .await
+                .map(|stream| SinkWriter::Stream(Box::new(stream))),
+            Self::Tcp(address) => TcpStream::connect(address)
+                .await
+                .map(|stream| SinkWriter::Stream(Box::new(stream))),
+            Self::File {
+                dir,
+                segment_max_bytes,
+            } => {
+                // Opening the segments may repair the last one: keep it off the runtime.
+                let (dir, segment_max_bytes) = (dir.clone(), *segment_max_bytes);
+                return tokio::task::spawn_blocking(move || {
+                    SegmentWriter::open(dir, segment_max_bytes)
+                })
+                .await
+                .map_err(|e| format!("Failed to open the segments: {}", e))?
+                .map(|segments| SinkWriter::Segments(Arc::new(Mutex::new(segments))));
+            }
+        };
+        writer.map_err(|e| format!("Failed to open {:?}: {}", self, e))
+    }
+}
+
+/// The persisted position of a sink.
+#[derive(Debug, Serialize, Deserialize)]
+struct SinkCursor {
+    /// The height of the next block to write.
+    next_height: u64,
+}
+
+/// Fan-out of the archived blocks to the configured sinks.
+pub struct BlockSinks {
+    /// The height of the last archived block.
+    tx_archived: watch::Sender<Option<u64>>,
+}
+
+impl BlockSinks {
+    /// Start one task per sink. The cursors are persisted in `cursor_dir`; a sink without cursor
+    /// starts at the first block of the archive.
+    pub fn spawn(
+        sinks: &[BlockSinkParameters],
+        archive: Arc<BlockArchive>,
+        cursor_dir: PathBuf,
+        registry: &Registry,
+    ) -> Result<Self, String> {
+        let mut names = HashSet::new();
+        let mut targets = Vec::with_capacity(sinks.len());
+        for parameters in sinks {
+            if parameters.name.trim().is_empty() || !names.insert(parameters.name.as_str()) {
+                return Err(format!(
+                    "Block sink names must be unique and non-empty, got {:?}",
+                    parameters.name
+                ));
+            }
+            targets.push(SinkTarget::new(parameters)?);
+        }
+        fs::create_dir_all(&cursor_dir)
+            .map_err(|e| format!("Failed to create {:?}: {}", cursor_dir, e))?;
+
+        let last_archived = archive
+            .last_height()
+            .map_err(|e| format!("Failed to read the block archive: {}", e))?;
+        let (tx_archived, _) = watch::channel(last_archived);
+        let metrics = BlockSinkMetrics::new(registry);
+
+        for (parameters, target) in sinks.iter().zip(targets) {
+            let cursor_path = cursor_dir.join(format!("{}.cursor", parameters.name));
+            let next_height = match load_cursor(&cursor_path)? {
+                Some(cursor) => cursor.next_height,
+                None => archive.first_height().unwrap_or(0),
+            };
+            info!(
+                "📤 [SINK] Block sink {} ({:?}) starting at height {}",
+                parameters.name, target, next_height
+            );
+            let sink = BlockSink {
+                name: parameters.name.clone(),
+                target,
+                retry_delay: parameters.retry_delay,
+                max_retry_delay: parameters.max_retry_delay,
+                archive: archive.clone(),
+                rx_archived: tx_archived.subscribe(),
+                cursor_path,
+                next_height,
+                writer: None,
+                metrics: metrics.clone(),
+            };
+            tokio::spawn(sink.run());
+        }
+        Ok(Self { tx_archived })
+    }
+
+    /// The block at `height` was written to the archive.
+    pub fn notify(&self, height: u64) {
+        let last_archived = (*self.tx_archived.borrow()).map_or(height, |last| last.max(height));
+        self.tx_archived.send_replace(Some(last_archived));
+    }
+}
+
+/// A sink, writing the archived blocks in height order.
+struct BlockSink {
+    name: String,
+    target: SinkTarget,
+    retry_delay: Duration,
+    max_retry_delay: Duration,
+    archive: Arc<BlockArchive>,
+    rx_archived: watch::Receiver<Option<u64>>,
+    cursor_path: PathBuf,
+    /// The height of the next block to write.
+    next_height: u64,
+    /// `None` until connected, and after a failed write.
+    writer: Option<SinkWriter>,
+    metrics: BlockSinkMetrics,
+}
+
+impl BlockSink {
+    async fn run(mut self) {
+        let mut retry_delay = self.retry_delay;
+        loop {
+            let last_archived = *self.rx_archived.borrow_and_update();
+            let lag = last_archived.map_or(0, |last| (last + 1).saturating_sub(self.next_height));
+            self.metrics
+                .block_sink_lag_blocks
+                .with_label_values(&[&self.name])
+                .set(lag as i64);
+            self.metrics
+                .block_sink_next_height
+                .with_label_values(&[&self.name])
+                .set(self.next_height as i64);
+
+            if lag > 0 {
+                match self.write_next().await {
+                    Ok(()) => retry_delay = self.retry_delay,
+                    Err(e) => {
+                        warn!(
+                            "⚠️ [SINK] Block sink {} failed at height {}, retrying in {}ms: {}",
+                            self.name,
+                            self.next_height,
+                            retry_delay.as_millis(),
+                            e
+                        );
+                        self.metrics
+                            .block_sink_write_errors
+                            .with_label_values(&[&self.name])
+                            .inc();
+                        self.writer = None;
+                        tokio::time::sleep(retry_delay).await;
+                        retry_delay = (retry_delay * 2).min(self.max_retry_delay);
+                    }
+                }
+                continue;
+            }
+
+            // Caught up: wait for the next archived block.
+            if self.rx_archived.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Write the block at `next_height` and advance the cursor.
+    async fn write_next(&mut self) -> Result<(), String> {
+        let block = match self.archive.read_block(self.next_height) {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                // The block was pruned before this sink wrote it.
+                return match self.archive.first_height() {
+                    Some(first) if first > self.next_height => {
+                        warn!(
+                            "⚠️ [SINK] Block sink {} skips heights {}..{} pruned from the archive",
+                            self.name, self.next_height, first
+                        );
+                        self.metrics
+                            .block_sink_skipped_blocks
+                            .with_label_values(&[&self.name])
+                            .inc_by(first - self.next_height);
+                        self.set_next_height(first).await;
+                        Ok(())
+                    }
+                    _ => Err(format!("Block {} is not in the archive", self.next_height)),
+                };
+            }
+            Err(e) => return Err(format!("Failed to read the block archive: {}", e)),
+        };
+
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => self.writer.insert(self.target.open().await?),
+        };
+        writer.write_block(block).await?;
+        self.set_next_height(self.next_height + 1).await;
+        Ok(())
+    }
+
+    /// Advance the cursor. Failing to persist it only means re-writing blocks after a restart.
+    async fn set_next_height(&mut self, next_height: u64) {
+        self.next_height = next_height;
+        if let Err(e) = save_cursor(&self.cursor_path, &SinkCursor { next_height }).await {
+            warn!(
+                "⚠️ [SINK] Failed to save the cursor of block sink {}: {}",
+                self.name, e
+            );
+        }
+    }
+}
+
+fn load_cursor(path: &Path) -> Result<Option<SinkCursor>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse {:?}: {}", path, e))
+}
+
+/// Write the cursor atomically (temp file + rename), without blocking the runtime: the cursor
+/// is saved after every block.
+async fn save_cursor(path: &Path, cursor: &SinkCursor) -> Result<(), String> {
+    let json = serde_json::to_string(cursor)
+        .map_err(|e| format!("Failed to serialize the cursor: {}", e))?;
+    let temp_path = path.with_extension("cursor.tmp");
+    tokio::fs::write(&temp_path, json)
+        .await
+        .map_err(|e| format!("Failed to write {:?}: {}", temp_path, e))?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .map_err(|e| format!("Failed to rename {:?}: {}", temp_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_segments::SegmentReader, NodeStorage};
+    use tempfile::TempDir;
+
+    fn block(height: u64) -> CommittedBlock {
+        CommittedBlock {
+            epoch: 0,
+            height,
+            transactions: Vec::new(),
+            header: None,
+            format_version: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn sink(name: &str, kind: BlockSinkKind, address: String) -> BlockSinkParameters {
+        BlockSinkParameters {
+            name: name.to_string(),
+            kind,
+            address,
+            retry_delay: Duration::from_millis(10),
+            max_retry_delay: Duration::from_millis(50),
+            segment_max_bytes: 1024 * 1024,
+        }
+    }
+
+    #[test]
+    fn tcp_sinks_must_be_local() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = NodeStorage::reopen(temp_dir.path().join("store")).block_archive;
+        let remote = sink("indexer", BlockSinkKind::Tcp, "10.0.0.1:9000".to_string());
+        assert!(BlockSinks::spawn(
+            &[remote],
+            archive,
+            temp_dir.path().join("cursors"),
+            &Registry::new()
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn unavailable_sink_does_not_block_file_sink() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = NodeStorage::reopen(temp_dir.path().join("store")).block_archive;
+        let cursor_dir = temp_dir.path().join("cursors");
+        let segments_dir = temp_dir.path().join("segments");
+        let sinks = vec![
+            // Nobody listens on this socket.
+            sink(
+                "shadow",
+                BlockSinkKind::Uds,
+                temp_dir.path().join("missing.sock").display().to_string(),
+            ),
+            sink(
+                "archive",
+                BlockSinkKind::File,
+                segments_dir.display().to_string(),
+            ),
+        ];
+        let registry = Registry::new();
+        let block_sinks =
+            BlockSinks::spawn(&sinks, archive.clone(), cursor_dir.clone(), &registry).unwrap();
+
+        for height in 0..3 {
+            archive.write_block(&block(height), &[]).unwrap();
+            block_sinks.notify(height);
+        }
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while load_cursor(&cursor_dir.join("archive.cursor"))
+            .unwrap()
+            .map_or(0, |cursor| cursor.next_height)
+            < 3
+        {
+            assert!(tokio::time::Instant::now() < deadline, "file sink is stuck");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let heights: Vec<_> = SegmentReader::open(&segments_dir)
+            .unwrap()
+            .blocks_from(0)
+            .map(|block| block.unwrap().height)
+            .collect();
+        assert_eq!(heights, vec![0, 1, 2]);
+        assert!(load_cursor(&cursor_dir.join("shadow.cursor"))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent set of the transactions already put in a block by the execution state.
+//!
+//! The same transaction sent to several workers or validators is included in several batches,
+//! and only the first of them (in commit order) delivers it. The transaction hashes are stored
+//! with the consensus index that delivered them, in the same write batch as the execution
+//! progress (see `ExecutionProgressStore`), so a restarted node skips the same transactions as
+//! the others. Entries are pruned below the watermark of the processed batches.
+
+use store::{
+    rocks::{DBBatch, DBMap, TypedStoreError},
+    traits::Map,
+};
+
+/// Convenience type to propagate store errors.
+pub type DeliveredTransactionResult<T> = Result<T, TypedStoreError>;
+
+pub struct DeliveredTransactionStore {
+    /// Transaction hash -> consensus index of the batch that delivered the transaction.
+    transactions: DBMap<Vec<u8>, u64>,
+    /// (consensus index, transaction hash) of the delivered transactions, to prune them by
+    /// consensus index.
+    transactions_by_index: DBMap<(u64, Vec<u8>), ()>,
+}
+
+impl DeliveredTransactionStore {
+    pub fn new(
+        transactions: DBMap<Vec<u8>, u64>,
+        transactions_by_index: DBMap<(u64, Vec<u8>), ()>,
+    ) -> Self {
+        Self {
+            transactions,
+            transactions_by_index,
+        }
+    }
+
+    /// Add the transactions delivered at `consensus_index` to `write_batch`.
+    pub fn record(
+        &self,
+        mut write_batch: DBBatch,
+        tx_hashes: &[Vec<u8>],
+        consensus_index: u64,
+    ) -> DeliveredTransactionResult<DBBatch> {
+        write_batch = write_batch.insert_batch(
+            &self.transactions,
+            tx_hashes
+                .iter()
+                .map(|tx_hash| (tx_hash.clone(), consensus_index)),
+        )?;
+        write_batch.insert_batch(
+            &self.transactions_by_index,
+            tx_hashes
+                .iter()
+                .map(|tx_hash| ((consensus_index, tx_hash.clone()), ())),
+        )
+    }
+
+    /// The consensus index a transaction was delivered at, if it was.
+    pub fn delivered_index(&self, tx_hash: &[u8]) -> DeliveredTransactionResult<Option<u64>> {
+        self.transactions.get(&tx_hash.to_vec())
+    }
+
+    /// Every recorded transaction delivered at a consensus index >= `watermark`.
+    pub fn read_from(&self, watermark: u64) -> DeliveredTransactionResult<Vec<(Vec<u8>, u64)>> {
+        Ok(self
+            .transactions_by_index
+            .iter()
+            .skip_to(&(watermark, Vec::new()))?
+            .map(|((consensus_index, tx_hash), _)| (tx_hash, consensus_index))
+            .collect())
+    }
+
+    /// Delete every transaction delivered at a consensus index < `watermark`.
+    /// Returns the number of pruned transactions.
+    pub fn prune_below(&self, watermark: u64) -> DeliveredTransactionResult<usize> {
+        let pruned: Vec<(u64, Vec<u8>)> = self
+            .transactions_by_index
+            .keys()
+            .take_while(|(consensus_index, _)| *consensus_index < watermark)
+            .collect();
+        if pruned.is_empty() {
+            return Ok(0);
+        }
+        let count = pruned.len();
+        self.delete(self.transactions.batch(), pruned)?.write()?;
+        Ok(count)
+    }
+
+    /// Add the deletion of every transaction to `write_batch`: consensus indices restart at a new
+    /// epoch.
+    pub fn clear(&self, write_batch: DBBatch) -> DeliveredTransactionResult<DBBatch> {
+        let all: Vec<(u64, Vec<u8>)> = self.transactions_by_index.keys().collect();
+        self.delete(write_batch, all)
+    }
+
+    fn delete(
+        &self,
+        mut write_batch: DBBatch,
+        keys: Vec<(u64, Vec<u8>)>,
+    ) -> DeliveredTransactionResult<DBBatch> {
+        let tx_hashes: Vec<Vec<u8>> = keys.iter().map(|(_, tx_hash)| tx_hash.clone()).collect();
+        write_batch = write_batch.delete_batch(&self.transactions, tx_hashes)?;
+        write_batch.delete_batch(&self.transactions_by_index, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::NodeStorage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn delivered_transactions_survive_reopen_and_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let store = NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+            let delivered = store.delivered_transactions();
+            for consensus_index in 0..5u8 {
+                delivered
+                    .record(
+                        store.batch(),
+                        &[vec![consensus_index; 32], vec![consensus_index + 10; 32]],
+                        consensus_index as u64,
+                    )
+                    .unwrap()
+                    .write()
+                    .unwrap();
+            }
+        }
+
+        let store = NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+        let delivered = store.delivered_transactions();
+        assert_eq!(delivered.delivered_index(&[13; 32]).unwrap(), Some(3));
+
+        assert_eq!(delivered.prune_below(3).unwrap(), 6);
+        assert_eq!(delivered.delivered_index(&[2; 32]).unwrap(), None);
+        assert_eq!(
+            delivered.read_from(4).unwrap(),
+            vec![(vec![4; 32], 4), (vec![14; 32], 4)]
+        );
+
+        delivered.clear(store.batch()).unwrap().write().unwrap();
+        assert!(delivered.read_from(0).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Execution progress of the node, stored in its RocksDB.
+//!
+//! The consensus index, the sent and confirmed heights, the block cursor and the dedup state
+//! (processed batches and delivered transactions) are committed together in one write batch, so
+//! a crash can never leave them disagreeing.
+
+use crate::{
+    block_policy::BlockCursor, delivered_transactions::DeliveredTransactionStore,
+    processed_batches::ProcessedBatchStore,
+};
+use config::BlockPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use store::{
+    rocks::{DBBatch, DBMap, TypedStoreError},
+    traits::Map,
+};
+use types::BatchDigest;
+
+/// Convenience type to propagate store errors.
+pub type ExecutionProgressResult<T> = Result<T, TypedStoreError>;
+
+/// The single key of the execution progress.
+const EXECUTION_PROGRESS_KEY: u8 = 0;
+
+/// Everything the execution state needs to resume the delivery of blocks after a restart.
+///
+/// Only the sent blocks are persisted: the execution resumes at the first certificate of the
+/// first block not sent yet and rebuilds the blocks being formed from the consensus output.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ExecutionProgress {
+    /// The first consensus index of the first block not sent yet in `epoch`: the consensus output
+    /// is handed to the execution state again from there after a restart.
+    pub next_consensus_index: u64,
+    /// The last height sent to the executor.
+    pub last_sent_height: Option<u64>,
+    /// The block policy the sent heights were numbered with (None: the default policy).
+    pub block_policy: Option<BlockPolicy>,
+    /// The block former cursor before `next_consensus_index` was assigned (policies not aligned
+    /// on the consensus index).
+    pub block_cursor: BlockCursor,
+    /// The epoch of `next_consensus_index` (consensus indices restart at every epoch).
+    pub epoch: u64,
+    /// Epoch -> height of the first block of the epoch (heights continue across epochs).
+    pub epoch_start_heights: BTreeMap<u64, u64>,
+    /// The last height acknowledged by the executor.
+    pub last_confirmed_height: Option<u64>,
+    /// First height -> block format of the blocks sent from that height (the format negotiated
+    /// with the executor can change on a reconnection).
+    pub block_formats: BTreeMap<u64, u32>,
+}
+
+/// The layout of `ExecutionProgress` stored by `VersionedExecutionProgress::V1`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ExecutionProgressV1 {
+    pub last_consensus_index: u64,
+    pub last_sent_height: Option<u64>,
+    pub block_policy: Option<BlockPolicy>,
+    pub block_cursor: BlockCursor,
+    pub epoch: u64,
+    pub epoch_start_heights: BTreeMap<u64, u64>,
+    pub last_confirmed_height: Option<u64>,
+}
+
+/// The layout of `ExecutionProgress` stored by `VersionedExecutionProgress::V2`, which recorded
+/// the last consensus index handed to the execution state. Also the format of the legacy
+/// `execution_state.json` file: the serde defaults only apply to that JSON import.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ExecutionProgressV2 {
+    pub last_consensus_index: u64,
+    pub last_sent_height: Option<u64>,
+    #[serde(default)]
+    pub block_policy: Option<BlockPolicy>,
+    #[serde(default)]
+    pub block_cursor: BlockCursor,
+    #[serde(default)]
+    pub epoch: u64,
+    #[serde(default)]
+    pub epoch_start_heights: BTreeMap<u64, u64>,
+    #[serde(default)]
+    pub last_confirmed_height: Option<u64>,
+    #[serde(default)]
+    pub block_formats: BTreeMap<u64, u32>,
+}
+
+impl From<ExecutionProgressV1> for ExecutionProgressV2 {
+    // No recorded block formats: see `UdsExecutionState::initialize`.
+    fn from(progress: ExecutionProgressV1) -> Self {
+        Self {
+            last_consensus_index: progress.last_consensus_index,
+            last_sent_height: progress.last_sent_height,
+            block_policy: progress.block_policy,
+            block_cursor: progress.block_cursor,
+            epoch: progress.epoch,
+            epoch_start_heights: progress.epoch_start_heights,
+            last_confirmed_height: progress.last_confirmed_height,
+            block_formats: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<ExecutionProgressV2> for ExecutionProgress {
+    // The earlier layouts resumed after the last consensus index handed to the execution state.
+    fn from(progress: ExecutionProgressV2) -> Self {
+        Self {
+            next_consensus_index: progress.last_consensus_index + 1,
+            last_sent_height: progress.last_sent_height,
+            block_policy: progress.block_policy,
+            block_cursor: progress.block_cursor,
+            epoch: progress.epoch,
+            epoch_start_heights: progress.epoch_start_heights,
+            last_confirmed_height: progress.last_confirmed_height,
+            block_formats: progress.block_formats,
+        }
+    }
+}
+
+/// The execution progress as stored in the database. The value is encoded with bincode, which
+/// ignores the serde defaults: a change to `ExecutionProgress` freezes its current layout in the
+/// latest variant and adds a new one, converted in `into_latest`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum VersionedExecutionProgress {
+    V1(ExecutionProgressV1),
+    V2(ExecutionProgressV2),
+    V3(ExecutionProgress),
+}
+
+impl VersionedExecutionProgress {
+    /// The progress in the current layout.
+    pub fn into_latest(self) -> ExecutionProgress {
+        match self {
+            VersionedExecutionProgress::V1(progress) => ExecutionProgressV2::from(progress).into(),
+            VersionedExecutionProgress::V2(progress) => progress.into(),
+            VersionedExecutionProgress::V3(progress) => progress,
+        }
+    }
+}
+
+/// What a certificate put in the blocks being formed, persisted once those blocks are sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    pub consensus_index: u64,
+    /// The batch executed at `consensus_index`, if it is new.
+    pub batch_digest: Option<BatchDigest>,
+    /// The transactions put in a block.
+    pub tx_hashes: Vec<Vec<u8>>,
+}
+
+pub struct ExecutionProgressStore {
+    /// The execution progress (single entry).
+    progress: DBMap<u8, VersionedExecutionProgress>,
+    /// The batches already put in a block, written with the progress.
+    processed_batches: ProcessedBatchStore,
+    /// The transactions already put in a block, written with the progress.
+    delivered_transactions: DeliveredTransactionStore,
+}
+
+impl ExecutionProgressStore {
+    pub fn new(
+        progress: DBMap<u8, VersionedExecutionProgress>,
+        processed_batches: ProcessedBatchStore,
+        delivered_transactions: DeliveredTransactionStore,
+    ) -> Self {
+        Self {
+            progress,
+            processed_batches,
+            delivered_transactions,
+        }
+    }
+
+    /// A new write batch of the node's database.
+    pub fn batch(&self) -> DBBatch {
+        self.progress.batch()
+    }
+
+    pub fn processed_batches(&self) -> &ProcessedBatchStore {
+        &self.processed_batches
+    }
+
+    pub fn delivered_transactions(&self) -> &DeliveredTransactionStore {
+        &self.delivered_transactions
+    }
+
+    /// The persisted execution progress, if any.
+    pub fn read(&self) -> ExecutionProgressResult<Option<ExecutionProgress>> {
+        Ok(self
+            .progress
+            .get(&EXECUTION_PROGRESS_KEY)?
+            .map(VersionedExecutionProgress::into_latest))
+    }
+
+    /// Persist the execution progress.
+    pub fn write(&self, progress: &ExecutionProgress) -> ExecutionProgressResult<()> {
+        self.with_progress(self.batch(), progress)?.write()
+    }
+
+    /// Persist the execution progress after a block was sent, atomically with the dedup state of
+    /// the certificates its blocks now cover (the consensus indices below `next_consensus_index`).
+    pub fn record_sent_block(
+        &self,
+        progress: &ExecutionProgress,
+        deliveries: &[Delivery],
+    ) -> ExecutionProgressResult<()> {
+        let mut write_batch = self.batch();
+        for delivery in deliveries {
+            if let Some(batch_digest) = delivery.batch_digest {
+                write_batch = self.processed_batches.record(
+                    write_batch,
+                    batch_digest,
+                    delivery.consensus_index,
+                )?;
+            }
+            write_batch = self.delivered_transactions.record(
+                write_batch,
+                &delivery.tx_hashes,
+                delivery.consensus_index,
+            )?;
+        }
+        self.with_progress(write_batch, progress)?.write()
+    }
+
+    /// Delete the dedup state below `watermark`.
+    /// Returns the number of pruned batches and transactions.
+    pub fn prune_below(&self, watermark: u64) -> ExecutionProgressResult<(usize, usize)> {
+        Ok((
+            self.processed_batches.prune_below(watermark)?,
+            self.delivered_transactions.prune_below(watermark)?,
+        ))
+    }
+
+    /// Persist the progress at the start of a new epoch and drop the dedup state of the previous
+    /// epoch atomically.
+    pub fn start_epoch(&self, progress: &ExecutionProgress) -> ExecutionProgressResult<()> {
+        let write_batch = self.processed_batches.clear(self.batch())?;
+        let write_batch = self.delivered_transactions.clear(write_batch)?;
+        self.with_progress(write_batch, progress)?.write()
+    }
+
+    fn with_progress(
+        &self,
+        write_batch: DBBatch,
+        progress: &ExecutionProgress,
+    ) -> ExecutionProgressResult<DBBatch> {
+        write_batch.insert_batch(
+            &self.progress,
+            std::iter::once((
+                EXECUTION_PROGRESS_KEY,
+                VersionedExecutionProgress::V3(progress.clone()),
+            )),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Delivery, ExecutionProgress, ExecutionProgressV1, ExecutionProgressV2,
+        VersionedExecutionProgress,
+    };
+    use crate::NodeStorage;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+    use types::BatchDigest;
+
+    #[test]
+    fn progress_and_dedup_state_are_written_together() {
+        let temp_dir = TempDir::new().unwrap();
+        let progress = ExecutionProgress {
+            next_consensus_index: 8,
+            last_sent_height: Some(3),
+            last_confirmed_height: Some(2),
+            epoch: 1,
+            epoch_start_heights: BTreeMap::from([(0, 0), (1, 2)]),
+            ..ExecutionProgress::default()
+        };
+        {
+            let store = NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+            assert_eq!(store.read().unwrap(), None);
+            store
+                .record_sent_block(
+                    &progress,
+                    &[Delivery {
+                        consensus_index: 7,
+                        batch_digest: Some(BatchDigest::new([1; 32])),
+                        tx_hashes: vec![vec![9; 32]],
+                    }],
+                )
+                .unwrap();
+        }
+
+        let store = NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+        assert_eq!(store.read().unwrap(), Some(progress.clone()));
+        assert_eq!(
+            store
+                .processed_batches()
+                .processed_index(&BatchDigest::new([1; 32]))
+                .unwrap(),
+            Some(7)
+        );
+        assert_eq!(
+            store
+                .delivered_transactions()
+                .delivered_index(&[9; 32])
+                .unwrap(),
+            Some(7)
+        );
+
+        let next_epoch = ExecutionProgress {
+            next_consensus_index: 0,
+            epoch: 2,
+            ..progress
+        };
+        store.start_epoch(&next_epoch).unwrap();
+        assert_eq!(store.read().unwrap(), Some(next_epoch));
+        assert!(store.processed_batches().read_from(0).unwrap().is_empty());
+        assert!(store
+            .delivered_transactions()
+            .read_from(0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn stored_progress_starts_with_its_format() {
+        let bytes =
+            bincode::serialize(&VersionedExecutionProgress::V3(ExecutionProgress::default()))
+                .unwrap();
+        assert_eq!(bytes[..4], 2u32.to_le_bytes());
+        let decoded: VersionedExecutionProgress = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.into_latest(), ExecutionProgress::default());
+    }
+
+    #[test]
+    fn earlier_progress_resumes_after_the_last_handed_index() {
+        let stored = ExecutionProgressV2 {
+            last_consensus_index: 7,
+            last_sent_height: Some(3),
+            block_formats: BTreeMap::from([(0, 2)]),
+            ..ExecutionProgressV2::default()
+        };
+        let bytes = bincode::serialize(&VersionedExecutionProgress::V2(stored)).unwrap();
+        let decoded: VersionedExecutionProgress = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(
+            decoded.into_latest(),
+            ExecutionProgress {
+                next_consensus_index: 8,
+                last_sent_height: Some(3),
+                block_formats: BTreeMap::from([(0, 2)]),
+                ..ExecutionProgress::default()
+            }
+        );
+    }
+
+    #[test]
+    fn v1_progress_is_read_in_the_latest_layout() {
+        let stored = ExecutionProgressV1 {
+            last_consensus_index: 7,
+            last_sent_height: Some(3),
+            epoch: 1,
+            ..ExecutionProgressV1::default()
+        };
+        let bytes = bincode::serialize(&VersionedExecutionProgress::V1(stored)).unwrap();
+        let decoded: VersionedExecutionProgress = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(
+            decoded.into_latest(),
+            ExecutionProgress {
+                next_consensus_index: 8,
+                last_sent_height: Some(3),
+                epoch: 1,
+                ..ExecutionProgress::default()
+            }
+        );
+    }
+
+    #[test]
+    fn legacy_execution_state_file_is_read_with_defaults() {
+        let progress: ExecutionProgressV2 =
+            serde_json::from_str(r#"{"last_consensus_index": 42, "last_sent_height": 4}"#).unwrap();
+        assert_eq!(progress.last_consensus_index, 42);
+        assert_eq!(progress.last_sent_height, Some(4));
+        assert_eq!(progress.epoch, 0);
+        assert_eq!(progress.last_confirmed_height, None);
+    }
+}
@@ -3,13 +3,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 // Include protobuf-generated code
-mod comm {
+pub mod comm {
     #![allow(clippy::derive_partial_eq_without_eq)]
     include!(concat!(env!("OUT_DIR"), "/comm.rs"));
 }
 
 // Include transaction protobuf để parse và tính hash đúng cách
-mod transaction {
+pub(crate) mod transaction {
     #![allow(clippy::derive_partial_eq_without_eq)]
     include!(concat!(env!("OUT_DIR"), "/transaction.rs"));
 }
@@ -18,27 +18,36 @@ use async_trait::async_trait;
 use bincode;
 use bytes::Bytes;
 use consensus::ConsensusOutput;
+use fastcrypto::hash::Hash as _;
 use executor::{ExecutionIndices, ExecutionState};
 use prost::Message;
 use sha3::{Digest, Keccak256};
 use hex;
-use types::{BatchDigest, ConsensusStore};
+use store::Store;
+use types::{Batch, BatchDigest, CertificateDigest, ConsensusStore, TransactionStatusStore};
+use primary::{ExecutionBackpressure, ForkDetector};
 use storage::CertificateStore;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
     path::PathBuf,
     fs,
 };
 use tokio::{
     io::AsyncWriteExt,
-    net::UnixStream,
-    sync::Mutex,
+    net::{unix::OwnedWriteHalf, UnixStream},
+    sync::{mpsc, oneshot, Mutex},
     time::{sleep, Duration as TokioDuration},
 };
+use crate::block_archive::BlockArchive;
+use crate::block_sink::BlockSinks;
+use crate::block_header::{self, ChainTip};
+use crate::block_policy::{self, BlockCursor, BlockFormer};
+use crate::execution_progress::{Delivery, ExecutionProgress, ExecutionProgressStore, ExecutionProgressV2};
+use config::BlockPolicy;
+use crate::uds_protocol::{self, AckTracker, BLOCK_FORMATS, BLOCK_FORMAT_V1, BLOCK_FORMAT_V2, UDS_PROTOCOL_V1, UDS_PROTOCOL_V2};
 use tracing::{debug, error, info, warn};
-use serde::{Serialize, Deserialize};
 
 /// Macro cho UDS debug logs - chỉ compile trong debug mode
 /// Giúp giảm overhead trong production builds
@@ -94,7 +103,7 @@ fn should_trace_tx(tx_hash_hex: &str) -> bool {
 /// NOTE: Function này giữ lại để maintain compatibility với node's transaction type
 /// Logic tính hash giống hệt worker::transaction_logger::calculate_transaction_hash
 /// nhưng sử dụng node's transaction::Transaction type
-fn calculate_transaction_hash_from_proto(tx: &transaction::Transaction) -> Vec<u8> {
+pub(crate) fn calculate_transaction_hash_from_proto(tx: &transaction::Transaction) -> Vec<u8> {
     // OPTIMIZATION: Logic giống hệt worker::transaction_logger::calculate_transaction_hash
     // Giữ lại function này vì node và worker có thể có different protobuf-generated types
     // nhưng logic tính hash hoàn toàn giống nhau
@@ -527,40 +536,11 @@ fn parse_transaction_and_calculate_hash(transaction_bytes: &[u8]) -> Option<(Str
     results.first().map(|(hash_hex, hash, _, _)| (hash_hex.clone(), hash.clone()))
 }
 
-/// Block size: Gộp BLOCK_SIZE consensus_index thành 1 block
-const BLOCK_SIZE: u64 = 10;
-
-/// GC depth: Số blocks giữ lại trong processed_batch_digests để cleanup entries cũ
-/// Giữ lại entries với consensus_index >= current_consensus_index - GC_DEPTH * BLOCK_SIZE
-/// Ví dụ: GC_DEPTH=100, BLOCK_SIZE=10 → giữ lại 1000 consensus_index gần nhất
-const GC_DEPTH: u64 = 100;
-
-/// Timeout để xem batch là "missed" (bị bỏ rơi) sau khi được commit
-/// Sau thời gian này, batch được xem là missed và cần retry
-/// Default: 5 giây
-const MISSED_BATCH_TIMEOUT_SECS: u64 = 5;
-
-/// Max retry attempts cho một batch bị missed
-/// Sau số lần retry này, batch sẽ bị bỏ qua
-/// Default: 3
-const MAX_MISSED_BATCH_RETRIES: u32 = 3;
-
-/// Thông tin batch bị missed (chưa được processed sau khi commit)
-#[derive(Clone, Debug)]
-struct MissedBatchInfo {
-    /// Thời gian batch được commit
-    commit_time: Instant,
-    /// Consensus index của batch
-    consensus_index: u64,
-    /// Round của batch
-    round: u64,
-    /// Block height của batch
-    block_height: u64,
-    /// Số lần đã retry
-    retry_count: u32,
-    /// Thời gian retry cuối cùng
-    last_retry_time: Instant,
-}
+/// Số block tối đa executor được yêu cầu replay trong một ReplayRequest
+const MAX_REPLAY_HEIGHTS: u64 = 1000;
+
+/// Prune processed batches (memory + store) mỗi khi watermark tăng thêm N consensus_index
+const PROCESSED_BATCHES_PRUNE_STEP: u64 = 100;
 
 /// Transaction entry với consensus_index để đảm bảo deterministic ordering
 #[derive(Clone)]
@@ -572,40 +552,93 @@ struct TransactionEntry {
     /// Batch digest để check duplicate khi retry block
     /// None nếu không có batch_digest (empty block hoặc batch không có trong certificate payload)
     batch_digest: Option<BatchDigest>,
+    /// Certificate đã đưa transaction vào block (block format v2)
+    certificate_digest: Option<CertificateDigest>,
+}
+
+/// Vị trí execution tiếp tục sau restart để dựng lại một block chưa gửi: consensus_index của certificate
+/// đầu tiên của block và cursor của BlockFormer trước khi certificate đó được gán height
+#[derive(Clone, Default)]
+struct ResumePoint {
+    consensus_index: u64,
+    block_cursor: BlockCursor,
 }
 
 struct BlockBuilder {
     epoch: u64,
-    /// Block height = consensus_index / BLOCK_SIZE
+    /// Block height do BlockFormer gán theo block policy
     height: u64,
     /// Transactions với consensus_index để sort deterministic
     transaction_entries: Vec<TransactionEntry>,
     /// Track transaction hashes trong block này để tránh duplicate
     transaction_hashes: HashSet<Vec<u8>>,
-}
-
-/// Execution state persisted to disk for crash recovery
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
-struct PersistedExecutionState {
-    last_consensus_index: u64,
-    last_sent_height: Option<u64>,
+    /// Consensus index đầu tiên và cuối cùng của các certificates đã đưa vào block (None = chưa có)
+    consensus_indices: Option<(u64, u64)>,
+    /// Round của leader đã commit certificate cuối cùng trong block
+    leader_round: u64,
 }
 
 /// Execution state that sends blocks progressively via UDS (no batching, no size limit)
 pub struct UdsExecutionState {
     /// UDS socket path
     socket_path: String,
-    /// Current epoch (assumed constant for now)
-    epoch: u64,
-    /// Current block being built, keyed by block height (consensus_index / BLOCK_SIZE)
+    /// Epoch của các certificates đang xử lý: chuyển sang epoch mới ở certificate đầu tiên của epoch đó
+    epoch: Arc<Mutex<u64>>,
+    /// Epoch → height của block đầu tiên của epoch (persist cùng execution state)
+    epoch_start_heights: Arc<Mutex<BTreeMap<u64, u64>>>,
+    /// Current block being built, keyed by block height (assigned by block_former)
     current_block: Arc<Mutex<Option<BlockBuilder>>>,
+    /// Gán certificates/transactions vào block heights theo block policy
+    block_former: Arc<Mutex<BlockFormer>>,
+    /// Số consensus_index giữ lại trong processed_batch_digests
+    processed_batches_gc_depth: u64,
+    /// Watermark của lần prune processed batches gần nhất (consensus_index - processed_batches_gc_depth)
+    processed_batches_watermark: Arc<Mutex<u64>>,
+    /// Execution progress trong RocksDB: consensus_index, heights đã gửi/xác nhận và processed batches
+    /// được ghi trong cùng một write batch → exactly-once qua restarts
+    execution_progress_store: Option<Arc<ExecutionProgressStore>>,
     /// Last sent height (to detect gaps and send empty blocks)
     /// None = chưa gửi block nào, Some(h) = đã gửi đến block h
     last_sent_height: Arc<Mutex<Option<u64>>>,
     /// Last consensus index processed
     last_consensus_index: Arc<Mutex<u64>>,
-    /// UDS stream (lazy connection)
-    stream: Arc<Mutex<Option<UnixStream>>>,
+    /// UDS stream write half (lazy connection). Read half thuộc về ack reader task (protocol v2)
+    stream: Arc<Mutex<Option<OwnedWriteHalf>>>,
+    /// UDS protocol version (UDS_PROTOCOL_V1 = legacy write-only, UDS_PROTOCOL_V2 = acked)
+    protocol_version: u32,
+    /// Thời gian chờ executor ACK một block trước khi xem connection là hỏng (protocol v2)
+    ack_timeout: Duration,
+    /// Blocks đã gửi nhưng chưa được executor ACK (protocol v2)
+    ack_tracker: AckTracker,
+    /// Format của transactions trong block theo cấu hình (BLOCK_FORMAT_V1 = wrapper, BLOCK_FORMAT_V2 = typed entries)
+    preferred_block_format: u32,
+    /// Format của các block mới: preferred_block_format nếu executor đọc được, ngược lại format chung cao nhất
+    /// (handshake ExecutorHello trên mỗi kết nối, protocol v2)
+    block_format: Arc<Mutex<u32>>,
+    /// Height đầu tiên → format của các blocks đã gửi từ height đó (persist cùng execution progress)
+    /// → replay dựng lại blocks theo đúng format đã gửi, không đổi format giữa chừng
+    sent_block_formats: Arc<Mutex<BTreeMap<u64, u32>>>,
+    /// Batch store để dựng lại blocks khi executor yêu cầu replay
+    batch_store: Option<Store<BatchDigest, Batch>>,
+    /// Replay requests từ executor (reader task → replay task)
+    tx_replay_requests: mpsc::Sender<comm::ReplayRequest>,
+    rx_replay_requests: Mutex<Option<mpsc::Receiver<comm::ReplayRequest>>>,
+    /// Persistent archive: height → CommittedBlock, tx_hash → (height, position)
+    block_archive: Option<Arc<BlockArchive>>,
+    /// Số blocks gần nhất giữ lại trong archive (0 = giữ tất cả)
+    archive_retention_blocks: u64,
+    /// Prune archive mỗi N blocks
+    archive_prune_interval: u64,
+    /// Block cuối cùng đã nối vào chuỗi (parent của block tiếp theo)
+    chain_tip: Arc<Mutex<Option<ChainTip>>>,
+    /// Lifecycle của transactions (TransactionStatus service): committed khi vào block, delivered khi block đã gửi
+    transaction_status_store: Option<Arc<TransactionStatusStore>>,
+    /// So sánh (height, block hash) đã gửi với các primaries khác; giữ block lại khi phát hiện fork (halt_on_fork)
+    fork_detector: Option<Arc<ForkDetector>>,
+    /// Outputs phụ (shadow executor, indexer...): đọc blocks từ archive với cursor riêng, không chặn executor chính
+    block_sinks: Option<Arc<BlockSinks>>,
+    /// Độ trễ của executor (blocks đã gửi nhưng chưa được xác nhận): làm chậm consensus và tạm dừng payload của proposer
+    execution_backpressure: Option<Arc<ExecutionBackpressure>>,
     /// Late certificates buffer: Lưu thông tin certificate đến muộn (sau khi block đã gửi)
     /// Format: (block_height, consensus_index, round, has_transaction)
     late_certificates: Arc<Mutex<Vec<(u64, u64, u64, bool)>>>,
@@ -618,35 +651,93 @@ pub struct UdsExecutionState {
     /// Format: HashMap<BatchDigest, u64> - map từ batch_digest đến consensus_index đã xử lý
     /// PRODUCTION-SAFE: Đảm bảo batch chỉ được xử lý một lần duy nhất cho mỗi consensus_index
     /// FORK-SAFE: Tất cả nodes track cùng batches → cùng quyết định skip → fork-safe
+    /// Cache của processed batches trong execution_progress_store (nếu có), khôi phục khi initialize; prune theo watermark
     processed_batch_digests: Arc<Mutex<HashMap<BatchDigest, u64>>>,
-    /// Track batches đã commit nhưng chưa được processed (có thể bị missed)
-    /// Format: HashMap<BatchDigest, MissedBatchInfo>
-    /// Mục đích: Phát hiện và retry batches bị bỏ rơi một cách thông minh (không retry liên tục)
-    missed_batches: Arc<Mutex<HashMap<BatchDigest, MissedBatchInfo>>>,
-    /// Timeout để xem batch là missed (milliseconds)
-    missed_batch_timeout_ms: u64,
-    /// Max retry attempts cho missed batch
-    max_missed_batch_retries: u32,
-    /// Track các batch đã log warning về duplicate để tránh log lặp lại (giới hạn 1000 entries)
-    /// Format: HashSet<BatchDigest> - chỉ log lần đầu tiên cho mỗi batch
-    logged_duplicate_batches: Arc<Mutex<HashSet<BatchDigest>>>,
-    /// Path to execution state file for persistence
-    execution_state_path: Option<PathBuf>,
-    /// Consensus store reference for catch-up mechanism
+    /// Transaction hash → consensus_index của batch đã đưa transaction vào block
+    /// CRITICAL: Cùng transaction gửi đến nhiều workers/validators nằm trong nhiều batches khác nhau
+    /// → chỉ batch đầu tiên (theo thứ tự commit) đưa transaction vào block
+    /// FORK-SAFE: Quyết định chỉ dựa trên cửa sổ processed_batches_gc_depth consensus_index
+    /// (xem delivered_within_window) → không phụ thuộc thời điểm GC
+    /// Persist cùng execution progress (execution_progress_store), khôi phục khi initialize, prune cùng watermark
+    /// với processed batches → node restart skip cùng transactions với các node khác
+    delivered_transaction_hashes: Arc<Mutex<HashMap<Vec<u8>, u64>>>,
+    /// Track các batch đã log warning về duplicate để tránh log lặp lại (prune cùng processed_batch_digests)
+    /// Format: HashMap<BatchDigest, u64> - batch → consensus_index đã xử lý batch, chỉ log lần đầu tiên
+    logged_duplicate_batches: Arc<Mutex<HashMap<BatchDigest, u64>>>,
+    /// File execution_state.json của phiên bản trước: import một lần vào execution_progress_store
+    legacy_execution_state_path: Option<PathBuf>,
+    /// Consensus store reference for replay
     consensus_store: Option<Arc<ConsensusStore>>,
     /// Certificate store reference for recovery
     certificate_store: Option<CertificateStore>,
-    /// Threshold for triggering catch-up (number of certificates lag)
-    catch_up_threshold: u64,
-    /// Interval for checking execution lag
-    catch_up_check_interval: Duration,
-    /// Counter for persistence (persist every N certificates)
-    persistence_counter: Arc<Mutex<u64>>,
+    /// Resume point của certificate đang xử lý (consensus_index mới nhất)
+    certificate_start: Arc<Mutex<ResumePoint>>,
+    /// Height → resume point của các blocks đã mở nhưng chưa gửi
+    /// CRITICAL: Execution progress chỉ persist blocks đã gửi → restart dựng lại blocks chưa gửi từ consensus output
+    unsent_blocks: Arc<Mutex<BTreeMap<u64, ResumePoint>>>,
+    /// Batches và transactions đã đưa vào blocks chưa gửi (theo consensus_index), persist khi block được gửi
+    unsent_deliveries: Arc<Mutex<Vec<Delivery>>>,
     /// Global state manager for centralized state management
     global_state: Option<Arc<crate::global_state::GlobalStateManager>>,
 }
 
 impl BlockBuilder {
+    fn new(epoch: u64, height: u64) -> Self {
+        Self {
+            epoch,
+            height,
+            transaction_entries: Vec::new(),
+            transaction_hashes: HashSet::new(),
+            consensus_indices: None,
+            leader_round: 0,
+        }
+    }
+
+    /// Ghi nhận certificate có transactions (hoặc payload rỗng) được đưa vào block
+    /// Header của block chứa range consensus_index và round của leader đã commit certificate cuối
+    fn record_certificate(&mut self, consensus_index: u64, leader_round: u64) {
+        self.consensus_indices = Some(match self.consensus_indices {
+            Some((first, last)) => (first.min(consensus_index), last.max(consensus_index)),
+            None => (consensus_index, consensus_index),
+        });
+        if self.consensus_indices.map_or(false, |(_, last)| last == consensus_index) {
+            self.leader_round = leader_round;
+        }
+    }
+
+    /// Thêm transactions đã parse của một batch vào block (replay path)
+    /// Cùng quy tắc với handle_consensus_transaction: bỏ qua transaction trùng hash trong cùng block
+    /// hoặc đã được đưa vào block bởi batch khác trong cửa sổ `window` consensus_index
+    fn push_parsed_transactions(
+        &mut self,
+        consensus_index: u64,
+        worker_id: u32,
+        batch_digest: BatchDigest,
+        certificate_digest: CertificateDigest,
+        parsed_transactions: Vec<(String, Vec<u8>, Option<transaction::Transaction>, Vec<u8>)>,
+        delivered: &mut HashMap<Vec<u8>, u64>,
+        window: u64,
+    ) {
+        for (tx_hash_hex, tx_hash, _tx_proto, raw_bytes) in parsed_transactions {
+            if delivered_within_window(delivered, &tx_hash, consensus_index, window)
+                || !self.transaction_hashes.insert(tx_hash.clone())
+            {
+                continue;
+            }
+            delivered.insert(tx_hash, consensus_index);
+            self.transaction_entries.push(TransactionEntry {
+                consensus_index,
+                transaction: comm::Transaction {
+                    digest: Bytes::from(raw_bytes),
+                    worker_id,
+                },
+                tx_hash_hex,
+                batch_digest: Some(batch_digest),
+                certificate_digest: Some(certificate_digest),
+            });
+        }
+    }
+
     /// Finalize block: sort transactions theo consensus_index và convert sang CommittedBlock
     /// Đảm bảo deterministic ordering - tất cả nodes tạo cùng block từ cùng certificates
     /// 
@@ -655,8 +746,11 @@ impl BlockBuilder {
     /// - Gửi wrapper bytes trong digest của transaction đầu tiên
     /// - Các transaction khác có digest rỗng (hoặc không gửi)
     /// 
+    /// Block format v2 (`block_format` = BLOCK_FORMAT_V2): mỗi transaction là một entry riêng với bytes gốc,
+    /// hash, worker_id, batch digest, certificate digest và consensus_index
+    ///
     /// Returns: (CommittedBlock, transaction_hashes_map, batch_digests) - map từ digest bytes → tx_hash_hex và danh sách batch_digests
-    fn finalize(&self) -> (comm::CommittedBlock, HashMap<Vec<u8>, String>, Vec<Option<BatchDigest>>) {
+    fn finalize(&self, block_format: u32) -> (comm::CommittedBlock, HashMap<Vec<u8>, String>, Vec<Option<BatchDigest>>) {
         // CRITICAL: Sort theo consensus_index để đảm bảo deterministic ordering
         // FORK-SAFE: 
         // - Primary sort: consensus_index (deterministic từ consensus)
@@ -676,9 +770,31 @@ impl BlockBuilder {
             }
         });
         
+        // Block format v2: một entry cho mỗi transaction, giữ nguyên bytes gốc và nguồn gốc của transaction
+        let entries: Vec<comm::BlockTransaction> = if block_format >= BLOCK_FORMAT_V2 {
+            sorted_entries.iter()
+                .map(|entry| comm::BlockTransaction {
+                    transaction: entry.transaction.digest.clone(),
+                    hash: Bytes::from(hex::decode(&entry.tx_hash_hex).expect("CRITICAL: Invalid transaction hash hex")),
+                    worker_id: entry.transaction.worker_id,
+                    batch_digest: entry.batch_digest.map_or_else(Bytes::new, |digest| Bytes::from(digest.0.to_vec())),
+                    certificate_digest: entry.certificate_digest.map_or_else(Bytes::new, |digest| Bytes::copy_from_slice(digest.as_ref())),
+                    consensus_index: entry.consensus_index,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
         // CRITICAL: Thống nhất format với Go - gửi Transactions wrapper
         // Gộp tất cả transactions trong block thành một Transactions wrapper
-        let (transactions, tx_hash_map): (Vec<comm::Transaction>, HashMap<Vec<u8>, String>) = if sorted_entries.is_empty() {
+        let (transactions, tx_hash_map): (Vec<comm::Transaction>, HashMap<Vec<u8>, String>) = if block_format >= BLOCK_FORMAT_V2 {
+            // Map bytes gốc → tx_hash_hex cho từng transaction (trace logs)
+            let tx_hash_map = sorted_entries.iter()
+                .map(|entry| (entry.transaction.digest.to_vec(), entry.tx_hash_hex.clone()))
+                .collect();
+            (Vec::new(), tx_hash_map)
+        } else if sorted_entries.is_empty() {
             (Vec::new(), HashMap::new())
         } else {
             // Parse tất cả transaction bytes từ digest
@@ -773,12 +889,30 @@ impl BlockBuilder {
         let batch_digests: Vec<Option<BatchDigest>> = sorted_entries.iter()
             .map(|e| e.batch_digest)
             .collect();
+
+        // Header: Merkle root theo đúng thứ tự transactions trong block
+        // parent_hash được nối khi gửi block (block_header::link) vì gap blocks có thể được gửi trước
+        let tx_hashes: Vec<Vec<u8>> = sorted_entries.iter()
+            .map(|e| hex::decode(&e.tx_hash_hex).expect("CRITICAL: Invalid transaction hash hex"))
+            .collect();
+        let (first_consensus_index, end_consensus_index) = self.consensus_indices
+            .map_or((0, 0), |(first, last)| (first, last + 1));
+        let header = comm::BlockHeader {
+            parent_hash: Bytes::new(),
+            transactions_root: Bytes::from(block_header::transactions_root(&tx_hashes)),
+            first_consensus_index,
+            end_consensus_index,
+            leader_round: self.leader_round,
+        };
         
         (
             comm::CommittedBlock {
                 epoch: self.epoch,
                 height: self.height,
                 transactions,
+                header: Some(header),
+                format_version: block_format,
+                entries,
             },
             tx_hash_map,
             batch_digests,
@@ -787,62 +921,96 @@ impl BlockBuilder {
 }
 
 impl UdsExecutionState {
-    /// Persist execution state to disk
-    async fn persist_execution_state(&self) -> Result<(), String> {
-        let state_path = match &self.execution_state_path {
-            Some(path) => path,
-            None => return Ok(()), // No persistence path configured
-        };
+    /// Execution progress hiện tại (ghi vào RocksDB): chỉ gồm các blocks đã gửi
+    async fn execution_progress(&self) -> ExecutionProgress {
+        let resume_point = self.resume_point().await;
+        let last_sent_height = *self.last_sent_height.lock().await;
+        let last_confirmed_height = self.ack_tracker.last_confirmed().await;
+        let epoch = *self.epoch.lock().await;
+        let epoch_start_heights = self.epoch_start_heights.lock().await.clone();
+        let block_formats = self.sent_block_formats.lock().await.clone();
+        let block_policy = self.block_former.lock().await.policy().clone();
+        ExecutionProgress {
+            next_consensus_index: resume_point.consensus_index,
+            last_sent_height,
+            block_policy: Some(block_policy),
+            block_cursor: resume_point.block_cursor,
+            epoch,
+            epoch_start_heights,
+            last_confirmed_height,
+            block_formats,
+        }
+    }
 
-        let state = {
-            let last_consensus_index = *self.last_consensus_index.lock().await;
-            let last_sent_height = *self.last_sent_height.lock().await;
-            PersistedExecutionState {
-                last_consensus_index,
-                last_sent_height,
-            }
-        };
+    /// Resume point sau block cuối cùng đã gửi: block chưa gửi đầu tiên, hoặc certificate đang xử lý
+    /// (certificate đã nằm trong các blocks đã gửi được bỏ qua khi replay)
+    async fn resume_point(&self) -> ResumePoint {
+        let first_unsent = self.unsent_blocks.lock().await.values().next().cloned();
+        match first_unsent {
+            Some(resume_point) => resume_point,
+            None => self.certificate_start.lock().await.clone(),
+        }
+    }
 
-        // Serialize to JSON
-        let json = serde_json::to_string_pretty(&state)
-            .map_err(|e| format!("Failed to serialize execution state: {}", e))?;
+    /// Persist execution progress vào RocksDB cùng dedup state của các certificates nằm trọn trong blocks đã gửi (một write batch)
+    async fn persist_execution_state(&self) -> Result<(), String> {
+        let store = match &self.execution_progress_store {
+            Some(store) => store,
+            None => return Ok(()), // No persistence configured
+        };
 
-        // Write atomically: write to temp file, then rename
-        let temp_path = state_path.with_extension("tmp");
-        fs::write(&temp_path, json)
-            .map_err(|e| format!("Failed to write execution state to {}: {}", temp_path.display(), e))?;
-        
-        fs::rename(&temp_path, state_path)
-            .map_err(|e| format!("Failed to rename execution state file: {}", e))?;
+        let progress = self.execution_progress().await;
+        // Certificates từ next_consensus_index được replay sau restart → dedup state của chúng được dựng lại khi replay
+        let mut unsent_deliveries = self.unsent_deliveries.lock().await;
+        let covered = unsent_deliveries.partition_point(|delivery| delivery.consensus_index < progress.next_consensus_index);
+        store
+            .record_sent_block(&progress, &unsent_deliveries[..covered])
+            .map_err(|e| format!("Failed to persist execution progress: {}", e))?;
+        unsent_deliveries.drain(..covered);
+        drop(unsent_deliveries);
 
-        debug!("💾 [UDS] Persisted execution state: last_consensus_index={}, last_sent_height={:?}", 
-            state.last_consensus_index, state.last_sent_height);
+        debug!("💾 [UDS] Persisted execution state: next_consensus_index={}, last_sent_height={:?}, last_confirmed_height={:?}", 
+            progress.next_consensus_index, progress.last_sent_height, progress.last_confirmed_height);
         
         Ok(())
     }
 
-    /// Load execution state from disk
-    async fn load_execution_state(&self) -> Result<PersistedExecutionState, String> {
-        let state_path = match &self.execution_state_path {
-            Some(path) => path,
-            None => return Ok(PersistedExecutionState::default()), // No persistence path configured
+    /// Load execution progress từ RocksDB. Store chưa có progress → import file JSON cũ (nếu có)
+    async fn load_execution_state(&self) -> Result<ExecutionProgress, String> {
+        let store = match &self.execution_progress_store {
+            Some(store) => store,
+            None => return Ok(ExecutionProgress::default()), // No persistence configured
         };
 
-        if !state_path.exists() {
-            debug!("💾 [UDS] Execution state file does not exist: {}, using default", state_path.display());
-            return Ok(PersistedExecutionState::default());
+        if let Some(progress) = store
+            .read()
+            .map_err(|e| format!("Failed to read execution progress: {}", e))?
+        {
+            info!("💾 [UDS] Loaded execution state: epoch={}, next_consensus_index={}, last_sent_height={:?}, last_confirmed_height={:?}", 
+                progress.epoch, progress.next_consensus_index, progress.last_sent_height, progress.last_confirmed_height);
+            return Ok(progress);
         }
 
+        let state_path = match &self.legacy_execution_state_path {
+            Some(path) if path.exists() => path,
+            _ => {
+                debug!("💾 [UDS] No execution progress in the store, using default");
+                return Ok(ExecutionProgress::default());
+            }
+        };
         let json = fs::read_to_string(state_path)
             .map_err(|e| format!("Failed to read execution state from {}: {}", state_path.display(), e))?;
+        let progress: ExecutionProgress = serde_json::from_str::<ExecutionProgressV2>(&json)
+            .map_err(|e| format!("Failed to deserialize execution state: {}", e))?
+            .into();
+        store
+            .write(&progress)
+            .map_err(|e| format!("Failed to import execution state: {}", e))?;
 
-        let state: PersistedExecutionState = serde_json::from_str(&json)
-            .map_err(|e| format!("Failed to deserialize execution state: {}", e))?;
+        info!("💾 [UDS] Imported execution state from {}: next_consensus_index={}, last_sent_height={:?}", 
+            state_path.display(), progress.next_consensus_index, progress.last_sent_height);
 
-        info!("💾 [UDS] Loaded execution state: last_consensus_index={}, last_sent_height={:?}", 
-            state.last_consensus_index, state.last_sent_height);
-
-        Ok(state)
+        Ok(progress)
     }
 
     pub fn new(
@@ -859,26 +1027,6 @@ impl UdsExecutionState {
         empty_block_timeout_ms: u64,
         max_send_retries: u32,
         retry_delay_base_ms: u64,
-    ) -> Self {
-        Self::new_with_retry_and_missed_detection(
-            socket_path,
-            epoch,
-            empty_block_timeout_ms,
-            max_send_retries,
-            retry_delay_base_ms,
-            MISSED_BATCH_TIMEOUT_SECS * 1000, // Convert to milliseconds
-            MAX_MISSED_BATCH_RETRIES,
-        )
-    }
-    
-    pub fn new_with_retry_and_missed_detection(
-        socket_path: String,
-        epoch: u64,
-        empty_block_timeout_ms: u64,
-        max_send_retries: u32,
-        retry_delay_base_ms: u64,
-        missed_batch_timeout_ms: u64,
-        max_missed_batch_retries: u32,
     ) -> Self {
         Self::new_with_state_and_stores(
             socket_path,
@@ -886,9 +1034,7 @@ impl UdsExecutionState {
             empty_block_timeout_ms,
             max_send_retries,
             retry_delay_base_ms,
-            missed_batch_timeout_ms,
-            max_missed_batch_retries,
-            None::<PathBuf>, // execution_state_path
+            None::<PathBuf>, // legacy_execution_state_path
             None::<Arc<ConsensusStore>>, // consensus_store
             None::<CertificateStore>, // certificate_store
             None, // global_state
@@ -901,39 +1047,141 @@ impl UdsExecutionState {
         empty_block_timeout_ms: u64,
         max_send_retries: u32,
         retry_delay_base_ms: u64,
-        missed_batch_timeout_ms: u64,
-        max_missed_batch_retries: u32,
-        execution_state_path: Option<PathBuf>,
+        legacy_execution_state_path: Option<PathBuf>,
         consensus_store: Option<Arc<ConsensusStore>>,
         certificate_store: Option<CertificateStore>,
         global_state: Option<Arc<crate::global_state::GlobalStateManager>>,
     ) -> Self {
-        info!("🚀 [UDS] Creating UdsExecutionState: socket_path='{}', epoch={}, empty_block_timeout_ms={}, max_retries={}, retry_delay_base_ms={}, missed_batch_timeout_ms={}, max_missed_batch_retries={}, execution_state_path={:?}", 
-            socket_path, epoch, empty_block_timeout_ms, max_send_retries, retry_delay_base_ms, missed_batch_timeout_ms, max_missed_batch_retries, execution_state_path);
+        let (tx_replay_requests, rx_replay_requests) = mpsc::channel(16);
+        info!("🚀 [UDS] Creating UdsExecutionState: socket_path='{}', epoch={}, empty_block_timeout_ms={}, max_retries={}, retry_delay_base_ms={}, legacy_execution_state_path={:?}", 
+            socket_path, epoch, empty_block_timeout_ms, max_send_retries, retry_delay_base_ms, legacy_execution_state_path);
         Self {
             socket_path,
-            epoch,
+            epoch: Arc::new(Mutex::new(epoch)),
+            epoch_start_heights: Arc::new(Mutex::new(BTreeMap::new())),
             current_block: Arc::new(Mutex::new(None)),
+            block_former: Arc::new(Mutex::new(BlockFormer::new(BlockPolicy::default()))),
+            processed_batches_gc_depth: BlockPolicy::default().processed_batches_gc_depth,
+            processed_batches_watermark: Arc::new(Mutex::new(0)),
+            execution_progress_store: None,
             last_sent_height: Arc::new(Mutex::new(None)), // None = chưa gửi block nào
             last_consensus_index: Arc::new(Mutex::new(0)),
             stream: Arc::new(Mutex::new(None)),
+            protocol_version: UDS_PROTOCOL_V1,
+            ack_timeout: Duration::from_secs(5),
+            ack_tracker: AckTracker::default(),
+            preferred_block_format: BLOCK_FORMAT_V1,
+            block_format: Arc::new(Mutex::new(BLOCK_FORMAT_V1)),
+            sent_block_formats: Arc::new(Mutex::new(BTreeMap::new())),
+            batch_store: None,
+            tx_replay_requests,
+            rx_replay_requests: Mutex::new(Some(rx_replay_requests)),
+            block_archive: None,
+            archive_retention_blocks: 0,
+            archive_prune_interval: 100,
+            chain_tip: Arc::new(Mutex::new(None)),
+            transaction_status_store: None,
+            fork_detector: None,
+            block_sinks: None,
+            execution_backpressure: None,
             late_certificates: Arc::new(Mutex::new(Vec::new())),
             max_send_retries,
             retry_delay_base_ms,
             processed_batch_digests: Arc::new(Mutex::new(HashMap::new())),
-            missed_batches: Arc::new(Mutex::new(HashMap::new())),
-            missed_batch_timeout_ms,
-            max_missed_batch_retries,
-            logged_duplicate_batches: Arc::new(Mutex::new(HashSet::new())),
-            execution_state_path,
+            delivered_transaction_hashes: Arc::new(Mutex::new(HashMap::new())),
+            logged_duplicate_batches: Arc::new(Mutex::new(HashMap::new())),
+            legacy_execution_state_path,
             consensus_store,
             certificate_store,
-            catch_up_threshold: 50, // Default: 50 certificates lag
-            catch_up_check_interval: Duration::from_secs(10), // Default: 10 seconds
-            persistence_counter: Arc::new(Mutex::new(0)),
+            certificate_start: Arc::new(Mutex::new(ResumePoint::default())),
+            unsent_blocks: Arc::new(Mutex::new(BTreeMap::new())),
+            unsent_deliveries: Arc::new(Mutex::new(Vec::new())),
             global_state,
         }
     }
+
+    /// Chọn UDS protocol version. Với UDS_PROTOCOL_V2 executor phải ACK từng block height,
+    /// block chưa ACK sẽ được gửi lại sau khi reconnect.
+    pub fn with_protocol_version(mut self, protocol_version: u32, ack_timeout: Duration) -> Self {
+        info!("🔧 [UDS] Using UDS protocol v{} (ack_timeout={:?})", protocol_version, ack_timeout);
+        self.protocol_version = protocol_version;
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    /// Chọn format của transactions trong block. Executor đọc `format_version` của từng block
+    /// nên format v1 (wrapper trong digest của transaction đầu tiên) vẫn dùng được với executor cũ.
+    /// Format thực sự được chọn trong handshake với executor (xem negotiate_block_format)
+    pub fn with_block_format(mut self, block_format: u32) -> Self {
+        self.preferred_block_format = match block_format {
+            BLOCK_FORMAT_V1 | BLOCK_FORMAT_V2 => block_format,
+            _ => {
+                warn!("⚠️ [UDS] Unknown block format v{}, using block format v{}", block_format, BLOCK_FORMAT_V1);
+                BLOCK_FORMAT_V1
+            }
+        };
+        self.block_format = Arc::new(Mutex::new(self.preferred_block_format));
+        info!("🔧 [UDS] Using block format v{}", self.preferred_block_format);
+        self
+    }
+
+    /// Batch store dùng để dựng lại blocks cho executor replay requests (protocol v2)
+    pub fn with_batch_store(mut self, batch_store: Store<BatchDigest, Batch>) -> Self {
+        self.batch_store = Some(batch_store);
+        self
+    }
+
+    /// Lưu mọi block đã finalize vào persistent archive, giữ lại `retention_blocks` blocks gần nhất
+    pub fn with_block_archive(mut self, block_archive: Arc<BlockArchive>, retention_blocks: u64, prune_interval: u64) -> Self {
+        self.block_archive = Some(block_archive);
+        self.archive_retention_blocks = retention_blocks;
+        self.archive_prune_interval = prune_interval.max(1);
+        self
+    }
+
+    /// Ghi execution progress và processed batches vào RocksDB của node (không có store → không persist)
+    pub fn with_execution_progress_store(mut self, execution_progress_store: Arc<ExecutionProgressStore>) -> Self {
+        self.execution_progress_store = Some(execution_progress_store);
+        self
+    }
+
+    /// Ghi consensus_index/height của transactions và height đã gửi cho executor (TransactionStatus service)
+    pub fn with_transaction_status_store(mut self, transaction_status_store: Arc<TransactionStatusStore>) -> Self {
+        self.transaction_status_store = Some(transaction_status_store);
+        self
+    }
+
+    /// Ghi fingerprint (height, block hash) của mỗi block đã gửi để gossip cho các primaries khác
+    pub fn with_fork_detector(mut self, fork_detector: Arc<ForkDetector>) -> Self {
+        self.fork_detector = Some(fork_detector);
+        self
+    }
+
+    /// Báo cho các block sinks mỗi block đã ghi vào archive (cần `with_block_archive`)
+    pub fn with_block_sinks(mut self, block_sinks: Arc<BlockSinks>) -> Self {
+        self.block_sinks = Some(block_sinks);
+        self
+    }
+
+    /// Theo dõi số blocks executor chưa xác nhận (ACK với protocol v2, đã ghi với v1)
+    pub fn with_execution_backpressure(mut self, execution_backpressure: Arc<ExecutionBackpressure>) -> Self {
+        self.execution_backpressure = Some(execution_backpressure);
+        self
+    }
+
+    /// Chọn cách gom certificates thành blocks. Policy sub_dag kết thúc block tại leader của mỗi sub-dag đã commit.
+    /// CRITICAL: Policy được ghi cùng execution state; node từ chối khởi động lại với policy đánh số lại heights đã gửi
+    pub fn with_block_policy(mut self, block_policy: BlockPolicy) -> Self {
+        info!("🔧 [UDS] Using block policy: {:?}", block_policy);
+        self.processed_batches_gc_depth = block_policy.processed_batches_gc_depth;
+        self.block_former = Arc::new(Mutex::new(BlockFormer::new(block_policy)));
+        self
+    }
+
+    /// Height lớn nhất đã được executor ACK (protocol v2)
+    pub async fn last_confirmed_height(&self) -> Option<u64> {
+        self.ack_tracker.last_confirmed().await
+    }
     
     /// Helper function để update global_state
     async fn update_global_state(&self) {
@@ -949,257 +1197,248 @@ impl UdsExecutionState {
         }
     }
 
+    /// Kiểm tra block policy hiện tại với policy đã dùng để đánh số các heights đã gửi
+    /// CRITICAL: Đổi policy sau khi đã gửi blocks sẽ đánh số lại heights → executor nhận height trùng/lệch → fork
+    /// This should be called before initialize(); node phải dừng nếu trả về Err
+    pub async fn check_block_policy(&self) -> Result<(), String> {
+        let block_former = self.block_former.lock().await;
+        let loaded_state = self.load_execution_state().await?;
+        if loaded_state.last_sent_height.is_none() {
+            return Ok(());
+        }
+        // File execution state cũ không ghi policy → blocks đã gửi theo policy mặc định (10 certificates/block)
+        let stored_policy = loaded_state.block_policy.unwrap_or_default();
+        if block_policy::renumbers_heights(&stored_policy, block_former.policy()) {
+            return Err(format!(
+                "Block policy {:?} would renumber the already sent heights (last_sent_height={:?}, stored policy {:?})",
+                block_former.policy(), loaded_state.last_sent_height, stored_policy
+            ));
+        }
+        Ok(())
+    }
+
     /// Initialize execution state by loading from disk
     /// This should be called after construction to load persisted state
     pub async fn initialize(&self) -> Result<(), String> {
-        // Load từ global_state trước (nếu có), sau đó load từ disk
+        // Execution progress trong RocksDB là nguồn duy nhất (global_state chỉ phản ánh giá trị hiện tại)
         let mut loaded_state = self.load_execution_state().await?;
+        let index_aligned = self.block_former.lock().await.is_index_aligned();
+
+        // Epoch đã persist (nếu mới hơn epoch của committee lúc khởi động) và height bắt đầu của nó
+        let epoch = {
+            let mut epoch_guard = self.epoch.lock().await;
+            *epoch_guard = (*epoch_guard).max(loaded_state.epoch);
+            *epoch_guard
+        };
+        let epoch_changed = epoch != loaded_state.epoch;
+        let epoch_start_height = {
+            // Epoch mới lúc khởi động → heights tiếp nối height cuối cùng đã gửi của epoch trước
+            let next_height = match loaded_state.last_sent_height {
+                Some(height) if epoch_changed => height + 1,
+                _ => 0,
+            };
+            let mut epoch_start_heights = self.epoch_start_heights.lock().await;
+            *epoch_start_heights = loaded_state.epoch_start_heights.clone();
+            epoch_start_heights.entry(loaded_state.epoch).or_insert(0);
+            *epoch_start_heights.entry(epoch).or_insert(next_height)
+        };
+        self.block_former.lock().await.start_epoch(epoch_start_height);
+        if epoch_changed {
+            // State đã persist thuộc epoch trước: consensus_index của nó không còn ý nghĩa
+            loaded_state.next_consensus_index = 0;
+            loaded_state.block_cursor = BlockCursor {
+                height: epoch_start_height,
+                ..BlockCursor::default()
+            };
+        }
         
-        // Override với global_state nếu có và global_state có giá trị lớn hơn
-        if let Some(ref gs) = self.global_state {
-            let state_snapshot = gs.get_state().await;
-            if state_snapshot.last_consensus_index > loaded_state.last_consensus_index {
-                loaded_state.last_consensus_index = state_snapshot.last_consensus_index;
-                info!(
-                    "✅ [UDS] Override last_consensus_index from global_state: {}",
-                    loaded_state.last_consensus_index
-                );
-            }
-            if state_snapshot.last_sent_height.is_some() && (loaded_state.last_sent_height.is_none() || 
-                state_snapshot.last_sent_height.unwrap() > loaded_state.last_sent_height.unwrap()) {
-                loaded_state.last_sent_height = state_snapshot.last_sent_height;
-                info!(
-                    "✅ [UDS] Override last_sent_height from global_state: {:?}",
-                    loaded_state.last_sent_height
-                );
+        if !index_aligned {
+            // Cursor trước certificate đầu tiên của block chưa gửi đầu tiên → block được đánh số lại như trước restart
+            self.block_former.lock().await.restore(loaded_state.block_cursor.clone());
+        }
+
+        // CRITICAL: Consensus ghi certificate vào consensus store TRƯỚC khi giao cho execution state
+        // → execution không thể đi trước consensus store. Ngược lại (consensus store bị xóa/khác DB) consensus
+        // sẽ đánh lại consensus_index từ đầu và execution state sẽ skip các certificates mới → không khởi động
+        if let Some(ref consensus_store) = self.consensus_store {
+            let consensus_next_index = consensus_store
+                .read_next_consensus_index()
+                .map_err(|e| format!("Failed to read the next consensus index: {}", e))?;
+            if loaded_state.next_consensus_index > consensus_next_index {
+                return Err(format!(
+                    "Execution progress is ahead of the consensus store: next_consensus_index={} (epoch {}) but the consensus store has {} sequenced certificates",
+                    loaded_state.next_consensus_index, epoch, consensus_next_index
+                ));
             }
         }
-        
-        *self.last_consensus_index.lock().await = loaded_state.last_consensus_index;
+
+        // Blocks chưa gửi được dựng lại từ consensus output bắt đầu tại next_consensus_index
+        *self.last_consensus_index.lock().await = loaded_state.next_consensus_index.saturating_sub(1);
+        *self.certificate_start.lock().await = ResumePoint {
+            consensus_index: loaded_state.next_consensus_index,
+            block_cursor: self.block_former.lock().await.cursor().clone(),
+        };
         *self.last_sent_height.lock().await = loaded_state.last_sent_height;
+        {
+            // Progress của phiên bản trước không ghi block format: blocks đã gửi theo format cấu hình lúc đó
+            let mut sent_block_formats = self.sent_block_formats.lock().await;
+            *sent_block_formats = loaded_state.block_formats.clone();
+            if sent_block_formats.is_empty() && loaded_state.last_sent_height.is_some() {
+                sent_block_formats.insert(0, self.preferred_block_format);
+            }
+        }
+        if epoch_changed {
+            // Processed batches đã persist thuộc epoch trước: consensus_index của chúng không còn ý nghĩa
+            self.persist_epoch_start().await;
+        } else {
+            self.restore_processed_batches(loaded_state.next_consensus_index).await?;
+        }
         
         // Update global_state với state đã load
         self.update_global_state().await;
+        if let (Some(gs), Some(height)) = (&self.global_state, loaded_state.last_confirmed_height) {
+            gs.update_last_confirmed_block(height).await;
+        }
         
-        info!("✅ [UDS] Initialized execution state: last_consensus_index={}, last_sent_height={:?}", 
-            loaded_state.last_consensus_index, loaded_state.last_sent_height);
+        info!("✅ [UDS] Initialized execution state: epoch={} (starting at height {}), next_consensus_index={}, last_sent_height={:?}", 
+            epoch, epoch_start_height, loaded_state.next_consensus_index, loaded_state.last_sent_height);
         Ok(())
     }
     
-    /// Spawn background task for periodic catch-up checks
-    /// This should be called after initialization
-    pub fn spawn_catchup_task(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(self.catch_up_check_interval);
-            loop {
-                interval.tick().await;
-                
-                match self.check_execution_lag().await {
-                    Ok(Some(execution_index)) => {
-                        // Lag detected, trigger recovery
-                        let consensus_store = match &self.consensus_store {
-                            Some(store) => store,
-                            None => continue, // No consensus store configured
-                        };
-
-                        let consensus_index = match consensus_store.read_last_consensus_index() {
-                            Ok(idx) => idx,
-                            Err(e) => {
-                                error!("❌ [UDS] Failed to read last consensus index for recovery: {}", e);
-                                continue;
-                            }
-                        };
-
-                        if let Err(e) = self.trigger_recovery(execution_index, consensus_index).await {
-                            error!("❌ [UDS] Recovery failed: {}", e);
-                        }
-                    }
-                    Ok(None) => {
-                        // No lag detected
-                        debug!("✅ [UDS] Execution is in sync");
-                    }
-                    Err(e) => {
-                        warn!("⚠️ [UDS] Error checking execution lag: {}", e);
-                    }
-                }
-            }
-        })
+    /// Kết nối UDS nếu chưa có. Returns: true nếu vừa tạo connection mới
+    async fn ensure_connection(&self) -> Result<bool, String> {
+        let mut stream_guard = self.stream.lock().await;
+        if stream_guard.is_none() {
+            let stream = UnixStream::connect(&self.socket_path)
+                .await
+                .map_err(|e| format!("Failed to connect to UDS {}: {}", self.socket_path, e))?;
+            *stream_guard = Some(self.open_connection(stream).await?);
+            info!("✅ [UDS] Connected to Unix Domain Socket: {}", self.socket_path);
+            return Ok(true);
+        }
+        Ok(false)
     }
 
-    /// Check if execution is lagging behind consensus
-    /// Returns Some(execution_index) if lag is detected, None otherwise
-    async fn check_execution_lag(&self) -> Result<Option<u64>, String> {
-        let consensus_store = match &self.consensus_store {
-            Some(store) => store,
-            None => return Ok(None), // No consensus store configured
-        };
-
-        let consensus_next_index = consensus_store
-            .read_last_consensus_index()
-            .map_err(|e| format!("Failed to read last consensus index: {}", e))?;
-
-        let execution_index = {
-            let guard = self.last_consensus_index.lock().await;
-            *guard
-        };
-
-        if consensus_next_index > execution_index + self.catch_up_threshold {
-            warn!("⚠️ [UDS] Execution lag detected: consensus_index={}, execution_index={}, lag={}", 
-                consensus_next_index, execution_index, consensus_next_index - execution_index);
-            Ok(Some(execution_index))
-        } else {
-            Ok(None)
-                            }
-                        }
-
-    /// Trigger recovery by reading and re-processing missing certificates
-    /// CRITICAL: Đảm bảo blocks được tạo tuần tự và gửi qua UDS trong quá trình recovery
-    /// - Xử lý certificates tuần tự theo consensus_index (deterministic, fork-safe)
-    /// - Tạo blocks từ certificates (có thể empty nếu không có transaction data)
-    /// - Gửi blocks qua UDS để đảm bảo execution tăng tiến
-    /// - Flush tất cả pending blocks sau recovery
-    async fn trigger_recovery(&self, start_index: u64, end_index: u64) -> Result<(), String> {
-        let consensus_store = match &self.consensus_store {
-            Some(store) => store,
-            None => return Err("Consensus store not configured".to_string()),
-        };
-
-        let certificate_store = match &self.certificate_store {
-            Some(store) => store,
-            None => return Err("Certificate store not configured".to_string()),
-        };
-
-        info!("🔄 [UDS] Starting recovery: reading certificates from {} to {} (ensuring sequential block creation and UDS sending)", 
-            start_index, end_index - 1);
-
-        // Read missing certificates from consensus store
-        // CRITICAL: Đọc tuần tự theo consensus_index để đảm bảo deterministic và fork-safe
-        let missing = consensus_store
-            .read_sequenced_certificates(&(start_index..=end_index - 1))
-            .map_err(|e| format!("Failed to read sequenced certificates: {}", e))?;
+    /// Handshake (protocol v2) trên connection mới: chọn block format theo ExecutorHello, rồi spawn reader task
+    /// Executor không đọc được format nào của node → Err, connection bị đóng
+    async fn open_connection(&self, stream: UnixStream) -> Result<OwnedWriteHalf, String> {
+        let (mut read_half, write_half) = stream.into_split();
+        if self.protocol_version < UDS_PROTOCOL_V2 {
+            return Ok(write_half);
+        }
 
-        let mut recovered_count = 0;
-        let mut last_processed_index = start_index.saturating_sub(1);
-        
-        // CRITICAL: Xử lý certificates tuần tự theo consensus_index
-        // Đảm bảo blocks được tạo tuần tự và gửi qua UDS
-        for (cert_digest_opt, seq) in missing.iter().zip(start_index..end_index) {
-            if let Some(cert_digest) = cert_digest_opt {
-                // Read certificate from certificate store
-                if let Ok(Some(cert)) = certificate_store.read(*cert_digest) {
-                    // CRITICAL: Đảm bảo xử lý tuần tự theo consensus_index
-                    // handle_consensus_transaction sẽ tự động xử lý gaps thông qua
-                    // fill_missing_blocks và flush_current_block_if_needed
-                    
-                    // Create ConsensusOutput
-                    let consensus_output = ConsensusOutput {
-                        certificate: cert.clone(),
-                        consensus_index: seq,
-                    };
+        let (executor_formats, first_message) =
+            uds_protocol::read_executor_hello(&mut read_half, self.ack_timeout).await?;
+        let block_format = uds_protocol::negotiate_block_format(self.preferred_block_format, &executor_formats)
+            .ok_or_else(|| format!(
+                "Executor reads block formats {:?}, the node sends block formats {:?}",
+                executor_formats, BLOCK_FORMATS
+            ))?;
+        let previous_format = std::mem::replace(&mut *self.block_format.lock().await, block_format);
+        if block_format != self.preferred_block_format {
+            warn!("⚠️ [UDS] Executor reads block formats {:?}: falling back from block format v{} to v{}",
+                executor_formats, self.preferred_block_format, block_format);
+        } else if previous_format != block_format {
+            info!("🔧 [UDS] Executor reads block format v{} again", block_format);
+        }
 
-                    // Create ExecutionIndices
-                    // CRITICAL: Tính toán ExecutionIndices đúng để đảm bảo deterministic processing
-                    let execution_indices = ExecutionIndices {
-                        next_certificate_index: seq,
-                        next_batch_index: 0, // Recovery không có batch index info, dùng 0
-                        next_transaction_index: 0, // Recovery không có transaction index info, dùng 0
-                    };
+        // Executor trả lời ACK/NACK trên cùng socket
+        let _reader_handle = uds_protocol::spawn_executor_reader(
+            read_half,
+            first_message,
+            self.ack_tracker.clone(),
+            self.global_state.clone(),
+            self.tx_replay_requests.clone(),
+        );
+        Ok(write_half)
+    }
 
-                    // CRITICAL: Check xem certificate đã được processed chưa
-                    // Nếu đã processed (consensus_index <= last_consensus_index), skip để tránh duplicate
-                    let should_process = {
-                        let guard = self.last_consensus_index.lock().await;
-                        seq > *guard
-                    };
-                    
-                    if should_process {
-                        // CRITICAL: Re-process certificate tuần tự
-                        // handle_consensus_transaction sẽ:
-                        // 1. Tạo blocks từ certificates (có thể empty nếu không có transaction data)
-                        // 2. Gửi blocks qua UDS khi đủ BLOCK_SIZE certificates
-                        // 3. Đảm bảo sequential execution và fork-safe
-                        // NOTE: Recovery không có transaction data, chỉ tạo empty blocks để đảm bảo sequential execution
-                        self.handle_consensus_transaction(&consensus_output, execution_indices, Vec::new()).await;
-                    } else {
-                        debug!("⏭️ [UDS] Skipping certificate in recovery: consensus_index={} <= last_consensus_index (already processed)", seq);
-                    }
-                    
-                    last_processed_index = seq;
-                    recovered_count += 1;
-                } else {
-                    warn!("⚠️ [UDS] Certificate not found in store: {:?}, consensus_index={}", cert_digest, seq);
-                    // Vẫn update last_processed_index để không tạo duplicate empty blocks
-                    last_processed_index = seq;
-                }
-            } else {
-                // Certificate digest không có → consensus_index này không có certificate
-                // CRITICAL: Không tạo block cho consensus_index không có certificate
-                // Chỉ update last_processed_index để tiếp tục xử lý
-                warn!("⚠️ [UDS] Certificate digest not found for consensus_index={}, skipping (no certificate)", seq);
-                last_processed_index = seq;
-                                    }
-                                }
-                                
-        // CRITICAL: Flush tất cả pending blocks sau recovery
-        // Đảm bảo tất cả blocks được gửi qua UDS, không bỏ sót
-        let last_consensus_index = {
-            let guard = self.last_consensus_index.lock().await;
-            *guard
-        };
-        
-        // Flush current block nếu có (đảm bảo block cuối cùng được gửi)
-        self.flush_current_block_if_needed(last_consensus_index).await;
-        
-        // CRITICAL: Đảm bảo tất cả blocks đã được gửi qua UDS
-        // Kiểm tra và fill gaps nếu cần (đảm bảo sequential execution và không fork)
-        let last_sent_height = {
-            let guard = self.last_sent_height.lock().await;
-            *guard
-        };
-        
-        if let Some(last_sent) = last_sent_height {
-            let expected_last_block = last_consensus_index / BLOCK_SIZE;
-            if expected_last_block > last_sent {
-                // Có blocks chưa được gửi → fill gaps
-                info!("🔄 [UDS] Filling gaps after recovery: last_sent={}, expected_last_block={}", 
-                    last_sent, expected_last_block);
-                if let Err(e) = self.send_empty_blocks_for_gaps(last_sent + 1, expected_last_block + 1).await {
-                    warn!("⚠️ [UDS] Failed to fill gaps after recovery: {}", e);
+    /// Chọn block format với executor lúc khởi động
+    /// CRITICAL: Executor không đọc được format nào của node → node không khởi động
+    /// Executor chưa chạy → handshake ở lần kết nối đầu tiên (blocks chỉ được gửi sau handshake)
+    pub async fn negotiate_block_format(&self) -> Result<(), String> {
+        if self.protocol_version < UDS_PROTOCOL_V2 {
+            // Protocol v1 không có handshake: executor không thể cho biết format nó đọc được
+            if self.preferred_block_format != BLOCK_FORMAT_V1 {
+                return Err(format!(
+                    "Block format v{} needs UDS protocol v{}: the executor advertises the block formats it reads in the handshake",
+                    self.preferred_block_format, UDS_PROTOCOL_V2
+                ));
+            }
+            return Ok(());
+        }
+        let mut stream_guard = self.stream.lock().await;
+        if stream_guard.is_none() {
+            match UnixStream::connect(&self.socket_path).await {
+                Ok(stream) => *stream_guard = Some(self.open_connection(stream).await?),
+                Err(e) => {
+                    warn!("⚠️ [UDS] Executor is not reachable on {} ({}): the block format is negotiated on the first connection",
+                        self.socket_path, e);
+                    return Ok(());
                 }
             }
         }
-        
-        info!("✅ [UDS] Recovery completed: recovered {} certificates from {} to {}, last_consensus_index={}", 
-            recovered_count, start_index, end_index - 1, last_consensus_index);
+        drop(stream_guard);
+        info!("🤝 [UDS] Sending blocks in format v{}", *self.block_format.lock().await);
+        Ok(())
+    }
 
-        // Persist state after recovery
-        if let Err(e) = self.persist_execution_state().await {
-            warn!("⚠️ [UDS] Failed to persist execution state after recovery: {}", e);
+    /// Chờ executor ACK block. Timeout hoặc mất kết nối → drop stream để lần gửi sau reconnect
+    async fn await_ack(&self, height: u64, ack_rx: oneshot::Receiver<Result<(), String>>) -> Result<(), String> {
+        match tokio::time::timeout(self.ack_timeout, ack_rx).await {
+            Ok(Ok(Ok(()))) => {
+                uds_debug!("✅ [UDS] Block {} ACKed by executor", height);
+                Ok(())
+            }
+            Ok(Ok(Err(reason))) => Err(format!("Executor NACKed block {}: {}", height, reason)),
+            Ok(Err(_)) => {
+                *self.stream.lock().await = None;
+                Err(format!("UDS connection closed before ACK of block {}", height))
+            }
+            Err(_) => {
+                *self.stream.lock().await = None;
+                Err(format!("Timed out after {:?} waiting for ACK of block {}", self.ack_timeout, height))
+            }
         }
-
-        Ok(())
     }
 
-    async fn ensure_connection(&self) -> Result<(), String> {
-        let mut stream_guard = self.stream.lock().await;
-        if stream_guard.is_none() {
-            let stream = UnixStream::connect(&self.socket_path)
-                .await
-                .map_err(|e| format!("Failed to connect to UDS {}: {}", self.socket_path, e))?;
-            *stream_guard = Some(stream);
-            info!("✅ [UDS] Connected to Unix Domain Socket: {}", self.socket_path);
-                                        }
+    /// Gửi lại (theo thứ tự height) các block chưa được ACK có height < below_height
+    /// CRITICAL: At-least-once delivery - executor phải chịu được block trùng (dựa vào height)
+    async fn retransmit_unacked(&self, below_height: u64) -> Result<(), String> {
+        let pending = self.ack_tracker.unacked_below(below_height).await;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        info!("🔁 [UDS] Retransmitting {} unacked blocks (heights {}..={})",
+            pending.len(), pending[0].height, pending[pending.len() - 1].height);
+
+        for block in pending {
+            let ack_rx = self.ack_tracker.track(&block).await;
+            {
+                let mut stream_guard = self.stream.lock().await;
+                let stream = stream_guard
+                    .as_mut()
+                    .ok_or_else(|| "UDS stream is not connected".to_string())?;
+                let message = uds_protocol::blocks_message(vec![block.clone()]);
+                if let Err(e) = uds_protocol::write_frame(stream, &message).await {
+                    *stream_guard = None;
+                    return Err(e);
+                }
+            }
+            self.await_ack(block.height, ack_rx).await?;
+        }
         Ok(())
     }
 
     /// Send a single block to UDS (progressive sending, no batching)
-    /// Gửi block với retry mechanism (exponential backoff)
+    /// Gửi block với retry mechanism (exponential backoff) cho đến khi executor nhận
+    /// Err chỉ khi height đã có block finalize khác chưa gửi (không bao giờ thay block thật bằng block khác)
     /// CRITICAL: Chỉ dựa vào last_sent_height để check duplicate
     /// - Check last_sent_height: Nếu block.height <= last_sent_height → block đã được gửi → skip retry
     /// - KHÔNG check processed_batch_digests ở đây vì batch được marked as processed SAU KHI được thêm vào block
     /// - Check processed_batch_digests chỉ dùng trong handle_consensus_transaction để tránh duplicate execution
     /// - FORK-SAFE: Tất cả nodes check cùng last_sent_height → cùng quyết định skip → fork-safe
-    async fn send_block_with_retry(&self, block: comm::CommittedBlock, tx_hash_map: HashMap<Vec<u8>, String>, batch_digests: Vec<Option<BatchDigest>>) -> Result<(), String> {
+    async fn send_block_with_retry(&self, mut block: comm::CommittedBlock, tx_hash_map: HashMap<Vec<u8>, String>, batch_digests: Vec<Option<BatchDigest>>) -> Result<(), String> {
         // CRITICAL: Check xem block đã được gửi thành công chưa (dựa vào last_sent_height)
         // CRITICAL: Chỉ dựa vào last_sent_height để check duplicate
         // KHÔNG check processed_batch_digests ở đây vì:
@@ -1207,58 +1446,90 @@ impl UdsExecutionState {
         // - Nếu check processed_batch_digests ở đây, sẽ skip block ngay cả khi batch vừa được thêm vào block hiện tại
         // - Check processed_batch_digests chỉ nên dùng trong handle_consensus_transaction để tránh duplicate execution
         
-        let mut last_error = None;
-        
-        for attempt in 0..self.max_send_retries {
-            // CRITICAL: Check trùng lặp TRƯỚC MỖI LẦN gọi send_block_internal
-            // Đảm bảo không retry nếu block đã được gửi
-            // 
-            // QUÁ TRÌNH CHECK:
-            // 1. Check last_sent_height: Nếu block.height <= last_sent → block đã được gửi → skip
-            // 2. Nếu không có duplicate → gọi send_block_internal
-            // 3. Nếu send_block_internal fail → sleep và retry (sẽ check lại ở iteration tiếp theo)
-            
-            // Check: last_sent_height (cách chắc chắn nhất để biết block đã được gửi)
-            let last_sent_guard = self.last_sent_height.lock().await;
-            if let Some(last_sent) = *last_sent_guard {
-                if block.height <= last_sent {
-                    drop(last_sent_guard);
-                    if attempt > 0 {
-                        uds_debug!("⏭️ [UDS] Stopping retry for block {} (attempt {}): Block already sent (last_sent_height={})", 
-                            block.height, attempt + 1, last_sent);
-                    } else {
-                        uds_debug!("⏭️ [UDS] Skipping retry for block {}: Block already sent (last_sent_height={})", 
-                            block.height, last_sent);
-                    }
-                    return Ok(()); // Block đã được gửi thành công
+        // FORK-SAFE: Validator khác báo block hash khác tại cùng height → không gửi thêm block cho executor
+        if let Some(fork_detector) = &self.fork_detector {
+            if fork_detector.is_halted() {
+                error!("🛑 [UDS] Fork detected: holding block {} until the delivery is resumed", block.height);
+                fork_detector.wait_until_resumed().await;
             }
         }
-            drop(last_sent_guard);
-            
-            // Không có duplicate → gọi send_block_internal
-            match self.send_block_internal(block.clone(), &tx_hash_map).await {
+
+        // Check: last_sent_height (cách chắc chắn nhất để biết block đã được gửi)
+        if let Some(last_sent) = *self.last_sent_height.lock().await {
+            if block.height <= last_sent {
+                uds_debug!("⏭️ [UDS] Skipping block {}: Block already sent (last_sent_height={})", block.height, last_sent);
+                return Ok(()); // Block đã được gửi thành công
+            }
+        }
+
+        // CRITICAL: Height đã có block finalize (đã nối vào chain, archive) nhưng chưa gửi → KHÔNG gửi block khác tại height này
+        // Gap block thay block thật sẽ ghi đè ack tracker và archive của block thật
+        if let Some(tip) = self.chain_tip.lock().await.as_ref() {
+            if block.height <= tip.height {
+                return Err(format!(
+                    "Block {} is already finalized but not sent (chain tip {}): refusing to send another block at this height",
+                    block.height, tip.height
+                ));
+            }
+        }
+
+        // Nối block vào block trước theo thứ tự gửi (gap blocks được gửi trước block đã finalize)
+        self.link_block(&mut block).await?;
+
+        if let Some(backpressure) = &self.execution_backpressure {
+            backpressure.block_finalized(block.height);
+        }
+
+        // CRITICAL: Block đã finalize được retry (exponential backoff) cho đến khi executor nhận
+        // Bỏ cuộc sẽ để lại height trống: block sau sẽ fill gap bằng block rỗng tại height của block thật
+        // → delivery dừng tại block này cho đến khi gửi được (max_send_retries: số lần thử giữa hai error log)
+        let max_send_retries = self.max_send_retries.max(1);
+        let mut attempt: u32 = 0;
+        let mut archived = false;
+        loop {
+            // CRITICAL: Archive TRƯỚC khi gửi, lỗi archive được retry như lỗi gửi: block đã gửi luôn có trong archive
+            // Block đã finalize là deterministic, ghi lại cùng height là idempotent
+            let archive_result = if archived { Ok(()) } else { self.archive_block(&block) };
+            archived = archive_result.is_ok();
+            let result = match archive_result {
+                Ok(()) => self.send_block_internal(block.clone(), &tx_hash_map).await,
+                Err(e) => Err(e),
+            };
+            match result {
             Ok(_) => {
                     if attempt > 0 {
                         info!("✅ [UDS] Block {} sent successfully after {} retries", block.height, attempt);
                     }
+                    if let Some(status_store) = &self.transaction_status_store {
+                        if let Err(e) = status_store.write_delivered(block.height) {
+                            warn!("⚠️ [UDS] Failed to record delivered block {}: {}", block.height, e);
+                        }
+                    }
+                    if let Some(fork_detector) = &self.fork_detector {
+                        fork_detector.record(block.height, block_header::block_hash(&block));
+                    }
+                    self.record_block_format(block.height, block.format_version).await;
+                    self.record_sent_block(block.height).await;
+                    if let Some(backpressure) = &self.execution_backpressure {
+                        backpressure.block_confirmed(block.height);
+                    }
                     return Ok(());
             }
             Err(e) => {
-                    last_error = Some(e.clone());
-                    if attempt < self.max_send_retries - 1 {
-                        // Chờ một khoảng thời gian trước khi retry (exponential backoff)
-                        let delay_ms = self.retry_delay_base_ms * 2_u64.pow(attempt);
-                        warn!("⚠️ [UDS] Failed to send block {} (attempt {}/{}): {}. Retrying in {}ms... (will check duplicates before next attempt)", 
-                            block.height, attempt + 1, self.max_send_retries, e, delay_ms);
-                        sleep(TokioDuration::from_millis(delay_ms)).await;
-                        // Lưu ý: Check trùng lặp sẽ được thực hiện lại ở đầu vòng lặp tiếp theo
+                    attempt += 1;
+                    // Exponential backoff, giới hạn ở delay của lần thử thứ max_send_retries
+                    let delay_ms = self.retry_delay_base_ms * 2_u64.pow((attempt - 1).min(max_send_retries - 1));
+                    if attempt % max_send_retries == 0 {
+                        error!("❌ [UDS] Failed to send block {} after {} attempts: {}. Delivery holds at this block, retrying in {}ms...", 
+                            block.height, attempt, e, delay_ms);
+                    } else {
+                        warn!("⚠️ [UDS] Failed to send block {} (attempt {}): {}. Retrying in {}ms...", 
+                            block.height, attempt, e, delay_ms);
                     }
+                    sleep(TokioDuration::from_millis(delay_ms)).await;
                 }
             }
         }
-        
-        Err(format!("Failed to send block {} after {} retries: {:?}", 
-            block.height, self.max_send_retries, last_error))
     }
     
     /// Internal method để gửi block (không retry)
@@ -1361,6 +1632,9 @@ impl UdsExecutionState {
                     }
                 }
             }
+        } else if !block.entries.is_empty() {
+            info!("📤 [UDS] Preparing to send block: Height={}, Epoch={}, TxCount={}, ProtoSize={} bytes (block format v{})", 
+                block.height, block.epoch, block.entries.len(), proto_buf.len(), block.format_version);
         } else {
             uds_debug!("📤 [UDS] Preparing to send EMPTY block: Height={}, Epoch={}, ProtoSize={} bytes", 
                 block.height, block.epoch, proto_buf.len());
@@ -1369,6 +1643,16 @@ impl UdsExecutionState {
         // Send via UDS
         // CRITICAL: Đảm bảo dữ liệu nhất quán - transaction bytes trong proto_buf phải khớp với tx.digest
         self.ensure_connection().await?;
+        let acked = self.protocol_version >= UDS_PROTOCOL_V2;
+        if acked {
+            // Giữ thứ tự height: block chưa ACK (do mất kết nối/NACK) phải tới executor trước block này
+            self.retransmit_unacked(block.height).await?;
+        }
+        let ack_rx = if acked {
+            Some(self.ack_tracker.track(&block).await)
+        } else {
+            None
+        };
         let mut stream_guard = self.stream.lock().await;
         if let Some(stream) = stream_guard.as_mut() {
             // VALIDATION: Verify transaction bytes trong block khớp với tx_hash_map
@@ -1438,31 +1722,17 @@ impl UdsExecutionState {
                 }
             }
             
-            // Write length prefix (2 bytes, little-endian)
-            let len_buf = (proto_buf.len() as u16).to_le_bytes();
-            stream.write_all(&len_buf)
-                .await
-                .map_err(|e| format!("Failed to write length to UDS: {}", e))?;
-            
-            // Write protobuf data
-            // CRITICAL: proto_buf chứa CommittedEpochData với CommittedBlock, 
-            // mỗi block chứa transactions với tx.digest là transaction bytes GỐC
-            // 
-            // QUAN TRỌNG VỀ BYTES GỐC:
-            // - tx.digest chứa transaction bytes gốc (đã extract từ wrapper hoặc nhận trực tiếp)
-            // - Khi protobuf serialize tx.digest (kiểu bytes field), nó chỉ thêm field tag + length prefix
-            // - Raw transaction bytes được giữ NGUYÊN VẸN trong protobuf message
-            // - Go side sẽ nhận được đúng transaction bytes gốc từ tx.digest field
-            // - KHÔNG có serialization lại transaction - chỉ serialize protobuf message structure
-            //
-            // Bytes này sẽ được Go side parse và tính hash - phải khớp với hash đã lưu
-            stream.write_all(&proto_buf)
-                .await
-                .map_err(|e| format!("Failed to write block to UDS: {}", e))?;
-            
-            stream.flush()
-                .await
-                .map_err(|e| format!("Failed to flush UDS stream: {}", e))?;
+            let write_result = if acked {
+                // Protocol v2: NodeMessage frame, executor ACK qua reader task
+                uds_protocol::write_frame(stream, &uds_protocol::blocks_message(epoch_data.blocks.clone())).await
+            } else {
+                Self::write_legacy_frame(stream, &proto_buf).await
+            };
+            if let Err(e) = write_result {
+                // Drop stream để lần retry sau reconnect
+                *stream_guard = None;
+                return Err(e);
+            }
 
             if !block.transactions.is_empty() {
                 info!("✅ [UDS] Successfully sent block {} to Unix Domain Socket: Height={}, Epoch={}, TxCount={}, TotalBytes={} (len_buf=2 + proto={})", 
@@ -1508,14 +1778,49 @@ impl UdsExecutionState {
                 }
             }
         }
+        drop(stream_guard);
+
+        // Protocol v2: block chỉ được xem là gửi thành công khi executor ACK
+        if let Some(ack_rx) = ack_rx {
+            self.await_ack(block.height, ack_rx).await?;
+        }
         Ok(())
     }
 
-    /// Send empty blocks for missing heights (gaps)
-    async fn send_empty_blocks_for_gaps(&self, from_height: u64, to_height: u64) -> Result<(), String> {
-        if from_height >= to_height {
-            return Ok(());
-        }
+    /// Ghi frame legacy (protocol v1): 2 byte little-endian length + CommittedEpochData
+    async fn write_legacy_frame(stream: &mut OwnedWriteHalf, proto_buf: &[u8]) -> Result<(), String> {
+        // Write length prefix (2 bytes, little-endian)
+        let len_buf = (proto_buf.len() as u16).to_le_bytes();
+        stream.write_all(&len_buf)
+            .await
+            .map_err(|e| format!("Failed to write length to UDS: {}", e))?;
+
+        // Write protobuf data
+        // CRITICAL: proto_buf chứa CommittedEpochData với CommittedBlock,
+        // mỗi block chứa transactions với tx.digest là transaction bytes GỐC
+        //
+        // QUAN TRỌNG VỀ BYTES GỐC:
+        // - tx.digest chứa transaction bytes gốc (đã extract từ wrapper hoặc nhận trực tiếp)
+        // - Khi protobuf serialize tx.digest (kiểu bytes field), nó chỉ thêm field tag + length prefix
+        // - Raw transaction bytes được giữ NGUYÊN VẸN trong protobuf message
+        // - Go side sẽ nhận được đúng transaction bytes gốc từ tx.digest field
+        // - KHÔNG có serialization lại transaction - chỉ serialize protobuf message structure
+        //
+        // Bytes này sẽ được Go side parse và tính hash - phải khớp với hash đã lưu
+        stream.write_all(proto_buf)
+            .await
+            .map_err(|e| format!("Failed to write block to UDS: {}", e))?;
+
+        stream.flush()
+            .await
+            .map_err(|e| format!("Failed to flush UDS stream: {}", e))
+    }
+
+    /// Send empty blocks for missing heights (gaps)
+    async fn send_empty_blocks_for_gaps(&self, from_height: u64, to_height: u64) -> Result<(), String> {
+        if from_height >= to_height {
+            return Ok(());
+        }
 
         let gap_count = to_height - from_height;
         info!("🔗 [UDS] Filling gaps: Sending {} empty blocks from height {} to {}", 
@@ -1523,9 +1828,12 @@ impl UdsExecutionState {
 
         for height in from_height..to_height {
             let empty_block = comm::CommittedBlock {
-                epoch: self.epoch,
+                epoch: self.epoch_of_height(height).await,
                 height,
                 transactions: Vec::new(),
+                header: None,
+                format_version: *self.block_format.lock().await,
+                entries: Vec::new(),
             };
             let empty_tx_hash_map = HashMap::new();
             // Empty block không có batch_digests
@@ -1534,7 +1842,6 @@ impl UdsExecutionState {
                 error!("❌ [UDS] Failed to send empty block for gap at height {} after retries: {}", height, e);
                 return Err(format!("Failed to send empty block height {}: {}", height, e));
             }
-            *self.last_sent_height.lock().await = Some(height);
         }
         
         // Update global_state sau khi fill gaps
@@ -1575,10 +1882,10 @@ impl ExecutionState for UdsExecutionState {
     /// Xử lý consensus transaction dựa hoàn toàn vào consensus_index
     /// 
     /// Logic mới:
-    /// - Block height = consensus_index / BLOCK_SIZE
-    /// - Gộp BLOCK_SIZE consensus_index thành 1 block
-    /// - Gửi block khi consensus_index >= (block_height + 1) * BLOCK_SIZE
-    /// - Đảm bảo: Tất cả certificates với consensus_index < (block_height + 1) * BLOCK_SIZE đã được xử lý
+    /// - Block height do BlockFormer gán theo block policy (N certificates/block, 1 block/sub-DAG, caps)
+    /// - Policy mặc định: block height = consensus_index / certificates_per_block
+    /// - Gửi block khi certificate/transaction tiếp theo thuộc height sau
+    /// - Đảm bảo: Tất cả transactions của block đã được xử lý trước khi gửi
     /// 
     /// Ưu điểm:
     /// - Không bỏ sót: consensus_index tuần tự tuyệt đối
@@ -1594,6 +1901,21 @@ impl ExecutionState for UdsExecutionState {
         let round = consensus_output.certificate.round();
         let consensus_index = consensus_output.consensus_index;
         let has_transaction = !transaction.is_empty();
+
+        // CRITICAL: Certificate đầu tiên của epoch mới → consensus đã xử lý NewEpoch và bắt đầu lại
+        // consensus_index từ 0. Chuyển epoch TRƯỚC khi so sánh consensus_index với last_consensus_index
+        // FORK-SAFE: Mọi node chuyển epoch tại cùng vị trí trong chuỗi consensus output
+        let certificate_epoch = consensus_output.certificate.epoch();
+        let current_epoch = *self.epoch.lock().await;
+        if certificate_epoch > current_epoch {
+            self.start_epoch(certificate_epoch).await;
+        } else if certificate_epoch < current_epoch {
+            warn!(
+                "⏭️ [UDS] Skipping certificate of epoch {} (current epoch {}): Round={}, ConsensusIndex={}",
+                certificate_epoch, current_epoch, round, consensus_index
+            );
+            return;
+        }
         
         // CRITICAL: consensus_output.certificate là certificate ĐÃ ĐƯỢC CONSENSUS COMMIT
         // Consensus chỉ gửi ConsensusOutput cho certificates đã commit thành công
@@ -1659,23 +1981,27 @@ impl ExecutionState for UdsExecutionState {
         }
         drop(last_consensus_guard);
         
+        // FORK-SAFE: Gán certificate vào block height theo block policy (deterministic từ consensus sequence)
+        // Gán TRƯỚC duplicate batch check để tất cả nodes đếm cùng certificates
+        // Certificate cuối của sub-dag là leader đã commit → kết thúc block ở policy sub_dag
+        let is_committed_leader = consensus_output.sub_dag.is_last(consensus_index);
+        let block_height = {
+            let mut block_former = self.block_former.lock().await;
+            // Certificate mới → ghi lại cursor trước khi gán height: restart giữa block dựng lại block từ certificate này
+            let mut certificate_start = self.certificate_start.lock().await;
+            if consensus_index > certificate_start.consensus_index {
+                *certificate_start = ResumePoint {
+                    consensus_index,
+                    block_cursor: block_former.cursor().clone(),
+                };
+            }
+            drop(certificate_start);
+            block_former.assign_certificate(consensus_index, is_committed_leader)
+        };
+        
         // Update global_state
         self.update_global_state().await;
         
-        // Periodic persistence: persist every 10 certificates
-        // Note: Persistence is done synchronously but quickly (just file I/O)
-        let mut counter_guard = self.persistence_counter.lock().await;
-        *counter_guard += 1;
-        let should_persist_now = *counter_guard >= 10; // Persist every 10 certificates
-        if should_persist_now {
-            *counter_guard = 0;
-            drop(counter_guard);
-            // Persist execution state (non-blocking file I/O)
-            if let Err(e) = self.persist_execution_state().await {
-                warn!("⚠️ [UDS] Failed to persist execution state: {}", e);
-            }
-        }
-        
         // CRITICAL: Extract batch digest từ certificate payload để check duplicate TRƯỚC KHI parse/log
         // Tối ưu: Check duplicate sớm để tránh parse/log không cần thiết khi batch đã processed
         let batch_index_in_payload = execution_indices.next_batch_index.saturating_sub(1) as usize;
@@ -1700,20 +2026,16 @@ impl ExecutionState for UdsExecutionState {
             };
             
             if let Some(processed_consensus_index) = processed_consensus_index_opt {
-                if processed_consensus_index != consensus_index {
+                // FORK-SAFE: Chỉ xét batches trong cửa sổ processed_batches_gc_depth → không phụ thuộc thời điểm prune
+                let within_window = processed_consensus_index >= consensus_index.saturating_sub(self.processed_batches_gc_depth);
+                if processed_consensus_index != consensus_index && within_window {
                     // Batch đã processed với consensus_index khác → skip duplicate
                     // FORK-SAFE: Tất cả nodes có cùng processed_batch_digests → cùng quyết định skip
                     
                     // Fast log: logged_duplicate_batches (minimal lock time)
                     let should_log = {
                         let mut logged_guard = self.logged_duplicate_batches.lock().await;
-                        let inserted = logged_guard.insert(batch_digest.clone());
-                        // Lazy GC: chỉ khi cache quá lớn
-                        const MAX_LOGGED_DUPLICATES: usize = 1000;
-                        if logged_guard.len() > MAX_LOGGED_DUPLICATES {
-                            logged_guard.clear(); // Fast clear
-                        }
-                        inserted
+                        logged_guard.insert(batch_digest, processed_consensus_index).is_none()
                     };
                     
                     if should_log {
@@ -1739,7 +2061,6 @@ impl ExecutionState for UdsExecutionState {
         // CRITICAL: Log info cho certificate có transaction với hash để trace (GIỐNG WORKER)
         if has_transaction {
             let tx_hex_full = hex::encode(&transaction);
-            let block_height = consensus_index / BLOCK_SIZE;
             
             if tx_count > 1 {
                 // Nhiều transactions trong Transactions protobuf - log từng transaction
@@ -1776,19 +2097,18 @@ impl ExecutionState for UdsExecutionState {
             }
         }
         
-        // CRITICAL: Block height = consensus_index / BLOCK_SIZE
-        // Ví dụ: consensus_index 0-9 → block 0, 10-19 → block 1, 20-29 → block 2
-        let block_height = consensus_index / BLOCK_SIZE;
-        let block_start_index = block_height * BLOCK_SIZE;
-        let block_end_index = (block_height + 1) * BLOCK_SIZE - 1;
-        
         // CRITICAL: Kiểm tra block đã gửi chưa
         let last_sent_guard = self.last_sent_height.lock().await;
         let last_sent = *last_sent_guard;
         drop(last_sent_guard);
         
+        // Block policy caps tách certificate qua nhiều blocks: sau restart, certificate đầu tiên của block chưa gửi
+        // có thể bắt đầu trong block đã gửi → transactions đã gửi được bỏ qua, phần còn lại vào block chưa gửi
+        let continues_in_unsent_block = matches!(last_sent, Some(last_sent_val) if block_height <= last_sent_val)
+            && !parsed_transactions.is_empty()
+            && self.block_former.lock().await.splits_certificates();
         if let Some(last_sent_val) = last_sent {
-            if block_height <= last_sent_val {
+            if block_height <= last_sent_val && !continues_in_unsent_block {
                     // Block đã gửi rồi → certificate này đến muộn (không nên xảy ra với consensus_index tuần tự)
                     // PRODUCTION: Buffer late certificate để retry sau
                     warn!(
@@ -1818,99 +2138,40 @@ impl ExecutionState for UdsExecutionState {
             }
         }
         
-        // TỐI ƯU: Track batch để detect missed (chỉ track, không retry phức tạp)
-        // Không blocking consensus - chỉ quick lookup và insert
-        if let Some(batch_digest) = batch_digest_opt {
-            // Quick check: processed_batch_digests (read-only, fast, minimal lock time)
-            let is_processed = {
-                let processed_batch_guard = self.processed_batch_digests.lock().await;
-                processed_batch_guard.contains_key(&batch_digest)
-            };
-            
-            // Quick update: missed_batches (minimal lock time)
-            let mut missed_guard = self.missed_batches.lock().await;
-            
-            if is_processed {
-                // Batch đã processed → remove khỏi missed_batches (fast remove)
-                missed_guard.remove(&batch_digest);
-            } else {
-                // Batch chưa processed → track (lazy GC - chỉ khi cache đầy)
-                const MAX_MISSED_BATCHES: usize = 5000;
-                if missed_guard.len() >= MAX_MISSED_BATCHES {
-                    // Fast GC: Xóa 50% entries (không sort - chỉ remove random để tránh overhead)
-                    let target_size = MAX_MISSED_BATCHES / 2;
-                    let mut removed = 0;
-                    let current_size = missed_guard.len();
-                    missed_guard.retain(|_, _| {
-                        removed += 1;
-                        // Remove mỗi entry thứ 2 để giảm size xuống target_size
-                        removed <= target_size || (removed - target_size) % 2 == 0
-                    });
-                    if missed_guard.len() < current_size {
-                        debug!("🧹 [UDS] GC: Cleaned missed_batches from {} to {} entries", current_size, missed_guard.len());
-                    }
-                }
-                
-                // Fast insert
-                if !missed_guard.contains_key(&batch_digest) {
-                    missed_guard.insert(batch_digest, MissedBatchInfo {
-                        commit_time: Instant::now(),
-                        consensus_index,
-                        round,
-                        block_height,
-                        retry_count: 0,
-                        last_retry_time: Instant::now(),
-                    });
-                }
-            }
-        }
-        
-        // TỐI ƯU: Check missed batches chỉ khi cần (không blocking consensus)
-        // Defer check ra khỏi hot path - chỉ check mỗi 100 certificates để tránh overhead
-        // Không spawn task để tránh lifetime issues - chỉ check inline nhưng nhanh
-        if consensus_index % 100 == 0 {
-            let missed_guard = self.missed_batches.lock().await;
-            if !missed_guard.is_empty() {
-                drop(missed_guard);
-                // Check missed batches inline (nhanh, không blocking)
-                self.check_missed_batches().await;
-            }
-        }
-        
         // Block chưa gửi → thêm transaction vào block
         let mut current_block_guard = self.current_block.lock().await;
         
         // Kiểm tra xem có cần tạo block mới không
-        let need_new_block = current_block_guard.is_none() 
-            || current_block_guard.as_ref().unwrap().height != block_height;
+        // Certificate tiếp tục trong block chưa gửi → block được mở bởi transaction đầu tiên chưa gửi (bên dưới)
+        let need_new_block = !continues_in_unsent_block
+            && (current_block_guard.is_none() || current_block_guard.as_ref().unwrap().height != block_height);
         
         // Lưu block cũ (nếu có) để gửi sau khi thêm transaction vào block mới
-        let mut old_block_to_send: Option<BlockBuilder> = None;
+        let mut blocks_to_send: Vec<BlockBuilder> = Vec::new();
         
         if need_new_block {
             // Cần tạo block mới → lưu block cũ để gửi sau
             if let Some(old_block) = current_block_guard.take() {
-                old_block_to_send = Some(old_block);
+                blocks_to_send.push(old_block);
             }
             
             // Tạo block mới cho block_height này
             if has_transaction {
                 if tx_count > 1 {
-                    info!("📊 [UDS] Creating block {} (consensus_index {}-{}): Round={}, ConsensusIndex={}, TxCount={} (Transactions protobuf)", 
-                        block_height, block_start_index, block_end_index, round, consensus_index, tx_count);
+                    info!("📊 [UDS] Creating block {}: Round={}, ConsensusIndex={}, TxCount={} (Transactions protobuf)", 
+                        block_height, round, consensus_index, tx_count);
                 } else if tx_count == 1 {
                     let first_hash = &parsed_transactions[0].0;
-                    info!("📊 [UDS] Creating block {} (consensus_index {}-{}): Round={}, ConsensusIndex={}, TxHash={}", 
-                        block_height, block_start_index, block_end_index, round, consensus_index, first_hash);
+                    info!("📊 [UDS] Creating block {}: Round={}, ConsensusIndex={}, TxHash={}", 
+                        block_height, round, consensus_index, first_hash);
                 }
             }
             
-            *current_block_guard = Some(BlockBuilder {
-                epoch: self.epoch,
-                height: block_height,
-                transaction_entries: Vec::new(),
-                transaction_hashes: HashSet::new(),
-            });
+            *current_block_guard = Some(BlockBuilder::new(certificate_epoch, block_height));
+            self.track_unsent_block(block_height).await;
+        }
+        if let Some(block) = current_block_guard.as_mut() {
+            block.record_certificate(consensus_index, consensus_output.sub_dag.leader_round);
         }
         
         // Thêm TẤT CẢ transactions vào block (nếu có)
@@ -1920,11 +2181,13 @@ impl ExecutionState for UdsExecutionState {
         // CRITICAL: Log để trace giao dịch cụ thể khi thêm vào block
         for (tx_hash_hex, _, _, _) in &parsed_transactions {
             if should_trace_tx(tx_hash_hex) {
-                info!("🔍 [UDS] TRACE: Adding transaction {} to block {} (consensus_index={}, block_height={}, block_start_index={}, block_end_index={})", 
-                    tx_hash_hex, block_height, consensus_index, block_height, block_start_index, block_end_index);
+                info!("🔍 [UDS] TRACE: Adding transaction {} to block {} (consensus_index={}, block_height={})", 
+                    tx_hash_hex, block_height, consensus_index, block_height);
             }
         }
         
+        // Transactions được đưa vào block ở lần gọi này → persist cùng execution progress (bên dưới)
+        let mut delivered_tx_hashes: Vec<Vec<u8>> = Vec::new();
         if !parsed_transactions.is_empty() {
             let worker_id = consensus_output
                 .certificate
@@ -1935,22 +2198,69 @@ impl ExecutionState for UdsExecutionState {
                 .map(|(_, worker_id)| *worker_id)
                 .unwrap_or(0u32);
             
-            if let Some(block) = current_block_guard.as_mut() {
+            if current_block_guard.is_some() || continues_in_unsent_block {
                 // Xử lý TẤT CẢ transactions trong Transactions protobuf
                 // Mỗi transaction có cùng consensus_index (từ certificate)
                 // CRITICAL: Đảm bảo dữ liệu nhất quán - transaction bytes phải giữ nguyên từ parse đến gửi UDS
                 for (tx_idx, (tx_hash_hex, tx_hash, _tx_proto, raw_bytes)) in parsed_transactions.iter().enumerate() {
+                    // FORK-SAFE: Block policy caps (max_transactions/max_bytes) - transaction không vừa block hiện tại
+                    // → block đã đầy, transaction mở height tiếp theo (theo thứ tự commit, deterministic)
+                    let tx_block_height = self.block_former.lock().await.assign_transaction(raw_bytes.len() as u64);
+                    if matches!(last_sent, Some(last_sent_val) if tx_block_height <= last_sent_val) {
+                        // Transaction nằm trong block đã gửi trước restart: chỉ dựng lại dedup state như lúc đưa vào block đó
+                        let mut delivered_guard = self.delivered_transaction_hashes.lock().await;
+                        if !delivered_within_window(&delivered_guard, tx_hash, consensus_index, self.processed_batches_gc_depth) {
+                            delivered_guard.insert(tx_hash.clone(), consensus_index);
+                            delivered_tx_hashes.push(tx_hash.clone());
+                        }
+                        continue;
+                    }
+                    if current_block_guard.as_ref().map_or(continues_in_unsent_block, |b| b.height != tx_block_height) {
+                        if let Some(full_block) = current_block_guard.take() {
+                            info!("✂️ [UDS] Block {} reached the block policy caps with {} transactions, continuing ConsensusIndex={} in block {}",
+                                full_block.height, full_block.transaction_entries.len(), consensus_index, tx_block_height);
+                            blocks_to_send.push(full_block);
+                        }
+                        // Certificate tiếp tục trong block mới → block mới cũng chứa certificate này
+                        let mut next_block = BlockBuilder::new(certificate_epoch, tx_block_height);
+                        next_block.record_certificate(consensus_index, consensus_output.sub_dag.leader_round);
+                        *current_block_guard = Some(next_block);
+                        self.track_unsent_block(tx_block_height).await;
+                    }
+                    let block = match current_block_guard.as_mut() {
+                        Some(block) => block,
+                        None => break,
+                    };
+                    
                     // CRITICAL: Check duplicate trong cùng block
                     // Note: Batch duplicate đã được xử lý bởi processed_batch_digests
-                    // Transaction duplicate giữa các blocks được prevent bởi batch-level deduplication
-                    // Chỉ cần check duplicate trong cùng block
+                    // Transaction duplicate giữa các batches được xử lý bởi delivered_transaction_hashes (bên dưới)
                     if block.transaction_hashes.contains(tx_hash) {
                         warn!("⚠️ [UDS] Duplicate transaction detected in block {}: TxHash={}, Round={}, ConsensusIndex={}, TxIdx={}/{}. Transaction already exists in block. This transaction will NOT be added to block again.", 
-                            block_height, tx_hash_hex, round, consensus_index, tx_idx, tx_count);
+                            block.height, tx_hash_hex, round, consensus_index, tx_idx, tx_count);
                         // CRITICAL: Log để trace giao dịch bị skip
                         if should_trace_tx(tx_hash_hex) {
                             error!("❌ [UDS] TRACE: Transaction {} was SKIPPED due to duplicate in block {} (Round={}, ConsensusIndex={})", 
-                                tx_hash_hex, block_height, round, consensus_index);
+                                tx_hash_hex, block.height, round, consensus_index);
+                        }
+                        continue;
+                    }
+
+                    // FORK-SAFE: Cross-batch duplicate - transaction đã được đưa vào block bởi batch khác
+                    // (client gửi cùng transaction đến nhiều workers/validators) → chỉ deliver một lần
+                    // Mọi node xử lý cùng chuỗi consensus output → cùng quyết định skip
+                    let delivered_at = {
+                        let delivered_guard = self.delivered_transaction_hashes.lock().await;
+                        delivered_guard.get(tx_hash).copied().filter(|_| {
+                            delivered_within_window(&delivered_guard, tx_hash, consensus_index, self.processed_batches_gc_depth)
+                        })
+                    };
+                    if let Some(delivered_index) = delivered_at {
+                        warn!("⚠️ [UDS] Transaction already delivered by another batch: TxHash={}, Round={}, ConsensusIndex={}, DeliveredAtConsensusIndex={}. Transaction will NOT be added to block {}.",
+                            tx_hash_hex, round, consensus_index, delivered_index, block.height);
+                        if should_trace_tx(tx_hash_hex) {
+                            error!("❌ [UDS] TRACE: Transaction {} was SKIPPED as a cross-batch duplicate (ConsensusIndex={}, DeliveredAtConsensusIndex={})",
+                                tx_hash_hex, consensus_index, delivered_index);
                         }
                         continue;
                     }
@@ -2043,21 +2353,29 @@ impl ExecutionState for UdsExecutionState {
                         },
                         tx_hash_hex: tx_hash_hex.clone(), // Lưu hash để dùng khi finalize
                         batch_digest: batch_digest_opt, // Lưu batch_digest để check khi retry
+                        certificate_digest: Some(consensus_output.certificate.digest()),
                     });
                     block.transaction_hashes.insert(tx_hash.clone());
+                    self.delivered_transaction_hashes.lock().await.insert(tx_hash.clone(), consensus_index);
+                    delivered_tx_hashes.push(tx_hash.clone());
+                    if let Some(status_store) = &self.transaction_status_store {
+                        if let Err(e) = status_store.write_committed(&tx_hash, consensus_index, block.height) {
+                            warn!("⚠️ [UDS] Failed to record committed transaction {}: {}", tx_hash_hex, e);
+                        }
+                    }
                     
                     // CRITICAL: Log để trace giao dịch được thêm vào block
                     if should_trace_tx(tx_hash_hex) {
                         info!("✅ [UDS] TRACE: Transaction {} was ADDED to block {} (Round={}, ConsensusIndex={}, TotalTxs={})", 
-                            tx_hash_hex, block_height, round, consensus_index, block.transaction_entries.len());
+                            tx_hash_hex, block.height, round, consensus_index, block.transaction_entries.len());
                     }
                     
                     if tx_count > 1 {
                         info!("📝 [UDS] Added transaction [{}/{}] to block {}: Round={}, ConsensusIndex={}, TxHash={}, TotalTxs={}, WorkerId={}", 
-                            tx_idx + 1, tx_count, block_height, round, consensus_index, tx_hash_hex, block.transaction_entries.len(), worker_id);
+                            tx_idx + 1, tx_count, block.height, round, consensus_index, tx_hash_hex, block.transaction_entries.len(), worker_id);
                     } else {
                         info!("📝 [UDS] Added transaction to block {}: Round={}, ConsensusIndex={}, TxHash={}, TotalTxs={}, WorkerId={}", 
-                            block_height, round, consensus_index, tx_hash_hex, block.transaction_entries.len(), worker_id);
+                            block.height, round, consensus_index, tx_hash_hex, block.transaction_entries.len(), worker_id);
                     }
                 }
             } else {
@@ -2081,11 +2399,13 @@ impl ExecutionState for UdsExecutionState {
         // - Transaction_hashes trong BlockBuilder prevent duplicate trong cùng block → an toàn
         //
         // FORK-SAFE: Tất cả nodes track cùng batches → cùng quyết định skip → fork-safe
+        let mut newly_processed_batch = None;
         if let Some(batch_digest) = batch_digest_opt {
             let mut processed_batch_guard = self.processed_batch_digests.lock().await;
             
-            // Check xem batch đã được processed chưa
-            if !processed_batch_guard.contains_key(&batch_digest) {
+            // Check xem batch đã được processed chưa (entry ngoài cửa sổ gc → xem như chưa processed)
+            let processed_here = matches!(processed_batch_guard.get(&batch_digest), Some(index) if *index == consensus_index);
+            if !processed_here {
                 // Batch chưa được processed → lưu (batch_digest, consensus_index) sau khi xử lý xong
                 processed_batch_guard.insert(batch_digest, consensus_index);
                 info!(
@@ -2095,38 +2415,11 @@ impl ExecutionState for UdsExecutionState {
                 // Batch đã processed → cleanup
                 drop(processed_batch_guard);
                 
-                // Remove khỏi missed_batches
-                let mut missed_guard = self.missed_batches.lock().await;
-                missed_guard.remove(&batch_digest);
-                drop(missed_guard);
-                
                 // Remove khỏi logged_duplicate_batches
                 let mut logged_guard = self.logged_duplicate_batches.lock().await;
                 logged_guard.remove(&batch_digest);
                 drop(logged_guard);
-                
-                processed_batch_guard = self.processed_batch_digests.lock().await;
-                
-                // FORK-SAFE: GC - cleanup entries cũ (giới hạn cache)
-                // CRITICAL: GC phải deterministic dựa trên consensus_index để đảm bảo tất cả nodes có cùng state
-                // Tất cả nodes với cùng consensus_index sẽ xóa cùng entries → fork-safe
-                const MAX_PROCESSED_BATCHES: usize = 10000;
-                if processed_batch_guard.len() > MAX_PROCESSED_BATCHES {
-                    // FORK-SAFE: Xóa entries cũ nhất dựa trên consensus_index (deterministic)
-                    // gc_threshold = consensus_index - GC_DEPTH * BLOCK_SIZE
-                    // Tất cả nodes với cùng consensus_index sẽ có cùng gc_threshold → xóa cùng entries
-                    let gc_threshold = consensus_index.saturating_sub(GC_DEPTH * BLOCK_SIZE);
-                    if gc_threshold > 0 {
-                        let before_size = processed_batch_guard.len();
-                        processed_batch_guard.retain(|_, stored_index| *stored_index >= gc_threshold);
-                        let after_size = processed_batch_guard.len();
-                        let cleaned = before_size.saturating_sub(after_size);
-                        if cleaned > 0 {
-                            debug!("🧹 [UDS] GC: Cleaned {} old batch entries (threshold: {}, before: {}, after: {})", 
-                                cleaned, gc_threshold, before_size, after_size);
-                        }
-                    }
-                }
+                newly_processed_batch = Some(batch_digest);
             } else {
                 // Batch đã được processed với cùng consensus_index → transaction tiếp theo trong batch
                 // Đã được check trước khi thêm transactions → transaction đã được thêm vào block
@@ -2134,15 +2427,31 @@ impl ExecutionState for UdsExecutionState {
                     "🔍 [UDS] Batch already processed with same consensus_index: BatchDigest={:?}, ConsensusIndex={}, Round={}, TxCount={}. Transaction continuation in batch (already added to block).",
                     batch_digest, consensus_index, round, tx_count
                 );
+                drop(processed_batch_guard);
             }
-            drop(processed_batch_guard);
+        }
+
+        // CRITICAL: Batch và transactions đã deliver được ghi cùng execution progress khi block chứa chúng được gửi (một write batch)
+        // → restart replay blocks chưa gửi với cùng dedup state như trước restart: không thực thi lại batch,
+        // skip cùng transactions (cross-batch duplicate), blocks dựng lại giống hệt
+        if (newly_processed_batch.is_some() || !delivered_tx_hashes.is_empty()) && self.execution_progress_store.is_some() {
+            self.unsent_deliveries.lock().await.push(Delivery {
+                consensus_index,
+                batch_digest: newly_processed_batch,
+                tx_hashes: delivered_tx_hashes,
+            });
+        }
+        if newly_processed_batch.is_some() {
+            // FORK-SAFE: GC theo watermark consensus_index (deterministic), không theo kích thước map
+            self.prune_processed_batches(consensus_index).await;
         }
         
         drop(current_block_guard);
         
         // Gửi block cũ SAU KHI đã thêm transaction vào block mới
         // Điều này đảm bảo block cũ có đầy đủ transactions trước khi gửi
-        if let Some(old_block) = old_block_to_send {
+        // Nhiều blocks khi block policy caps tách block giữa certificate (gửi theo thứ tự height)
+        for old_block in blocks_to_send {
             let old_block_height = old_block.height;
             let old_block_tx_count = old_block.transaction_entries.len();
             info!("📤 [UDS] Switching to new block {}: Sending previous block {} with {} transactions (after adding transaction to new block)", 
@@ -2157,7 +2466,7 @@ impl ExecutionState for UdsExecutionState {
             }
             
             // Gửi block cũ trực tiếp (không cần lấy từ current_block vì đã lấy rồi)
-            let (block_to_send, tx_hash_map, batch_digests) = old_block.finalize();
+            let (block_to_send, tx_hash_map, batch_digests) = old_block.finalize(*self.block_format.lock().await);
             
             // FORK-SAFE: Atomic check-and-send
             // CRITICAL: Tất cả nodes check cùng last_sent_height → cùng quyết định gửi block → fork-safe
@@ -2173,7 +2482,9 @@ impl ExecutionState for UdsExecutionState {
                     if block_to_send.height > last_sent_val + 1 {
                         drop(last_sent_guard);
                         if let Err(e) = self.send_empty_blocks_for_gaps(last_sent_val + 1, block_to_send.height).await {
-                            error!("❌ [UDS] Failed to send empty blocks for gaps: {}", e);
+                            // CRITICAL: Height trước block chưa có block → không gửi block sau nó (chain phải liên tục)
+                            error!("❌ [UDS] Failed to send empty blocks for gaps, holding block {}: {}", block_to_send.height, e);
+                            return;
                         }
                         last_sent_guard = self.last_sent_height.lock().await;
                     }
@@ -2182,7 +2493,9 @@ impl ExecutionState for UdsExecutionState {
                     if block_to_send.height > 0 {
                         drop(last_sent_guard);
                         if let Err(e) = self.send_empty_blocks_for_gaps(0, block_to_send.height).await {
-                            error!("❌ [UDS] Failed to send empty blocks for gaps: {}", e);
+                            // CRITICAL: Height trước block chưa có block → không gửi block sau nó (chain phải liên tục)
+                            error!("❌ [UDS] Failed to send empty blocks for gaps, holding block {}: {}", block_to_send.height, e);
+                            return;
                         }
                         last_sent_guard = self.last_sent_height.lock().await;
                     }
@@ -2211,6 +2524,8 @@ impl ExecutionState for UdsExecutionState {
                                 }
                             }
                         }
+                        // Không gửi blocks sau block lỗi: chain phải liên tục
+                        return;
                     } else {
                         info!("✅ [UDS] Successfully sent block {} with {} transactions", 
                             old_block_height, block_to_send.transactions.len());
@@ -2261,28 +2576,22 @@ impl ExecutionState for UdsExecutionState {
             }
         }
         
-        // CRITICAL: Gửi block CHỈ KHI có certificate từ block tiếp theo
-        // - KHÔNG gửi khi chỉ đạt block_end_index vì batch có thể có nhiều transactions
+        // CRITICAL: Gửi block CHỈ KHI có certificate/transaction từ block tiếp theo
+        // - KHÔNG gửi khi block policy vừa đóng block vì batch có thể có nhiều transactions
         // - Các transactions trong cùng batch (cùng consensus_index) đến tuần tự (async)
-        // - Nếu gửi block ngay khi consensus_index >= block_end_index, transaction thứ 2, 3... có thể đến muộn
-        // - CHỈ gửi khi consensus_index >= next_block_start_index để đảm bảo TẤT CẢ transactions từ batch đã đến
-        // 
-        // Ví dụ: Block 247 (consensus_index 2470-2479)
-        // - Nếu gửi khi consensus_index = 2479 → transaction thứ 2 từ batch có thể đến muộn (vẫn consensus_index = 2479)
-        // - CHỈ gửi khi consensus_index >= 2480 (certificate từ block 248) → đảm bảo tất cả transactions từ block 247 đã đến
-        let next_block_start_index = (block_height + 1) * BLOCK_SIZE;
+        // - Nếu gửi block ngay khi certificate cuối của block đến, transaction thứ 2, 3... có thể đến muộn
+        // - CHỈ gửi khi BlockFormer đã chuyển sang height sau để đảm bảo TẤT CẢ transactions từ batch đã đến
+        let current_height = self.block_former.lock().await.current_height();
         
         // Debug: Log các giá trị để kiểm tra
-        debug!("🔍 [UDS] Block send check: BlockHeight={}, ConsensusIndex={}, BlockStartIndex={}, BlockEndIndex={}, NextBlockStartIndex={}, HasTransaction={}", 
-            block_height, consensus_index, block_start_index, block_end_index, next_block_start_index, has_transaction);
+        debug!("🔍 [UDS] Block send check: BlockHeight={}, ConsensusIndex={}, CurrentHeight={}, HasTransaction={}", 
+            block_height, consensus_index, current_height, has_transaction);
         
-        // CRITICAL: Gửi block hiện tại nếu consensus_index đã vượt quá block_end_index
-        // Điều này đảm bảo block được gửi ngay cả khi không có block mới tiếp theo
-        // Logic: Nếu consensus_index >= next_block_start_index, block hiện tại (block_height - 1) nên được gửi
+        // CRITICAL: Gửi block đang build nếu BlockFormer đã chuyển sang height sau (vd. block policy caps tách block)
         // Nhưng nếu need_new_block = true, block cũ đã được gửi trong logic trên rồi
         // Chỉ cần kiểm tra và gửi block hiện tại nếu nó chưa được gửi
-        if consensus_index >= next_block_start_index {
-            // consensus_index đã vượt quá block hiện tại → cần kiểm tra block hiện tại có cần gửi không
+        if current_height > block_height {
+            // BlockFormer đã vượt quá block_height → cần kiểm tra block hiện tại có cần gửi không
             let mut current_block_guard = self.current_block.lock().await;
             if let Some(block) = current_block_guard.as_ref() {
                 // Block hiện tại có thể là block_height hoặc block cũ hơn
@@ -2293,8 +2602,8 @@ impl ExecutionState for UdsExecutionState {
                 drop(last_sent_guard);
                 
                 // CRITICAL: Log để debug vấn đề "cứ 20 giao dịch là bị đứng"
-                info!("🔍 [UDS] DEBUG: consensus_index {} >= next_block_start_index {}, block.height={}, block_height={}, last_sent={:?}", 
-                    consensus_index, next_block_start_index, block.height, block_height, last_sent);
+                info!("🔍 [UDS] DEBUG: current_height {} > block_height {}, block.height={}, consensus_index={}, last_sent={:?}", 
+                    current_height, block_height, block.height, consensus_index, last_sent);
                 
                 // Chỉ gửi nếu block chưa được gửi
                 let should_send_block = if let Some(last_sent_val) = last_sent {
@@ -2303,14 +2612,8 @@ impl ExecutionState for UdsExecutionState {
                     true // Chưa gửi block nào
                 };
                 
-                // CRITICAL: Gửi block nếu:
-                // 1. block.height < block_height (block cũ chưa được gửi)
-                // 2. HOẶC block.height == block_height nhưng consensus_index đã vượt quá block_end_index
-                let block_end_index_for_current = (block.height + 1) * BLOCK_SIZE - 1;
-                let should_send = should_send_block && (
-                    block.height < block_height || 
-                    (block.height == block_height && consensus_index > block_end_index_for_current)
-                );
+                // CRITICAL: Gửi block nếu block.height < current_height (block cũ chưa được gửi)
+                let should_send = should_send_block && block.height < current_height;
                 
                 if should_send {
                     // Block cần được gửi → gửi ngay
@@ -2319,8 +2622,8 @@ impl ExecutionState for UdsExecutionState {
                     
                     let old_block_height = old_block.height;
                     let old_block_tx_count = old_block.transaction_entries.len();
-                    info!("📤 [UDS] Sending pending block {} with {} transactions (consensus_index {} >= next_block_start_index {}, block_height={}, block_end_index={})", 
-                        old_block_height, old_block_tx_count, consensus_index, next_block_start_index, block_height, block_end_index_for_current);
+                    info!("📤 [UDS] Sending pending block {} with {} transactions (current_height={}, block_height={}, consensus_index={})", 
+                        old_block_height, old_block_tx_count, current_height, block_height, consensus_index);
                     
                     // CRITICAL: Log để trace giao dịch trong block
                     for entry in &old_block.transaction_entries {
@@ -2330,7 +2633,7 @@ impl ExecutionState for UdsExecutionState {
                         }
                     }
                     
-                    let (block_to_send, tx_hash_map, batch_digests) = old_block.finalize();
+                    let (block_to_send, tx_hash_map, batch_digests) = old_block.finalize(*self.block_format.lock().await);
                     
                     // Atomic check-and-send
                     let mut last_sent_guard = self.last_sent_height.lock().await;
@@ -2351,6 +2654,8 @@ impl ExecutionState for UdsExecutionState {
                                     }
                                 }
                             }
+                            // Không gửi blocks sau block lỗi: chain phải liên tục
+                            return;
                         } else {
                             info!("✅ [UDS] Successfully sent pending block {} with {} transactions", 
                                 old_block_height, block_to_send.transactions.len());
@@ -2378,27 +2683,22 @@ impl ExecutionState for UdsExecutionState {
                             
                             // Update global_state
                             self.update_global_state().await;
-                            
-                            // Persist state after sending block successfully
-                            if let Err(e) = self.persist_execution_state().await {
-                                warn!("⚠️ [UDS] Failed to persist execution state after sending block {}: {}", block_to_send.height, e);
-                            }
                         }
                     } else {
                         warn!("⚠️ [UDS] Block {} already sent (last_sent_height check), skipping", old_block_height);
                     }
                 } else {
-                    debug!("⏳ [UDS] Block {} not ready to send yet (should_send_block={}, block.height={}, block_height={}, consensus_index={}, block_end_index={})", 
-                        block.height, should_send_block, block.height, block_height, consensus_index, block_end_index_for_current);
+                    debug!("⏳ [UDS] Block {} not ready to send yet (should_send_block={}, block.height={}, current_height={}, consensus_index={})", 
+                        block.height, should_send_block, block.height, current_height, consensus_index);
                 }
             } else {
-                warn!("⚠️ [UDS] No current block when consensus_index {} >= next_block_start_index {}", 
-                    consensus_index, next_block_start_index);
+                warn!("⚠️ [UDS] No current block when current_height {} > block_height {} (consensus_index={})", 
+                    current_height, block_height, consensus_index);
             }
         } else if has_transaction {
             // Log để debug: Block có transaction nhưng chưa được gửi (đợi certificate từ block tiếp theo)
-            debug!("⏳ [UDS] Block {} has transaction (ConsensusIndex={}) but waiting for certificate from next block (BlockEndIndex={}, NextBlockStartIndex={})", 
-                block_height, consensus_index, block_end_index, next_block_start_index);
+            debug!("⏳ [UDS] Block {} has transaction (ConsensusIndex={}) but waiting for certificate from next block", 
+                block_height, consensus_index);
         }
         
         // CRITICAL: Xử lý các blocks trước đó chưa được gửi (nếu có)
@@ -2410,54 +2710,50 @@ impl ExecutionState for UdsExecutionState {
         drop(last_sent_guard);
         
         // Gửi các blocks còn thiếu (fill gaps)
-        if let Some(last_sent_val) = last_sent {
-            for h in (last_sent_val + 1)..block_height {
-                if let Err(e) = self.send_empty_block(h).await {
-                    error!("❌ [UDS] Failed to send empty block {}: {}", h, e);
-                }
-            }
-        } else {
-            // Chưa gửi block nào → fill từ 0 đến block_height
-            for h in 0..block_height {
-                if let Err(e) = self.send_empty_block(h).await {
-                    error!("❌ [UDS] Failed to send empty block {}: {}", h, e);
-                }
+        // Height lỗi → không gửi blocks sau nó (kể cả flush bên dưới): chain phải liên tục
+        let first_missing = last_sent.map_or(0, |last_sent_val| last_sent_val + 1);
+        for h in first_missing..block_height {
+            if let Err(e) = self.send_empty_block(h).await {
+                error!("❌ [UDS] Failed to send empty block {}: {}", h, e);
+                return;
             }
         }
         
         // CRITICAL: Kiểm tra và gửi block hiện tại nếu cần
-        // Đảm bảo block hiện tại được gửi khi consensus_index đã vượt quá block_end_index
+        // Đảm bảo block hiện tại được gửi khi BlockFormer đã chuyển sang height sau
         // Điều này giải quyết vấn đề "cứ 20 giao dịch là bị đứng"
         self.flush_current_block_if_needed(consensus_index).await;
     }
 
     async fn load_execution_indices(&self) -> ExecutionIndices {
-        let last_consensus_index = {
-            let guard = self.last_consensus_index.lock().await;
-            *guard
-        };
+        // Consensus output được giao lại từ certificate đầu tiên của block chưa gửi đầu tiên
+        let resume_point = self.resume_point().await;
         
         ExecutionIndices {
-            next_certificate_index: last_consensus_index + 1,
+            next_certificate_index: resume_point.consensus_index,
             next_batch_index: 0,
             next_transaction_index: 0,
         }
     }
+
+    fn execution_backpressure(&self) -> Option<Arc<ExecutionBackpressure>> {
+        self.execution_backpressure.clone()
+    }
 }
 
 impl UdsExecutionState {
     /// Flush block hiện tại nếu cần thiết
-    /// Gửi block hiện tại khi consensus_index đã vượt quá block_end_index
+    /// Gửi block hiện tại khi BlockFormer đã gán consensus_index vào height sau
     /// Điều này đảm bảo block được gửi ngay cả khi không có certificate từ block tiếp theo
     /// 
     /// OPTIMIZED: Minimize lock scope để giảm contention
     async fn flush_current_block_if_needed(&self, consensus_index: u64) {
+        let current_height = self.block_former.lock().await.current_height();
         // OPTIMIZATION: Quick check với minimal lock time
         let (block_height, block_tx_count, should_flush) = {
             let current_block_guard = self.current_block.lock().await;
             if let Some(block) = current_block_guard.as_ref() {
-                let block_end_index = (block.height + 1) * BLOCK_SIZE - 1;
-                let should_flush = consensus_index > block_end_index;
+                let should_flush = current_height > block.height;
                 if should_flush {
                     (Some(block.height), Some(block.transaction_entries.len()), true)
                 } else {
@@ -2474,7 +2770,6 @@ impl UdsExecutionState {
         
         let block_height = block_height.unwrap();
         let block_tx_count = block_tx_count.unwrap();
-        let block_end_index = (block_height + 1) * BLOCK_SIZE - 1;
         
         // OPTIMIZATION: Check last_sent_height trước khi lock current_block lâu
         let last_sent = {
@@ -2492,8 +2787,8 @@ impl UdsExecutionState {
             return;
         }
         
-        info!("📤 [UDS] Flushing block {} with {} transactions (consensus_index {} > block_end_index {})", 
-            block_height, block_tx_count, consensus_index, block_end_index);
+        info!("📤 [UDS] Flushing block {} with {} transactions (consensus_index {} is in block {})", 
+            block_height, block_tx_count, consensus_index, current_height);
         
         // OPTIMIZATION: Lock current_block chỉ khi cần take block
         let (block_to_send, tx_hash_map, batch_digests, trace_hashes) = {
@@ -2508,7 +2803,7 @@ impl UdsExecutionState {
                 let old_block = current_block_guard.take().unwrap();
                 drop(current_block_guard);
                 
-                let (block_to_send, tx_hash_map, batch_digests) = old_block.finalize();
+                let (block_to_send, tx_hash_map, batch_digests) = old_block.finalize(*self.block_format.lock().await);
                 (block_to_send, tx_hash_map, batch_digests, trace_hashes)
             } else {
                 return; // Block đã được take bởi thread khác
@@ -2522,20 +2817,13 @@ impl UdsExecutionState {
         }
         
         // Atomic check-and-send
+        // last_sent_height chỉ tiến khi block đã gửi (send_block_with_retry): đặt trước sẽ làm block bị bỏ qua
         let final_should_send = {
-            let mut last_sent_guard = self.last_sent_height.lock().await;
-            let should_send = last_sent_guard.is_none() || 
-                block_to_send.height > last_sent_guard.unwrap();
-            if should_send {
-                *last_sent_guard = Some(block_to_send.height);
-            }
-            drop(last_sent_guard);
-            should_send
+            let last_sent_guard = self.last_sent_height.lock().await;
+            last_sent_guard.is_none() || block_to_send.height > last_sent_guard.unwrap()
         };
         
         if final_should_send {
-            // Update global_state
-            self.update_global_state().await;
             // OPTIMIZATION: Sử dụng trace_hashes đã collect trước đó thay vì loop lại
             match self.send_block_with_retry(block_to_send.clone(), tx_hash_map.clone(), batch_digests.clone()).await {
                 Err(e) => {
@@ -2561,84 +2849,188 @@ impl UdsExecutionState {
 }
 
 impl UdsExecutionState {
-    /// TỐI ƯU: Check và log missed batches (không retry phức tạp)
-    /// Chỉ phát hiện và log, không ảnh hưởng consensus
-    /// Không blocking - chỉ quick operations
-    async fn check_missed_batches(&self) {
-        let now = Instant::now();
-        let timeout_duration = Duration::from_millis(self.missed_batch_timeout_ms);
-        
-        // TỐI ƯU: Collect batches cần xử lý trước để tránh nested locks và borrow conflict
-        // Minimize lock time - chỉ lock một lần để collect data
-        let mut to_remove: Vec<BatchDigest> = Vec::new();
-        let mut to_log: Vec<(BatchDigest, u64, u64, u64)> = Vec::new(); // (digest, consensus_index, round, block_height)
-        
-        // Quick snapshot: Collect data với minimal lock time
-        {
-            let missed_guard = self.missed_batches.lock().await;
-            // Quick check: processed_batch_digests (read-only, fast)
-            let processed_guard = self.processed_batch_digests.lock().await;
-            
-            for (batch_digest, info) in missed_guard.iter() {
-                // Check xem batch đã được processed chưa (fast lookup)
-                if processed_guard.contains_key(batch_digest) {
-                    to_remove.push(*batch_digest);
-                    continue;
-                }
-                
-                // Check xem batch có bị missed không (đã quá timeout)
-                let elapsed = now.duration_since(info.commit_time);
-                if elapsed >= timeout_duration && info.retry_count == 0 {
-                    // Batch bị missed → sẽ log sau
-                    to_log.push((*batch_digest, info.consensus_index, info.round, info.block_height));
-                }
+    /// Chuyển sang epoch mới tại certificate đầu tiên của epoch đó
+    ///
+    /// FORK-SAFE: Mọi node chuyển epoch tại cùng vị trí trong chuỗi consensus output → cùng heights:
+    /// - Block đang mở của epoch cũ được gửi ngay (không chờ certificate của block tiếp theo)
+    /// - Heights của epoch mới tiếp nối height cuối cùng của epoch cũ: executor thấy một chuỗi liên tục,
+    ///   bảng epoch → starting height được persist cùng execution state
+    /// - Cửa sổ dedup batches/transactions theo consensus_index được reset cùng consensus_index
+    async fn start_epoch(&self, new_epoch: u64) {
+        let old_epoch = *self.epoch.lock().await;
+        let last_sent = *self.last_sent_height.lock().await;
+        let start_height = {
+            let mut block_former = self.block_former.lock().await;
+            let start_height = block_former
+                .next_height()
+                .max(last_sent.map_or(0, |height| height + 1));
+            block_former.start_epoch(start_height);
+            start_height
+        };
+
+        // Gửi block đang mở của epoch cũ (BlockFormer đã chuyển sang start_height)
+        let open_height = self.current_block.lock().await.as_ref().map(|block| block.height);
+        if let Some(open_height) = open_height {
+            let next_unsent = last_sent.map_or(0, |height| height + 1);
+            if let Err(e) = self.send_empty_blocks_for_gaps(next_unsent, open_height).await {
+                error!("❌ [UDS] Failed to send empty blocks before the last block of epoch {}: {}", old_epoch, e);
             }
-            drop(processed_guard);
         }
-        
-        // Fast remove: Batches đã processed
-        if !to_remove.is_empty() {
-            let mut missed_guard = self.missed_batches.lock().await;
-            for batch_digest in to_remove {
-                missed_guard.remove(&batch_digest);
+        self.flush_current_block_if_needed(0).await;
+
+        *self.epoch.lock().await = new_epoch;
+        self.epoch_start_heights.lock().await.insert(new_epoch, start_height);
+        *self.last_consensus_index.lock().await = 0;
+        *self.certificate_start.lock().await = ResumePoint {
+            consensus_index: 0,
+            block_cursor: self.block_former.lock().await.cursor().clone(),
+        };
+        self.unsent_blocks.lock().await.clear();
+        self.unsent_deliveries.lock().await.clear();
+        self.processed_batch_digests.lock().await.clear();
+        self.logged_duplicate_batches.lock().await.clear();
+        *self.processed_batches_watermark.lock().await = 0;
+        self.delivered_transaction_hashes.lock().await.clear();
+        info!("🔁 [UDS] Epoch {} → {}: blocks of epoch {} start at height {}", old_epoch, new_epoch, new_epoch, start_height);
+
+        self.update_global_state().await;
+        self.persist_epoch_start().await;
+    }
+
+    /// Height của block vừa mở bởi certificate đang xử lý: block chưa gửi, dựng lại từ resume point nếu restart
+    async fn track_unsent_block(&self, height: u64) {
+        let resume_point = self.certificate_start.lock().await.clone();
+        self.unsent_blocks.lock().await.entry(height).or_insert(resume_point);
+    }
+
+    /// Block `height` đã gửi: tiến last_sent_height và persist execution progress đến block này
+    /// CRITICAL: Progress chỉ tiến khi block đã gửi → restart dựng lại đúng các blocks chưa gửi (cùng bytes)
+    async fn record_sent_block(&self, height: u64) {
+        {
+            let mut last_sent_guard = self.last_sent_height.lock().await;
+            if last_sent_guard.map_or(true, |last_sent| height > last_sent) {
+                *last_sent_guard = Some(height);
             }
         }
-        
-        // Fast log: Missed batches và update retry_count
-        if !to_log.is_empty() {
-            let mut missed_guard = self.missed_batches.lock().await;
-            for (batch_digest, consensus_index, round, block_height) in to_log {
-                if let Some(info) = missed_guard.get(&batch_digest) {
-                    let elapsed = now.duration_since(info.commit_time);
-                    warn!("⚠️ [UDS] Missed batch detected: BatchDigest={:?}, ConsensusIndex={}, Round={}, BlockHeight={}, Elapsed={:?}ms", 
-                        batch_digest, consensus_index, round, block_height, elapsed.as_millis());
-                    // Update retry_count để chỉ log một lần
-                    if let Some(missed_info) = missed_guard.get_mut(&batch_digest) {
-                        missed_info.retry_count = 1;
-                    }
-                }
+        self.unsent_blocks.lock().await.retain(|unsent_height, _| *unsent_height > height);
+        self.update_global_state().await;
+        if let Err(e) = self.persist_execution_state().await {
+            error!("❌ [UDS] Failed to persist the execution progress after block {}: {}", height, e);
+        }
+    }
+
+    /// Persist progress của epoch mới và xóa dedup state (processed batches, transactions) của epoch trước trong cùng write batch
+    async fn persist_epoch_start(&self) {
+        if let Some(store) = &self.execution_progress_store {
+            let progress = self.execution_progress().await;
+            if let Err(e) = store.start_epoch(&progress) {
+                warn!("⚠️ [UDS] Failed to persist execution state at epoch {}: {}", progress.epoch, e);
             }
         }
-        
-        // TỐI ƯU: Lazy GC - chỉ khi cache quá lớn
+    }
+
+    /// Ghi nhận format của block `height` vừa gửi (chỉ khi format khác với các blocks trước nó)
+    async fn record_block_format(&self, height: u64, block_format: u32) {
+        let mut sent_block_formats = self.sent_block_formats.lock().await;
+        let previous = sent_block_formats.range(..=height).next_back().map(|(_, format)| *format);
+        if previous != Some(block_format) {
+            sent_block_formats.insert(height, block_format);
+        }
+    }
+
+    /// Format của block `height` đã gửi (blocks chưa gửi: format hiện tại)
+    async fn block_format_of_height(&self, height: u64) -> u32 {
+        let sent_format = self.sent_block_formats.lock().await.range(..=height).next_back().map(|(_, format)| *format);
+        match sent_format {
+            Some(block_format) => block_format,
+            None => *self.block_format.lock().await,
+        }
+    }
+
+    /// Epoch của block `height` theo bảng epoch → starting height
+    async fn epoch_of_height(&self, height: u64) -> u64 {
+        let epoch_start_heights = self.epoch_start_heights.lock().await;
+        let epoch = epoch_start_heights
+            .iter()
+            .filter(|(_, start_height)| **start_height <= height)
+            .map(|(epoch, _)| *epoch)
+            .max();
+        drop(epoch_start_heights);
+        match epoch {
+            Some(epoch) => epoch,
+            None => *self.epoch.lock().await,
+        }
+    }
+}
+
+impl UdsExecutionState {
+    /// Khôi phục processed batches và transactions đã deliver từ store khi khởi động
+    /// (trong cửa sổ gc trước next_consensus_index), trước khi replay
+    async fn restore_processed_batches(&self, next_consensus_index: u64) -> Result<(), String> {
+        let store = match &self.execution_progress_store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let watermark = next_consensus_index.saturating_sub(self.processed_batches_gc_depth);
+        let batches = store
+            .processed_batches()
+            .read_from(watermark)
+            .map_err(|e| format!("Failed to read the processed batches: {}", e))?;
+        let transactions = store
+            .delivered_transactions()
+            .read_from(watermark)
+            .map_err(|e| format!("Failed to read the delivered transactions: {}", e))?;
+        let (restored_batches, restored_transactions) = (batches.len(), transactions.len());
+        self.processed_batch_digests.lock().await.extend(batches);
+        self.delivered_transaction_hashes.lock().await.extend(transactions);
+        *self.processed_batches_watermark.lock().await = watermark;
+        info!("💾 [UDS] Restored {} processed batches and {} delivered transactions from consensus_index {}",
+            restored_batches, restored_transactions, watermark);
+        Ok(())
+    }
+
+    /// Prune processed batches và transactions đã deliver (memory + store) dưới watermark consensus_index - processed_batches_gc_depth
+    /// FORK-SAFE: Quyết định skip chỉ xét cửa sổ gc (xem handle_consensus_transaction) → thời điểm prune không ảnh hưởng
+    async fn prune_processed_batches(&self, consensus_index: u64) {
+        let watermark = consensus_index.saturating_sub(self.processed_batches_gc_depth);
         {
-            let mut missed_guard = self.missed_batches.lock().await;
-            const MAX_MISSED_BATCHES: usize = 5000;
-            if missed_guard.len() > MAX_MISSED_BATCHES {
-                // Fast GC: Sử dụng retain thay vì sort để tránh allocation
-                let target_size = MAX_MISSED_BATCHES / 2;
-                let current_size = missed_guard.len();
-                if current_size > target_size {
-                    // Remove entries cũ nhất (không sort - chỉ remove random entries để tránh overhead)
-                    let mut removed = 0;
-                    missed_guard.retain(|_, _| {
-                        removed += 1;
-                        removed <= target_size || (removed - target_size) % 2 == 0
-                    });
-                    debug!("🧹 [UDS] GC: Cleaned missed_batches from {} to {} entries", current_size, missed_guard.len());
-                }
+            let mut watermark_guard = self.processed_batches_watermark.lock().await;
+            if watermark < *watermark_guard + PROCESSED_BATCHES_PRUNE_STEP {
+                return;
+            }
+            *watermark_guard = watermark;
+        }
+
+        let (before_size, after_size) = {
+            let mut processed_batch_guard = self.processed_batch_digests.lock().await;
+            let before_size = processed_batch_guard.len();
+            processed_batch_guard.retain(|_, stored_index| *stored_index >= watermark);
+            (before_size, processed_batch_guard.len())
+        };
+        self.logged_duplicate_batches
+            .lock()
+            .await
+            .retain(|_, stored_index| *stored_index >= watermark);
+        self.delivered_transaction_hashes
+            .lock()
+            .await
+            .retain(|_, delivered_index| *delivered_index >= watermark);
+        self.unsent_deliveries
+            .lock()
+            .await
+            .retain(|delivery| delivery.consensus_index >= watermark);
+        if let Some(store) = &self.execution_progress_store {
+            if let Err(e) = store.prune_below(watermark) {
+                warn!("⚠️ [UDS] Failed to prune the dedup state below consensus_index {}: {}", watermark, e);
+            }
+        }
+        if let Some(status_store) = &self.transaction_status_store {
+            if let Err(e) = status_store.prune_committed_below(watermark) {
+                warn!("⚠️ [UDS] Failed to prune the committed transaction statuses below consensus_index {}: {}", watermark, e);
             }
         }
+        debug!("🧹 [UDS] GC: Cleaned {} old batch entries (watermark: {}, before: {}, after: {})",
+            before_size - after_size, watermark, before_size, after_size);
     }
 }
 
@@ -2651,7 +3043,7 @@ impl UdsExecutionState {
             if block.height == block_height {
                 // Block đúng height → gửi
                 drop(current_block_guard);
-                let (block_to_send, tx_hash_map, batch_digests) = block.finalize();
+                let (block_to_send, tx_hash_map, batch_digests) = block.finalize(*self.block_format.lock().await);
                 
                 // Atomic check-and-send
                 let mut last_sent_guard = self.last_sent_height.lock().await;
@@ -2703,9 +3095,10 @@ impl UdsExecutionState {
                         drop(last_sent_guard);
                         
                         if !block_to_send.transactions.is_empty() {
-                            info!("📤 [UDS] Sending block {} (consensus_index range {}): Epoch={}, TxCount={}", 
+                            let index_range = self.block_former.lock().await.index_range(block_to_send.height);
+                            info!("📤 [UDS] Sending block {} (consensus_index range {:?}): Epoch={}, TxCount={}", 
                                 block_to_send.height, 
-                                format!("{}-{}", block_to_send.height * BLOCK_SIZE, (block_to_send.height + 1) * BLOCK_SIZE - 1),
+                                index_range,
                                 block_to_send.epoch, 
                                 block_to_send.transactions.len());
                         }
@@ -2758,9 +3151,12 @@ impl UdsExecutionState {
     /// Gửi empty block cho một height cụ thể
     async fn send_empty_block(&self, height: u64) -> Result<(), String> {
         let empty_block = comm::CommittedBlock {
-            epoch: self.epoch,
+            epoch: self.epoch_of_height(height).await,
             height,
             transactions: Vec::new(),
+            header: None,
+            format_version: *self.block_format.lock().await,
+            entries: Vec::new(),
         };
         
         // Atomic check-and-send
@@ -2788,19 +3184,322 @@ impl UdsExecutionState {
     }
 
     async fn load_execution_indices(&self) -> ExecutionIndices {
-        let last_consensus_index = {
-            let guard = self.last_consensus_index.lock().await;
-            *guard
-        };
+        // Consensus output được giao lại từ certificate đầu tiên của block chưa gửi đầu tiên
+        let resume_point = self.resume_point().await;
         
         ExecutionIndices {
-            next_certificate_index: last_consensus_index + 1,
+            next_certificate_index: resume_point.consensus_index,
             next_batch_index: 0,
             next_transaction_index: 0,
         }
     }
 }
 
+impl UdsExecutionState {
+    /// Spawn background task phục vụ replay requests của executor (protocol v2)
+    /// This should be called after initialization
+    pub fn spawn_replay_task(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut rx_replay_requests = match self.rx_replay_requests.lock().await.take() {
+                Some(rx) => rx,
+                None => {
+                    warn!("⚠️ [UDS] Replay task already spawned");
+                    return;
+                }
+            };
+
+            while let Some(request) = rx_replay_requests.recv().await {
+                let (from_height, to_height) = (request.from_height, request.to_height);
+                let response = match self.rebuild_blocks(from_height, to_height).await {
+                    Ok(blocks) => {
+                        info!("🔄 [UDS] Rebuilt {} blocks for replay {}..={}", blocks.len(), from_height, to_height);
+                        comm::ReplayResponse {
+                            from_height,
+                            to_height,
+                            blocks,
+                            error: String::new(),
+                        }
+                    }
+                    Err(e) => {
+                        warn!("⚠️ [UDS] Cannot replay blocks {}..={}: {}", from_height, to_height, e);
+                        comm::ReplayResponse {
+                            from_height,
+                            to_height,
+                            blocks: Vec::new(),
+                            error: e,
+                        }
+                    }
+                };
+
+                let mut stream_guard = self.stream.lock().await;
+                if let Some(stream) = stream_guard.as_mut() {
+                    let message = uds_protocol::replay_response_message(response);
+                    if let Err(e) = uds_protocol::write_frame(stream, &message).await {
+                        warn!("⚠️ [UDS] Failed to send replay response {}..={}: {}", from_height, to_height, e);
+                        *stream_guard = None;
+                    }
+                } else {
+                    warn!("⚠️ [UDS] Dropping replay response {}..={}: not connected", from_height, to_height);
+                }
+            }
+        })
+    }
+
+    /// Dựng lại blocks [from_height, to_height] từ ConsensusStore + CertificateStore + batch store
+    ///
+    /// FORK-SAFE: Cùng quy tắc với handle_consensus_transaction:
+    /// - consensus_index i thuộc block i / certificates_per_block (block policy index-aligned)
+    /// - Batches theo thứ tự payload của certificate, transactions theo thứ tự trong batch
+    /// - Batch đã xử lý ở consensus_index khác → skip (duplicate)
+    /// - Transaction trùng hash trong cùng block → skip
+    /// - BlockBuilder::finalize sort theo (consensus_index, tx_hash_hex)
+    /// Chỉ replay blocks đã gửi (height <= last_sent_height) để block không bị thiếu certificates.
+    ///
+    /// NOTE: Với block policy không index-aligned (sub_dag hoặc có caps), height phụ thuộc vào
+    /// toàn bộ lịch sử nên blocks được đọc từ block archive thay vì dựng lại.
+    async fn rebuild_blocks(&self, from_height: u64, to_height: u64) -> Result<Vec<comm::CommittedBlock>, String> {
+        if from_height > to_height {
+            return Err(format!("Invalid replay range {}..={}", from_height, to_height));
+        }
+        if to_height - from_height + 1 > MAX_REPLAY_HEIGHTS {
+            return Err(format!("Replay range {}..={} exceeds {} blocks", from_height, to_height, MAX_REPLAY_HEIGHTS));
+        }
+        let last_sent = *self.last_sent_height.lock().await;
+        match last_sent {
+            Some(last_sent_val) if to_height <= last_sent_val => {}
+            _ => {
+                return Err(format!("Block {} has not been delivered yet (last_sent_height={:?})", to_height, last_sent));
+            }
+        }
+
+        // NOTE: Consensus store bị xóa khi chuyển epoch → blocks của các epochs trước đọc từ block archive
+        let (per_block, epoch_start_height) = {
+            let block_former = self.block_former.lock().await;
+            if block_former.is_index_aligned() {
+                (Some(block_former.certificates_per_block()), block_former.epoch_start_height())
+            } else {
+                (None, block_former.epoch_start_height())
+            }
+        };
+        let per_block = match per_block {
+            Some(per_block) if from_height >= epoch_start_height => per_block,
+            _ => return self.read_archived_blocks(from_height, to_height),
+        };
+
+        let consensus_store = self.consensus_store.as_ref()
+            .ok_or_else(|| "Consensus store not configured".to_string())?;
+        let certificate_store = self.certificate_store.as_ref()
+            .ok_or_else(|| "Certificate store not configured".to_string())?;
+        let batch_store = self.batch_store.as_ref()
+            .ok_or_else(|| "Batch store not configured".to_string())?;
+
+        let start_index = (from_height - epoch_start_height) * per_block;
+        let end_index = (to_height - epoch_start_height + 1) * per_block - 1;
+
+        let sequence = consensus_store
+            .read_sequenced_certificates(&(start_index..=end_index))
+            .map_err(|e| format!("Failed to read sequenced certificates: {}", e))?;
+        if sequence.len() as u64 != end_index - start_index + 1 {
+            return Err(format!("Consensus store has {} of {} certificates for consensus_index {}..={}",
+                sequence.len(), end_index - start_index + 1, start_index, end_index));
+        }
+        let certificates = certificate_store
+            .read_all(sequence.iter().map(|(_, digest)| *digest))
+            .map_err(|e| format!("Failed to read certificates: {}", e))?;
+
+        let processed_batches = self.processed_batch_digests.lock().await.clone();
+        let mut seen_batches: HashMap<BatchDigest, u64> = HashMap::new();
+        // Transactions đã deliver trước replay window (các transactions trong window được thêm lại khi dựng blocks)
+        // NOTE: Sau restart, delivered_transaction_hashes được khôi phục từ store trong initialize (trước replay)
+        let mut delivered_transactions: HashMap<Vec<u8>, u64> = self
+            .delivered_transaction_hashes
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, index)| **index < start_index)
+            .map(|(hash, index)| (hash.clone(), *index))
+            .collect();
+        let epoch = *self.epoch.lock().await;
+        let mut builders: Vec<BlockBuilder> = (from_height..=to_height)
+            .map(|height| BlockBuilder::new(epoch, height))
+            .collect();
+
+        for ((consensus_index, digest), certificate) in sequence.iter().zip(certificates) {
+            let consensus_index = *consensus_index;
+            let certificate = certificate
+                .ok_or_else(|| format!("Certificate {:?} (consensus_index {}) not found", digest, consensus_index))?;
+            let builder = &mut builders[(epoch_start_height + consensus_index / per_block - from_height) as usize];
+            // Cùng leader round với ConsensusOutput đã gửi cho handle_consensus_transaction
+            let leader_round = consensus_store
+                .read_committed_sub_dag(consensus_index)
+                .map_err(|e| format!("Failed to read the sub-dag of consensus_index {}: {}", consensus_index, e))?
+                .map_or(certificate.round(), |sub_dag| sub_dag.leader_round);
+            if certificate.header.payload.is_empty() {
+                builder.record_certificate(consensus_index, leader_round);
+            }
+
+            for (batch_digest, worker_id) in certificate.header.payload.iter() {
+                // Batch đã xử lý ở consensus_index trước đó (trong hoặc trước replay window) → skip
+                let earlier = seen_batches.get(batch_digest).copied()
+                    .or_else(|| processed_batches.get(batch_digest).copied().filter(|i| {
+                        *i < start_index && *i >= consensus_index.saturating_sub(self.processed_batches_gc_depth)
+                    }));
+                if matches!(earlier, Some(index) if index != consensus_index) {
+                    continue;
+                }
+                seen_batches.insert(*batch_digest, consensus_index);
+                builder.record_certificate(consensus_index, leader_round);
+
+                let batch = batch_store
+                    .read(*batch_digest)
+                    .await
+                    .map_err(|e| format!("Failed to read batch {}: {}", batch_digest, e))?
+                    .ok_or_else(|| format!("Batch {} (consensus_index {}) not available", batch_digest, consensus_index))?;
+                for transaction in batch.0.iter() {
+                    builder.push_parsed_transactions(
+                        consensus_index,
+                        *worker_id,
+                        *batch_digest,
+                        *digest,
+                        parse_transactions_from_bytes(transaction),
+                        &mut delivered_transactions,
+                        self.processed_batches_gc_depth,
+                    );
+                }
+            }
+        }
+
+        // Nối blocks dựng lại vào block trước from_height để có cùng hash với blocks đã gửi
+        let mut parent = self.parent_of(from_height).await?;
+        if parent.is_none() && from_height > 0 {
+            return Err(format!("Parent of block {} is not available to link the rebuilt blocks", from_height));
+        }
+        let mut blocks = Vec::with_capacity(builders.len());
+        for builder in &builders {
+            // Cùng format với block đã gửi (format có thể đổi khi executor reconnect)
+            let mut block = builder.finalize(self.block_format_of_height(builder.height).await).0;
+            block_header::link(&mut block, parent.as_ref());
+            parent = Some(ChainTip::of(&block));
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    /// Đọc blocks [from_height, to_height] từ block archive (block policy không index-aligned)
+    fn read_archived_blocks(&self, from_height: u64, to_height: u64) -> Result<Vec<comm::CommittedBlock>, String> {
+        let archive = self.block_archive.as_ref()
+            .ok_or_else(|| "Block archive not configured: blocks of a stateful block policy cannot be rebuilt".to_string())?;
+        (from_height..=to_height)
+            .map(|height| {
+                archive
+                    .read_block(height)
+                    .map_err(|e| format!("Failed to read archived block {}: {}", height, e))?
+                    .ok_or_else(|| format!("Block {} is not in the block archive", height))
+            })
+            .collect()
+    }
+}
+
+/// FORK-SAFE: Transaction đã được đưa vào block ở consensus_index trong cửa sổ `window` trước `consensus_index`
+/// Entries cũ hơn cửa sổ bị bỏ qua dù chưa bị GC → kết quả không phụ thuộc thời điểm GC
+fn delivered_within_window(
+    delivered: &HashMap<Vec<u8>, u64>,
+    tx_hash: &[u8],
+    consensus_index: u64,
+    window: u64,
+) -> bool {
+    matches!(delivered.get(tx_hash), Some(index) if *index >= consensus_index.saturating_sub(window))
+}
+
+/// Tính hash của tất cả transactions trong block theo thứ tự (format v1: digest là Transactions wrapper)
+fn block_transaction_hashes(block: &comm::CommittedBlock) -> Vec<Vec<u8>> {
+    if block.format_version >= BLOCK_FORMAT_V2 {
+        return block.entries.iter().map(|entry| entry.hash.to_vec()).collect();
+    }
+    let mut hashes = Vec::new();
+    for tx in &block.transactions {
+        match transaction::Transactions::decode(tx.digest.as_ref()) {
+            Ok(wrapper) if !wrapper.transactions.is_empty() => {
+                hashes.extend(wrapper.transactions.iter().map(calculate_transaction_hash_from_proto));
+            }
+            _ => {
+                hashes.extend(parse_transactions_from_bytes(tx.digest.as_ref()).into_iter().map(|(_, hash, _, _)| hash));
+            }
+        }
+    }
+    hashes
+}
+
+impl UdsExecutionState {
+    /// Parent của block `height`: chain tip nếu là block height - 1, nếu không thì block height - 1
+    /// trong archive (sau restart). None cho block đầu tiên hoặc khi parent không còn
+    /// Err khi không đọc được parent trong archive: nối block như block đầu tiên sẽ đổi block hash
+    async fn parent_of(&self, height: u64) -> Result<Option<ChainTip>, String> {
+        if height == 0 {
+            return Ok(None);
+        }
+        if let Some(tip) = self.chain_tip.lock().await.as_ref() {
+            if tip.height + 1 == height {
+                return Ok(Some(tip.clone()));
+            }
+        }
+        let archive = match &self.block_archive {
+            Some(archive) => archive,
+            None => return Ok(None),
+        };
+        let parent = archive
+            .read_block(height - 1)
+            .map_err(|e| format!("Failed to read parent block {} from archive: {}", height - 1, e))?;
+        Ok(parent.as_ref().map(ChainTip::of))
+    }
+
+    /// Nối block vào parent (parent_hash, range consensus_index của block rỗng) và cập nhật chain tip
+    async fn link_block(&self, block: &mut comm::CommittedBlock) -> Result<(), String> {
+        let parent = self.parent_of(block.height).await?;
+        if parent.is_none() && block.height > 0 {
+            warn!("⚠️ [UDS] Parent of block {} not found (chain tip, archive): linking it as the first block", block.height);
+        }
+        block_header::link(block, parent.as_ref());
+
+        let mut chain_tip_guard = self.chain_tip.lock().await;
+        if chain_tip_guard.as_ref().map_or(true, |tip| block.height > tip.height) {
+            *chain_tip_guard = Some(ChainTip::of(block));
+        }
+        Ok(())
+    }
+
+    /// Ghi block vào persistent archive và prune theo retention
+    /// CRITICAL: Err → block không được gửi (replay và block sinks đọc blocks đã gửi từ archive)
+    fn archive_block(&self, block: &comm::CommittedBlock) -> Result<(), String> {
+        let archive = match &self.block_archive {
+            Some(archive) => archive,
+            None => return Ok(()),
+        };
+
+        let tx_hashes = block_transaction_hashes(block);
+        archive
+            .write_block(block, &tx_hashes)
+            .map_err(|e| format!("Failed to archive block {}: {}", block.height, e))?;
+        uds_debug!("💾 [UDS] Archived block {} with {} transactions", block.height, tx_hashes.len());
+        if let Some(block_sinks) = &self.block_sinks {
+            block_sinks.notify(block.height);
+        }
+
+        if self.archive_retention_blocks > 0
+            && block.height % self.archive_prune_interval == 0
+            && block.height >= self.archive_retention_blocks
+        {
+            let prune_below = block.height + 1 - self.archive_retention_blocks;
+            match archive.prune_below(prune_below, block_transaction_hashes) {
+                Ok(0) => {}
+                Ok(pruned) => info!("🧹 [UDS] Pruned {} archived blocks below height {}", pruned, prune_below),
+                Err(e) => warn!("⚠️ [UDS] Failed to prune block archive below height {}: {}", prune_below, e),
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Simple execution state for testing/fallback (sends transactions to channel)
 pub struct SimpleExecutionState {
     tx_confirmation: tokio::sync::mpsc::Sender<u64>,
@@ -2831,3 +3530,729 @@ impl ExecutionState for SimpleExecutionState {
         ExecutionIndices::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds_executor::{StandInConfig, StandInExecutor, StandInHandle};
+    use crate::uds_protocol::{ack_message, hello_message, read_frame, write_frame};
+    use comm::{node_message, NodeMessage};
+    use tempfile::TempDir;
+    use tokio::net::{unix::OwnedReadHalf, UnixListener};
+
+    fn empty_block(height: u64) -> comm::CommittedBlock {
+        comm::CommittedBlock {
+            epoch: 0,
+            height,
+            transactions: Vec::new(),
+            header: None,
+            format_version: BLOCK_FORMAT_V1,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Đọc một NodeMessage và trả về các block heights trong đó
+    async fn read_heights(reader: &mut OwnedReadHalf) -> Vec<u64> {
+        let message: NodeMessage = read_frame(reader).await.unwrap().expect("connection closed");
+        match message.payload {
+            Some(node_message::Payload::Blocks(data)) => {
+                data.blocks.iter().map(|b| b.height).collect()
+            }
+            other => panic!("unexpected payload: {:?}", other),
+        }
+    }
+
+    fn parsed(tx_hash: u8) -> (String, Vec<u8>, Option<transaction::Transaction>, Vec<u8>) {
+        (hex::encode([tx_hash]), vec![tx_hash], None, vec![tx_hash])
+    }
+
+    /// Consensus output của certificate `consensus_index` với một batch: Transactions wrapper gồm `tx_count` transactions
+    fn certificate_output(
+        fixture: &test_utils::CommitteeFixture,
+        consensus_index: u64,
+        tx_count: u8,
+    ) -> (ConsensusOutput, Vec<u8>) {
+        let transactions = transaction::Transactions {
+            transactions: (0..tx_count)
+                .map(|i| transaction::Transaction {
+                    nonce: vec![consensus_index as u8, i].into(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        let batch_entry = transactions.encode_to_vec();
+        let authority = fixture.authorities().next().unwrap();
+        let header = authority
+            .header_builder(&fixture.committee())
+            .round(consensus_index + 1)
+            .with_payload_batch(Batch(vec![batch_entry.clone()]), 0)
+            .build(authority.keypair())
+            .unwrap();
+        (ConsensusOutput::standalone(fixture.certificate(&header), consensus_index), batch_entry)
+    }
+
+    /// Giao consensus output cho execution state như notifier
+    async fn hand_over(state: &UdsExecutionState, outputs: &[(ConsensusOutput, Vec<u8>)]) {
+        for (output, batch_entry) in outputs {
+            let execution_indices = ExecutionIndices {
+                next_certificate_index: output.consensus_index + 1,
+                next_batch_index: 1,
+                next_transaction_index: 1,
+            };
+            state.handle_consensus_transaction(output, execution_indices, batch_entry.clone()).await;
+        }
+    }
+
+    /// Execution state giao blocks qua protocol v2, persist progress và archive vào `storage`
+    fn stored_execution_state(socket_path: &std::path::Path, storage: &crate::NodeStorage, policy: &BlockPolicy) -> UdsExecutionState {
+        UdsExecutionState::new_with_retry(socket_path.to_string_lossy().to_string(), 0, 100, 1, 10)
+            .with_protocol_version(UDS_PROTOCOL_V2, Duration::from_millis(300))
+            .with_block_policy(policy.clone())
+            .with_execution_progress_store(storage.execution_progress_store.clone())
+            .with_block_archive(storage.block_archive.clone(), 0, 1)
+    }
+
+    /// Như stored_execution_state, với các stores để dựng lại blocks cho replay requests
+    fn replaying_execution_state(socket_path: &std::path::Path, storage: &crate::NodeStorage, policy: &BlockPolicy) -> UdsExecutionState {
+        UdsExecutionState::new_with_state_and_stores(
+            socket_path.to_string_lossy().to_string(),
+            0,
+            100,
+            1,
+            10,
+            None,
+            Some(storage.consensus_store.clone()),
+            Some(storage.certificate_store.clone()),
+            None,
+        )
+        .with_protocol_version(UDS_PROTOCOL_V2, Duration::from_millis(300))
+        .with_block_policy(policy.clone())
+        .with_execution_progress_store(storage.execution_progress_store.clone())
+        .with_block_archive(storage.block_archive.clone(), 0, 1)
+        .with_batch_store(storage.batch_store.clone())
+    }
+
+    fn encoded_blocks(blocks: &[comm::CommittedBlock]) -> Vec<Vec<u8>> {
+        blocks.iter().map(|block| block.encode_to_vec()).collect()
+    }
+
+    /// Ghi consensus output vào stores như consensus và workers (trước khi giao cho execution state)
+    async fn sequence_outputs(storage: &crate::NodeStorage, outputs: &[(ConsensusOutput, Vec<u8>)]) {
+        for (output, batch_entry) in outputs {
+            let certificate = &output.certificate;
+            storage.certificate_store.write(certificate.clone()).unwrap();
+            storage
+                .consensus_store
+                .write_consensus_state(&HashMap::new(), &output.consensus_index, &certificate.digest(), None, None)
+                .unwrap();
+            let batch = Batch(vec![batch_entry.clone()]);
+            storage.batch_store.write(batch.digest(), batch).await;
+        }
+    }
+
+    #[test]
+    fn cross_batch_duplicates_are_delivered_once() {
+        let mut delivered = HashMap::new();
+        let mut first = BlockBuilder::new(0, 0);
+        first.push_parsed_transactions(
+            0,
+            0,
+            BatchDigest::default(),
+            CertificateDigest::default(),
+            vec![parsed(1), parsed(2)],
+            &mut delivered,
+            10,
+        );
+        // Same transactions in a batch of another certificate: only the new one is delivered.
+        let mut second = BlockBuilder::new(0, 1);
+        second.push_parsed_transactions(
+            5,
+            1,
+            BatchDigest::new([1; 32]),
+            CertificateDigest::default(),
+            vec![parsed(2), parsed(3)],
+            &mut delivered,
+            10,
+        );
+        assert_eq!(first.transaction_entries.len(), 2);
+        assert_eq!(second.transaction_entries.len(), 1);
+        assert_eq!(
+            second.transaction_entries[0].tx_hash_hex,
+            hex::encode([3u8])
+        );
+
+        // Outside the window the transaction is delivered again.
+        let mut third = BlockBuilder::new(0, 2);
+        third.push_parsed_transactions(
+            16,
+            0,
+            BatchDigest::new([2; 32]),
+            CertificateDigest::default(),
+            vec![parsed(2)],
+            &mut delivered,
+            10,
+        );
+        assert_eq!(third.transaction_entries.len(), 1);
+    }
+
+    #[test]
+    fn finalized_header_covers_recorded_certificates() {
+        let mut block = BlockBuilder::new(0, 3);
+        block.record_certificate(7, 4);
+        block.record_certificate(9, 6);
+        // Further batches of an earlier certificate do not change the leader round.
+        block.record_certificate(8, 4);
+
+        let header = block.finalize(BLOCK_FORMAT_V1).0.header.unwrap();
+        assert_eq!(
+            (header.first_consensus_index, header.end_consensus_index),
+            (7, 10)
+        );
+        assert_eq!(header.leader_round, 6);
+        assert_eq!(
+            header.transactions_root.as_ref(),
+            block_header::transactions_root(&[]).as_slice()
+        );
+        // The parent is linked when the block is sent.
+        assert!(header.parent_hash.is_empty());
+    }
+
+    #[test]
+    fn typed_block_carries_transaction_provenance() {
+        let mut delivered = HashMap::new();
+        let mut block = BlockBuilder::new(0, 1);
+        let certificate_digest = CertificateDigest::new([7; 32]);
+        block.push_parsed_transactions(
+            4,
+            1,
+            BatchDigest::new([1; 32]),
+            certificate_digest,
+            vec![parsed(2)],
+            &mut delivered,
+            10,
+        );
+        block.push_parsed_transactions(
+            3,
+            0,
+            BatchDigest::new([2; 32]),
+            CertificateDigest::new([8; 32]),
+            vec![parsed(1)],
+            &mut delivered,
+            10,
+        );
+
+        let typed = block.finalize(BLOCK_FORMAT_V2).0;
+        assert_eq!(typed.format_version, BLOCK_FORMAT_V2);
+        assert!(typed.transactions.is_empty());
+
+        // One entry per transaction, in consensus order, with its provenance.
+        let entries = &typed.entries;
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].consensus_index, entries[0].worker_id), (3, 0));
+        assert_eq!(entries[0].transaction.as_ref(), [1u8].as_slice());
+        assert_eq!(entries[0].batch_digest.as_ref(), [2u8; 32].as_slice());
+        assert_eq!((entries[1].consensus_index, entries[1].worker_id), (4, 1));
+        assert_eq!(entries[1].certificate_digest.as_ref(), certificate_digest.as_ref());
+
+        // The hashes are carried as is and cover the transactions root.
+        let hashes = block_transaction_hashes(&typed);
+        assert_eq!(hashes, vec![vec![1u8], vec![2u8]]);
+        assert_eq!(
+            typed.header.unwrap().transactions_root.as_ref(),
+            block_header::transactions_root(&hashes).as_slice()
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_blocks_carry_the_epoch_of_their_height() {
+        let state =
+            UdsExecutionState::new_with_retry("/nonexistent.sock".to_string(), 0, 100, 1, 10);
+        *state.epoch_start_heights.lock().await = BTreeMap::from([(0, 0), (1, 12), (3, 40)]);
+        *state.epoch.lock().await = 3;
+
+        assert_eq!(state.epoch_of_height(0).await, 0);
+        assert_eq!(state.epoch_of_height(11).await, 0);
+        assert_eq!(state.epoch_of_height(12).await, 1);
+        assert_eq!(state.epoch_of_height(39).await, 1);
+        assert_eq!(state.epoch_of_height(40).await, 3);
+        assert_eq!(state.epoch_of_height(100).await, 3);
+    }
+
+    #[tokio::test]
+    async fn initialize_restores_the_execution_progress() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = crate::NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+        let progress = ExecutionProgress {
+            next_consensus_index: 550,
+            last_sent_height: Some(54),
+            ..ExecutionProgress::default()
+        };
+        let delivery = |consensus_index, batch_digest, tx_hash| Delivery {
+            consensus_index,
+            batch_digest,
+            tx_hashes: vec![tx_hash],
+        };
+        store
+            .record_sent_block(
+                &progress,
+                &[
+                    delivery(5, Some(BatchDigest::new([1; 32])), vec![1; 32]),
+                    delivery(500, Some(BatchDigest::new([2; 32])), vec![2; 32]),
+                    delivery(501, None, vec![3; 32]),
+                ],
+            )
+            .unwrap();
+
+        let execution_state = |epoch| {
+            UdsExecutionState::new_with_retry("/nonexistent.sock".to_string(), epoch, 100, 1, 10)
+                .with_block_policy(BlockPolicy {
+                    processed_batches_gc_depth: 100,
+                    ..BlockPolicy::default()
+                })
+                .with_execution_progress_store(store.clone())
+        };
+        let state = execution_state(0);
+        state.initialize().await.unwrap();
+        assert_eq!(*state.last_consensus_index.lock().await, 549);
+        assert_eq!(state.load_execution_indices().await.next_certificate_index, 550);
+        assert_eq!(*state.last_sent_height.lock().await, Some(54));
+        // Only the batches within the gc window are remembered
+        assert_eq!(
+            *state.processed_batch_digests.lock().await,
+            HashMap::from([(BatchDigest::new([2; 32]), 500)])
+        );
+        assert_eq!(
+            *state.delivered_transaction_hashes.lock().await,
+            HashMap::from([(vec![2; 32], 500), (vec![3; 32], 501)])
+        );
+
+        // Consensus indices restart at a new epoch: the batches of the previous epoch are dropped
+        let state = execution_state(1);
+        state.initialize().await.unwrap();
+        assert_eq!(*state.last_consensus_index.lock().await, 0);
+        assert!(state.processed_batch_digests.lock().await.is_empty());
+        assert!(state.delivered_transaction_hashes.lock().await.is_empty());
+        let stored = store.read().unwrap().unwrap();
+        assert_eq!((stored.epoch, stored.next_consensus_index), (1, 0));
+        assert_eq!(stored.epoch_start_heights.get(&1), Some(&55));
+        assert!(store.processed_batches().read_from(0).unwrap().is_empty());
+        assert!(store.delivered_transactions().read_from(0).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn restart_in_the_middle_of_a_block_delivers_the_same_blocks() {
+        let fixture = test_utils::CommitteeFixture::builder().build();
+        let outputs: Vec<_> = (0..8).map(|i| certificate_output(&fixture, i, 3)).collect();
+        // (block policy, số certificates giao trước khi crash)
+        let scenarios = [
+            // Crash khi block 1 (certificates 2, 3) mới có certificate 2
+            (
+                BlockPolicy {
+                    certificates_per_block: 2,
+                    ..BlockPolicy::default()
+                },
+                3,
+            ),
+            // Caps tách certificate 1 qua block 1 (đã gửi) và block 2 (chưa gửi)
+            (
+                BlockPolicy {
+                    certificates_per_block: 3,
+                    max_transactions: 2,
+                    ..BlockPolicy::default()
+                },
+                2,
+            ),
+        ];
+
+        for (policy, crash_after) in scenarios {
+            let temp_dir = TempDir::new().unwrap();
+            let stand_in_config = |name: &str| StandInConfig {
+                socket_path: temp_dir.path().join(name),
+                protocol_version: UDS_PROTOCOL_V2,
+                segments_dir: None,
+                first_height: Some(0),
+            };
+
+            // Không restart
+            let expected = StandInExecutor::spawn(stand_in_config("expected.sock")).unwrap();
+            let storage = crate::NodeStorage::reopen(temp_dir.path().join("expected"));
+            let state = stored_execution_state(&temp_dir.path().join("expected.sock"), &storage, &policy);
+            state.initialize().await.unwrap();
+            hand_over(&state, &outputs).await;
+
+            // Crash sau `crash_after` certificates: block đang mở bị mất, replay từ execution indices đã persist
+            let restarted = StandInExecutor::spawn(stand_in_config("restarted.sock")).unwrap();
+            let storage = crate::NodeStorage::reopen(temp_dir.path().join("restarted"));
+            let state = stored_execution_state(&temp_dir.path().join("restarted.sock"), &storage, &policy);
+            state.initialize().await.unwrap();
+            hand_over(&state, &outputs[..crash_after]).await;
+            let sent_before_crash = restarted.blocks().len();
+            assert!(sent_before_crash > 0);
+            drop(state);
+
+            let state = stored_execution_state(&temp_dir.path().join("restarted.sock"), &storage, &policy);
+            state.initialize().await.unwrap();
+            let next_certificate_index = state.load_execution_indices().await.next_certificate_index;
+            assert!(next_certificate_index < crash_after as u64);
+            hand_over(&state, &outputs[next_certificate_index as usize..]).await;
+
+            let bytes = |stand_in: &StandInHandle| stand_in.blocks().into_iter().map(|b| (b.height, b.bytes)).collect::<Vec<_>>();
+            assert!(expected.blocks().len() > sent_before_crash);
+            assert_eq!(bytes(&restarted), bytes(&expected));
+            assert!(expected.violations().is_empty());
+            assert!(restarted.violations().is_empty());
+            expected.stop();
+            restarted.stop();
+        }
+    }
+
+    #[tokio::test]
+    async fn rebuilt_blocks_match_the_delivered_blocks() {
+        let fixture = test_utils::CommitteeFixture::builder().build();
+        let outputs: Vec<_> = (0..8).map(|i| certificate_output(&fixture, i, 3)).collect();
+        let policies = [
+            // Index-aligned: blocks dựng lại từ consensus store, certificate store và batch store
+            BlockPolicy {
+                certificates_per_block: 2,
+                ..BlockPolicy::default()
+            },
+            // Caps: blocks đọc từ block archive
+            BlockPolicy {
+                certificates_per_block: 3,
+                max_transactions: 2,
+                ..BlockPolicy::default()
+            },
+        ];
+        let delivered = |stand_in: &StandInHandle| stand_in.blocks().into_iter().map(|b| b.bytes).collect::<Vec<_>>();
+
+        for policy in policies {
+            let temp_dir = TempDir::new().unwrap();
+            let socket_path = temp_dir.path().join("executor.sock");
+            let stand_in = StandInExecutor::spawn(StandInConfig {
+                socket_path: socket_path.clone(),
+                protocol_version: UDS_PROTOCOL_V2,
+                segments_dir: None,
+                first_height: Some(0),
+            })
+            .unwrap();
+            let storage = crate::NodeStorage::reopen(temp_dir.path().join("storage"));
+            sequence_outputs(&storage, &outputs).await;
+
+            let state = replaying_execution_state(&socket_path, &storage, &policy);
+            state.initialize().await.unwrap();
+            hand_over(&state, &outputs[..5]).await;
+            let last_sent = state.last_sent_height.lock().await.expect("no block sent");
+            assert_eq!(encoded_blocks(&state.rebuild_blocks(0, last_sent).await.unwrap()), delivered(&stand_in));
+            // Block chưa gửi không được replay
+            assert!(state.rebuild_blocks(0, last_sent + 1).await.is_err());
+            drop(state);
+
+            // Sau restart: blocks đã gửi trước restart, rồi cả các blocks gửi sau restart
+            let state = replaying_execution_state(&socket_path, &storage, &policy);
+            state.initialize().await.unwrap();
+            assert_eq!(encoded_blocks(&state.rebuild_blocks(0, last_sent).await.unwrap()), delivered(&stand_in));
+            let next_certificate_index = state.load_execution_indices().await.next_certificate_index;
+            hand_over(&state, &outputs[next_certificate_index as usize..]).await;
+            let last_sent_after_restart = state.last_sent_height.lock().await.unwrap();
+            assert!(last_sent_after_restart > last_sent);
+            assert_eq!(
+                encoded_blocks(&state.rebuild_blocks(0, last_sent_after_restart).await.unwrap()),
+                delivered(&stand_in)
+            );
+            assert!(stand_in.violations().is_empty());
+            stand_in.stop();
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_request_is_answered_on_the_socket() {
+        let fixture = test_utils::CommitteeFixture::builder().build();
+        let outputs: Vec<_> = (0..5).map(|i| certificate_output(&fixture, i, 3)).collect();
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("executor.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake executor: ACK blocks 0 và 1, rồi trả lại connection để gửi replay request
+        let executor = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.into_split();
+            write_frame(&mut writer, &hello_message(vec![BLOCK_FORMAT_V1])).await.unwrap();
+            let mut blocks = Vec::new();
+            while blocks.len() < 2 {
+                let message: NodeMessage = read_frame(&mut reader).await.unwrap().unwrap();
+                match message.payload {
+                    Some(node_message::Payload::Blocks(data)) => {
+                        for block in data.blocks {
+                            write_frame(&mut writer, &ack_message(block.height, true, String::new())).await.unwrap();
+                            blocks.push(block);
+                        }
+                    }
+                    other => panic!("unexpected payload: {:?}", other),
+                }
+            }
+            (reader, writer, blocks)
+        });
+
+        let storage = crate::NodeStorage::reopen(temp_dir.path().join("storage"));
+        sequence_outputs(&storage, &outputs).await;
+        let policy = BlockPolicy {
+            certificates_per_block: 2,
+            ..BlockPolicy::default()
+        };
+        let state = Arc::new(replaying_execution_state(&socket_path, &storage, &policy));
+        state.initialize().await.unwrap();
+        let _replay_task = state.clone().spawn_replay_task();
+        hand_over(&state, &outputs).await;
+        let (mut reader, mut writer, blocks) = executor.await.unwrap();
+        assert_eq!(*state.last_sent_height.lock().await, Some(1));
+
+        let replay = |from_height, to_height| uds_protocol::replay_request_message(from_height, to_height);
+        write_frame(&mut writer, &replay(0, 1)).await.unwrap();
+        let response = match read_frame::<_, NodeMessage>(&mut reader).await.unwrap().unwrap().payload {
+            Some(node_message::Payload::Replay(response)) => response,
+            other => panic!("unexpected payload: {:?}", other),
+        };
+        assert_eq!((response.from_height, response.to_height), (0, 1));
+        assert!(response.error.is_empty());
+        assert_eq!(encoded_blocks(&response.blocks), encoded_blocks(&blocks));
+
+        // Block 2 chưa gửi → replay bị từ chối
+        write_frame(&mut writer, &replay(1, 2)).await.unwrap();
+        let response = match read_frame::<_, NodeMessage>(&mut reader).await.unwrap().unwrap().payload {
+            Some(node_message::Payload::Replay(response)) => response,
+            other => panic!("unexpected payload: {:?}", other),
+        };
+        assert!(response.blocks.is_empty());
+        assert!(!response.error.is_empty());
+    }
+
+    #[tokio::test]
+    async fn acked_delivery_survives_socket_drop() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("executor.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake executor:
+        // - connection 1: ACK block 0, nhận block 1 rồi đóng socket mà KHÔNG ACK
+        // - connection 2: ACK mọi block nhận được
+        let executor = tokio::spawn(async move {
+            let mut received = Vec::new();
+
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.into_split();
+            for height in read_heights(&mut reader).await {
+                received.push(height);
+                write_frame(&mut writer, &ack_message(height, true, String::new()))
+                    .await
+                    .unwrap();
+            }
+            received.extend(read_heights(&mut reader).await);
+            drop(reader);
+            drop(writer);
+
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.into_split();
+            for _ in 0..2 {
+                for height in read_heights(&mut reader).await {
+                    received.push(height);
+                    write_frame(&mut writer, &ack_message(height, true, String::new()))
+                        .await
+                        .unwrap();
+                }
+            }
+            received
+        });
+
+        let state = UdsExecutionState::new_with_retry(
+            socket_path.to_string_lossy().to_string(),
+            0,
+            100,
+            /* max_send_retries */ 1,
+            10,
+        )
+        .with_protocol_version(UDS_PROTOCOL_V2, Duration::from_millis(300));
+
+        state
+            .send_block_with_retry(empty_block(0), HashMap::new(), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(state.last_confirmed_height().await, Some(0));
+
+        // Executor đóng socket trước khi ACK → block 1 được gửi lại trên connection mới
+        state
+            .send_block_with_retry(empty_block(1), HashMap::new(), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(state.last_confirmed_height().await, Some(1));
+
+        state
+            .send_block_with_retry(empty_block(2), HashMap::new(), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(state.last_confirmed_height().await, Some(2));
+        assert_eq!(state.ack_tracker.unacked_count().await, 0);
+
+        let received = executor.await.unwrap();
+        assert_eq!(received, vec![0, 1, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn nacked_block_is_retried_until_accepted() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("executor.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake executor: NACK block 3 lần rồi ACK
+        let executor = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.into_split();
+            let mut received = Vec::new();
+            for attempt in 0..4 {
+                for height in read_heights(&mut reader).await {
+                    received.push(height);
+                    write_frame(&mut writer, &ack_message(height, attempt == 3, "not ready".to_string()))
+                        .await
+                        .unwrap();
+                }
+            }
+            received
+        });
+
+        let state = UdsExecutionState::new_with_retry(
+            socket_path.to_string_lossy().to_string(),
+            0,
+            100,
+            /* max_send_retries */ 2,
+            10,
+        )
+        .with_protocol_version(UDS_PROTOCOL_V2, Duration::from_millis(300));
+
+        state
+            .send_block_with_retry(empty_block(0), HashMap::new(), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(*state.last_sent_height.lock().await, Some(0));
+        assert_eq!(state.last_confirmed_height().await, Some(0));
+
+        // Height 0 đã có block: không gửi gap block tại height này
+        state.send_empty_block(0).await.unwrap();
+        assert_eq!(executor.await.unwrap(), vec![0, 0, 0, 0]);
+
+        // Block 1 đã finalize nhưng chưa gửi → block khác tại height 1 bị từ chối
+        let mut finalized = empty_block(1);
+        block_header::link(&mut finalized, state.chain_tip.lock().await.as_ref());
+        *state.chain_tip.lock().await = Some(ChainTip::of(&finalized));
+        assert!(state.send_empty_block(1).await.is_err());
+        assert_eq!(*state.last_sent_height.lock().await, Some(0));
+    }
+
+    #[tokio::test]
+    async fn handshake_selects_the_block_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("executor.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Executor chỉ đọc được format v1
+        let executor = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.into_split();
+            write_frame(&mut writer, &hello_message(vec![BLOCK_FORMAT_V1])).await.unwrap();
+            let message: NodeMessage = read_frame(&mut reader).await.unwrap().unwrap();
+            let blocks = match message.payload {
+                Some(node_message::Payload::Blocks(data)) => data.blocks,
+                other => panic!("unexpected payload: {:?}", other),
+            };
+            for block in &blocks {
+                write_frame(&mut writer, &ack_message(block.height, true, String::new())).await.unwrap();
+            }
+            blocks.iter().map(|b| (b.height, b.format_version)).collect::<Vec<_>>()
+        });
+
+        let state = UdsExecutionState::new_with_retry(socket_path.to_string_lossy().to_string(), 0, 100, 1, 10)
+            .with_protocol_version(UDS_PROTOCOL_V2, Duration::from_millis(300))
+            .with_block_format(BLOCK_FORMAT_V2);
+        state.negotiate_block_format().await.unwrap();
+        assert_eq!(*state.block_format.lock().await, BLOCK_FORMAT_V1);
+        state.send_empty_block(0).await.unwrap();
+        assert_eq!(executor.await.unwrap(), vec![(0, BLOCK_FORMAT_V1)]);
+
+        // Format của từng block đã gửi được ghi lại cho replay
+        state.record_block_format(1, BLOCK_FORMAT_V1).await;
+        state.record_block_format(5, BLOCK_FORMAT_V2).await;
+        assert_eq!(
+            *state.sent_block_formats.lock().await,
+            BTreeMap::from([(0, BLOCK_FORMAT_V1), (5, BLOCK_FORMAT_V2)])
+        );
+        assert_eq!(state.block_format_of_height(4).await, BLOCK_FORMAT_V1);
+        assert_eq!(state.block_format_of_height(5).await, BLOCK_FORMAT_V2);
+        assert_eq!(state.execution_progress().await.block_formats.len(), 2);
+
+        // Không có format chung → node không khởi động
+        let socket_path = temp_dir.path().join("unknown.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let _executor = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (_reader, mut writer) = stream.into_split();
+            write_frame(&mut writer, &hello_message(vec![7])).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let state = UdsExecutionState::new_with_retry(socket_path.to_string_lossy().to_string(), 0, 100, 1, 10)
+            .with_protocol_version(UDS_PROTOCOL_V2, Duration::from_millis(300));
+        assert!(state.negotiate_block_format().await.is_err());
+
+        // Protocol v1 không có handshake: chỉ format v1
+        let state = UdsExecutionState::new_with_retry("/nonexistent.sock".to_string(), 0, 100, 1, 10)
+            .with_block_format(BLOCK_FORMAT_V2);
+        assert!(state.negotiate_block_format().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fork_detector_holds_delivery() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("executor.sock");
+        let _listener = UnixListener::bind(&socket_path).unwrap();
+
+        let fixture = test_utils::CommitteeFixture::builder().build();
+        let fork_detector = Arc::new(ForkDetector::new(
+            config::ForkDetectionParameters {
+                halt_on_fork: true,
+                ..Default::default()
+            },
+            Arc::new(arc_swap::ArcSwap::from_pointee(fixture.committee())),
+            &prometheus::Registry::new(),
+        ));
+        let state = UdsExecutionState::new_with_retry(
+            socket_path.to_string_lossy().to_string(),
+            0,
+            100,
+            /* max_send_retries */ 1,
+            10,
+        )
+        .with_fork_detector(fork_detector.clone());
+
+        // Block đã gửi được ghi fingerprint (block đã nối vào genesis)
+        state
+            .send_block_with_retry(empty_block(0), HashMap::new(), Vec::new())
+            .await
+            .unwrap();
+        let mut delivered = empty_block(0);
+        block_header::link(&mut delivered, None);
+        assert_eq!(
+            fork_detector.fingerprints(),
+            vec![(0, block_header::block_hash(&delivered))]
+        );
+
+        // Validators với f+1 stake báo cùng block hash khác tại height 0 → block 1 bị giữ lại
+        let peers: Vec<_> = fixture.authorities().map(|a| a.public_key()).collect();
+        assert_eq!(fork_detector.check(&peers[0], &[(0, vec![0; 32])]), vec![0]);
+        assert!(!fork_detector.is_halted());
+        assert_eq!(fork_detector.check(&peers[1], &[(0, vec![0; 32])]), vec![0]);
+        assert!(tokio::time::timeout(
+            Duration::from_millis(100),
+            state.send_block_with_retry(empty_block(1), HashMap::new(), Vec::new())
+        )
+        .await
+        .is_err());
+        assert_eq!(fork_detector.fingerprints().len(), 1);
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use clap::{crate_name, crate_version, App, AppSettings};
+use eyre::Context;
+use narwhal_node::uds_executor::{StandInConfig, StandInExecutor};
+use std::path::PathBuf;
+use tracing::{info, subscriber::set_global_default};
+use tracing_subscriber::filter::EnvFilter;
+
+#[tokio::main]
+async fn main() -> Result<(), eyre::Report> {
+    let matches = App::new(crate_name!())
+        .version(crate_version!())
+        .about("Executor stand-in for Narwhal and Tusk.")
+        .long_about("Thay thế executor Go khi test: lắng nghe trên Unix socket (uds_block_path) của node,\n\
+        kiểm tra hash của từng transaction (TransactionHashData), transactions root, height liên tục và\n\
+        parent hash của mỗi block, ACK các block hợp lệ (protocol v2) và in/lưu các block đã nhận.")
+        .args_from_usage("<SOCKET> 'Đường dẫn Unix socket mà node gửi blocks tới (uds_block_path)'")
+        .args_from_usage("--protocol=[INT] 'Phiên bản UDS protocol của node: 1 hoặc 2 (mặc định: 2)'")
+        .args_from_usage("--first-height=[INT] 'Height của block đầu tiên cần nhận (mặc định: bất kỳ)'")
+        .args_from_usage("--segments=[DIR] 'Lưu các block đã nhận thành segment files trong thư mục này'")
+        .setting(AppSettings::ArgRequiredElseHelp)
+        .get_matches();
+
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt::Subscriber::builder()
+        .with_env_filter(env_filter)
+        .with_writer(std::io::stderr)
+        .finish();
+    set_global_default(subscriber).expect("Failed to set subscriber");
+
+    let protocol_version = matches
+        .value_of("protocol")
+        .unwrap_or("2")
+        .parse::<u32>()
+        .context("Phiên bản protocol phải là số nguyên")?;
+    let first_height = matches
+        .value_of("first-height")
+        .map(|height| height.parse::<u64>())
+        .transpose()
+        .context("Height phải là số nguyên không âm")?;
+    let config = StandInConfig {
+        socket_path: PathBuf::from(matches.value_of("SOCKET").unwrap()),
+        protocol_version,
+        segments_dir: matches.value_of("segments").map(PathBuf::from),
+        first_height,
+    };
+
+    info!(
+        "Listening on {:?} (UDS protocol v{})",
+        config.socket_path, protocol_version
+    );
+    let stand_in = StandInExecutor::spawn(config).map_err(|e| eyre::eyre!(e))?;
+
+    tokio::signal::ctrl_c().await?;
+    let blocks = stand_in.blocks();
+    let violations = stand_in.violations();
+    info!(
+        "Received {} blocks (last height {:?}), {} invalid",
+        blocks.len(),
+        blocks.last().map(|block| block.height),
+        violations.len()
+    );
+    for violation in &violations {
+        info!("  ❌ {}", violation);
+    }
+    Ok(())
+}
@@ -4,20 +4,26 @@
 //! Global State Manager for Narwhal-Bullshark
 //!
 //! Quản lý tập trung tất cả state của các components để đảm bảo consistency và dễ dàng recovery.
+//! State được lưu trong RocksDB của node (column family `global_state`). Execution progress
+//! (consensus index, heights đã gửi) có store riêng, xem `execution_progress`; các trường
+//! execution ở đây chỉ phản ánh giá trị hiện tại cho các subscribers.
 
 use std::{
     collections::HashMap,
-    path::PathBuf,
     sync::Arc,
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use store::{rocks::DBMap, traits::Map};
 use tokio::sync::{RwLock, watch};
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info};
 use types::{CertificateDigest, GlobalStateManager as GlobalStateManagerTrait, GlobalStateSnapshot as GlobalStateSnapshotTrait, Round, SequenceNumber};
 use crypto::PublicKey;
 
-/// Global state snapshot - được serialize để lưu vào disk
+/// Key duy nhất của global state trong column family
+const GLOBAL_STATE_KEY: u8 = 0;
+
+/// Global state snapshot - được serialize để lưu vào RocksDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalStateSnapshot {
     /// Last committed round từ Consensus
@@ -59,8 +65,8 @@ pub struct GlobalStateManager {
     state: Arc<RwLock<GlobalStateSnapshot>>,
     /// Watch channel để broadcast state updates
     tx_state_updates: watch::Sender<GlobalStateSnapshot>,
-    /// Path để lưu state vào disk
-    state_path: PathBuf,
+    /// Column family lưu state
+    store: DBMap<u8, GlobalStateSnapshot>,
     /// Counter để persist định kỳ (mỗi N updates)
     persistence_counter: Arc<RwLock<u64>>,
     /// Số updates trước khi persist (configurable)
@@ -69,68 +75,49 @@ pub struct GlobalStateManager {
 
 impl GlobalStateManager {
     /// Tạo GlobalStateManager mới
-    pub fn new(state_path: PathBuf, persistence_interval: u64) -> Self {
+    pub fn new(store: DBMap<u8, GlobalStateSnapshot>, persistence_interval: u64) -> Self {
         let initial_state = GlobalStateSnapshot::default();
         let (tx_state_updates, _) = watch::channel(initial_state.clone());
         
         Self {
             state: Arc::new(RwLock::new(initial_state)),
             tx_state_updates,
-            state_path,
+            store,
             persistence_counter: Arc::new(RwLock::new(0)),
             persistence_interval,
         }
     }
 
-    /// Load state từ disk (nếu có)
+    /// Load state từ RocksDB (nếu có)
     pub async fn load_from_disk(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if !self.state_path.exists() {
-            info!("📁 [GlobalState] No existing state file, starting fresh");
-            return Ok(());
-        }
-
-        match tokio::fs::read_to_string(&self.state_path).await {
-            Ok(content) => {
-                match serde_json::from_str::<GlobalStateSnapshot>(&content) {
-                    Ok(snapshot) => {
-                        info!(
-                            "✅ [GlobalState] Loaded state from disk: last_committed_round={}, proposer_round={}, last_consensus_index={}",
-                            snapshot.last_committed_round,
-                            snapshot.proposer_round,
-                            snapshot.last_consensus_index
-                        );
-                        
-                        // Cập nhật state
-                        *self.state.write().await = snapshot.clone();
-                        
-                        // Broadcast state update
-                        let _ = self.tx_state_updates.send(snapshot);
-                        
-                        Ok(())
-                    }
-                    Err(e) => {
-                        warn!("⚠️ [GlobalState] Failed to parse state file: {}, starting fresh", e);
-                        Ok(())
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("⚠️ [GlobalState] Failed to read state file: {}, starting fresh", e);
-                Ok(())
+        let snapshot = match self.store.get(&GLOBAL_STATE_KEY)? {
+            Some(snapshot) => snapshot,
+            None => {
+                info!("📁 [GlobalState] No existing state, starting fresh");
+                return Ok(());
             }
-        }
+        };
+        info!(
+            "✅ [GlobalState] Loaded state from disk: last_committed_round={}, proposer_round={}, last_consensus_index={}",
+            snapshot.last_committed_round,
+            snapshot.proposer_round,
+            snapshot.last_consensus_index
+        );
+        
+        // Cập nhật state
+        *self.state.write().await = snapshot.clone();
+        
+        // Broadcast state update
+        let _ = self.tx_state_updates.send(snapshot);
+        
+        Ok(())
     }
 
-    /// Persist state vào disk
+    /// Persist state vào RocksDB
     pub async fn persist_to_disk(&self) -> Result<(), Box<dyn std::error::Error>> {
         let state = self.state.read().await.clone();
         
-        let json = serde_json::to_string_pretty(&state)?;
-        
-        // Atomic write: write to temp file, then rename
-        let temp_path = self.state_path.with_extension("tmp");
-        tokio::fs::write(&temp_path, json).await?;
-        tokio::fs::rename(&temp_path, &self.state_path).await?;
+        self.store.insert(&GLOBAL_STATE_KEY, &state)?;
         
         debug!(
             "💾 [GlobalState] Persisted state to disk: last_committed_round={}, proposer_round={}, last_consensus_index={}",
@@ -482,9 +469,11 @@ mod tests {
     #[tokio::test]
     async fn test_global_state_manager() {
         let temp_dir = TempDir::new().unwrap();
-        let state_path = temp_dir.path().join("global_state.json");
         
-        let manager = GlobalStateManager::new(state_path.clone(), 10);
+        let manager = GlobalStateManager::new(
+            crate::NodeStorage::reopen(temp_dir.path()).global_state_store,
+            10,
+        );
         
         // Test initial state
         let state = manager.get_state().await;
@@ -498,10 +487,13 @@ mod tests {
         
         // Test persistence
         manager.force_persist().await.unwrap();
-        assert!(state_path.exists());
+        drop(manager);
         
         // Test load
-        let mut manager2 = GlobalStateManager::new(state_path.clone(), 10);
+        let mut manager2 = GlobalStateManager::new(
+            crate::NodeStorage::reopen(temp_dir.path()).global_state_store,
+            10,
+        );
         manager2.load_from_disk().await.unwrap();
         let state2 = manager2.get_state().await;
         assert_eq!(state2.last_committed_round, 100);
@@ -1,40 +1,67 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
-use config::{Parameters, SharedCommittee, SharedWorkerCache, WorkerId};
+use block_archive::{BlockArchive, TxLocation};
+use config::{ConsensusProtocolKind, Parameters, SharedCommittee, SharedWorkerCache, WorkerId};
 use consensus::{
     bullshark::Bullshark,
     dag::Dag,
     metrics::{ChannelMetrics, ConsensusMetrics},
-    Consensus, ConsensusOutput,
+    tusk::Tusk,
+    Consensus, ConsensusOutput, LeaderSchedule,
 };
 
 use crypto::{KeyPair, NetworkKeyPair, PublicKey};
-use executor::{get_restored_consensus_output, ExecutionState, Executor, SubscriberResult};
+use delivered_transactions::DeliveredTransactionStore;
+use execution_progress::{ExecutionProgressStore, VersionedExecutionProgress};
+use executor::{
+    get_restored_consensus_output, ExecutionState, Executor, SubscriberError, SubscriberResult,
+};
 use fastcrypto::traits::{KeyPair as _, VerifyingKey};
+use global_state::GlobalStateSnapshot;
 use itertools::Itertools;
 use network::P2pNetwork;
-use primary::{NetworkModel, PayloadToken, Primary, PrimaryChannelMetrics};
+use primary::{
+    CommittedOutputFeed, ForkDetector, NetworkModel, PayloadToken, Primary, PrimaryChannelMetrics,
+};
+use processed_batches::ProcessedBatchStore;
 use prometheus::{IntGauge, Registry};
 use std::sync::Arc;
 use storage::{CertificateStore, CertificateToken};
 use store::{
     reopen,
     rocks::{open_cf, DBMap},
+    traits::Map,
     Store,
 };
 use tokio::sync::oneshot;
 use tokio::{sync::watch, task::JoinHandle};
 use tracing::{debug, info};
 use types::{
-    metered_channel, Batch, BatchDigest, Certificate, CertificateDigest, ConsensusStore, Header,
-    HeaderDigest, ReconfigureNotification, Round, RoundVoteDigestPair, SequenceNumber,
+    metered_channel, Batch, BatchCertificate, BatchDigest, Certificate, CertificateDigest,
+    CommittedSubDagInfo, ConsensusStore, Header, HeaderDigest, LeaderReputation,
+    ReconfigureNotification, Round, RoundVoteDigestPair, SequenceNumber, TransactionCommit,
+    TransactionStatusStore,
+};
+use worker::{
+    metrics::initialise_metrics, ProtoTransactionValidator, TransactionValidator,
+    TrivialTransactionValidator, Worker,
 };
-use worker::{metrics::initialise_metrics, Worker};
 
+pub mod block_archive;
+pub mod block_header;
+pub mod block_policy;
+pub mod block_segments;
+pub mod block_sink;
+pub mod delivered_transactions;
+pub mod execution_progress;
 pub mod execution_state;
 pub mod global_state;
 pub mod metrics;
+pub mod processed_batches;
 pub mod restarter;
+pub mod uds_executor;
+pub mod uds_protocol;
+pub mod validator_source;
 
 /// All the data stores of the node.
 pub struct NodeStorage {
@@ -45,6 +72,12 @@ pub struct NodeStorage {
     pub batch_store: Store<BatchDigest, Batch>,
     pub consensus_store: Arc<ConsensusStore>,
     pub temp_batch_store: Store<(CertificateDigest, BatchDigest), Batch>,
+    pub block_archive: Arc<BlockArchive>,
+    pub transaction_status_store: Arc<TransactionStatusStore>,
+    pub execution_progress_store: Arc<ExecutionProgressStore>,
+    pub global_state_store: DBMap<u8, GlobalStateSnapshot>,
+    /// The consensus protocol the database was created with (single entry).
+    pub consensus_protocol_store: DBMap<u8, ConsensusProtocolKind>,
 }
 
 impl NodeStorage {
@@ -57,7 +90,28 @@ impl NodeStorage {
     const BATCHES_CF: &'static str = "batches";
     const LAST_COMMITTED_CF: &'static str = "last_committed";
     const SEQUENCE_CF: &'static str = "sequence";
+    const SUB_DAGS_CF: &'static str = "sub_dags";
+    const LEADER_REPUTATION_CF: &'static str = "leader_reputation";
     const TEMP_BATCH_CF: &'static str = "temp_batches";
+    const COMMITTED_BLOCKS_CF: &'static str = "committed_blocks";
+    const COMMITTED_TX_INDEX_CF: &'static str = "committed_tx_index";
+    const TRANSACTION_BATCHES_CF: &'static str = "transaction_batches";
+    const TRANSACTION_BATCHES_BY_ROUND_CF: &'static str = "transaction_batches_by_round";
+    const BATCH_CERTIFICATES_CF: &'static str = "batch_certificates";
+    const BATCH_CERTIFICATES_BY_ROUND_CF: &'static str = "batch_certificates_by_round";
+    const TRANSACTION_COMMITS_CF: &'static str = "transaction_commits";
+    const TRANSACTION_COMMITS_BY_INDEX_CF: &'static str = "transaction_commits_by_index";
+    const DELIVERED_HEIGHT_CF: &'static str = "delivered_height";
+    const PROCESSED_BATCHES_CF: &'static str = "processed_batches";
+    const PROCESSED_BATCHES_BY_INDEX_CF: &'static str = "processed_batches_by_index";
+    const DELIVERED_TRANSACTIONS_CF: &'static str = "delivered_transactions";
+    const DELIVERED_TRANSACTIONS_BY_INDEX_CF: &'static str = "delivered_transactions_by_index";
+    const EXECUTION_PROGRESS_CF: &'static str = "execution_progress";
+    const GLOBAL_STATE_CF: &'static str = "global_state";
+    const CONSENSUS_PROTOCOL_CF: &'static str = "consensus_protocol";
+
+    /// The single key of the consensus protocol.
+    const CONSENSUS_PROTOCOL_KEY: u8 = 0;
 
     /// Open or reopen all the storage of the node.
     pub fn reopen<Path: AsRef<std::path::Path>>(store_path: Path) -> Self {
@@ -73,7 +127,25 @@ impl NodeStorage {
                 Self::BATCHES_CF,
                 Self::LAST_COMMITTED_CF,
                 Self::SEQUENCE_CF,
+                Self::SUB_DAGS_CF,
+                Self::LEADER_REPUTATION_CF,
                 Self::TEMP_BATCH_CF,
+                Self::COMMITTED_BLOCKS_CF,
+                Self::COMMITTED_TX_INDEX_CF,
+                Self::TRANSACTION_BATCHES_CF,
+                Self::TRANSACTION_BATCHES_BY_ROUND_CF,
+                Self::BATCH_CERTIFICATES_CF,
+                Self::BATCH_CERTIFICATES_BY_ROUND_CF,
+                Self::TRANSACTION_COMMITS_CF,
+                Self::TRANSACTION_COMMITS_BY_INDEX_CF,
+                Self::DELIVERED_HEIGHT_CF,
+                Self::PROCESSED_BATCHES_CF,
+                Self::PROCESSED_BATCHES_BY_INDEX_CF,
+                Self::DELIVERED_TRANSACTIONS_CF,
+                Self::DELIVERED_TRANSACTIONS_BY_INDEX_CF,
+                Self::EXECUTION_PROGRESS_CF,
+                Self::GLOBAL_STATE_CF,
+                Self::CONSENSUS_PROTOCOL_CF,
             ],
         )
         .expect("Cannot open database");
@@ -87,7 +159,25 @@ impl NodeStorage {
             batch_map,
             last_committed_map,
             sequence_map,
+            sub_dags_map,
+            leader_reputation_map,
             temp_batch_map,
+            committed_blocks_map,
+            committed_tx_index_map,
+            transaction_batches_map,
+            transaction_batches_by_round_map,
+            batch_certificates_map,
+            batch_certificates_by_round_map,
+            transaction_commits_map,
+            transaction_commits_by_index_map,
+            delivered_height_map,
+            processed_batches_map,
+            processed_batches_by_index_map,
+            delivered_transactions_map,
+            delivered_transactions_by_index_map,
+            execution_progress_map,
+            global_state_map,
+            consensus_protocol_map,
         ) = reopen!(&rocksdb,
             Self::VOTES_CF;<PublicKey, RoundVoteDigestPair>,
             Self::HEADERS_CF;<HeaderDigest, Header>,
@@ -97,7 +187,25 @@ impl NodeStorage {
             Self::BATCHES_CF;<BatchDigest, Batch>,
             Self::LAST_COMMITTED_CF;<PublicKey, Round>,
             Self::SEQUENCE_CF;<SequenceNumber, CertificateDigest>,
-            Self::TEMP_BATCH_CF;<(CertificateDigest, BatchDigest), Batch>
+            Self::SUB_DAGS_CF;<SequenceNumber, CommittedSubDagInfo>,
+            Self::LEADER_REPUTATION_CF;<u8, LeaderReputation>,
+            Self::TEMP_BATCH_CF;<(CertificateDigest, BatchDigest), Batch>,
+            Self::COMMITTED_BLOCKS_CF;<u64, Vec<u8>>,
+            Self::COMMITTED_TX_INDEX_CF;<Vec<u8>, TxLocation>,
+            Self::TRANSACTION_BATCHES_CF;<Vec<u8>, BatchDigest>,
+            Self::TRANSACTION_BATCHES_BY_ROUND_CF;<(Round, Vec<u8>), ()>,
+            Self::BATCH_CERTIFICATES_CF;<BatchDigest, BatchCertificate>,
+            Self::BATCH_CERTIFICATES_BY_ROUND_CF;<(Round, BatchDigest), ()>,
+            Self::TRANSACTION_COMMITS_CF;<Vec<u8>, TransactionCommit>,
+            Self::TRANSACTION_COMMITS_BY_INDEX_CF;<(SequenceNumber, Vec<u8>), ()>,
+            Self::DELIVERED_HEIGHT_CF;<u8, u64>,
+            Self::PROCESSED_BATCHES_CF;<BatchDigest, u64>,
+            Self::PROCESSED_BATCHES_BY_INDEX_CF;<(u64, BatchDigest), ()>,
+            Self::DELIVERED_TRANSACTIONS_CF;<Vec<u8>, u64>,
+            Self::DELIVERED_TRANSACTIONS_BY_INDEX_CF;<(u64, Vec<u8>), ()>,
+            Self::EXECUTION_PROGRESS_CF;<u8, VersionedExecutionProgress>,
+            Self::GLOBAL_STATE_CF;<u8, GlobalStateSnapshot>,
+            Self::CONSENSUS_PROTOCOL_CF;<u8, ConsensusProtocolKind>
         );
 
         let vote_digest_store = Store::new(votes_map);
@@ -105,8 +213,31 @@ impl NodeStorage {
         let certificate_store = CertificateStore::new(certificate_map, certificate_id_by_round_map);
         let payload_store = Store::new(payload_map);
         let batch_store = Store::new(batch_map);
-        let consensus_store = Arc::new(ConsensusStore::new(last_committed_map, sequence_map));
+        let consensus_store = Arc::new(ConsensusStore::new(
+            last_committed_map,
+            sequence_map,
+            sub_dags_map,
+            leader_reputation_map,
+        ));
         let temp_batch_store = Store::new(temp_batch_map);
+        let block_archive = Arc::new(BlockArchive::new(committed_blocks_map, committed_tx_index_map));
+        let transaction_status_store = Arc::new(TransactionStatusStore::new(
+            transaction_batches_map,
+            transaction_batches_by_round_map,
+            batch_certificates_map,
+            batch_certificates_by_round_map,
+            transaction_commits_map,
+            transaction_commits_by_index_map,
+            delivered_height_map,
+        ));
+        let execution_progress_store = Arc::new(ExecutionProgressStore::new(
+            execution_progress_map,
+            ProcessedBatchStore::new(processed_batches_map, processed_batches_by_index_map),
+            DeliveredTransactionStore::new(
+                delivered_transactions_map,
+                delivered_transactions_by_index_map,
+            ),
+        ));
 
         Self {
             vote_digest_store,
@@ -116,7 +247,50 @@ impl NodeStorage {
             batch_store,
             consensus_store,
             temp_batch_store,
+            block_archive,
+            transaction_status_store,
+            execution_progress_store,
+            global_state_store: global_state_map,
+            consensus_protocol_store: consensus_protocol_map,
+        }
+    }
+
+    /// Record the consensus protocol on a new database, or check that it is the one the
+    /// database was created with: the committed sequence of one protocol cannot be continued
+    /// by the other. A database that committed before the protocol was recorded was written by
+    /// Bullshark, the only protocol of the earlier versions.
+    pub fn check_consensus_protocol(
+        &self,
+        protocol: ConsensusProtocolKind,
+    ) -> SubscriberResult<()> {
+        let stored = match self
+            .consensus_protocol_store
+            .get(&Self::CONSENSUS_PROTOCOL_KEY)?
+        {
+            Some(stored) => stored,
+            None => {
+                let committed = self.consensus_store.read_next_consensus_index()? > 0
+                    || self
+                        .consensus_store
+                        .read_last_committed_sub_dag()?
+                        .is_some();
+                let stored = if committed {
+                    ConsensusProtocolKind::Bullshark
+                } else {
+                    protocol
+                };
+                self.consensus_protocol_store
+                    .insert(&Self::CONSENSUS_PROTOCOL_KEY, &stored)?;
+                stored
+            }
+        };
+        if stored != protocol {
+            return Err(SubscriberError::ConsensusProtocolMismatch {
+                stored,
+                configured: protocol,
+            });
         }
+        Ok(())
     }
 }
 
@@ -151,12 +325,18 @@ impl Node {
         execution_state: Arc<State>,
         // Global state manager for centralized state management
         global_state: Option<Arc<global_state::GlobalStateManager>>,
+        // Compares the blocks delivered to the executor with the other primaries (if enabled)
+        fork_detector: Option<Arc<ForkDetector>>,
         // A prometheus exporter Registry to use for the metrics
         registry: &Registry,
     ) -> SubscriberResult<Vec<JoinHandle<()>>>
     where
         State: ExecutionState + Send + Sync + 'static,
     {
+        // A node cannot switch protocols on an existing database, even with an external
+        // consensus.
+        store.check_consensus_protocol(parameters.consensus_protocol)?;
+
         let initial_committee = ReconfigureNotification::NewEpoch((**committee.load()).clone());
         let (tx_reconfigure, _rx_reconfigure) = watch::channel(initial_committee);
 
@@ -186,12 +366,30 @@ impl Node {
         let (tx_get_block_commands, rx_get_block_commands) =
             metered_channel::channel(Self::CHANNEL_CAPACITY, &tx_get_block_commands_counter);
 
+        // The committed certificates streamed to the clients of the consensus API.
+        let committed_output_feed = Arc::new(CommittedOutputFeed::new(
+            store.consensus_store.clone(),
+            store.certificate_store.clone(),
+            store.batch_store.clone(),
+        ));
+
         // Compute the public key of this authority.
         let name = keypair.public().clone();
         let mut handles = Vec::new();
         let (rx_executor_network, tx_executor_network) = oneshot::channel();
+        // The proposer pauses its payload while the executor is behind.
+        let execution_backpressure = execution_state.execution_backpressure();
+        // Bullshark elects the leaders the proposer waits for with the reputation of the authorities.
+        let leader_schedule = (internal_consensus
+            && parameters.consensus_protocol == ConsensusProtocolKind::Bullshark)
+            .then(|| {
+                LeaderSchedule::new(
+                    (**committee.load()).clone(),
+                    parameters.leader_reputation.clone(),
+                )
+            });
         let (dag, network_model) = if !internal_consensus {
-            debug!("Consensus is disabled: the primary will run w/o Tusk");
+            debug!("Consensus is disabled: the primary will run w/o Bullshark or Tusk");
             let consensus_metrics = Arc::new(ConsensusMetrics::new(registry));
             let (handle, dag) = Dag::new(&committee.load(), rx_new_certificates, consensus_metrics);
 
@@ -210,12 +408,19 @@ impl Node {
                 &tx_reconfigure,
                 rx_new_certificates,
                 tx_consensus.clone(),
+                committed_output_feed.clone(),
                 global_state.clone(),
+                leader_schedule.clone(),
                 registry,
             )
             .await?;
             handles.extend(consensus_handles);
-            (None, NetworkModel::PartiallySynchronous)
+            // Tusk does not rely on timeouts: the proposer does not wait for the leader.
+            let network_model = match parameters.consensus_protocol {
+                ConsensusProtocolKind::Bullshark => NetworkModel::PartiallySynchronous,
+                ConsensusProtocolKind::Tusk => NetworkModel::Asynchronous,
+            };
+            (None, network_model)
         };
 
         // Inject memory profiling here if we build with dhat-heap feature flag
@@ -256,6 +461,11 @@ impl Node {
             registry,
             Some(rx_executor_network),
             global_state.clone().map(|gs| gs as Arc<dyn types::GlobalStateManager>),
+            store.transaction_status_store.clone(),
+            committed_output_feed,
+            fork_detector,
+            execution_backpressure,
+            leader_schedule,
         );
         handles.extend(primary_handles);
 
@@ -292,13 +502,20 @@ impl Node {
         tx_reconfigure: &watch::Sender<ReconfigureNotification>,
         rx_new_certificates: metered_channel::Receiver<Certificate>,
         tx_feedback: metered_channel::Sender<Certificate>,
+        committed_output_feed: Arc<CommittedOutputFeed>,
         global_state: Option<Arc<global_state::GlobalStateManager>>,
+        leader_schedule: Option<LeaderSchedule>,
         registry: &Registry,
     ) -> SubscriberResult<Vec<JoinHandle<()>>>
     where
         PublicKey: VerifyingKey,
         State: ExecutionState + Send + Sync + 'static,
     {
+        info!(
+            "Running the {:?} consensus protocol",
+            parameters.consensus_protocol
+        );
+
         let consensus_metrics = Arc::new(ConsensusMetrics::new(registry));
         let channel_metrics = ChannelMetrics::new(registry);
 
@@ -327,26 +544,60 @@ impl Node {
             .recovered_consensus_output
             .inc_by(len_restored);
 
-        // Spawn the consensus core who only sequences transactions.
-        let ordering_engine = Bullshark::new(
-            (**committee.load()).clone(),
-            store.consensus_store.clone(),
-            parameters.gc_depth,
-        );
-        let consensus_handles = Consensus::spawn(
-            (**committee.load()).clone(),
-            store.consensus_store.clone(),
-            store.certificate_store.clone(),
-            tx_reconfigure.subscribe(),
-            /* rx_primary */ rx_new_certificates,
-            /* tx_primary */ tx_feedback,
-            /* tx_output */ tx_sequence,
-            ordering_engine,
-            consensus_metrics.clone(),
-            parameters.gc_depth,
-            global_state.clone().map(|gs| gs as Arc<dyn types::GlobalStateManager>),
+        // The certificates before the restored output were streamed before the restart.
+        committed_output_feed.restore(
+            committee.load().epoch(),
+            execution_state
+                .load_execution_indices()
+                .await
+                .next_certificate_index,
         );
 
+        // Spawn the consensus core who only sequences transactions.
+        let committee_snapshot = (**committee.load()).clone();
+        let global_state = global_state.map(|gs| gs as Arc<dyn types::GlobalStateManager>);
+        let consensus_handles = match parameters.consensus_protocol {
+            ConsensusProtocolKind::Bullshark => {
+                let mut ordering_engine = Bullshark::new(
+                    committee_snapshot.clone(),
+                    store.consensus_store.clone(),
+                    parameters.gc_depth,
+                );
+                if let Some(leader_schedule) = leader_schedule {
+                    ordering_engine = ordering_engine.with_leader_schedule(leader_schedule);
+                }
+                Consensus::spawn(
+                    committee_snapshot,
+                    store.consensus_store.clone(),
+                    store.certificate_store.clone(),
+                    tx_reconfigure.subscribe(),
+                    /* rx_primary */ rx_new_certificates,
+                    /* tx_primary */ tx_feedback,
+                    /* tx_output */ tx_sequence,
+                    ordering_engine,
+                    consensus_metrics.clone(),
+                    parameters.gc_depth,
+                    global_state,
+                )
+            }
+            ConsensusProtocolKind::Tusk => Consensus::spawn(
+                committee_snapshot.clone(),
+                store.consensus_store.clone(),
+                store.certificate_store.clone(),
+                tx_reconfigure.subscribe(),
+                /* rx_primary */ rx_new_certificates,
+                /* tx_primary */ tx_feedback,
+                /* tx_output */ tx_sequence,
+                Tusk::new(
+                    committee_snapshot,
+                    store.consensus_store.clone(),
+                    parameters.gc_depth,
+                ),
+                consensus_metrics.clone(),
+                parameters.gc_depth,
+                global_state,
+            ),
+        };
 
         // Spawn the client executing the transactions. It can also synchronize with the
         // subscriber handler if it missed some transactions.
@@ -361,6 +612,7 @@ impl Node {
             /* rx_consensus */ rx_sequence,
             registry,
             restored_consensus_output,
+            Some(committed_output_feed),
         )?;
 
         handles.extend(executor_handles);
@@ -390,6 +642,16 @@ impl Node {
 
         let metrics = initialise_metrics(registry);
 
+        // Client transactions are checked before they are batched (disabled for synthetic benchmark load).
+        let validator: Arc<dyn TransactionValidator> = if parameters.transaction_validation.enabled
+        {
+            Arc::new(ProtoTransactionValidator::new(
+                &parameters.transaction_validation,
+            ))
+        } else {
+            Arc::new(TrivialTransactionValidator)
+        };
+
         for (id, keypair) in ids_and_keypairs {
             let worker_handles = Worker::spawn(
                 primary_name.clone(),
@@ -399,6 +661,8 @@ impl Node {
                 worker_cache.clone(),
                 parameters.clone(),
                 store.batch_store.clone(),
+                validator.clone(),
+                store.transaction_status_store.clone(),
                 metrics.clone(),
             );
             handles.extend(worker_handles);
@@ -406,3 +670,67 @@ impl Node {
         handles
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::NodeStorage;
+    use config::ConsensusProtocolKind;
+    use executor::SubscriberError;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+    use types::CertificateDigest;
+
+    #[test]
+    fn database_keeps_its_consensus_protocol() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let store = NodeStorage::reopen(temp_dir.path());
+            store
+                .check_consensus_protocol(ConsensusProtocolKind::Tusk)
+                .unwrap();
+        }
+
+        let store = NodeStorage::reopen(temp_dir.path());
+        store
+            .check_consensus_protocol(ConsensusProtocolKind::Tusk)
+            .unwrap();
+        assert!(matches!(
+            store.check_consensus_protocol(ConsensusProtocolKind::Bullshark),
+            Err(SubscriberError::ConsensusProtocolMismatch {
+                stored: ConsensusProtocolKind::Tusk,
+                configured: ConsensusProtocolKind::Bullshark,
+            })
+        ));
+    }
+
+    #[test]
+    fn database_without_protocol_that_committed_is_bullshark() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            // Written before the protocol was recorded.
+            let store = NodeStorage::reopen(temp_dir.path());
+            store
+                .consensus_store
+                .write_consensus_state(
+                    &HashMap::new(),
+                    &0,
+                    &CertificateDigest::default(),
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+
+        let store = NodeStorage::reopen(temp_dir.path());
+        assert!(matches!(
+            store.check_consensus_protocol(ConsensusProtocolKind::Tusk),
+            Err(SubscriberError::ConsensusProtocolMismatch {
+                stored: ConsensusProtocolKind::Bullshark,
+                configured: ConsensusProtocolKind::Tusk,
+            })
+        ));
+        store
+            .check_consensus_protocol(ConsensusProtocolKind::Bullshark)
+            .unwrap();
+    }
+}
@@ -18,11 +18,14 @@ use fastcrypto::{generate_production_keypair, traits::KeyPair as _};
 use futures::future::join_all;
 use narwhal_node as node;
 use node::{
+    block_sink::BlockSinks,
     execution_state::{SimpleExecutionState, UdsExecutionState},
     global_state,
     metrics::{primary_metrics_registry, start_prometheus_server, worker_metrics_registry},
+    validator_source::ValidatorSource,
     Node, NodeStorage,
 };
+use primary::{ExecutionBackpressure, ForkDetector};
 use prometheus::Registry;
 use std::sync::Arc;
 use telemetry_subscribers::TelemetryGuards;
@@ -114,7 +117,11 @@ async fn main() -> Result<(), eyre::Report> {
         ("run", Some(sub_matches)) => {
             let primary_key_file = sub_matches.value_of("primary-keys").unwrap();
             // Try to load as PrimaryKeyConfig first (with uds_block_path), fallback to KeyPair
-            let (primary_keypair, uds_block_path_from_keyfile) = match PrimaryKeyConfig::import(primary_key_file) {
+            let (
+                primary_keypair,
+                uds_block_path_from_keyfile,
+                uds_get_validators_path_from_keyfile,
+            ) = match PrimaryKeyConfig::import(primary_key_file) {
                 Ok(key_config) => {
                     let uds_path = key_config.uds_block_path.clone();
                     info!("Loaded PrimaryKeyConfig, uds_block_path='{}'", uds_path);
@@ -125,13 +132,13 @@ async fn main() -> Result<(), eyre::Report> {
                     } else {
                         info!("PrimaryKeyConfig loaded but uds_block_path is empty");
                     }
-                    (kp, Some(uds_path))
+                    (kp, Some(uds_path), Some(key_config.uds_get_validators_path))
                 }
                 Err(_) => {
                     // Fallback to standard KeyPair format
                     let kp = KeyPair::import(primary_key_file)
                         .context("Failed to load the node's primary keypair")?;
-                    (kp, None)
+                    (kp, None, None)
                 }
             };
             let primary_network_key_file = sub_matches.value_of("primary-network-keys").unwrap();
@@ -170,6 +177,7 @@ async fn main() -> Result<(), eyre::Report> {
                 worker_keypair,
                 registry,
                 uds_block_path_from_keyfile,
+                uds_get_validators_path_from_keyfile,
             )
             .await?
         }
@@ -233,6 +241,7 @@ async fn run(
     worker_keypair: NetworkKeyPair,
     registry: Registry,
     uds_block_path_from_keyfile: Option<String>,
+    uds_get_validators_path_from_keyfile: Option<String>,
 ) -> Result<(), eyre::Report> {
     let committee_file = matches.value_of("committee").unwrap();
     let workers_file = matches.value_of("workers").unwrap();
@@ -264,13 +273,48 @@ async fn run(
     }
     info!("Final uds_block_path value: '{}'", parameters.uds_block_path);
 
+    // Set uds_get_validators_path from primary-key file if present
+    if let Some(validators_path) = uds_get_validators_path_from_keyfile {
+        if !validators_path.trim().is_empty() {
+            info!(
+                "Loaded uds_get_validators_path from primary-key file: '{}'",
+                validators_path
+            );
+            parameters.uds_get_validators_path = validators_path;
+        }
+    }
+
+    // Lấy tập validators từ executor (nếu được cấu hình) thay cho bản sao trong committee file
+    let validator_source = (!parameters.uds_get_validators_path.trim().is_empty()).then(|| {
+        ValidatorSource::new(
+            parameters.uds_get_validators_path.clone(),
+            parameters.validator_source.clone(),
+        )
+    });
+    if let Some(source) = &validator_source {
+        match source.fetch_at_startup(committee.load().epoch).await {
+            Ok((executor_committee, executor_workers)) => {
+                info!(
+                    "✅ [VALIDATORS] Loaded the validator set of epoch {} from the executor: {} validators",
+                    executor_committee.epoch,
+                    executor_committee.size()
+                );
+                committee.store(Arc::new(executor_committee));
+                worker_cache.store(Arc::new(executor_workers));
+            }
+            Err(e) => warn!(
+                "⚠️ [VALIDATORS] Failed to get the validator set from the executor: {}, using the committee file",
+                e
+            ),
+        }
+    }
+
     // Make the data store.
     let store = NodeStorage::reopen(store_path);
 
-    // Create GlobalStateManager
-    let global_state_path = std::path::PathBuf::from(store_path).join("global_state.json");
+    // Create GlobalStateManager (lưu trong RocksDB của node)
     let mut global_state = Arc::new(global_state::GlobalStateManager::new(
-        global_state_path,
+        store.global_state_store.clone(),
         10, // persistence_interval: persist mỗi 10 updates
     ));
 
@@ -291,6 +335,16 @@ async fn run(
     let (tx_transaction_confirmation, rx_transaction_confirmation) =
         channel(Node::CHANNEL_CAPACITY);
 
+    // Theo dõi epoch mới của executor: process của primary báo NewEpoch cho primary và các workers của nó
+    let _validator_source_handle = validator_source.map(|source| {
+        source.spawn(
+            primary_keypair.public().clone(),
+            committee.clone(),
+            worker_cache.clone(),
+            /* notify */ matches.subcommand_name() == Some("primary"),
+        )
+    });
+
     // Check whether to run a primary, a worker, or an entire authority.
     let node_handles = match matches.subcommand() {
         // Spawn the primary and consensus core.
@@ -300,30 +354,73 @@ async fn run(
                 let epoch = (**committee.load()).epoch;
                 info!("Using UdsExecutionState with UDS path: {}", parameters.uds_block_path);
                 
-                // Create execution state path from store path
-                let execution_state_path = std::path::PathBuf::from(store_path).join("execution_state.json");
+                // File execution state của phiên bản trước: import một lần vào RocksDB
+                let legacy_execution_state_path = std::path::PathBuf::from(store_path).join("execution_state.json");
                 
-                let uds_state = Arc::new(UdsExecutionState::new_with_state_and_stores(
+                // So sánh (height, block hash) của các blocks đã gửi với các primaries khác
+                let fork_detector = parameters.fork_detection.enabled.then(|| {
+                    Arc::new(ForkDetector::new(parameters.fork_detection.clone(), committee.clone(), &registry))
+                });
+
+                let mut uds_state = UdsExecutionState::new_with_state_and_stores(
                     parameters.uds_block_path.clone(),
                     epoch,
                     100, // empty_block_timeout_ms: 100ms - send empty blocks if no transactions for this duration
                     3, // max_send_retries
                     100, // retry_delay_base_ms
-                    5000, // missed_batch_timeout_ms (5 seconds)
-                    3, // max_missed_batch_retries
-                    Some(execution_state_path), // execution_state_path
+                    Some(legacy_execution_state_path), // legacy_execution_state_path
                     Some(store.consensus_store.clone()), // consensus_store
                     Some(store.certificate_store.clone()), // certificate_store
                     Some(global_state.clone()), // global_state
-                ));
-                
-                // Initialize execution state (load from disk)
-                if let Err(e) = uds_state.initialize().await {
-                    warn!("⚠️ Failed to initialize execution state: {}", e);
+                )
+                .with_protocol_version(
+                    parameters.uds_protocol.version,
+                    parameters.uds_protocol.ack_timeout,
+                )
+                .with_block_format(parameters.uds_protocol.block_format)
+                .with_batch_store(store.batch_store.clone())
+                .with_block_archive(
+                    store.block_archive.clone(),
+                    parameters.block_archive.retention_blocks,
+                    parameters.block_archive.prune_interval_blocks,
+                )
+                .with_transaction_status_store(store.transaction_status_store.clone())
+                .with_execution_progress_store(store.execution_progress_store.clone())
+                .with_block_policy(parameters.block_policy.clone());
+                if let Some(fork_detector) = &fork_detector {
+                    uds_state = uds_state.with_fork_detector(fork_detector.clone());
                 }
+                // Outputs phụ nhận bản sao các blocks từ archive (mỗi sink có cursor riêng)
+                if !parameters.block_sinks.is_empty() {
+                    let block_sinks = BlockSinks::spawn(
+                        &parameters.block_sinks,
+                        store.block_archive.clone(),
+                        std::path::PathBuf::from(store_path).join("block_sinks"),
+                        &registry,
+                    )
+                    .map_err(|e| eyre::eyre!(e))?;
+                    uds_state = uds_state.with_block_sinks(Arc::new(block_sinks));
+                }
+                // Làm chậm consensus và tạm dừng payload của proposer khi executor không theo kịp
+                let execution_backpressure = ExecutionBackpressure::new(
+                    parameters.execution_backpressure.clone(),
+                    &registry,
+                );
+                uds_state = uds_state.with_execution_backpressure(Arc::new(execution_backpressure));
+                let uds_state = Arc::new(uds_state);
+
+                // CRITICAL: Không khởi động nếu block policy mới đánh số lại các height đã gửi cho executor
+                uds_state.check_block_policy().await.map_err(|e| eyre::eyre!(e))?;
                 
-                // Spawn catch-up task
-                let _catchup_handle = uds_state.clone().spawn_catchup_task();
+                // Initialize execution state (load from RocksDB)
+                // CRITICAL: Không khởi động nếu execution progress không khớp consensus store
+                uds_state.initialize().await.map_err(|e| eyre::eyre!(e))?;
+
+                // CRITICAL: Không khởi động nếu executor không đọc được block format nào của node
+                uds_state.negotiate_block_format().await.map_err(|e| eyre::eyre!(e))?;
+
+                // Spawn replay task (phục vụ ReplayRequest của executor, protocol v2)
+                let _replay_handle = uds_state.clone().spawn_replay_task();
                 
                 Node::spawn_primary(
                     primary_keypair,
@@ -336,6 +433,7 @@ async fn run(
                     /* execution_state */
                     uds_state,
                     Some(global_state.clone()),
+                    fork_detector,
                     &registry,
                 )
                 .await?
@@ -351,6 +449,7 @@ async fn run(
                     /* execution_state */
                     Arc::new(SimpleExecutionState::new(tx_transaction_confirmation)),
                     Some(global_state.clone()),
+                    /* fork_detector */ None,
                     &registry,
                 )
                 .await?
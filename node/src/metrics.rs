@@ -5,7 +5,10 @@ use config::WorkerId;
 use crypto::PublicKey;
 use multiaddr::Multiaddr;
 use mysten_network::multiaddr::to_socket_addr;
-use prometheus::{Registry, TextEncoder};
+use prometheus::{
+    register_int_counter_vec_with_registry, register_int_gauge_vec_with_registry, IntCounterVec,
+    IntGaugeVec, Registry, TextEncoder,
+};
 use std::collections::HashMap;
 use tokio::task::JoinHandle;
 
@@ -54,3 +57,50 @@ async fn metrics(registry: Extension<Registry>) -> (StatusCode, String) {
         ),
     }
 }
+
+#[derive(Clone)]
+pub struct BlockSinkMetrics {
+    /// the number of archived blocks not yet written to a sink, sink is a label
+    pub block_sink_lag_blocks: IntGaugeVec,
+    /// the height of the next block written to a sink, sink is a label
+    pub block_sink_next_height: IntGaugeVec,
+    /// count the failed writes to a sink, sink is a label
+    pub block_sink_write_errors: IntCounterVec,
+    /// count the blocks a sink skipped because they were pruned from the archive, sink is a label
+    pub block_sink_skipped_blocks: IntCounterVec,
+}
+
+impl BlockSinkMetrics {
+    pub fn new(registry: &Registry) -> Self {
+        Self {
+            block_sink_lag_blocks: register_int_gauge_vec_with_registry!(
+                "block_sink_lag_blocks",
+                "Number of archived blocks not yet written to the sink",
+                &["sink"],
+                registry
+            )
+            .unwrap(),
+            block_sink_next_height: register_int_gauge_vec_with_registry!(
+                "block_sink_next_height",
+                "Height of the next block written to the sink",
+                &["sink"],
+                registry
+            )
+            .unwrap(),
+            block_sink_write_errors: register_int_counter_vec_with_registry!(
+                "block_sink_write_errors",
+                "Number of failed writes to the sink",
+                &["sink"],
+                registry
+            )
+            .unwrap(),
+            block_sink_skipped_blocks: register_int_counter_vec_with_registry!(
+                "block_sink_skipped_blocks",
+                "Number of blocks skipped by the sink because they were pruned from the archive",
+                &["sink"],
+                registry
+            )
+            .unwrap(),
+        }
+    }
+}
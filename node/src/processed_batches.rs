@@ -0,0 +1,158 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent set of the batches already put in a block by the execution state.
+//!
+//! A batch re-included by a later certificate must not be executed twice, including after a
+//! restart. Every batch is stored with the consensus index of the certificate that executed it,
+//! in the same write batch as the execution progress (see `ExecutionProgressStore`).
+//! Entries are pruned below a consensus-index watermark, which is deterministic across nodes.
+
+use store::{
+    rocks::{DBBatch, DBMap, TypedStoreError},
+    traits::Map,
+};
+use types::BatchDigest;
+
+/// Convenience type to propagate store errors.
+pub type ProcessedBatchResult<T> = Result<T, TypedStoreError>;
+
+pub struct ProcessedBatchStore {
+    /// Batch digest -> consensus index of the certificate that executed the batch.
+    batches: DBMap<BatchDigest, u64>,
+    /// (consensus index, batch digest) of the executed batches, to prune them by consensus index.
+    batches_by_index: DBMap<(u64, BatchDigest), ()>,
+}
+
+impl ProcessedBatchStore {
+    pub fn new(
+        batches: DBMap<BatchDigest, u64>,
+        batches_by_index: DBMap<(u64, BatchDigest), ()>,
+    ) -> Self {
+        Self {
+            batches,
+            batches_by_index,
+        }
+    }
+
+    /// Add a batch executed at `consensus_index` to `write_batch`.
+    pub fn record(
+        &self,
+        mut write_batch: DBBatch,
+        batch_digest: BatchDigest,
+        consensus_index: u64,
+    ) -> ProcessedBatchResult<DBBatch> {
+        write_batch = write_batch.insert_batch(
+            &self.batches,
+            std::iter::once((batch_digest, consensus_index)),
+        )?;
+        write_batch = write_batch.insert_batch(
+            &self.batches_by_index,
+            std::iter::once(((consensus_index, batch_digest), ())),
+        )
+    }
+
+    /// The consensus index a batch was executed at, if it was.
+    pub fn processed_index(&self, batch_digest: &BatchDigest) -> ProcessedBatchResult<Option<u64>> {
+        self.batches.get(batch_digest)
+    }
+
+    /// Every recorded batch executed at a consensus index >= `watermark`.
+    pub fn read_from(&self, watermark: u64) -> ProcessedBatchResult<Vec<(BatchDigest, u64)>> {
+        Ok(self
+            .batches_by_index
+            .iter()
+            .skip_to(&(watermark, BatchDigest::default()))?
+            .map(|((consensus_index, batch_digest), _)| (batch_digest, consensus_index))
+            .collect())
+    }
+
+    /// Delete every batch executed at a consensus index < `watermark`.
+    /// Returns the number of pruned batches.
+    pub fn prune_below(&self, watermark: u64) -> ProcessedBatchResult<usize> {
+        let pruned: Vec<(u64, BatchDigest)> = self
+            .batches_by_index
+            .keys()
+            .take_while(|(consensus_index, _)| *consensus_index < watermark)
+            .collect();
+        if pruned.is_empty() {
+            return Ok(0);
+        }
+        let count = pruned.len();
+        self.delete(self.batches.batch(), pruned)?.write()?;
+        Ok(count)
+    }
+
+    /// Add the deletion of every batch to `write_batch`: consensus indices restart at a new epoch.
+    pub fn clear(&self, write_batch: DBBatch) -> ProcessedBatchResult<DBBatch> {
+        let all: Vec<(u64, BatchDigest)> = self.batches_by_index.keys().collect();
+        self.delete(write_batch, all)
+    }
+
+    fn delete(
+        &self,
+        mut write_batch: DBBatch,
+        keys: Vec<(u64, BatchDigest)>,
+    ) -> ProcessedBatchResult<DBBatch> {
+        let digests: Vec<BatchDigest> = keys.iter().map(|(_, digest)| *digest).collect();
+        write_batch = write_batch.delete_batch(&self.batches, digests)?;
+        write_batch.delete_batch(&self.batches_by_index, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::NodeStorage;
+    use tempfile::TempDir;
+    use types::BatchDigest;
+
+    #[test]
+    fn processed_batches_survive_reopen_and_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        {
+            let store = NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+            let processed_batches = store.processed_batches();
+            for consensus_index in 0..5u8 {
+                let write_batch = processed_batches
+                    .record(
+                        store.batch(),
+                        BatchDigest::new([consensus_index; 32]),
+                        consensus_index as u64,
+                    )
+                    .unwrap();
+                write_batch.write().unwrap();
+            }
+        }
+
+        let store = NodeStorage::reopen(temp_dir.path()).execution_progress_store;
+        let processed_batches = store.processed_batches();
+        assert_eq!(
+            processed_batches
+                .processed_index(&BatchDigest::new([3; 32]))
+                .unwrap(),
+            Some(3)
+        );
+
+        assert_eq!(processed_batches.prune_below(3).unwrap(), 3);
+        assert_eq!(
+            processed_batches
+                .processed_index(&BatchDigest::new([2; 32]))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            processed_batches.read_from(0).unwrap(),
+            vec![
+                (BatchDigest::new([3; 32]), 3),
+                (BatchDigest::new([4; 32]), 4)
+            ]
+        );
+
+        processed_batches
+            .clear(store.batch())
+            .unwrap()
+            .write()
+            .unwrap();
+        assert!(processed_batches.read_from(0).unwrap().is_empty());
+    }
+}
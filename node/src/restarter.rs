@@ -79,6 +79,7 @@ impl NodeRestarter {
                 /* consensus */ true,
                 execution_state.clone(),
                 None, // global_state - restarter không có global_state
+                None, // fork_detector
                 registry,
             )
             .await
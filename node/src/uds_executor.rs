@@ -0,0 +1,466 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A stand-in for the executor on `uds_block_path`, to test the block delivery without the Go
+//! executor (see the `executor_stand_in` binary).
+//!
+//! It listens on the socket, decodes the blocks (protocol v1 or v2), recomputes every transaction
+//! hash with the `TransactionHashData` rule, checks the transactions root, the height contiguity
+//! and the parent hash, ACKs (v2) the valid blocks and optionally stores them as segments.
+
+use crate::{
+    block_header,
+    block_segments::SegmentWriter,
+    execution_state::{
+        calculate_transaction_hash_from_proto,
+        comm::{node_message, CommittedBlock, CommittedEpochData, ExecutorMessage, NodeMessage},
+        transaction,
+    },
+    uds_protocol::{self, UDS_PROTOCOL_V2},
+};
+use prost::Message;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::{UnixListener, UnixStream},
+    sync::watch,
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+/// The size of the segments written by the stand-in.
+const SEGMENT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The maximum number of blocks asked in one replay request (the node serves up to 1000).
+const MAX_REPLAY_HEIGHTS: u64 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct StandInConfig {
+    /// The socket the node delivers the blocks to (`uds_block_path`).
+    pub socket_path: PathBuf,
+    /// The UDS protocol spoken by the node (`uds_protocol.version`).
+    pub protocol_version: u32,
+    /// Where to store the accepted blocks as segments, if anywhere.
+    pub segments_dir: Option<PathBuf>,
+    /// The height expected first; any height if `None`.
+    pub first_height: Option<u64>,
+}
+
+/// A block accepted by the stand-in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedBlock {
+    pub epoch: u64,
+    pub height: u64,
+    /// The transaction hashes, in block order.
+    pub tx_hashes: Vec<Vec<u8>>,
+    pub block_hash: Vec<u8>,
+    /// The encoded block, to compare the blocks of several nodes byte for byte.
+    pub bytes: Vec<u8>,
+}
+
+/// What the stand-in received, shared with its handle.
+#[derive(Default)]
+struct Received {
+    blocks: Vec<ReceivedBlock>,
+    /// A description of every invalid block.
+    violations: Vec<String>,
+}
+
+/// Handle of a running stand-in executor.
+pub struct StandInHandle {
+    received: Arc<Mutex<Received>>,
+    rx_last_height: watch::Receiver<Option<u64>>,
+    handle: JoinHandle<()>,
+}
+
+impl StandInHandle {
+    /// The accepted blocks, in height order.
+    pub fn blocks(&self) -> Vec<ReceivedBlock> {
+        self.received.lock().unwrap().blocks.clone()
+    }
+
+    /// The invalid blocks received so far.
+    pub fn violations(&self) -> Vec<String> {
+        self.received.lock().unwrap().violations.clone()
+    }
+
+    /// Wait until the block at `height` is accepted.
+    pub async fn wait_for_height(&self, height: u64) {
+        let mut rx_last_height = self.rx_last_height.clone();
+        while !matches!(*rx_last_height.borrow_and_update(), Some(last) if last >= height) {
+            if rx_last_height.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    pub fn stop(&self) {
+        self.handle.abort();
+    }
+}
+
+/// Verifies, acknowledges and records the blocks delivered on a Unix socket.
+pub struct StandInExecutor {
+    config: StandInConfig,
+    /// The last accepted block.
+    last_block: Option<CommittedBlock>,
+    segments: Option<SegmentWriter>,
+    /// The replay request to send after the current blocks, for the heights the node skipped.
+    pending_replay: Option<ExecutorMessage>,
+    /// The first height of the last replay request, not to ask again for the same gap.
+    last_replay_from: Option<u64>,
+    received: Arc<Mutex<Received>>,
+    tx_last_height: watch::Sender<Option<u64>>,
+}
+
+impl StandInExecutor {
+    /// Bind the socket (replacing a stale one) and serve the node connections, one at a time.
+    pub fn spawn(config: StandInConfig) -> Result<StandInHandle, String> {
+        if config.socket_path.exists() {
+            std::fs::remove_file(&config.socket_path)
+                .map_err(|e| format!("Failed to remove {:?}: {}", config.socket_path, e))?;
+        }
+        let listener = UnixListener::bind(&config.socket_path)
+            .map_err(|e| format!("Failed to bind {:?}: {}", config.socket_path, e))?;
+        let segments = config
+            .segments_dir
+            .as_ref()
+            .map(|dir| SegmentWriter::open(dir, SEGMENT_MAX_BYTES))
+            .transpose()?;
+
+        let received = Arc::new(Mutex::new(Received::default()));
+        let (tx_last_height, rx_last_height) = watch::channel(None);
+        let mut executor = Self {
+            config,
+            last_block: None,
+            segments,
+            pending_replay: None,
+            last_replay_from: None,
+            received: received.clone(),
+            tx_last_height,
+        };
+        let handle = tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("⚠️ [STAND-IN] Failed to accept a connection: {}", e);
+                        continue;
+                    }
+                };
+                info!("🔌 [STAND-IN] Node connected");
+                match executor.serve(stream).await {
+                    Ok(()) => info!("🔌 [STAND-IN] Node closed the connection"),
+                    Err(e) => warn!("⚠️ [STAND-IN] Connection failed: {}", e),
+                }
+            }
+        });
+        Ok(StandInHandle {
+            received,
+            rx_last_height,
+            handle,
+        })
+    }
+
+    async fn serve(&mut self, stream: UnixStream) -> Result<(), String> {
+        let (mut reader, mut writer) = stream.into_split();
+        if self.config.protocol_version < UDS_PROTOCOL_V2 {
+            while let Some(data) = read_legacy_frame(&mut reader).await? {
+                for block in data.blocks {
+                    // Nothing to recover the skipped blocks with: report the gap and go on.
+                    if let Err(reason) = self.process(block, true) {
+                        self.violation(reason);
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        while let Some(message) = uds_protocol::read_frame::<_, NodeMessage>(&mut reader).await? {
+            match message.payload {
+                Some(node_message::Payload::Blocks(data)) => {
+                    for block in data.blocks {
+                        let height = block.height;
+                        let ack = match self.process(block, false) {
+                            Ok(()) => uds_protocol::ack_message(height, true, String::new()),
+                            Err(reason) => {
+                                self.violation(reason.clone());
+                                uds_protocol::ack_message(height, false, reason)
+                            }
+                        };
+                        uds_protocol::write_frame(&mut writer, &ack).await?;
+                    }
+                    // Ask for the blocks the node skipped, if any.
+                    if let Some(request) = self.pending_replay.take() {
+                        uds_protocol::write_frame(&mut writer, &request).await?;
+                    }
+                }
+                Some(node_message::Payload::Replay(response)) => {
+                    if !response.error.is_empty() {
+                        self.violation(format!(
+                            "Replay of blocks {}..={} failed: {}",
+                            response.from_height, response.to_height, response.error
+                        ));
+                    }
+                    for block in response.blocks {
+                        if let Err(reason) = self.process(block, false) {
+                            self.violation(reason);
+                        }
+                    }
+                }
+                None => warn!("⚠️ [STAND-IN] Node message without payload"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify a block and accept it if it is the next one (or any later one with `accept_gap`).
+    /// Blocks already accepted are ignored.
+    fn process(&mut self, block: CommittedBlock, accept_gap: bool) -> Result<(), String> {
+        let expected_height = match &self.last_block {
+            Some(last_block) => Some(last_block.height + 1),
+            None => self.config.first_height,
+        };
+        if let Some(expected_height) = expected_height {
+            if block.height < expected_height {
+                return Ok(());
+            }
+            if block.height > expected_height {
+                let reason = format!(
+                    "Expected block {}, got block {}",
+                    expected_height, block.height
+                );
+                if !accept_gap {
+                    if self.last_replay_from != Some(expected_height) {
+                        self.last_replay_from = Some(expected_height);
+                        self.pending_replay = Some(uds_protocol::replay_request_message(
+                            expected_height,
+                            (block.height - 1).min(expected_height + MAX_REPLAY_HEIGHTS - 1),
+                        ));
+                    }
+                    return Err(reason);
+                }
+                self.violation(reason);
+            }
+        }
+
+        let tx_hashes = verify_transactions(&block)?;
+        if let Some(header) = &block.header {
+            if header.transactions_root.as_ref() != block_header::transactions_root(&tx_hashes) {
+                return Err(format!(
+                    "Transactions root of block {} does not match its transactions",
+                    block.height
+                ));
+            }
+        }
+        if let Some(last_block) = &self.last_block {
+            let contiguous = block.height == last_block.height + 1;
+            if contiguous && block.header.is_some() && !block_header::follows(&block, last_block) {
+                return Err(format!(
+                    "Parent hash of block {} is not the hash of block {}",
+                    block.height, last_block.height
+                ));
+            }
+        }
+
+        if let Some(segments) = &mut self.segments {
+            segments.append(&block)?;
+        }
+        let received = ReceivedBlock {
+            epoch: block.epoch,
+            height: block.height,
+            tx_hashes,
+            block_hash: block_header::block_hash(&block),
+            bytes: block.encode_to_vec(),
+        };
+        info!(
+            "📥 [STAND-IN] Block {} (epoch {}): {} transactions, hash {}",
+            received.height,
+            received.epoch,
+            received.tx_hashes.len(),
+            hex::encode(&received.block_hash)
+        );
+        self.received.lock().unwrap().blocks.push(received);
+        self.tx_last_height.send_replace(Some(block.height));
+        self.last_block = Some(block);
+        Ok(())
+    }
+
+    fn violation(&self, reason: String) {
+        warn!("❌ [STAND-IN] {}", reason);
+        self.received.lock().unwrap().violations.push(reason);
+    }
+}
+
+/// Recompute the transaction hashes of a block with the `TransactionHashData` rule; the hashes
+/// carried by a v2 block must match.
+fn verify_transactions(block: &CommittedBlock) -> Result<Vec<Vec<u8>>, String> {
+    if block.format_version >= uds_protocol::BLOCK_FORMAT_V2 {
+        return block
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| {
+                let tx =
+                    transaction::Transaction::decode(entry.transaction.as_ref()).map_err(|e| {
+                        format!(
+                            "Block {} Tx[{}] is not a transaction: {}",
+                            block.height, position, e
+                        )
+                    })?;
+                let tx_hash = calculate_transaction_hash_from_proto(&tx);
+                if tx_hash != entry.hash.as_ref() {
+                    return Err(format!(
+                        "Block {} Tx[{}]: hash {} but the transaction hashes to {}",
+                        block.height,
+                        position,
+                        hex::encode(&entry.hash),
+                        hex::encode(&tx_hash)
+                    ));
+                }
+                Ok(tx_hash)
+            })
+            .collect();
+    }
+
+    let mut tx_hashes = Vec::new();
+    for (position, tx) in block.transactions.iter().enumerate() {
+        let wrapper = transaction::Transactions::decode(tx.digest.as_ref()).map_err(|e| {
+            format!(
+                "Block {} Tx[{}] is not a transactions wrapper: {}",
+                block.height, position, e
+            )
+        })?;
+        tx_hashes.extend(
+            wrapper
+                .transactions
+                .iter()
+                .map(calculate_transaction_hash_from_proto),
+        );
+    }
+    Ok(tx_hashes)
+}
+
+/// Read a legacy (v1) frame: 2 byte little-endian length + `CommittedEpochData`.
+async fn read_legacy_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<CommittedEpochData>, String> {
+    let mut len_buf = [0u8; 2];
+    match reader.read_exact(&mut len_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(format!("Failed to read frame length: {}", e)),
+    }
+    let mut body = vec![0u8; u16::from_le_bytes(len_buf) as usize];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| format!("Failed to read frame: {}", e))?;
+    CommittedEpochData::decode(body.as_slice())
+        .map(Some)
+        .map_err(|e| format!("Failed to decode frame: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution_state::comm::{executor_message, BlockHeader, BlockTransaction};
+    use bytes::Bytes;
+    use tempfile::TempDir;
+
+    fn block(height: u64, nonce: u8, parent: Option<&CommittedBlock>) -> CommittedBlock {
+        let tx = transaction::Transaction {
+            nonce: vec![nonce].into(),
+            ..Default::default()
+        };
+        let tx_hash = calculate_transaction_hash_from_proto(&tx);
+        let mut block = CommittedBlock {
+            epoch: 0,
+            height,
+            transactions: Vec::new(),
+            header: Some(BlockHeader {
+                transactions_root: Bytes::from(block_header::transactions_root(&[tx_hash.clone()])),
+                ..BlockHeader::default()
+            }),
+            format_version: uds_protocol::BLOCK_FORMAT_V2,
+            entries: vec![BlockTransaction {
+                transaction: tx.encode_to_vec().into(),
+                hash: tx_hash.into(),
+                ..BlockTransaction::default()
+            }],
+        };
+        block_header::link(&mut block, parent.map(block_header::ChainTip::of).as_ref());
+        block
+    }
+
+    async fn deliver(stream: &mut UnixStream, block: &CommittedBlock) -> ExecutorMessage {
+        uds_protocol::write_frame(stream, &uds_protocol::blocks_message(vec![block.clone()]))
+            .await
+            .unwrap();
+        uds_protocol::read_frame(stream).await.unwrap().unwrap()
+    }
+
+    fn accepted(message: ExecutorMessage) -> bool {
+        match message.payload {
+            Some(executor_message::Payload::Ack(ack)) => ack.accepted,
+            other => panic!("Expected an ack, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_and_acknowledges_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("executor.sock");
+        let stand_in = StandInExecutor::spawn(StandInConfig {
+            socket_path: socket_path.clone(),
+            protocol_version: UDS_PROTOCOL_V2,
+            segments_dir: None,
+            first_height: Some(0),
+        })
+        .unwrap();
+        let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+
+        let first = block(0, 1, None);
+        let second = block(1, 2, Some(&first));
+        assert!(accepted(deliver(&mut stream, &first).await));
+        assert!(accepted(deliver(&mut stream, &second).await));
+        // Re-sent after a reconnection.
+        assert!(accepted(deliver(&mut stream, &first).await));
+
+        // A wrong transaction hash.
+        let mut corrupted = block(2, 3, Some(&second));
+        corrupted.entries[0].hash = vec![0u8; 32].into();
+        assert!(!accepted(deliver(&mut stream, &corrupted).await));
+
+        // A gap: NACK, then ask for the skipped block.
+        let third = block(2, 3, Some(&second));
+        let fourth = block(3, 4, Some(&third));
+        assert!(!accepted(deliver(&mut stream, &fourth).await));
+        match uds_protocol::read_frame::<_, ExecutorMessage>(&mut stream)
+            .await
+            .unwrap()
+            .unwrap()
+            .payload
+        {
+            Some(executor_message::Payload::ReplayRequest(request)) => {
+                assert_eq!((request.from_height, request.to_height), (2, 2))
+            }
+            other => panic!("Expected a replay request, got {:?}", other),
+        }
+        assert!(accepted(deliver(&mut stream, &third).await));
+        assert!(accepted(deliver(&mut stream, &fourth).await));
+
+        stand_in.wait_for_height(3).await;
+        let blocks = stand_in.blocks();
+        assert_eq!(
+            blocks.iter().map(|block| block.height).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(blocks[3].block_hash, block_header::block_hash(&fourth));
+        assert_eq!(blocks[3].bytes, fourth.encode_to_vec());
+        assert_eq!(stand_in.violations().len(), 2);
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Framing và ACK tracking cho UDS block delivery protocol.
+//!
+//! - v1 (legacy): node chỉ ghi `u16 LE length + CommittedEpochData`, executor không phản hồi.
+//! - v2: node ghi `u32 LE length + NodeMessage`, executor trả lời `u32 LE length + ExecutorMessage`
+//!   trên cùng socket. Mỗi block height phải được ACK; block chưa ACK được gửi lại sau khi reconnect.
+//!   Frame đầu tiên của executor trên mỗi kết nối là `ExecutorHello` (các block formats nó đọc được).
+
+use crate::execution_state::comm::{
+    self, executor_message, node_message, BlockAck, ExecutorHello, ExecutorMessage, NodeMessage,
+    ReplayRequest, ReplayResponse,
+};
+use prost::Message;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::unix::OwnedReadHalf,
+    sync::{mpsc, oneshot, Mutex},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+/// Legacy protocol: write-only, 2-byte length prefix.
+pub const UDS_PROTOCOL_V1: u32 = 1;
+/// Acknowledged protocol: 4-byte length prefix, executor ACKs/NACKs every block height.
+pub const UDS_PROTOCOL_V2: u32 = 2;
+
+/// Block format v1: all the transactions of a block in one wrapper, in the first `transactions` entry.
+pub const BLOCK_FORMAT_V1: u32 = 1;
+/// Block format v2: one typed `entries` element per transaction, with its hash and provenance.
+pub const BLOCK_FORMAT_V2: u32 = 2;
+/// The block formats this node can send.
+pub const BLOCK_FORMATS: [u32; 2] = [BLOCK_FORMAT_V1, BLOCK_FORMAT_V2];
+
+/// Giới hạn kích thước một frame v2 để tránh cấp phát vô hạn khi peer gửi length sai.
+const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+/// Build a `NodeMessage` carrying the given blocks.
+pub fn blocks_message(blocks: Vec<comm::CommittedBlock>) -> NodeMessage {
+    NodeMessage {
+        version: UDS_PROTOCOL_V2,
+        payload: Some(node_message::Payload::Blocks(comm::CommittedEpochData {
+            blocks,
+        })),
+    }
+}
+
+/// Build a `NodeMessage` answering a replay request.
+pub fn replay_response_message(response: ReplayResponse) -> NodeMessage {
+    NodeMessage {
+        version: UDS_PROTOCOL_V2,
+        payload: Some(node_message::Payload::Replay(response)),
+    }
+}
+
+/// Build an `ExecutorMessage` asking the node to replay `[from_height, to_height]`.
+pub fn replay_request_message(from_height: u64, to_height: u64) -> ExecutorMessage {
+    ExecutorMessage {
+        version: UDS_PROTOCOL_V2,
+        payload: Some(executor_message::Payload::ReplayRequest(ReplayRequest {
+            from_height,
+            to_height,
+        })),
+    }
+}
+
+/// Build an `ExecutorMessage` acknowledging (or rejecting) a block height.
+pub fn ack_message(height: u64, accepted: bool, reason: String) -> ExecutorMessage {
+    ExecutorMessage {
+        version: UDS_PROTOCOL_V2,
+        payload: Some(executor_message::Payload::Ack(BlockAck {
+            height,
+            accepted,
+            reason,
+        })),
+    }
+}
+
+/// Build the `ExecutorHello` an executor sends first on every connection.
+pub fn hello_message(block_formats: Vec<u32>) -> ExecutorMessage {
+    ExecutorMessage {
+        version: UDS_PROTOCOL_V2,
+        payload: Some(executor_message::Payload::Hello(ExecutorHello {
+            block_formats,
+        })),
+    }
+}
+
+/// Format của blocks gửi cho executor: `preferred` nếu executor đọc được, ngược lại format cao nhất
+/// mà cả node và executor hỗ trợ. None nếu không có format chung.
+pub fn negotiate_block_format(preferred: u32, executor_formats: &[u32]) -> Option<u32> {
+    if executor_formats.contains(&preferred) {
+        return Some(preferred);
+    }
+    BLOCK_FORMATS
+        .iter()
+        .rev()
+        .find(|format| executor_formats.contains(format))
+        .copied()
+}
+
+/// Đọc handshake của executor trên một kết nối mới (protocol v2).
+/// Returns: các block formats executor đọc được, và message đầu tiên nếu nó không phải `ExecutorHello`
+/// (message đó phải được xử lý như các message sau).
+/// Executor không gửi `ExecutorHello` trong `timeout` (trước handshake) chỉ đọc được format v1.
+pub async fn read_executor_hello<R>(
+    reader: &mut R,
+    timeout: Duration,
+) -> Result<(Vec<u32>, Option<ExecutorMessage>), String>
+where
+    R: AsyncRead + Unpin,
+{
+    match tokio::time::timeout(timeout, read_frame::<_, ExecutorMessage>(reader)).await {
+        Ok(Ok(Some(ExecutorMessage {
+            payload: Some(executor_message::Payload::Hello(hello)),
+            ..
+        }))) => Ok((hello.block_formats, None)),
+        Ok(Ok(Some(message))) => Ok((vec![BLOCK_FORMAT_V1], Some(message))),
+        Ok(Ok(None)) => Err("Executor closed the connection before the handshake".to_string()),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Ok((vec![BLOCK_FORMAT_V1], None)),
+    }
+}
+
+/// Encode một frame v2: 4 byte little-endian length + protobuf message.
+pub fn encode_frame<M: Message>(message: &M) -> Result<Vec<u8>, String> {
+    let body_len = message.encoded_len();
+    if body_len > MAX_FRAME_SIZE {
+        return Err(format!(
+            "Frame too large: {} bytes (max {})",
+            body_len, MAX_FRAME_SIZE
+        ));
+    }
+    let mut frame = Vec::with_capacity(4 + body_len);
+    frame.extend_from_slice(&(body_len as u32).to_le_bytes());
+    message
+        .encode(&mut frame)
+        .map_err(|e| format!("Failed to encode frame: {}", e))?;
+    Ok(frame)
+}
+
+/// Ghi một frame v2: 4 byte little-endian length + protobuf message.
+pub async fn write_frame<W, M>(writer: &mut W, message: &M) -> Result<(), String>
+where
+    W: AsyncWrite + Unpin,
+    M: Message,
+{
+    let frame = encode_frame(message)?;
+    writer
+        .write_all(&frame)
+        .await
+        .map_err(|e| format!("Failed to write frame to UDS: {}", e))?;
+    writer
+        .flush()
+        .await
+        .map_err(|e| format!("Failed to flush UDS stream: {}", e))
+}
+
+/// Đọc một frame v2. Trả về `Ok(None)` khi peer đóng kết nối ở ranh giới frame.
+pub async fn read_frame<R, M>(reader: &mut R) -> Result<Option<M>, String>
+where
+    R: AsyncRead + Unpin,
+    M: Message + Default,
+{
+    let mut len_buf = [0u8; 4];
+    match reader.read_exact(&mut len_buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(format!("Failed to read frame length from UDS: {}", e)),
+    }
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(format!(
+            "Frame too large: {} bytes (max {})",
+            len, MAX_FRAME_SIZE
+        ));
+    }
+    let mut body = vec![0u8; len];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| format!("Failed to read frame from UDS: {}", e))?;
+    M::decode(body.as_slice())
+        .map(Some)
+        .map_err(|e| format!("Failed to decode frame: {}", e))
+}
+
+/// Theo dõi các block đã gửi nhưng chưa được executor ACK.
+///
+/// Shared giữa writer (UdsExecutionState) và reader task của từng connection.
+#[derive(Clone, Default)]
+pub struct AckTracker {
+    /// Blocks đã gửi nhưng chưa ACK, theo thứ tự height để gửi lại đúng thứ tự
+    unacked: Arc<Mutex<BTreeMap<u64, comm::CommittedBlock>>>,
+    /// Writer đang chờ ACK/NACK cho từng height
+    waiters: Arc<Mutex<HashMap<u64, oneshot::Sender<Result<(), String>>>>>,
+    /// Height lớn nhất đã được executor ACK
+    last_confirmed: Arc<Mutex<Option<u64>>>,
+}
+
+impl AckTracker {
+    /// Ghi nhận block sắp được gửi và đăng ký waiter cho ACK của nó.
+    /// Phải gọi TRƯỚC khi ghi frame để không bỏ lỡ ACK đến sớm.
+    pub async fn track(
+        &self,
+        block: &comm::CommittedBlock,
+    ) -> oneshot::Receiver<Result<(), String>> {
+        self.unacked
+            .lock()
+            .await
+            .insert(block.height, block.clone());
+        let (tx, rx) = oneshot::channel();
+        self.waiters.lock().await.insert(block.height, tx);
+        rx
+    }
+
+    /// Các block chưa ACK có height < `below_height`, theo thứ tự tăng dần.
+    pub async fn unacked_below(&self, below_height: u64) -> Vec<comm::CommittedBlock> {
+        self.unacked
+            .lock()
+            .await
+            .range(..below_height)
+            .map(|(_, block)| block.clone())
+            .collect()
+    }
+
+    pub async fn unacked_count(&self) -> usize {
+        self.unacked.lock().await.len()
+    }
+
+    pub async fn last_confirmed(&self) -> Option<u64> {
+        *self.last_confirmed.lock().await
+    }
+
+    /// Xử lý ACK/NACK từ executor.
+    /// Returns: Some(height) nếu last_confirmed được nâng lên.
+    pub async fn on_ack(&self, ack: BlockAck) -> Option<u64> {
+        let waiter = self.waiters.lock().await.remove(&ack.height);
+        if !ack.accepted {
+            warn!(
+                "⚠️ [UDS] Executor NACKed block {}: {}",
+                ack.height, ack.reason
+            );
+            if let Some(waiter) = waiter {
+                let _ = waiter.send(Err(ack.reason));
+            }
+            return None;
+        }
+
+        self.unacked.lock().await.remove(&ack.height);
+        let advanced = {
+            let mut last_confirmed = self.last_confirmed.lock().await;
+            if last_confirmed.map_or(true, |h| ack.height > h) {
+                *last_confirmed = Some(ack.height);
+                true
+            } else {
+                false
+            }
+        };
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(Ok(()));
+        }
+        advanced.then(|| ack.height)
+    }
+}
+
+/// Spawn task đọc message của executor trên read half của một connection.
+/// - ACK/NACK → cập nhật `AckTracker` (và last_confirmed_block trong global state)
+/// - ReplayRequest → chuyển sang replay task qua `tx_replay`
+/// `first_message`: message đã đọc trong handshake (executor không gửi `ExecutorHello`).
+///
+/// Task kết thúc khi executor đóng socket; writer sẽ phát hiện qua ACK timeout và reconnect.
+pub fn spawn_executor_reader(
+    mut reader: OwnedReadHalf,
+    first_message: Option<ExecutorMessage>,
+    tracker: AckTracker,
+    global_state: Option<Arc<crate::global_state::GlobalStateManager>>,
+    tx_replay: mpsc::Sender<ReplayRequest>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Some(message) = first_message {
+            handle_executor_message(message, &tracker, &global_state, &tx_replay).await;
+        }
+        loop {
+            match read_frame::<_, ExecutorMessage>(&mut reader).await {
+                Ok(Some(message)) => {
+                    handle_executor_message(message, &tracker, &global_state, &tx_replay).await
+                }
+                Ok(None) => {
+                    info!("🔌 [UDS] Executor closed the connection");
+                    break;
+                }
+                Err(e) => {
+                    warn!("⚠️ [UDS] Stopped reading executor messages: {}", e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+async fn handle_executor_message(
+    message: ExecutorMessage,
+    tracker: &AckTracker,
+    global_state: &Option<Arc<crate::global_state::GlobalStateManager>>,
+    tx_replay: &mpsc::Sender<ReplayRequest>,
+) {
+    match message.payload {
+        Some(executor_message::Payload::Ack(ack)) => {
+            if let Some(height) = tracker.on_ack(ack).await {
+                if let Some(gs) = global_state {
+                    gs.update_last_confirmed_block(height).await;
+                }
+            }
+        }
+        Some(executor_message::Payload::ReplayRequest(request)) => {
+            info!(
+                "🔄 [UDS] Executor requested replay of blocks {}..={}",
+                request.from_height, request.to_height
+            );
+            if tx_replay.send(request).await.is_err() {
+                warn!("⚠️ [UDS] Replay task is not running, dropping replay request");
+            }
+        }
+        Some(executor_message::Payload::Hello(hello)) => {
+            warn!(
+                "⚠️ [UDS] Ignoring executor hello after the handshake (block formats {:?})",
+                hello.block_formats
+            );
+        }
+        None => {
+            warn!(
+                "⚠️ [UDS] Received executor message without payload (version {})",
+                message.version
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_format_falls_back_to_a_common_format() {
+        assert_eq!(
+            negotiate_block_format(BLOCK_FORMAT_V2, &[BLOCK_FORMAT_V1, BLOCK_FORMAT_V2]),
+            Some(BLOCK_FORMAT_V2)
+        );
+        assert_eq!(
+            negotiate_block_format(BLOCK_FORMAT_V2, &[BLOCK_FORMAT_V1]),
+            Some(BLOCK_FORMAT_V1)
+        );
+        // The preferred format wins over a higher one.
+        assert_eq!(
+            negotiate_block_format(BLOCK_FORMAT_V1, &[BLOCK_FORMAT_V2, BLOCK_FORMAT_V1]),
+            Some(BLOCK_FORMAT_V1)
+        );
+        assert_eq!(
+            negotiate_block_format(BLOCK_FORMAT_V1, &[BLOCK_FORMAT_V2, 7]),
+            Some(BLOCK_FORMAT_V2)
+        );
+        assert_eq!(negotiate_block_format(BLOCK_FORMAT_V1, &[7]), None);
+        assert_eq!(negotiate_block_format(BLOCK_FORMAT_V1, &[]), None);
+    }
+
+    #[tokio::test]
+    async fn executor_without_hello_reads_format_v1() {
+        let (mut node, mut executor) = tokio::io::duplex(1024);
+        write_frame(&mut executor, &hello_message(vec![BLOCK_FORMAT_V2]))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_executor_hello(&mut node, Duration::from_millis(100))
+                .await
+                .unwrap(),
+            (vec![BLOCK_FORMAT_V2], None)
+        );
+
+        // No hello: format v1 only.
+        assert_eq!(
+            read_executor_hello(&mut node, Duration::from_millis(100))
+                .await
+                .unwrap(),
+            (vec![BLOCK_FORMAT_V1], None)
+        );
+
+        // The first message is not a hello: it is handed back.
+        let ack = ack_message(3, true, String::new());
+        write_frame(&mut executor, &ack).await.unwrap();
+        assert_eq!(
+            read_executor_hello(&mut node, Duration::from_millis(100))
+                .await
+                .unwrap(),
+            (vec![BLOCK_FORMAT_V1], Some(ack))
+        );
+    }
+}
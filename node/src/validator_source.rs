@@ -0,0 +1,433 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The validator set sourced from the executor on `uds_get_validators_path`.
+//!
+//! The staking logic lives in the executor: instead of a manual copy in the committee file, the
+//! node asks it for the validator set (keys, stake, addresses) at startup, then polls it to detect
+//! a new epoch. A validator set is translated into a `Committee` and a `WorkerCache`; a new epoch
+//! is fed to the primary and its workers as a `ReconfigureNotification::NewEpoch`, the message
+//! they already handle to change epoch.
+//!
+//! Each query is a single `ValidatorSetRequest` / `ValidatorSet` exchange on a new connection,
+//! framed like the protocol v2 of the block delivery.
+
+use crate::{
+    execution_state::comm::{ValidatorSet, ValidatorSetRequest},
+    uds_protocol::{self, UDS_PROTOCOL_V2},
+};
+use config::{
+    Authority, Committee, Epoch, SharedCommittee, SharedWorkerCache, ValidatorSourceParameters,
+    WorkerCache, WorkerIndex, WorkerInfo,
+};
+use crypto::{NetworkKeyPair, NetworkPublicKey, PublicKey};
+use fastcrypto::traits::{KeyPair as _, ToFromBytes};
+use multiaddr::Multiaddr;
+use network::{P2pNetwork, ReliableNetwork};
+use primary::PrimaryWorkerMessage;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    net::UnixStream,
+    task::JoinHandle,
+    time::{interval, sleep, timeout, Instant, MissedTickBehavior},
+};
+use tracing::{debug, info, warn};
+use types::{ReconfigureNotification, WorkerPrimaryMessage};
+
+/// The maximum time to wait for the executor to answer one query.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The maximum time to wait for the primary and its workers to receive a new epoch.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Translate the validator set returned by the executor into a committee and a worker cache.
+pub fn committee_from_validator_set(
+    set: &ValidatorSet,
+) -> Result<(Committee, WorkerCache), String> {
+    if !set.error.is_empty() {
+        return Err(format!(
+            "Executor could not return the validator set: {}",
+            set.error
+        ));
+    }
+    if set.validators.is_empty() {
+        return Err(format!("Empty validator set for epoch {}", set.epoch));
+    }
+
+    let mut authorities = BTreeMap::new();
+    let mut workers = BTreeMap::new();
+    for validator in &set.validators {
+        let name = PublicKey::from_bytes(&validator.public_key)
+            .map_err(|e| format!("Invalid validator public key: {}", e))?;
+        if validator.stake == 0 {
+            return Err(format!("Validator {} has no stake", name));
+        }
+        let authority = Authority {
+            stake: validator.stake,
+            primary_address: parse_address(&validator.primary_address)?,
+            network_key: NetworkPublicKey::from_bytes(&validator.network_key)
+                .map_err(|e| format!("Invalid network key of validator {}: {}", name, e))?,
+        };
+
+        let mut index = BTreeMap::new();
+        for worker in &validator.workers {
+            let info = WorkerInfo {
+                name: NetworkPublicKey::from_bytes(&worker.network_key).map_err(|e| {
+                    format!("Invalid key of worker {} of {}: {}", worker.id, name, e)
+                })?,
+                transactions: parse_address(&worker.transactions_address)?,
+                worker_address: parse_address(&worker.worker_address)?,
+            };
+            if index.insert(worker.id, info).is_some() {
+                return Err(format!(
+                    "Duplicate worker {} of validator {}",
+                    worker.id, name
+                ));
+            }
+        }
+
+        match authorities.entry(name.clone()) {
+            Entry::Occupied(_) => return Err(format!("Duplicate validator {}", name)),
+            Entry::Vacant(entry) => {
+                entry.insert(authority);
+            }
+        }
+        workers.insert(name, WorkerIndex(index));
+    }
+
+    Ok((
+        Committee {
+            authorities,
+            epoch: set.epoch,
+        },
+        WorkerCache {
+            workers,
+            epoch: set.epoch,
+        },
+    ))
+}
+
+fn parse_address(address: &str) -> Result<Multiaddr, String> {
+    address
+        .parse()
+        .map_err(|e| format!("Invalid address '{}': {}", address, e))
+}
+
+/// Queries the validator set from the executor.
+pub struct ValidatorSource {
+    socket_path: PathBuf,
+    parameters: ValidatorSourceParameters,
+}
+
+impl ValidatorSource {
+    pub fn new(socket_path: impl Into<PathBuf>, parameters: ValidatorSourceParameters) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+            parameters,
+        }
+    }
+
+    /// Ask the executor for the validator set of its latest epoch.
+    pub async fn fetch(&self, current_epoch: Epoch) -> Result<(Committee, WorkerCache), String> {
+        let set = timeout(REQUEST_TIMEOUT, self.request(current_epoch))
+            .await
+            .map_err(|_| {
+                format!(
+                    "Timed out waiting for the validator set on {:?}",
+                    self.socket_path
+                )
+            })??;
+        committee_from_validator_set(&set)
+    }
+
+    async fn request(&self, current_epoch: Epoch) -> Result<ValidatorSet, String> {
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .await
+            .map_err(|e| format!("Failed to connect to {:?}: {}", self.socket_path, e))?;
+        let request = ValidatorSetRequest {
+            version: UDS_PROTOCOL_V2,
+            current_epoch,
+        };
+        uds_protocol::write_frame(&mut stream, &request).await?;
+        uds_protocol::read_frame(&mut stream)
+            .await?
+            .ok_or_else(|| "Executor closed the connection without a validator set".to_string())
+    }
+
+    /// Fetch the validator set at startup, retrying while the executor is not ready, for at most
+    /// `startup_timeout`.
+    pub async fn fetch_at_startup(
+        &self,
+        current_epoch: Epoch,
+    ) -> Result<(Committee, WorkerCache), String> {
+        let deadline = Instant::now() + self.parameters.startup_timeout;
+        loop {
+            match self.fetch(current_epoch).await {
+                Ok(validators) => return Ok(validators),
+                Err(e) if Instant::now() >= deadline => return Err(e),
+                Err(e) => {
+                    debug!("[VALIDATORS] Validator set not available yet: {}", e);
+                    sleep(self.parameters.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Poll the executor for a new epoch. The workers of a new validator set are added to the
+    /// worker cache; if `notify` is set (in the primary's process), the new committee is sent to
+    /// the primary `name` and its workers, which switch to the new epoch.
+    pub fn spawn(
+        self,
+        name: PublicKey,
+        committee: SharedCommittee,
+        worker_cache: SharedWorkerCache,
+        notify: bool,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            self.run(name, committee, worker_cache, notify).await;
+        })
+    }
+
+    async fn run(
+        self,
+        name: PublicKey,
+        committee: SharedCommittee,
+        worker_cache: SharedWorkerCache,
+        notify: bool,
+    ) {
+        info!(
+            "[VALIDATORS] Polling the validator set on {:?} every {} ms",
+            self.socket_path,
+            self.parameters.poll_interval.as_millis()
+        );
+        let mut ticker = interval(self.parameters.poll_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The last epoch applied to the worker cache (and sent to the primary and its workers).
+        let mut last_epoch = committee.load().epoch;
+        let mut network = None;
+
+        loop {
+            ticker.tick().await;
+            let current = committee.load_full();
+            let (new_committee, new_workers) = match self.fetch(current.epoch).await {
+                Ok(validators) => validators,
+                Err(e) => {
+                    debug!("[VALIDATORS] Failed to fetch the validator set: {}", e);
+                    continue;
+                }
+            };
+            if new_committee.epoch <= last_epoch.max(current.epoch) {
+                continue;
+            }
+            if new_committee.primary(&name).is_err() {
+                warn!(
+                    "[VALIDATORS] {} is not a validator of epoch {}: staying in epoch {}",
+                    name, new_committee.epoch, current.epoch
+                );
+                last_epoch = new_committee.epoch;
+                continue;
+            }
+            info!(
+                "[VALIDATORS] New validator set for epoch {}: {} validators",
+                new_committee.epoch,
+                new_committee.size()
+            );
+
+            // The primary and the workers keep the workers of the cache for the members of the new
+            // committee: the cache must know the new members before they switch.
+            let our_workers = worker_cache.load_full();
+            worker_cache.swap(Arc::new(new_workers));
+
+            if notify {
+                let network = network.get_or_insert_with(Self::network);
+                if let Err(e) =
+                    Self::notify_new_epoch(network, &name, &current, &our_workers, &new_committee)
+                        .await
+                {
+                    warn!(
+                        "[VALIDATORS] Failed to notify epoch {}: {}",
+                        new_committee.epoch, e
+                    );
+                    // Retry on the next poll.
+                    worker_cache.swap(our_workers);
+                    continue;
+                }
+            }
+            last_epoch = new_committee.epoch;
+        }
+    }
+
+    /// A network to reach the primary and its workers, with a key of its own.
+    fn network() -> anemo::Network {
+        let keypair = NetworkKeyPair::generate(&mut rand::rngs::OsRng);
+        anemo::Network::bind("127.0.0.1:0")
+            .server_name("narwhal")
+            .private_key(keypair.private().0.to_bytes())
+            .start(anemo::Router::new())
+            .expect("Failed to start the validator source network")
+    }
+
+    /// Send the new committee to the primary `name` and its workers, at their addresses of the
+    /// current epoch.
+    async fn notify_new_epoch(
+        network: &anemo::Network,
+        name: &PublicKey,
+        current: &Committee,
+        worker_cache: &WorkerCache,
+        new_committee: &Committee,
+    ) -> Result<(), String> {
+        let primary_key = current.network_key(name).map_err(|e| e.to_string())?;
+        let mut peers = vec![(
+            current.primary(name).map_err(|e| e.to_string())?,
+            primary_key.clone(),
+        )];
+        let workers = worker_cache.our_workers(name).map_err(|e| e.to_string())?;
+        for worker in &workers {
+            peers.push((worker.worker_address.clone(), worker.name.clone()));
+        }
+        for (address, key) in &peers {
+            let peer_id = anemo::PeerId(key.0.to_bytes());
+            if network.peer(peer_id).is_none() {
+                let address = network::multiaddr_to_address(address).map_err(|e| e.to_string())?;
+                network
+                    .connect_with_peer_id(address, peer_id)
+                    .await
+                    .map_err(|e| format!("Failed to connect to {}: {}", peer_id, e))?;
+            }
+        }
+
+        let mut p2p = P2pNetwork::new(network.clone());
+        let notification = ReconfigureNotification::NewEpoch(new_committee.clone());
+        let mut handles = vec![
+            p2p.send(
+                primary_key,
+                &WorkerPrimaryMessage::Reconfigure(notification.clone()),
+            )
+            .await,
+        ];
+        handles.extend(
+            p2p.broadcast(
+                workers.into_iter().map(|worker| worker.name).collect(),
+                &PrimaryWorkerMessage::Reconfigure(notification),
+            )
+            .await,
+        );
+        for handle in handles {
+            timeout(NOTIFY_TIMEOUT, handle)
+                .await
+                .map_err(|_| "Timed out notifying the new epoch".to_string())?
+                .map_err(|e| e.to_string())?;
+        }
+        info!(
+            "[VALIDATORS] Primary {} and its workers notified of epoch {}",
+            name, new_committee.epoch
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution_state::comm::{Validator, ValidatorWorker};
+    use tempfile::TempDir;
+    use test_utils::CommitteeFixture;
+    use tokio::net::UnixListener;
+
+    fn validator_set(committee: &Committee, worker_cache: &WorkerCache) -> ValidatorSet {
+        ValidatorSet {
+            version: UDS_PROTOCOL_V2,
+            epoch: committee.epoch,
+            validators: committee
+                .authorities
+                .iter()
+                .map(|(name, authority)| Validator {
+                    public_key: name.as_bytes().to_vec().into(),
+                    stake: authority.stake,
+                    primary_address: authority.primary_address.to_string(),
+                    network_key: authority.network_key.as_bytes().to_vec().into(),
+                    workers: worker_cache.workers[name]
+                        .0
+                        .iter()
+                        .map(|(id, worker)| ValidatorWorker {
+                            id: *id,
+                            network_key: worker.name.as_bytes().to_vec().into(),
+                            transactions_address: worker.transactions.to_string(),
+                            worker_address: worker.worker_address.to_string(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            error: String::new(),
+        }
+    }
+
+    #[test]
+    fn translates_validator_set() {
+        let fixture = CommitteeFixture::builder().build();
+        let committee = fixture.committee();
+        let worker_cache = fixture.worker_cache();
+
+        let (translated, workers) =
+            committee_from_validator_set(&validator_set(&committee, &worker_cache)).unwrap();
+        assert_eq!(translated, committee);
+        assert_eq!(workers.epoch, worker_cache.epoch);
+        for name in committee.authorities.keys() {
+            assert_eq!(workers.workers[name].0, worker_cache.workers[name].0);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_validator_set() {
+        let fixture = CommitteeFixture::builder().build();
+        let set = validator_set(&fixture.committee(), &fixture.worker_cache());
+
+        let mut duplicate = set.clone();
+        duplicate.validators.push(duplicate.validators[0].clone());
+        assert!(committee_from_validator_set(&duplicate).is_err());
+
+        let mut no_stake = set.clone();
+        no_stake.validators[1].stake = 0;
+        assert!(committee_from_validator_set(&no_stake).is_err());
+
+        let mut bad_address = set.clone();
+        bad_address.validators[2].primary_address = "not an address".to_string();
+        assert!(committee_from_validator_set(&bad_address).is_err());
+
+        let failed = ValidatorSet {
+            error: "staking state unavailable".to_string(),
+            ..set
+        };
+        assert!(committee_from_validator_set(&failed).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetches_validator_set_from_executor() {
+        let temp_dir = TempDir::new().unwrap();
+        let socket_path = temp_dir.path().join("validators.sock");
+        let fixture = CommitteeFixture::builder().build();
+        let mut committee = fixture.committee();
+        committee.epoch = 3;
+        let set = validator_set(&committee, &fixture.worker_cache());
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request: ValidatorSetRequest = uds_protocol::read_frame(&mut stream)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(request.current_epoch, 2);
+            uds_protocol::write_frame(&mut stream, &set).await.unwrap();
+        });
+
+        let source = ValidatorSource::new(&socket_path, ValidatorSourceParameters::default());
+        let (fetched, _) = source.fetch(2).await.unwrap();
+        assert_eq!(fetched, committee);
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use crate::CHANNEL_CAPACITY;
+use config::Epoch;
+use consensus::ConsensusOutput;
+use fastcrypto::hash::Hash;
+use std::sync::{Arc, Mutex};
+use storage::CertificateStore;
+use store::Store;
+use tokio::sync::broadcast;
+use types::{Batch, BatchDigest, ConsensusStore, SequenceNumber, StoreResult};
+
+#[cfg(test)]
+#[path = "tests/committed_output_tests.rs"]
+mod committed_output_tests;
+
+/// The position of a certificate in the committed output. The consensus indices restart at 0
+/// with every epoch.
+pub type OutputPosition = (Epoch, SequenceNumber);
+
+/// A committed certificate with the batches of its payload.
+#[derive(Clone, Debug)]
+pub struct CommittedOutput {
+    pub output: ConsensusOutput,
+    /// The batches in the order of the payload of the certificate; `None` if the batch is no
+    /// longer in the store.
+    pub batches: Vec<Option<Batch>>,
+}
+
+/// The committed certificates of this primary, for the clients streaming them. The executor
+/// publishes every certificate once it fetched its batches; the clients replay the older ones
+/// from the consensus sequence and the stores.
+pub struct CommittedOutputFeed {
+    /// The global consensus sequence.
+    consensus_store: Arc<ConsensusStore>,
+    /// The certificates of the sequence.
+    certificate_store: CertificateStore,
+    /// The batches of the published certificates, for the replays.
+    batch_store: Store<BatchDigest, Batch>,
+    /// Broadcast the published certificates.
+    tx_output: broadcast::Sender<Arc<CommittedOutput>>,
+    /// The position of the last published certificate. The subscriptions read it under the
+    /// same lock as the broadcast so no certificate is missed between replay and tail.
+    last_published: Mutex<Option<OutputPosition>>,
+}
+
+impl CommittedOutput {
+    pub fn position(&self) -> OutputPosition {
+        (self.output.certificate.epoch(), self.output.consensus_index)
+    }
+}
+
+impl CommittedOutputFeed {
+    pub fn new(
+        consensus_store: Arc<ConsensusStore>,
+        certificate_store: CertificateStore,
+        batch_store: Store<BatchDigest, Batch>,
+    ) -> Self {
+        let (tx_output, _rx_output) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            consensus_store,
+            certificate_store,
+            batch_store,
+            tx_output,
+            last_published: Mutex::new(None),
+        }
+    }
+
+    /// Start the feed after a restart: the certificates of `epoch` before `next_consensus_index`
+    /// were published before the crash and are only served by replay.
+    pub fn restore(&self, epoch: Epoch, next_consensus_index: SequenceNumber) {
+        let mut last_published = self.last_published.lock().unwrap();
+        if last_published.is_none() {
+            *last_published = next_consensus_index
+                .checked_sub(1)
+                .map(|consensus_index| (epoch, consensus_index));
+        }
+    }
+
+    /// Publish a committed certificate with the batches of its payload (in the payload order).
+    /// Certificates already published (eg. re-sent by the executor after a restart) are ignored;
+    /// the first certificate of a new epoch follows the last one of the previous epoch.
+    pub async fn publish(&self, output: ConsensusOutput, batches: Vec<Batch>) {
+        let position = (output.certificate.epoch(), output.consensus_index);
+        if self.last_published() >= Some(position) {
+            return;
+        }
+        for batch in &batches {
+            self.batch_store.write(batch.digest(), batch.clone()).await;
+        }
+
+        let output = Arc::new(CommittedOutput {
+            output,
+            batches: batches.into_iter().map(Some).collect(),
+        });
+        let mut last_published = self.last_published.lock().unwrap();
+        *last_published = Some(position);
+        // There may be no subscriber.
+        let _ = self.tx_output.send(output);
+    }
+
+    /// Subscribe to the published certificates. Also returns the position of the last
+    /// certificate published before the subscription (to replay from the store).
+    pub fn subscribe(
+        &self,
+    ) -> (
+        broadcast::Receiver<Arc<CommittedOutput>>,
+        Option<OutputPosition>,
+    ) {
+        let last_published = self.last_published.lock().unwrap();
+        (self.tx_output.subscribe(), *last_published)
+    }
+
+    /// The position of the last published certificate.
+    pub fn last_published(&self) -> Option<OutputPosition> {
+        *self.last_published.lock().unwrap()
+    }
+
+    /// Load the committed certificates of `epoch` with consensus index in `from..=to` from the
+    /// stores. The consensus store only holds the sequence of the current epoch: the
+    /// certificates of the previous epochs are no longer found.
+    pub async fn read(
+        &self,
+        epoch: Epoch,
+        from: SequenceNumber,
+        to: SequenceNumber,
+    ) -> StoreResult<Vec<CommittedOutput>> {
+        if from > to {
+            return Ok(Vec::new());
+        }
+
+        let sequence = self
+            .consensus_store
+            .read_sequenced_certificates(&(from..=to))?
+            .into_iter()
+            .map(|(consensus_index, digest)| (digest, consensus_index))
+            .collect::<Vec<_>>();
+        let certificates = self
+            .certificate_store
+            .read_all(sequence.iter().map(|(digest, _)| *digest))?;
+
+        let mut outputs = Vec::with_capacity(sequence.len());
+        for ((_, consensus_index), certificate) in sequence.into_iter().zip(certificates) {
+            // The certificates may have been garbage collected, or belong to the new epoch.
+            let certificate = match certificate {
+                Some(certificate) if certificate.epoch() == epoch => certificate,
+                _ => continue,
+            };
+            let batches = self
+                .batch_store
+                .read_all(certificate.header.payload.keys().copied())
+                .await?;
+            let output = match self
+                .consensus_store
+                .read_committed_sub_dag(consensus_index)?
+            {
+                Some(sub_dag) => ConsensusOutput {
+                    certificate,
+                    consensus_index,
+                    sub_dag,
+                },
+                None => ConsensusOutput::standalone(certificate, consensus_index),
+            };
+            outputs.push(CommittedOutput { output, batches });
+        }
+        Ok(outputs)
+    }
+}
@@ -25,7 +25,8 @@ use types::{
     ensure,
     error::{DagError, DagError::StoreError, DagResult},
     metered_channel::{Receiver, Sender},
-    Certificate, Header, HeaderDigest, ReconfigureNotification, Round, RoundVoteDigestPair, Vote,
+    Certificate, Header, HeaderDigest, ReconfigureNotification, Round, RoundVoteDigestPair,
+    TransactionStatusStore, Vote,
 };
 
 #[cfg(test)]
@@ -90,6 +91,8 @@ pub struct Core {
     metrics: Arc<PrimaryMetrics>,
     /// Global state manager for centralized state management
     global_state: Option<Arc<dyn types::GlobalStateManager>>,
+    /// Records the certificate of the batches (for the `TransactionStatus` service).
+    transaction_status_store: Arc<TransactionStatusStore>,
 }
 
 impl Core {
@@ -117,6 +120,7 @@ impl Core {
         tx_proposer_certified: Sender<Header>,
         primary_network: P2pNetwork,
         global_state: Option<Arc<dyn types::GlobalStateManager>>,
+        transaction_status_store: Arc<TransactionStatusStore>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
             // Load state từ global_state nếu có
@@ -158,6 +162,7 @@ impl Core {
                 cancel_handlers: HashMap::with_capacity(2 * gc_depth as usize),
                 metrics,
                 global_state,
+                transaction_status_store,
             }
             .run()
             .await;
@@ -502,6 +507,13 @@ impl Core {
 
         // Store the certificate.
         self.certificate_store.write(certificate.clone())?;
+        if let Err(e) = self.transaction_status_store.write_certified(
+            certificate.digest(),
+            certificate.round(),
+            certificate.header.payload.keys().copied(),
+        ) {
+            warn!("Failed to record the batches of {:?}: {}", certificate, e);
+        }
 
         let certificate_source = if self.name.eq(&certificate.header.author) {
             "own"
@@ -876,6 +888,9 @@ impl Core {
                         self.certificates_aggregators.retain(|k, _| k > &gc_round);
                         self.cancel_handlers.retain(|k, _| k > &gc_round);
                         self.gc_round = gc_round;
+                        if let Err(e) = self.transaction_status_store.prune_certified_below(gc_round) {
+                            warn!("Failed to prune the certified batches below round {}: {}", gc_round, e);
+                        }
                         
                         // Update global_state
                         if let Some(ref gs) = self.global_state {
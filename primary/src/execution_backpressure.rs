@@ -0,0 +1,98 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use crate::metrics::ExecutionBackpressureMetrics;
+use config::ExecutionBackpressureParameters;
+use prometheus::Registry;
+use std::{sync::Mutex, time::Duration};
+
+#[cfg(test)]
+#[path = "tests/execution_backpressure_tests.rs"]
+mod execution_backpressure_tests;
+
+/// The heights of the blocks handed to the executor and confirmed by it.
+#[derive(Default)]
+struct Heights {
+    /// The first block handed to the executor since the start: the blocks before it are confirmed.
+    first_finalized: Option<u64>,
+    last_finalized: Option<u64>,
+    last_confirmed: Option<u64>,
+}
+
+/// The signal of a slow executor: the number of blocks handed to the execution sink but not
+/// confirmed yet (acknowledged, or written with the legacy protocol). Past a first threshold the
+/// delivery of the committed certificates to the execution state is slowed down, past a second
+/// one the proposer stops including new batches in its headers. Both only depend on the local
+/// executor: they change the pace of this primary, not the content of the committed blocks.
+pub struct ExecutionBackpressure {
+    parameters: ExecutionBackpressureParameters,
+    heights: Mutex<Heights>,
+    metrics: ExecutionBackpressureMetrics,
+}
+
+impl ExecutionBackpressure {
+    pub fn new(parameters: ExecutionBackpressureParameters, registry: &Registry) -> Self {
+        Self {
+            parameters,
+            heights: Mutex::new(Heights::default()),
+            metrics: ExecutionBackpressureMetrics::new(registry),
+        }
+    }
+
+    /// The block at `height` is handed to the executor.
+    pub fn block_finalized(&self, height: u64) {
+        let mut heights = self.heights.lock().unwrap();
+        heights.first_finalized.get_or_insert(height);
+        heights.last_finalized = heights.last_finalized.max(Some(height));
+        self.update_lag(&heights);
+    }
+
+    /// The executor confirmed the blocks up to `height`.
+    pub fn block_confirmed(&self, height: u64) {
+        let mut heights = self.heights.lock().unwrap();
+        heights.last_confirmed = heights.last_confirmed.max(Some(height));
+        self.update_lag(&heights);
+    }
+
+    /// The number of blocks handed to the executor but not confirmed yet.
+    pub fn lag(&self) -> u64 {
+        Self::lag_of(&self.heights.lock().unwrap())
+    }
+
+    fn lag_of(heights: &Heights) -> u64 {
+        let (first_finalized, last_finalized) =
+            match (heights.first_finalized, heights.last_finalized) {
+                (Some(first), Some(last)) => (first, last),
+                _ => return 0,
+            };
+        let next_unconfirmed = heights
+            .last_confirmed
+            .map_or(first_finalized, |confirmed| confirmed + 1)
+            .max(first_finalized);
+        (last_finalized + 1).saturating_sub(next_unconfirmed)
+    }
+
+    fn update_lag(&self, heights: &Heights) {
+        self.metrics
+            .execution_lag_blocks
+            .set(Self::lag_of(heights) as i64);
+    }
+
+    /// The delay to add before delivering the next committed certificate to the execution state,
+    /// if the executor is behind.
+    pub fn throttle_delay(&self) -> Option<Duration> {
+        let threshold = self.parameters.throttle_lag_blocks;
+        if threshold == 0 || self.lag() < threshold {
+            return None;
+        }
+        self.metrics.execution_throttled_certificates.inc();
+        Some(self.parameters.throttle_delay)
+    }
+
+    /// Whether the proposer should leave the new batches out of its headers.
+    pub fn pause_payload(&self) -> bool {
+        let threshold = self.parameters.pause_payload_lag_blocks;
+        let paused = threshold > 0 && self.lag() >= threshold;
+        self.metrics.proposer_payload_paused.set(paused as i64);
+        paused
+    }
+}
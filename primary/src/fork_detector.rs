@@ -0,0 +1,246 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use crate::metrics::ForkDetectorMetrics;
+use config::{ForkDetectionParameters, SharedCommittee, Stake};
+use crypto::PublicKey;
+use fastcrypto::traits::EncodeDecodeBase64;
+use network::{P2pNetwork, UnreliableNetwork};
+use prometheus::Registry;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+use tracing::{error, info};
+use types::{PrimaryMessage, ReconfigureNotification};
+
+#[cfg(test)]
+#[path = "tests/fork_detector_tests.rs"]
+mod fork_detector_tests;
+
+/// Compares the (height, block hash) of the blocks this node delivered to the executor with
+/// the fingerprints gossiped by the other primaries. A peer reporting a different hash for a
+/// height means that the executed chains diverged. A single (possibly byzantine) peer cannot
+/// halt the delivery: it takes f+1 stake reporting the same conflicting fingerprint.
+pub struct ForkDetector {
+    parameters: ForkDetectionParameters,
+    /// The committee, weighing the reports of the peers.
+    committee: SharedCommittee,
+    /// The hashes of the most recent blocks delivered by this node, by height.
+    local_fingerprints: Mutex<BTreeMap<u64, Vec<u8>>>,
+    /// The authorities that reported a fingerprint conflicting with ours, by (height, hash).
+    conflicting_reports: Mutex<BTreeMap<(u64, Vec<u8>), BTreeSet<PublicKey>>>,
+    /// Whether the delivery of blocks to the executor is halted.
+    halted: watch::Sender<bool>,
+    metrics: ForkDetectorMetrics,
+}
+
+impl ForkDetector {
+    pub fn new(
+        parameters: ForkDetectionParameters,
+        committee: SharedCommittee,
+        registry: &Registry,
+    ) -> Self {
+        let (halted, _) = watch::channel(false);
+        Self {
+            parameters,
+            committee,
+            local_fingerprints: Mutex::new(BTreeMap::new()),
+            conflicting_reports: Mutex::new(BTreeMap::new()),
+            halted,
+            metrics: ForkDetectorMetrics::new(registry),
+        }
+    }
+
+    /// Remember the hash of a block delivered by this node. Only the most recent
+    /// `retained_heights` fingerprints are kept, and the reports about older heights are dropped.
+    pub fn record(&self, height: u64, block_hash: Vec<u8>) {
+        let oldest = {
+            let mut local_fingerprints = self.local_fingerprints.lock().unwrap();
+            local_fingerprints.insert(height, block_hash);
+            while local_fingerprints.len() as u64 > self.parameters.retained_heights.max(1) {
+                let oldest = *local_fingerprints.keys().next().unwrap();
+                local_fingerprints.remove(&oldest);
+            }
+            *local_fingerprints.keys().next().unwrap()
+        };
+        let mut conflicting_reports = self.conflicting_reports.lock().unwrap();
+        *conflicting_reports = conflicting_reports.split_off(&(oldest, Vec::new()));
+    }
+
+    /// The fingerprints of the most recent blocks delivered by this node, by increasing height.
+    pub fn fingerprints(&self) -> Vec<(u64, Vec<u8>)> {
+        self.local_fingerprints
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(height, block_hash)| (*height, block_hash.clone()))
+            .collect()
+    }
+
+    /// Compare the fingerprints gossiped by `from` (an authenticated member of the committee)
+    /// with ours and return the heights with a different block hash. The heights we did not
+    /// deliver (yet) are ignored: they are compared when the peer sends them again, or by the
+    /// peer when we send ours. The delivery is halted (if `halt_on_fork`) once the authorities
+    /// reporting the same conflicting fingerprint hold f+1 stake, so at least one of them is
+    /// honest.
+    pub fn check(&self, from: &PublicKey, fingerprints: &[(u64, Vec<u8>)]) -> Vec<u64> {
+        let conflicts: Vec<_> = {
+            let local_fingerprints = self.local_fingerprints.lock().unwrap();
+            fingerprints
+                .iter()
+                .filter(|(height, peer_hash)| {
+                    let local_hash = match local_fingerprints.get(height) {
+                        Some(local_hash) => local_hash,
+                        None => return false,
+                    };
+                    if local_hash == peer_hash {
+                        return false;
+                    }
+                    error!(
+                        "Fork detected at height {height}: our block hash is {}, peer {} has {}",
+                        hex::encode(local_hash),
+                        from.encode_base64(),
+                        hex::encode(peer_hash)
+                    );
+                    true
+                })
+                .cloned()
+                .collect()
+        };
+        if conflicts.is_empty() {
+            return Vec::new();
+        }
+        self.metrics
+            .block_fingerprint_mismatches
+            .with_label_values(&[&from.encode_base64()])
+            .inc_by(conflicts.len() as u64);
+
+        let committee = self.committee.load();
+        let mut conflicting_reports = self.conflicting_reports.lock().unwrap();
+        let mut max_reported_stake: Stake = 0;
+        for (height, peer_hash) in &conflicts {
+            let reporters = conflicting_reports
+                .entry((*height, peer_hash.clone()))
+                .or_default();
+            reporters.insert(from.clone());
+            let reported_stake = reporters.iter().map(|name| committee.stake(name)).sum();
+            max_reported_stake = max_reported_stake.max(reported_stake);
+        }
+        drop(conflicting_reports);
+
+        if self.parameters.halt_on_fork
+            && max_reported_stake >= committee.validity_threshold()
+            && !self.is_halted()
+        {
+            error!("Halting the delivery of blocks to the executor: f+1 stake reported a different block hash");
+            self.halted.send_replace(true);
+            self.metrics.fork_detector_halted.set(1);
+        }
+        conflicts.into_iter().map(|(height, _)| height).collect()
+    }
+
+    /// Whether the delivery of blocks to the executor is halted because of a fork.
+    pub fn is_halted(&self) -> bool {
+        *self.halted.borrow()
+    }
+
+    /// Resume the delivery of blocks to the executor (eg. once the operator resolved the fork).
+    pub fn resume(&self) {
+        if self.halted.send_replace(false) {
+            info!("Resuming the delivery of blocks to the executor");
+        }
+        self.metrics.fork_detector_halted.set(0);
+    }
+
+    /// Wait until the delivery of blocks to the executor is no longer halted.
+    pub async fn wait_until_resumed(&self) {
+        let mut rx_halted = self.halted.subscribe();
+        while *rx_halted.borrow_and_update() {
+            // The sender lives as long as `self`.
+            let _ = rx_halted.changed().await;
+        }
+    }
+}
+
+/// Periodically sends the fingerprints of the blocks this node delivered to the other primaries.
+pub struct BlockFingerprintGossip {
+    /// The public key of this authority.
+    name: PublicKey,
+    /// The committee information.
+    committee: SharedCommittee,
+    /// The fingerprints of our blocks.
+    fork_detector: Arc<ForkDetector>,
+    /// How often the fingerprints are sent.
+    gossip_interval: Duration,
+    /// Receive reconfiguration updates.
+    rx_reconfigure: watch::Receiver<ReconfigureNotification>,
+    /// A network sender to send the fingerprints to the other primaries.
+    network: P2pNetwork,
+}
+
+impl BlockFingerprintGossip {
+    #[must_use]
+    pub fn spawn(
+        name: PublicKey,
+        committee: SharedCommittee,
+        fork_detector: Arc<ForkDetector>,
+        gossip_interval: Duration,
+        rx_reconfigure: watch::Receiver<ReconfigureNotification>,
+        network: P2pNetwork,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            Self {
+                name,
+                committee,
+                fork_detector,
+                gossip_interval,
+                rx_reconfigure,
+                network,
+            }
+            .run()
+            .await;
+        })
+    }
+
+    fn gossip(&mut self) {
+        let fingerprints = self.fork_detector.fingerprints();
+        if fingerprints.is_empty() {
+            return;
+        }
+        let peers = self
+            .committee
+            .load()
+            .others_primaries(&self.name)
+            .into_iter()
+            .map(|(_, _, network_key)| network_key)
+            .collect();
+        let message = PrimaryMessage::BlockFingerprints {
+            fingerprints,
+            from: self.name.clone(),
+        };
+        self.network.unreliable_broadcast(peers, &message);
+    }
+
+    async fn run(&mut self) {
+        let mut timer = interval(self.gossip_interval);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = timer.tick() => self.gossip(),
+
+                result = self.rx_reconfigure.changed() => {
+                    result.expect("Committee channel dropped");
+                    if let ReconfigureNotification::Shutdown = *self.rx_reconfigure.borrow() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
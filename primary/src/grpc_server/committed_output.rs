@@ -0,0 +1,188 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use crate::{CommittedOutput, CommittedOutputFeed, OutputPosition};
+use fastcrypto::hash::Hash as _;
+use futures::Stream;
+use std::{pin::Pin, sync::Arc};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::debug;
+use types::{
+    Batch, CommittedBatch, CommittedCertificate, CommittedOutput as CommittedOutputService,
+    SubscribeCommittedRequest, TransactionProto,
+};
+
+/// The number of certificates loaded from the store at once when replaying.
+const REPLAY_CHUNK_SIZE: u64 = 100;
+
+/// The number of certificates buffered for a slow client.
+const STREAM_BUFFER_SIZE: usize = 100;
+
+pub struct NarwhalCommittedOutput {
+    /// The committed certificates, live and from the store.
+    committed_output_feed: Arc<CommittedOutputFeed>,
+}
+
+impl NarwhalCommittedOutput {
+    pub fn new(committed_output_feed: Arc<CommittedOutputFeed>) -> Self {
+        Self {
+            committed_output_feed,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl CommittedOutputService for NarwhalCommittedOutput {
+    type SubscribeCommittedStream =
+        Pin<Box<dyn Stream<Item = Result<CommittedCertificate, Status>> + Send>>;
+
+    async fn subscribe_committed(
+        &self,
+        request: Request<SubscribeCommittedRequest>,
+    ) -> Result<Response<Self::SubscribeCommittedStream>, Status> {
+        let request = request.into_inner();
+        let from = (request.epoch, request.from_consensus_index);
+        let (tx_stream, rx_stream) = mpsc::channel(STREAM_BUFFER_SIZE);
+        tokio::spawn(
+            Subscription {
+                feed: self.committed_output_feed.clone(),
+                tx_stream,
+                next: from,
+            }
+            .run(),
+        );
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx_stream))))
+    }
+}
+
+/// Streams the committed certificates to a client, in consensus order and without gaps.
+struct Subscription {
+    feed: Arc<CommittedOutputFeed>,
+    tx_stream: mpsc::Sender<Result<CommittedCertificate, Status>>,
+    /// The position of the next certificate to stream.
+    next: OutputPosition,
+}
+
+impl Subscription {
+    async fn run(mut self) {
+        // Subscribe before replaying so the certificates committed meanwhile are not missed.
+        let (mut rx_output, last_published) = self.feed.subscribe();
+        if let Some(last_published) = last_published {
+            if self.replay(last_published).await.is_err() {
+                return;
+            }
+        }
+
+        loop {
+            let result = match rx_output.recv().await {
+                Ok(output) => {
+                    let (epoch, consensus_index) = output.position();
+                    if (epoch, consensus_index) < self.next {
+                        continue;
+                    }
+                    // Fill the gap, if any, from the store. The first certificate of an epoch
+                    // has nothing before it in the store.
+                    if (epoch, consensus_index) > self.next
+                        && consensus_index > 0
+                        && self.replay((epoch, consensus_index - 1)).await.is_err()
+                    {
+                        return;
+                    }
+                    self.send(&output).await
+                }
+                // This client is too slow for the live feed: catch up from the store.
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Committed output subscription lagged by {skipped} certificates");
+                    match self.feed.last_published() {
+                        Some(last_published) => self.replay(last_published).await,
+                        None => Ok(()),
+                    }
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if result.is_err() {
+                // The client is gone.
+                return;
+            }
+        }
+    }
+
+    /// Stream the certificates from the store up to `to` (included).
+    async fn replay(&mut self, to: OutputPosition) -> Result<(), ()> {
+        let (epoch, to) = to;
+        if self.next.0 < epoch {
+            // The consensus store is cleared on epoch change: only the current epoch is replayed.
+            debug!(
+                "Committed output subscription skips from epoch {} to epoch {epoch}",
+                self.next.0
+            );
+            self.next = (epoch, 0);
+        }
+        while self.next.0 == epoch && self.next.1 <= to {
+            let from = self.next.1;
+            let end = to.min(from.saturating_add(REPLAY_CHUNK_SIZE - 1));
+            let outputs = match self.feed.read(epoch, from, end).await {
+                Ok(outputs) => outputs,
+                Err(e) => {
+                    let _ = self
+                        .tx_stream
+                        .send(Err(Status::internal(e.to_string())))
+                        .await;
+                    return Err(());
+                }
+            };
+            for output in &outputs {
+                self.send(output).await?;
+            }
+            // Skip the certificates that are no longer in the store.
+            self.next = self.next.max((epoch, end + 1));
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, output: &CommittedOutput) -> Result<(), ()> {
+        self.tx_stream
+            .send(Ok(output.into()))
+            .await
+            .map_err(|_| ())?;
+        let (epoch, consensus_index) = output.position();
+        self.next = (epoch, consensus_index + 1);
+        Ok(())
+    }
+}
+
+impl From<&CommittedOutput> for CommittedCertificate {
+    fn from(committed: &CommittedOutput) -> Self {
+        let output = &committed.output;
+        let certificate = &output.certificate;
+        let batches = certificate
+            .header
+            .payload
+            .iter()
+            .zip(&committed.batches)
+            .map(|((digest, worker_id), batch)| CommittedBatch {
+                digest: digest.0.to_vec().into(),
+                worker_id: *worker_id,
+                transactions: batch
+                    .iter()
+                    .flat_map(|Batch(transactions)| transactions.iter().cloned())
+                    .map(TransactionProto::from)
+                    .collect(),
+                missing: batch.is_none(),
+            })
+            .collect();
+
+        CommittedCertificate {
+            consensus_index: output.consensus_index,
+            certificate_digest: Some(certificate.digest().into()),
+            round: certificate.round(),
+            author: Some(certificate.origin().into()),
+            epoch: certificate.epoch(),
+            sub_dag_index: output.sub_dag.sub_dag_index,
+            leader: Some(output.sub_dag.leader.into()),
+            leader_round: output.sub_dag.leader_round,
+            batches,
+        }
+    }
+}
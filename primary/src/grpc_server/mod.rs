@@ -1,11 +1,14 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use self::{configuration::NarwhalConfiguration, validator::NarwhalValidator};
+use self::{
+    committed_output::NarwhalCommittedOutput, configuration::NarwhalConfiguration,
+    transaction_status::NarwhalTransactionStatus, validator::NarwhalValidator,
+};
 use crate::{
     block_synchronizer::handler::Handler,
     grpc_server::{metrics::EndpointMetrics, proposer::NarwhalProposer},
-    BlockCommand, BlockRemoverCommand,
+    BlockCommand, BlockRemoverCommand, CommittedOutputFeed,
 };
 use config::SharedCommittee;
 use consensus::dag::Dag;
@@ -15,11 +18,16 @@ use multiaddr::Multiaddr;
 use std::{sync::Arc, time::Duration};
 use tokio::task::JoinHandle;
 use tracing::{error, info};
-use types::{metered_channel::Sender, ConfigurationServer, ProposerServer, ValidatorServer};
+use types::{
+    metered_channel::Sender, CommittedOutputServer, ConfigurationServer, ProposerServer,
+    TransactionStatusServer, TransactionStatusStore, ValidatorServer,
+};
 
+mod committed_output;
 mod configuration;
 pub mod metrics;
 mod proposer;
+mod transaction_status;
 mod validator;
 
 pub struct ConsensusAPIGrpc<SynchronizerHandler: Handler + Send + Sync + 'static> {
@@ -33,6 +41,8 @@ pub struct ConsensusAPIGrpc<SynchronizerHandler: Handler + Send + Sync + 'static
     block_synchronizer_handler: Arc<SynchronizerHandler>,
     dag: Option<Arc<Dag>>,
     committee: SharedCommittee,
+    transaction_status_store: Arc<TransactionStatusStore>,
+    committed_output_feed: Arc<CommittedOutputFeed>,
     endpoints_metrics: EndpointMetrics,
 }
 
@@ -48,6 +58,8 @@ impl<SynchronizerHandler: Handler + Send + Sync + 'static> ConsensusAPIGrpc<Sync
         block_synchronizer_handler: Arc<SynchronizerHandler>,
         dag: Option<Arc<Dag>>,
         committee: SharedCommittee,
+        transaction_status_store: Arc<TransactionStatusStore>,
+        committed_output_feed: Arc<CommittedOutputFeed>,
         endpoints_metrics: EndpointMetrics,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
@@ -61,6 +73,8 @@ impl<SynchronizerHandler: Handler + Send + Sync + 'static> ConsensusAPIGrpc<Sync
                 block_synchronizer_handler,
                 dag,
                 committee,
+                transaction_status_store,
+                committed_output_feed,
                 endpoints_metrics,
             }
             .run()
@@ -88,12 +102,19 @@ impl<SynchronizerHandler: Handler + Send + Sync + 'static> ConsensusAPIGrpc<Sync
             Arc::clone(&self.committee),
         );
 
+        let narwhal_transaction_status =
+            NarwhalTransactionStatus::new(self.transaction_status_store.clone());
+        let narwhal_committed_output =
+            NarwhalCommittedOutput::new(self.committed_output_feed.clone());
+
         let config = mysten_network::config::Config::default();
         let server = config
             .server_builder_with_metrics(self.endpoints_metrics.clone())
             .add_service(ValidatorServer::new(narwhal_validator))
             .add_service(ConfigurationServer::new(narwhal_configuration))
             .add_service(ProposerServer::new(narwhal_proposer))
+            .add_service(TransactionStatusServer::new(narwhal_transaction_status))
+            .add_service(CommittedOutputServer::new(narwhal_committed_output))
             .bind(&self.socket_address)
             .await?;
         let local_addr = server.local_addr();
@@ -104,3 +125,57 @@ impl<SynchronizerHandler: Handler + Send + Sync + 'static> ConsensusAPIGrpc<Sync
         Ok(())
     }
 }
+
+/// The gRPC server of a primary running the consensus internally: it only serves the
+/// services for the clients, `TransactionStatus` and `CommittedOutput` (the other services of
+/// the consensus API are for an external consensus).
+pub struct ClientAPIGrpc {
+    // Multiaddr of gRPC server
+    socket_address: Multiaddr,
+    transaction_status_store: Arc<TransactionStatusStore>,
+    committed_output_feed: Arc<CommittedOutputFeed>,
+    endpoints_metrics: EndpointMetrics,
+}
+
+impl ClientAPIGrpc {
+    #[must_use]
+    pub fn spawn(
+        socket_address: Multiaddr,
+        transaction_status_store: Arc<TransactionStatusStore>,
+        committed_output_feed: Arc<CommittedOutputFeed>,
+        endpoints_metrics: EndpointMetrics,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let _ = Self {
+                socket_address,
+                transaction_status_store,
+                committed_output_feed,
+                endpoints_metrics,
+            }
+            .run()
+            .await
+            .map_err(|e| error!("{:?}", e));
+        })
+    }
+
+    async fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let narwhal_transaction_status =
+            NarwhalTransactionStatus::new(self.transaction_status_store.clone());
+        let narwhal_committed_output =
+            NarwhalCommittedOutput::new(self.committed_output_feed.clone());
+
+        let config = mysten_network::config::Config::default();
+        let server = config
+            .server_builder_with_metrics(self.endpoints_metrics.clone())
+            .add_service(TransactionStatusServer::new(narwhal_transaction_status))
+            .add_service(CommittedOutputServer::new(narwhal_committed_output))
+            .bind(&self.socket_address)
+            .await?;
+        let local_addr = server.local_addr();
+        info!("Client API gRPC Server listening on {local_addr}");
+
+        server.serve().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use types::{
+    BatchDigest, TransactionStatus, TransactionStatusRequest, TransactionStatusResponse,
+    TransactionStatusStore,
+};
+
+pub struct NarwhalTransactionStatus {
+    /// The certificates of the batches and the blocks of the committed transactions.
+    transaction_status_store: Arc<TransactionStatusStore>,
+}
+
+impl NarwhalTransactionStatus {
+    pub fn new(transaction_status_store: Arc<TransactionStatusStore>) -> Self {
+        Self {
+            transaction_status_store,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl TransactionStatus for NarwhalTransactionStatus {
+    async fn get_transaction_status(
+        &self,
+        request: Request<TransactionStatusRequest>,
+    ) -> Result<Response<TransactionStatusResponse>, Status> {
+        let request = request.into_inner();
+        // The primary does not know the batch of a transaction before it is committed unless it
+        // shares its store with the worker: the client may give the batch reported by the worker.
+        let batch = if request.batch_digest.is_empty() {
+            None
+        } else {
+            let digest =
+                request.batch_digest.as_ref().try_into().map_err(|_| {
+                    Status::invalid_argument("Invalid batch digest: expected 32 bytes")
+                })?;
+            Some(BatchDigest::new(digest))
+        };
+
+        self.transaction_status_store
+            .read_status(&request.transaction_hash, batch)
+            .map(Response::new)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
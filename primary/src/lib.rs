@@ -13,7 +13,10 @@ mod block_remover;
 pub mod block_synchronizer;
 mod block_waiter;
 mod certificate_waiter;
+mod committed_output;
 mod core;
+mod execution_backpressure;
+mod fork_detector;
 mod grpc_server;
 mod header_waiter;
 mod helper;
@@ -41,6 +44,9 @@ pub use crate::{
         BlockHeader,
     },
     block_waiter::{BlockCommand, BlockWaiter, GetBlockResponse},
+    committed_output::{CommittedOutput, CommittedOutputFeed, OutputPosition},
+    execution_backpressure::ExecutionBackpressure,
+    fork_detector::ForkDetector,
     grpc_server::metrics::EndpointMetrics,
     metrics::PrimaryChannelMetrics,
     primary::{NetworkModel, PayloadToken, Primary, PrimaryWorkerMessage, CHANNEL_CAPACITY},
@@ -6,8 +6,9 @@ use network::metrics::NetworkMetrics;
 use prometheus::{
     core::{AtomicI64, GenericGauge},
     default_registry, register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
-    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, HistogramVec,
-    IntCounterVec, IntGauge, IntGaugeVec, Registry,
+    register_int_counter_with_registry, register_int_gauge_vec_with_registry,
+    register_int_gauge_with_registry, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Registry,
 };
 use std::time::Duration;
 use tonic::Code;
@@ -429,6 +430,69 @@ impl Default for PrimaryMetrics {
     }
 }
 
+#[derive(Clone)]
+pub struct ForkDetectorMetrics {
+    /// count number of block heights for which a peer reported a different block hash
+    pub block_fingerprint_mismatches: IntCounterVec,
+    /// 1 if the delivery of blocks to the executor is halted because of a fork, 0 otherwise
+    pub fork_detector_halted: IntGauge,
+}
+
+impl ForkDetectorMetrics {
+    pub fn new(registry: &Registry) -> Self {
+        Self {
+            block_fingerprint_mismatches: register_int_counter_vec_with_registry!(
+                "block_fingerprint_mismatches",
+                "Number of block heights for which a peer reported a different block hash",
+                &["peer"],
+                registry
+            )
+            .unwrap(),
+            fork_detector_halted: register_int_gauge_with_registry!(
+                "fork_detector_halted",
+                "1 if the delivery of blocks to the executor is halted because of a fork",
+                registry
+            )
+            .unwrap(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExecutionBackpressureMetrics {
+    /// the number of blocks handed to the executor but not confirmed yet
+    pub execution_lag_blocks: IntGauge,
+    /// count the committed certificates whose delivery to the execution state was delayed
+    pub execution_throttled_certificates: IntCounter,
+    /// 1 if the headers are proposed without new batches because the executor is behind, 0 otherwise
+    pub proposer_payload_paused: IntGauge,
+}
+
+impl ExecutionBackpressureMetrics {
+    pub fn new(registry: &Registry) -> Self {
+        Self {
+            execution_lag_blocks: register_int_gauge_with_registry!(
+                "execution_lag_blocks",
+                "Number of blocks handed to the executor but not confirmed yet",
+                registry
+            )
+            .unwrap(),
+            execution_throttled_certificates: register_int_counter_with_registry!(
+                "execution_throttled_certificates",
+                "Number of committed certificates whose delivery to the execution state was delayed",
+                registry
+            )
+            .unwrap(),
+            proposer_payload_paused: register_int_gauge_with_registry!(
+                "proposer_payload_paused",
+                "1 if the headers are proposed without new batches because the executor is behind",
+                registry
+            )
+            .unwrap(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PrimaryEndpointMetrics {
     /// Counter of requests, route is a label (ie separate timeseries per route)
@@ -11,7 +11,9 @@ use crate::{
     block_waiter::{BatchMessageError, BatchResult, BlockWaiter},
     certificate_waiter::CertificateWaiter,
     core::Core,
-    grpc_server::ConsensusAPIGrpc,
+    execution_backpressure::ExecutionBackpressure,
+    fork_detector::{BlockFingerprintGossip, ForkDetector},
+    grpc_server::{ClientAPIGrpc, ConsensusAPIGrpc},
     header_waiter::HeaderWaiter,
     helper::Helper,
     metrics::{initialise_metrics, PrimaryMetrics},
@@ -19,7 +21,7 @@ use crate::{
     proposer::Proposer,
     state_handler::StateHandler,
     synchronizer::Synchronizer,
-    BlockCommand, BlockRemover, CertificatesResponse, DeleteBatchMessage,
+    BlockCommand, BlockRemover, CertificatesResponse, CommittedOutputFeed, DeleteBatchMessage,
     PayloadAvailabilityResponse,
 };
 
@@ -27,7 +29,7 @@ use anemo::{types::PeerInfo, PeerId};
 use anemo_tower::{callback::CallbackLayer, trace::TraceLayer};
 use async_trait::async_trait;
 use config::{Parameters, SharedCommittee, SharedWorkerCache, WorkerId, WorkerInfo};
-use consensus::dag::Dag;
+use consensus::{dag::Dag, LeaderSchedule};
 use crypto::{KeyPair, NetworkKeyPair, PublicKey};
 use fastcrypto::{
     traits::{EncodeDecodeBase64, KeyPair as _},
@@ -43,13 +45,14 @@ use store::Store;
 use tokio::sync::oneshot;
 use tokio::{sync::watch, task::JoinHandle};
 use tower::ServiceBuilder;
-use tracing::info;
+use tracing::{info, warn};
 use types::{
     error::DagError,
     metered_channel::{channel, Receiver, Sender},
     BatchDigest, BatchMessage, Certificate, Header, HeaderDigest, PrimaryToPrimary,
-    PrimaryToPrimaryServer, ReconfigureNotification, RoundVoteDigestPair, WorkerInfoResponse,
-    WorkerPrimaryError, WorkerPrimaryMessage, WorkerToPrimary, WorkerToPrimaryServer,
+    PrimaryToPrimaryServer, ReconfigureNotification, RoundVoteDigestPair, TransactionStatusStore,
+    WorkerInfoResponse, WorkerPrimaryError, WorkerPrimaryMessage, WorkerToPrimary,
+    WorkerToPrimaryServer,
 };
 pub use types::{PrimaryMessage, PrimaryWorkerMessage};
 
@@ -95,6 +98,11 @@ impl Primary {
         // See comments in Subscriber::spawn
         rx_executor_network: Option<oneshot::Sender<P2pNetwork>>,
         global_state: Option<Arc<dyn types::GlobalStateManager>>,
+        transaction_status_store: Arc<TransactionStatusStore>,
+        committed_output_feed: Arc<CommittedOutputFeed>,
+        fork_detector: Option<Arc<ForkDetector>>,
+        execution_backpressure: Option<Arc<ExecutionBackpressure>>,
+        leader_schedule: Option<LeaderSchedule>,
     ) -> Vec<JoinHandle<()>> {
         // Write the parameters to the logs.
         parameters.tracing();
@@ -209,6 +217,8 @@ impl Primary {
             tx_primary_messages: tx_primary_messages.clone(),
             tx_helper_requests,
             tx_availability_responses,
+            committee: committee.clone(),
+            fork_detector: fork_detector.clone(),
         });
         let worker_service = WorkerToPrimaryServer::new(WorkerReceiverHandler {
             tx_our_digests,
@@ -317,6 +327,7 @@ impl Primary {
             /* tx_proposer_certified */ tx_proposer_certified.clone(),
             core_primary_network,
             global_state.clone(),
+            transaction_status_store.clone(),
         );
         // Receives batch digests from other workers. They are only used to validate headers.
         let payload_receiver_handle = PayloadReceiver::spawn(
@@ -435,6 +446,8 @@ impl Primary {
             /* rx_sequenced */ rx_proposer_sequenced,
             /* rx_certified */ rx_proposer_certified,
             global_state.clone(),
+            execution_backpressure,
+            leader_schedule,
         );
 
         // The `Helper` is dedicated to reply to certificates & payload availability requests
@@ -450,6 +463,18 @@ impl Primary {
             helper_primary_network,
         );
 
+        // Exchanges the fingerprints of the blocks delivered to the executor with the other primaries.
+        let fingerprint_gossip_handle = fork_detector.map(|fork_detector| {
+            BlockFingerprintGossip::spawn(
+                name.clone(),
+                committee.clone(),
+                fork_detector,
+                parameters.fork_detection.gossip_interval,
+                tx_reconfigure.subscribe(),
+                P2pNetwork::new(network.clone()),
+            )
+        });
+
         // Keeps track of the latest consensus round and allows other tasks to clean up their their internal state
         let state_handler_handle = StateHandler::spawn(
             name.clone(),
@@ -466,7 +491,7 @@ impl Primary {
 
         let consensus_api_handle = if !internal_consensus {
             // Spawn a grpc server to accept requests from external consensus layer.
-            Some(ConsensusAPIGrpc::spawn(
+            ConsensusAPIGrpc::spawn(
                 name.clone(),
                 parameters.consensus_api_grpc.socket_addr,
                 tx_get_block_commands,
@@ -476,10 +501,19 @@ impl Primary {
                 block_synchronizer_handler,
                 dag,
                 committee.clone(),
+                transaction_status_store,
+                committed_output_feed,
                 endpoint_metrics,
-            ))
+            )
         } else {
-            None
+            // Clients still query the lifecycle of their transactions and stream the committed
+            // output on the consensus API address.
+            ClientAPIGrpc::spawn(
+                parameters.consensus_api_grpc.socket_addr,
+                transaction_status_store,
+                committed_output_feed,
+                endpoint_metrics,
+            )
         };
 
         // NOTE: This log entry is used to compute performance.
@@ -503,12 +537,9 @@ impl Primary {
             proposer_handle,
             helper_handle,
             state_handler_handle,
+            consensus_api_handle,
         ];
-
-        if let Some(h) = consensus_api_handle {
-            handles.push(h);
-        }
-
+        handles.extend(fingerprint_gossip_handle);
         handles
     }
 }
@@ -519,6 +550,22 @@ struct PrimaryReceiverHandler {
     tx_primary_messages: Sender<PrimaryMessage>,
     tx_helper_requests: Sender<PrimaryMessage>,
     tx_availability_responses: Sender<AvailabilityResponse>,
+    /// The committee information, to authenticate the senders of block fingerprints.
+    committee: SharedCommittee,
+    /// Compares the block fingerprints of the other primaries with ours (if enabled).
+    fork_detector: Option<Arc<ForkDetector>>,
+}
+
+impl PrimaryReceiverHandler {
+    /// The authority whose primary holds the network key of `peer_id`.
+    fn authority_of(&self, peer_id: &PeerId) -> Option<PublicKey> {
+        self.committee
+            .load()
+            .authorities
+            .iter()
+            .find(|(_, authority)| PeerId(authority.network_key.0.to_bytes()) == *peer_id)
+            .map(|(name, _)| name.clone())
+    }
 }
 
 #[async_trait]
@@ -527,6 +574,7 @@ impl PrimaryToPrimary for PrimaryReceiverHandler {
         &self,
         request: anemo::Request<PrimaryMessage>,
     ) -> Result<anemo::Response<()>, anemo::rpc::Status> {
+        let peer_id = request.peer_id().copied();
         let message = request.into_body();
 
         match message {
@@ -577,6 +625,22 @@ impl PrimaryToPrimary for PrimaryReceiverHandler {
                 }))
                 .await
                 .map_err(|_| DagError::ShuttingDown),
+            PrimaryMessage::BlockFingerprints { fingerprints, from } => {
+                if let Some(fork_detector) = &self.fork_detector {
+                    // Only the authority itself can report its fingerprints.
+                    match peer_id.and_then(|peer_id| self.authority_of(&peer_id)) {
+                        Some(sender) if sender == from => {
+                            fork_detector.check(&from, &fingerprints);
+                        }
+                        sender => warn!(
+                            "Dropping the block fingerprints of {} sent by {:?}",
+                            from.encode_base64(),
+                            sender.map(|sender| sender.encode_base64())
+                        ),
+                    }
+                }
+                Ok(())
+            }
             _ => self
                 .tx_primary_messages
                 .send(message)
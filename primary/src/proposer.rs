@@ -1,8 +1,9 @@
 // Copyright(C) Facebook, Inc. and its affiliates.
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
-use crate::{metrics::PrimaryMetrics, NetworkModel};
+use crate::{metrics::PrimaryMetrics, ExecutionBackpressure, NetworkModel};
 use config::{Committee, Epoch, WorkerId};
+use consensus::LeaderSchedule;
 use crypto::{PublicKey, Signature};
 use fastcrypto::{hash::Digest, hash::Hash as _, SignatureService};
 use std::{cmp::Ordering, collections::{HashMap, HashSet}, sync::Arc};
@@ -80,6 +81,13 @@ pub struct Proposer {
     rx_certified: Receiver<Header>,
     /// Global state manager for centralized state management
     global_state: Option<Arc<dyn types::GlobalStateManager>>,
+    /// Signals when the local executor falls behind the committed blocks.
+    execution_backpressure: Option<Arc<ExecutionBackpressure>>,
+    /// The leader schedule of the consensus, if it is not the stake-weighted one.
+    leader_schedule: Option<LeaderSchedule>,
+    /// Whether the new batches from our workers are left out of the next header because the
+    /// executor is behind. The InFlight batches are still re-included.
+    payload_paused: bool,
 }
 
 impl Proposer {
@@ -101,6 +109,8 @@ impl Proposer {
         rx_sequenced: Receiver<Certificate>,
         rx_certified: Receiver<Header>,
         global_state: Option<Arc<dyn types::GlobalStateManager>>,
+        execution_backpressure: Option<Arc<ExecutionBackpressure>>,
+        leader_schedule: Option<LeaderSchedule>,
     ) -> JoinHandle<()> {
         let genesis = Certificate::genesis(&committee);
         tokio::spawn(async move {
@@ -138,6 +148,9 @@ impl Proposer {
                 rx_sequenced,
                 rx_certified,
                 global_state,
+                execution_backpressure,
+                leader_schedule,
+                payload_paused: false,
             }
             .run()
             .await;
@@ -145,8 +158,13 @@ impl Proposer {
     }
 
     async fn make_header(&mut self) -> DagResult<()> {
-        // Collect all batches: new digests from workers + InFlight batches from certified headers
-        let mut all_digests = self.digests.drain(..).collect::<Vec<_>>();
+        // Collect all batches: new digests from workers + InFlight batches from certified headers.
+        // While the executor is behind, the new digests wait for a later header.
+        let mut all_digests = if self.payload_paused {
+            Vec::new()
+        } else {
+            self.digests.drain(..).collect::<Vec<_>>()
+        };
         
         // FORK-SAFE: Re-include InFlight batches (from certified headers, not yet sequenced)
         // CRITICAL: Only re-include batches that:
@@ -368,9 +386,7 @@ impl Proposer {
             // In partial synchrony, if this node is going to be the leader of the next
             // round, we set a lower timeout value to increase its chance of committing
             // the leader committed.
-            NetworkModel::PartiallySynchronous
-                if self.committee.leader(self.round + 1) == self.name =>
-            {
+            NetworkModel::PartiallySynchronous if self.leader(self.round + 1) == self.name => {
                 Instant::now() + self.max_header_delay / 2
             }
 
@@ -379,9 +395,17 @@ impl Proposer {
         }
     }
 
+    /// The leader of `round`, as elected by the consensus.
+    fn leader(&self, round: Round) -> PublicKey {
+        match &self.leader_schedule {
+            Some(leader_schedule) => leader_schedule.leader(round),
+            None => self.committee.leader(round),
+        }
+    }
+
     /// Update the last leader certificate. This is only relevant in partial synchrony.
     fn update_leader(&mut self) -> bool {
-        let leader_name = self.committee.leader(self.round);
+        let leader_name = self.leader(self.round);
         self.last_leader = self
             .last_parents
             .iter()
@@ -443,6 +467,28 @@ impl Proposer {
         }
     }
 
+    /// Pauses (or resumes) the new batches in our headers depending on the executor's lag.
+    fn update_payload_paused(&mut self) {
+        let paused = self
+            .execution_backpressure
+            .as_ref()
+            .map_or(false, |backpressure| backpressure.pause_payload());
+        if paused != self.payload_paused {
+            if paused {
+                warn!(
+                    "⏸️ [PROPOSER] Executor is behind: leaving new batches out of the headers (round {})",
+                    self.round
+                );
+            } else {
+                info!(
+                    "▶️ [PROPOSER] Executor caught up: including new batches again (round {})",
+                    self.round
+                );
+            }
+            self.payload_paused = paused;
+        }
+    }
+
     /// Main loop listening to incoming messages.
     pub async fn run(&mut self) {
         debug!("Dag starting at round {}", self.round);
@@ -460,7 +506,8 @@ impl Proposer {
             // the leader or the leader has enough votes to enable a commit). The latter condition only matters
             // in partially synchrony.
             let enough_parents = !self.last_parents.is_empty();
-            let enough_digests = self.payload_size >= self.header_size;
+            self.update_payload_paused();
+            let enough_digests = !self.payload_paused && self.payload_size >= self.header_size;
             let mut timer_expired = timer.is_elapsed();
 
             if (timer_expired || (enough_digests && advance)) && enough_parents {
@@ -555,7 +602,9 @@ impl Proposer {
                             .set(self.round as i64);
                     },
                 }
-                self.payload_size = 0;
+                if !self.payload_paused {
+                    self.payload_size = 0;
+                }
 
                 // Reschedule the timer.
                 let deadline = self.timeout_value();
@@ -743,7 +792,8 @@ impl Proposer {
                 }
 
                 // Receive digests from our workers.
-                Some((digest, worker_id)) = self.rx_workers.recv() => {
+                // While the payload is paused, the workers' channel fills up and they back off.
+                Some((digest, worker_id)) = self.rx_workers.recv(), if !self.payload_paused => {
                     self.payload_size += Digest::from(digest).size();
                     self.digests.push((digest, worker_id));
                 }
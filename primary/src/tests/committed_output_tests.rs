@@ -0,0 +1,230 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+use crate::common::create_db_stores;
+use std::collections::HashMap;
+use test_utils::{
+    fixture_batch_with_transactions, make_consensus_store, open_batch_store, temp_dir,
+    CommitteeFixture,
+};
+use tokio::sync::broadcast::error::TryRecvError;
+
+/// Sequence `count` certificates (with 2 batches each) of `epoch` in the consensus store and
+/// return their outputs and batches.
+fn sequence_certificates(
+    consensus_store: &ConsensusStore,
+    certificate_store: &CertificateStore,
+    epoch: Epoch,
+    count: u64,
+) -> Vec<(ConsensusOutput, Vec<Batch>)> {
+    let mut fixture = CommitteeFixture::builder().build();
+    for _ in 0..epoch {
+        fixture.bump_epoch();
+    }
+    let committee = fixture.committee();
+    let author = fixture.authorities().next().unwrap();
+
+    (0..count)
+        .map(|consensus_index| {
+            let batches = vec![
+                fixture_batch_with_transactions(10),
+                fixture_batch_with_transactions(5),
+            ];
+            let header = author
+                .header_builder(&committee)
+                .with_payload_batch(batches[0].clone(), 0)
+                .with_payload_batch(batches[1].clone(), 1)
+                .build(author.keypair())
+                .unwrap();
+            let certificate = fixture.certificate(&header);
+            certificate_store.write(certificate.clone()).unwrap();
+
+            consensus_store
+                .write_consensus_state(
+                    &HashMap::new(),
+                    &consensus_index,
+                    &certificate.digest(),
+                    None,
+                    None,
+                )
+                .unwrap();
+            (
+                ConsensusOutput::standalone(certificate, consensus_index),
+                batches,
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn replay_published_certificates() {
+    let (_, certificate_store, _) = create_db_stores();
+    let consensus_store = make_consensus_store(&temp_dir());
+    let feed = CommittedOutputFeed::new(
+        consensus_store.clone(),
+        certificate_store.clone(),
+        open_batch_store(),
+    );
+
+    let sequence = sequence_certificates(&consensus_store, &certificate_store, 0, 3);
+    for (output, batches) in sequence.clone() {
+        feed.publish(output, batches).await;
+    }
+    assert_eq!(feed.last_published(), Some((0, 2)));
+
+    let replayed = feed.read(0, 0, 2).await.unwrap();
+    assert_eq!(replayed.len(), 3);
+    for (committed, (output, batches)) in replayed.iter().zip(&sequence) {
+        assert_eq!(committed.output.consensus_index, output.consensus_index);
+        assert_eq!(
+            committed.output.certificate.digest(),
+            output.certificate.digest()
+        );
+        let expected: Vec<_> = batches.iter().cloned().map(Some).collect();
+        assert_eq!(committed.batches, expected);
+    }
+
+    // A range within the sequence.
+    let replayed = feed.read(0, 1, 1).await.unwrap();
+    assert_eq!(replayed.len(), 1);
+    assert_eq!(replayed[0].output.consensus_index, 1);
+    assert!(feed.read(0, 2, 1).await.unwrap().is_empty());
+    // The certificates of another epoch are not served for these indices.
+    assert!(feed.read(1, 0, 2).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn replay_unpublished_batches_as_missing() {
+    let (_, certificate_store, _) = create_db_stores();
+    let consensus_store = make_consensus_store(&temp_dir());
+    let feed = CommittedOutputFeed::new(
+        consensus_store.clone(),
+        certificate_store.clone(),
+        open_batch_store(),
+    );
+
+    // The certificates were sequenced but their batches were never published.
+    sequence_certificates(&consensus_store, &certificate_store, 0, 2);
+    let replayed = feed.read(0, 0, 1).await.unwrap();
+    assert_eq!(replayed.len(), 2);
+    assert!(replayed
+        .iter()
+        .all(|committed| committed.batches == vec![None, None]));
+}
+
+#[tokio::test]
+async fn subscribe_tails_published_certificates() {
+    let (_, certificate_store, _) = create_db_stores();
+    let consensus_store = make_consensus_store(&temp_dir());
+    let feed = CommittedOutputFeed::new(
+        consensus_store.clone(),
+        certificate_store.clone(),
+        open_batch_store(),
+    );
+    let mut sequence =
+        sequence_certificates(&consensus_store, &certificate_store, 0, 2).into_iter();
+
+    let (mut rx_output, last_published) = feed.subscribe();
+    assert_eq!(last_published, None);
+
+    let (output, batches) = sequence.next().unwrap();
+    feed.publish(output.clone(), batches).await;
+    let received = rx_output.recv().await.unwrap();
+    assert_eq!(received.output.consensus_index, 0);
+    assert_eq!(received.batches.len(), 2);
+
+    // Publishing a certificate twice does not broadcast it again.
+    feed.publish(output, Vec::new()).await;
+    assert!(matches!(rx_output.try_recv(), Err(TryRecvError::Empty)));
+
+    // A late subscriber replays up to the last published certificate.
+    let (_rx_output, last_published) = feed.subscribe();
+    assert_eq!(last_published, Some((0, 0)));
+}
+
+#[tokio::test]
+async fn restore_skips_certificates_published_before_restart() {
+    let (_, certificate_store, _) = create_db_stores();
+    let consensus_store = make_consensus_store(&temp_dir());
+    let feed = CommittedOutputFeed::new(
+        consensus_store.clone(),
+        certificate_store.clone(),
+        open_batch_store(),
+    );
+    let sequence = sequence_certificates(&consensus_store, &certificate_store, 0, 2);
+
+    feed.restore(0, 1);
+    assert_eq!(feed.last_published(), Some((0, 0)));
+
+    let (mut rx_output, _) = feed.subscribe();
+    for (output, batches) in sequence {
+        feed.publish(output, batches).await;
+    }
+    let received = rx_output.recv().await.unwrap();
+    assert_eq!(received.output.consensus_index, 1);
+    assert!(matches!(rx_output.try_recv(), Err(TryRecvError::Empty)));
+}
+
+#[tokio::test]
+async fn publish_the_certificates_of_a_new_epoch() {
+    let (_, certificate_store, _) = create_db_stores();
+    let consensus_store = make_consensus_store(&temp_dir());
+    let feed = CommittedOutputFeed::new(
+        consensus_store.clone(),
+        certificate_store.clone(),
+        open_batch_store(),
+    );
+    for (output, batches) in sequence_certificates(&consensus_store, &certificate_store, 0, 3) {
+        feed.publish(output, batches).await;
+    }
+    assert_eq!(feed.last_published(), Some((0, 2)));
+
+    // The consensus store is cleared on epoch change and the consensus indices restart at 0.
+    consensus_store.clear().unwrap();
+    let (mut rx_output, _) = feed.subscribe();
+    let mut sequence = sequence_certificates(&consensus_store, &certificate_store, 1, 2);
+    let (output, batches) = sequence.remove(0);
+    feed.publish(output, batches).await;
+    let received = rx_output.recv().await.unwrap();
+    assert_eq!(received.position(), (1, 0));
+    assert_eq!(feed.last_published(), Some((1, 0)));
+
+    // Only the sequence of the new epoch is left to replay.
+    assert!(feed.read(0, 0, 2).await.unwrap().is_empty());
+    let replayed = feed.read(1, 0, 1).await.unwrap();
+    assert_eq!(replayed.len(), 2);
+    assert_eq!(replayed[1].output.certificate.epoch(), 1);
+    assert_eq!(replayed[1].batches, vec![None, None]);
+}
+
+#[tokio::test]
+async fn replay_keeps_the_consensus_index_of_each_certificate() {
+    let (_, certificate_store, _) = create_db_stores();
+    let sequence =
+        sequence_certificates(&make_consensus_store(&temp_dir()), &certificate_store, 0, 3);
+
+    // The certificate with consensus index 1 is missing from the sequence.
+    let consensus_store = make_consensus_store(&temp_dir());
+    for (output, _) in sequence.iter().step_by(2) {
+        consensus_store
+            .write_consensus_state(
+                &HashMap::new(),
+                &output.consensus_index,
+                &output.certificate.digest(),
+                None,
+                None,
+            )
+            .unwrap();
+    }
+    let feed = CommittedOutputFeed::new(consensus_store, certificate_store, open_batch_store());
+
+    let replayed = feed.read(0, 0, 2).await.unwrap();
+    assert_eq!(replayed.len(), 2);
+    for (committed, (output, _)) in replayed.iter().zip(sequence.iter().step_by(2)) {
+        assert_eq!(committed.output.consensus_index, output.consensus_index);
+        assert_eq!(
+            committed.output.certificate.digest(),
+            output.certificate.digest()
+        );
+    }
+}
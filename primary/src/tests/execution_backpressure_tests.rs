@@ -0,0 +1,77 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+
+fn backpressure(throttle_lag_blocks: u64, pause_payload_lag_blocks: u64) -> ExecutionBackpressure {
+    ExecutionBackpressure::new(
+        ExecutionBackpressureParameters {
+            throttle_lag_blocks,
+            throttle_delay: Duration::from_millis(10),
+            pause_payload_lag_blocks,
+        },
+        &Registry::new(),
+    )
+}
+
+#[test]
+fn lag_counts_unconfirmed_blocks() {
+    let backpressure = backpressure(2, 4);
+    assert_eq!(backpressure.lag(), 0);
+
+    // After a restart, the first block handed to the executor is at height 100.
+    backpressure.block_finalized(100);
+    assert_eq!(backpressure.lag(), 1);
+    backpressure.block_confirmed(100);
+    assert_eq!(backpressure.lag(), 0);
+
+    for height in 101..104 {
+        backpressure.block_finalized(height);
+    }
+    assert_eq!(backpressure.lag(), 3);
+    backpressure.block_confirmed(102);
+    assert_eq!(backpressure.lag(), 1);
+    // Late confirmations do not go back.
+    backpressure.block_confirmed(101);
+    assert_eq!(backpressure.lag(), 1);
+}
+
+#[test]
+fn thresholds_throttle_then_pause() {
+    let backpressure = backpressure(2, 4);
+    backpressure.block_finalized(0);
+    assert_eq!(backpressure.throttle_delay(), None);
+    assert!(!backpressure.pause_payload());
+
+    backpressure.block_finalized(1);
+    assert_eq!(
+        backpressure.throttle_delay(),
+        Some(Duration::from_millis(10))
+    );
+    assert!(!backpressure.pause_payload());
+
+    for height in 2..4 {
+        backpressure.block_finalized(height);
+    }
+    assert!(backpressure.pause_payload());
+    assert_eq!(backpressure.metrics.proposer_payload_paused.get(), 1);
+
+    backpressure.block_confirmed(3);
+    assert_eq!(backpressure.throttle_delay(), None);
+    assert!(!backpressure.pause_payload());
+    assert_eq!(backpressure.metrics.proposer_payload_paused.get(), 0);
+    assert_eq!(
+        backpressure.metrics.execution_throttled_certificates.get(),
+        1
+    );
+}
+
+#[test]
+fn zero_thresholds_disable_backpressure() {
+    let backpressure = backpressure(0, 0);
+    for height in 0..1000 {
+        backpressure.block_finalized(height);
+    }
+    assert_eq!(backpressure.lag(), 1000);
+    assert_eq!(backpressure.throttle_delay(), None);
+    assert!(!backpressure.pause_payload());
+}
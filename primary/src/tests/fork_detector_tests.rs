@@ -0,0 +1,136 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+use arc_swap::ArcSwap;
+use test_utils::CommitteeFixture;
+
+fn parameters(retained_heights: u64, halt_on_fork: bool) -> ForkDetectionParameters {
+    ForkDetectionParameters {
+        retained_heights,
+        halt_on_fork,
+        ..ForkDetectionParameters::default()
+    }
+}
+
+fn fork_detector(
+    fixture: &CommitteeFixture,
+    retained_heights: u64,
+    halt_on_fork: bool,
+) -> ForkDetector {
+    ForkDetector::new(
+        parameters(retained_heights, halt_on_fork),
+        Arc::new(ArcSwap::from_pointee(fixture.committee())),
+        &Registry::new(),
+    )
+}
+
+fn peers(fixture: &CommitteeFixture) -> Vec<PublicKey> {
+    fixture.authorities().map(|a| a.public_key()).collect()
+}
+
+#[test]
+fn record_keeps_most_recent_fingerprints() {
+    let detector = fork_detector(&CommitteeFixture::builder().build(), 3, false);
+    for height in 0..5 {
+        detector.record(height, vec![height as u8; 32]);
+    }
+    assert_eq!(
+        detector.fingerprints(),
+        vec![(2, vec![2; 32]), (3, vec![3; 32]), (4, vec![4; 32])]
+    );
+}
+
+#[test]
+fn check_reports_different_hashes() {
+    let fixture = CommitteeFixture::builder().build();
+    let detector = fork_detector(&fixture, 10, false);
+    let peer = peers(&fixture)[0].clone();
+    detector.record(1, vec![1; 32]);
+    detector.record(2, vec![2; 32]);
+
+    // Same chain, and heights we did not deliver yet.
+    let same = vec![(1, vec![1; 32]), (2, vec![2; 32]), (3, vec![3; 32])];
+    assert!(detector.check(&peer, &same).is_empty());
+
+    let forked = vec![(1, vec![1; 32]), (2, vec![9; 32])];
+    assert_eq!(detector.check(&peer, &forked), vec![2]);
+    assert_eq!(
+        detector
+            .metrics
+            .block_fingerprint_mismatches
+            .with_label_values(&[&peer.encode_base64()])
+            .get(),
+        1
+    );
+    // Halting is disabled.
+    assert_eq!(detector.check(&peers(&fixture)[1], &forked), vec![2]);
+    assert!(!detector.is_halted());
+}
+
+#[test]
+fn one_peer_cannot_halt_delivery() {
+    let fixture = CommitteeFixture::builder().build();
+    let detector = fork_detector(&fixture, 10, true);
+    let peers = peers(&fixture);
+    detector.record(1, vec![1; 32]);
+
+    // A single authority (f = 1) reporting a different hash, however often, does not halt.
+    for _ in 0..3 {
+        assert_eq!(detector.check(&peers[0], &[(1, vec![9; 32])]), vec![1]);
+    }
+    assert!(!detector.is_halted());
+
+    // Another authority reporting yet another hash: no f+1 stake behind any single fingerprint.
+    assert_eq!(detector.check(&peers[1], &[(1, vec![8; 32])]), vec![1]);
+    assert!(!detector.is_halted());
+
+    // f+1 stake behind the same conflicting fingerprint.
+    assert_eq!(detector.check(&peers[2], &[(1, vec![9; 32])]), vec![1]);
+    assert!(detector.is_halted());
+}
+
+#[test]
+fn reports_of_forgotten_heights_are_dropped() {
+    let fixture = CommitteeFixture::builder().build();
+    let detector = fork_detector(&fixture, 2, true);
+    let peers = peers(&fixture);
+    detector.record(1, vec![1; 32]);
+    detector.check(&peers[0], &[(1, vec![9; 32])]);
+
+    // Height 1 falls out of the retained fingerprints, along with its report.
+    detector.record(2, vec![2; 32]);
+    detector.record(3, vec![3; 32]);
+    assert!(detector.check(&peers[1], &[(1, vec![9; 32])]).is_empty());
+    assert!(detector.conflicting_reports.lock().unwrap().is_empty());
+    assert!(!detector.is_halted());
+}
+
+#[tokio::test]
+async fn fork_halts_delivery_until_resumed() {
+    let fixture = CommitteeFixture::builder().build();
+    let detector = Arc::new(fork_detector(&fixture, 10, true));
+    detector.record(1, vec![1; 32]);
+    // Not halted: returns immediately.
+    detector.wait_until_resumed().await;
+
+    for peer in &peers(&fixture)[..2] {
+        detector.check(peer, &[(1, vec![9; 32])]);
+    }
+    assert!(detector.is_halted());
+    assert_eq!(detector.metrics.fork_detector_halted.get(), 1);
+
+    let waiter = tokio::spawn({
+        let detector = detector.clone();
+        async move { detector.wait_until_resumed().await }
+    });
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), detector.wait_until_resumed())
+            .await
+            .is_err()
+    );
+
+    detector.resume();
+    waiter.await.unwrap();
+    assert!(!detector.is_halted());
+    assert_eq!(detector.metrics.fork_detector_halted.get(), 0);
+}
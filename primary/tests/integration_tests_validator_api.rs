@@ -29,7 +29,7 @@ use types::{
     ReadCausalRequest, ReconfigureNotification, RemoveCollectionsRequest, RetrievalResult,
     Transaction, ValidatorClient,
 };
-use worker::Worker;
+use worker::{TrivialTransactionValidator, Worker};
 
 #[tokio::test]
 async fn test_get_collections() {
@@ -143,6 +143,8 @@ async fn test_get_collections() {
         worker_cache.clone(),
         parameters.clone(),
         store.batch_store.clone(),
+        Arc::new(TrivialTransactionValidator),
+        store.transaction_status_store.clone(),
         metrics,
     );
 
@@ -360,6 +362,8 @@ async fn test_remove_collections() {
         worker_cache.clone(),
         parameters.clone(),
         store.batch_store.clone(),
+        Arc::new(TrivialTransactionValidator),
+        store.transaction_status_store.clone(),
         metrics,
     );
 
@@ -965,6 +969,8 @@ async fn test_get_collections_with_missing_certificates() {
         worker_cache.clone(),
         parameters.clone(),
         store_primary_1.batch_store,
+        Arc::new(TrivialTransactionValidator),
+        store_primary_1.transaction_status_store.clone(),
         metrics_1,
     );
 
@@ -1014,6 +1020,8 @@ async fn test_get_collections_with_missing_certificates() {
         worker_cache.clone(),
         parameters.clone(),
         store_primary_2.batch_store,
+        Arc::new(TrivialTransactionValidator),
+        store_primary_2.transaction_status_store.clone(),
         metrics_2,
     );
 
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::{temp_dir, CommitteeFixture};
 use arc_swap::ArcSwap;
+use bytes::Bytes;
 use config::{Parameters, SharedCommittee, SharedWorkerCache, WorkerId};
 use crypto::{KeyPair, NetworkKeyPair, PublicKey};
 use executor::SerializedTransaction;
@@ -9,12 +10,21 @@ use fastcrypto::traits::KeyPair as _;
 use itertools::Itertools;
 use multiaddr::Multiaddr;
 use node::{
-    execution_state::SimpleExecutionState,
+    execution_state::{SimpleExecutionState, UdsExecutionState},
     metrics::{primary_metrics_registry, worker_metrics_registry},
+    uds_executor::{ReceivedBlock, StandInConfig, StandInExecutor, StandInHandle},
     Node, NodeStorage,
 };
 use prometheus::{proto::Metric, Registry};
-use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc, sync::Arc, time::Duration};
+use prost::Message as _;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 use telemetry_subscribers::TelemetryGuards;
 use tokio::{
     sync::{broadcast::Sender, mpsc::channel, RwLock},
@@ -22,7 +32,10 @@ use tokio::{
 };
 use tonic::transport::Channel;
 use tracing::info;
-use types::{ConfigurationClient, ProposerClient, TransactionsClient};
+use types::{
+    ConfigurationClient, ConsensusStore, ProposerClient, TransactionProto, TransactionsClient,
+};
+use worker::transaction_logger::transaction::{Transaction, Transactions};
 
 #[cfg(test)]
 #[path = "tests/cluster_tests.rs"]
@@ -48,6 +61,25 @@ impl Cluster {
     /// be disabled and the gRPC server will be enabled to manage the Collections & the
     /// DAG externally.
     pub fn new(parameters: Option<Parameters>, internal_consensus_enabled: bool) -> Self {
+        Self::build(parameters, internal_consensus_enabled, false)
+    }
+
+    /// Initialises a new cluster like `new`, where every primary delivers its committed blocks
+    /// through a `UdsExecutionState` to an executor stand-in capturing them. The stand-in of an
+    /// authority outlives the restarts of its primary, so the blocks of the whole run can be
+    /// compared with `assert_identical_blocks`.
+    pub fn new_with_block_capture(
+        parameters: Option<Parameters>,
+        internal_consensus_enabled: bool,
+    ) -> Self {
+        Self::build(parameters, internal_consensus_enabled, true)
+    }
+
+    fn build(
+        parameters: Option<Parameters>,
+        internal_consensus_enabled: bool,
+        block_capture: bool,
+    ) -> Self {
         let fixture = CommitteeFixture::builder().randomize_ports(true).build();
         let c = fixture.committee();
         let shared_worker_cache = fixture.shared_worker_cache();
@@ -70,6 +102,7 @@ impl Cluster {
                 shared_committee.clone(),
                 shared_worker_cache.clone(),
                 internal_consensus_enabled,
+                block_capture,
             );
             nodes.insert(id, authority);
         }
@@ -238,6 +271,58 @@ impl Cluster {
         rounds
     }
 
+    /// Waits until every authority capturing its blocks delivered the block at `height`.
+    pub async fn wait_for_block_height(&self, height: u64) {
+        for id in 0..self.authorities.len() {
+            if let Some(block_capture) = self.authority(id).primary().await.block_capture {
+                block_capture.wait_for_height(height).await;
+                info!("[Node {id}] Delivered block {height}");
+            }
+        }
+    }
+
+    /// Asserts that the authorities capturing their blocks delivered byte-identical blocks at
+    /// every height they all reached, whether they were restarted or not. Returns the number of
+    /// heights compared.
+    pub async fn assert_identical_blocks(&self) -> u64 {
+        let mut captured: Vec<(usize, BTreeMap<u64, ReceivedBlock>)> = Vec::new();
+        for id in 0..self.authorities.len() {
+            if let Some(blocks) = self.authority(id).primary().await.captured_blocks() {
+                let blocks = blocks
+                    .into_iter()
+                    .map(|block| (block.height, block))
+                    .collect();
+                captured.push((id, blocks));
+            }
+        }
+        let ((reference_id, reference), others) = captured
+            .split_first()
+            .expect("No authority captures its blocks");
+
+        let mut compared = 0;
+        for (height, block) in reference {
+            let mut reached_by_all = true;
+            for (id, blocks) in others {
+                match blocks.get(height) {
+                    Some(other) => assert_eq!(
+                        block.bytes, other.bytes,
+                        "Node {reference_id} and node {id} delivered different blocks at height {height} (hashes {:?} and {:?})",
+                        block.block_hash, other.block_hash
+                    ),
+                    None => reached_by_all = false,
+                }
+            }
+            if reached_by_all {
+                compared += 1;
+            }
+        }
+        info!(
+            "Compared the blocks of {} nodes at {compared} heights",
+            captured.len()
+        );
+        compared
+    }
+
     async fn authorities_latest_commit_round(&self) -> HashMap<usize, f64> {
         let mut authorities_latest_commit = HashMap::new();
 
@@ -280,6 +365,12 @@ pub struct PrimaryNodeDetails {
     parameters: Parameters,
     handlers: Rc<RefCell<Vec<JoinHandle<()>>>>,
     internal_consensus_enabled: bool,
+    /// Where the executor stand-in listens, when the committed blocks are captured.
+    block_capture_dir: Option<PathBuf>,
+    /// The executor stand-in capturing the committed blocks, started with the first primary.
+    block_capture: Option<Arc<StandInHandle>>,
+    /// The consensus store of the running primary, to observe its sequence.
+    consensus_store: Option<Arc<ConsensusStore>>,
 }
 
 impl PrimaryNodeDetails {
@@ -291,6 +382,7 @@ impl PrimaryNodeDetails {
         committee: SharedCommittee,
         worker_cache: SharedWorkerCache,
         internal_consensus_enabled: bool,
+        block_capture: bool,
     ) -> Self {
         // used just to initialise the struct value
         let (tx, _) = tokio::sync::broadcast::channel(1);
@@ -307,9 +399,95 @@ impl PrimaryNodeDetails {
             parameters,
             handlers: Rc::new(RefCell::new(Vec::new())),
             internal_consensus_enabled,
+            block_capture_dir: block_capture.then(temp_dir),
+            block_capture: None,
+            consensus_store: None,
         }
     }
 
+    /// The blocks delivered by this primary since the cluster started, in height order, if they
+    /// are captured.
+    pub fn captured_blocks(&self) -> Option<Vec<ReceivedBlock>> {
+        self.block_capture
+            .as_ref()
+            .map(|stand_in| stand_in.blocks())
+    }
+
+    /// Waits until the primary sequenced some of the certificates of a block but not all of them,
+    /// so a restart loses the block being formed. Needs an index-aligned block policy. Returns
+    /// the number of sequenced certificates.
+    pub async fn wait_for_open_block(&self) -> u64 {
+        let consensus_store = self
+            .consensus_store
+            .as_ref()
+            .expect("The primary is not running");
+        let certificates_per_block = self.parameters.block_policy.certificates_per_block.max(1);
+        loop {
+            let sequenced = consensus_store.read_next_consensus_index().unwrap();
+            if sequenced % certificates_per_block != 0 {
+                return sequenced;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// The blocks rejected by the executor stand-in, if the blocks are captured.
+    pub fn captured_violations(&self) -> Option<Vec<String>> {
+        self.block_capture
+            .as_ref()
+            .map(|stand_in| stand_in.violations())
+    }
+
+    /// Builds the execution state delivering the blocks to the executor stand-in, starting the
+    /// stand-in on the first call.
+    async fn capturing_execution_state(
+        &mut self,
+        store: &NodeStorage,
+        capture_dir: PathBuf,
+    ) -> Arc<UdsExecutionState> {
+        let socket_path = capture_dir.join("executor.sock");
+        if self.block_capture.is_none() {
+            let stand_in = StandInExecutor::spawn(StandInConfig {
+                socket_path: socket_path.clone(),
+                protocol_version: self.parameters.uds_protocol.version,
+                segments_dir: None,
+                first_height: Some(0),
+            })
+            .unwrap();
+            self.block_capture = Some(Arc::new(stand_in));
+        }
+
+        let execution_state = UdsExecutionState::new_with_state_and_stores(
+            socket_path.to_string_lossy().into_owned(),
+            self.committee.load().epoch,
+            100,  // empty_block_timeout_ms
+            3,    // max_send_retries
+            100,  // retry_delay_base_ms
+            None, // legacy_execution_state_path
+            Some(store.consensus_store.clone()),
+            Some(store.certificate_store.clone()),
+            None, // global_state
+        )
+        .with_protocol_version(
+            self.parameters.uds_protocol.version,
+            self.parameters.uds_protocol.ack_timeout,
+        )
+        .with_block_format(self.parameters.uds_protocol.block_format)
+        .with_batch_store(store.batch_store.clone())
+        .with_block_archive(
+            store.block_archive.clone(),
+            self.parameters.block_archive.retention_blocks,
+            self.parameters.block_archive.prune_interval_blocks,
+        )
+        .with_execution_progress_store(store.execution_progress_store.clone())
+        .with_block_policy(self.parameters.block_policy.clone());
+        let execution_state = Arc::new(execution_state);
+        execution_state.check_block_policy().await.unwrap();
+        execution_state.initialize().await.unwrap();
+        execution_state.negotiate_block_format().await.unwrap();
+        execution_state
+    }
+
     /// Returns the metric - if exists - identified by the provided name.
     /// If metric has not been found then None is returned instead.
     pub fn metric(&self, name: &str) -> Option<Metric> {
@@ -343,22 +521,50 @@ impl PrimaryNodeDetails {
         let (tx_transaction_confirmation, mut rx_transaction_confirmation) =
             channel(Node::CHANNEL_CAPACITY);
 
-        // Primary node
+        // Primary node. The store of the previous run is released before it is reopened.
+        self.consensus_store = None;
         let primary_store: NodeStorage = NodeStorage::reopen(store_path.clone());
-        let mut primary_handlers = Node::spawn_primary(
-            self.key_pair.copy(),
-            self.network_key_pair.copy(),
-            self.committee.clone(),
-            self.worker_cache.clone(),
-            &primary_store,
-            self.parameters.clone(),
-            /* consensus */ self.internal_consensus_enabled,
-            /* execution_state */
-            Arc::new(SimpleExecutionState::new(tx_transaction_confirmation)),
-            &registry,
-        )
-        .await
-        .unwrap();
+        let mut primary_handlers = match self.block_capture_dir.clone() {
+            Some(capture_dir) => {
+                let execution_state = self
+                    .capturing_execution_state(&primary_store, capture_dir)
+                    .await;
+                let replay_handle = execution_state.clone().spawn_replay_task();
+                let mut handlers = Node::spawn_primary(
+                    self.key_pair.copy(),
+                    self.network_key_pair.copy(),
+                    self.committee.clone(),
+                    self.worker_cache.clone(),
+                    &primary_store,
+                    self.parameters.clone(),
+                    /* consensus */ self.internal_consensus_enabled,
+                    execution_state,
+                    /* global_state */ None,
+                    /* fork_detector */ None,
+                    &registry,
+                )
+                .await
+                .unwrap();
+                handlers.push(replay_handle);
+                handlers
+            }
+            None => Node::spawn_primary(
+                self.key_pair.copy(),
+                self.network_key_pair.copy(),
+                self.committee.clone(),
+                self.worker_cache.clone(),
+                &primary_store,
+                self.parameters.clone(),
+                /* consensus */ self.internal_consensus_enabled,
+                /* execution_state */
+                Arc::new(SimpleExecutionState::new(tx_transaction_confirmation)),
+                /* global_state */ None,
+                /* fork_detector */ None,
+                &registry,
+            )
+            .await
+            .unwrap(),
+        };
 
         let (tx, _) = tokio::sync::broadcast::channel(primary::CHANNEL_CAPACITY);
         let transactions_sender = tx.clone();
@@ -377,6 +583,7 @@ impl PrimaryNodeDetails {
 
         self.handlers.replace(primary_handlers);
         self.store_path = store_path;
+        self.consensus_store = Some(primary_store.consensus_store.clone());
         self.registry = registry;
         self.tx_transaction_confirmation = tx;
     }
@@ -513,6 +720,7 @@ impl AuthorityDetails {
         committee: SharedCommittee,
         worker_cache: SharedWorkerCache,
         internal_consensus_enabled: bool,
+        block_capture: bool,
     ) -> Self {
         // Create all the nodes we have in the committee
         let name = key_pair.public().clone();
@@ -524,6 +732,7 @@ impl AuthorityDetails {
             committee.clone(),
             worker_cache.clone(),
             internal_consensus_enabled,
+            block_capture,
         );
 
         // Create all the workers - even if we don't intend to start them all. Those
@@ -788,6 +997,21 @@ impl AuthorityDetails {
     }
 }
 
+/// A client transaction accepted by the workers' default validation: an unsigned
+/// `transaction.proto` transaction, made unique by its nonce.
+pub fn proto_transaction(nonce: u64) -> TransactionProto {
+    let transaction = Transaction {
+        nonce: nonce.to_be_bytes().to_vec(),
+        ..Transaction::default()
+    };
+    let transactions = Transactions {
+        transactions: vec![transaction],
+    };
+    TransactionProto {
+        transaction: Bytes::from(transactions.encode_to_vec()),
+    }
+}
+
 pub fn setup_tracing() -> TelemetryGuards {
     // Setup tracing
     let tracing_level = "debug";
@@ -25,12 +25,13 @@ use store::{reopen, rocks, rocks::DBMap, Store};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tracing::info;
 use types::{
-    Batch, BatchDigest, Certificate, CertificateDigest, ConsensusStore, Header, HeaderBuilder,
-    PrimaryMessage, PrimaryToPrimary, PrimaryToPrimaryServer, PrimaryToWorker,
-    PrimaryToWorkerServer, PrimaryWorkerMessage, RequestBatchRequest, RequestBatchResponse, Round,
-    SequenceNumber, Transaction, Vote, WorkerBatchRequest, WorkerBatchResponse, WorkerInfoResponse,
-    WorkerMessage, WorkerPrimaryMessage, WorkerSynchronizeMessage, WorkerToPrimary,
-    WorkerToPrimaryServer, WorkerToWorker, WorkerToWorkerServer,
+    Batch, BatchCertificate, BatchDigest, Certificate, CertificateDigest, CommittedSubDagInfo,
+    ConsensusStore, Header, HeaderBuilder, LeaderReputation, PrimaryMessage, PrimaryToPrimary,
+    PrimaryToPrimaryServer, PrimaryToWorker, PrimaryToWorkerServer, PrimaryWorkerMessage,
+    RequestBatchRequest, RequestBatchResponse, Round, SequenceNumber, Transaction,
+    TransactionCommit, TransactionStatusStore, Vote, WorkerBatchRequest, WorkerBatchResponse,
+    WorkerInfoResponse, WorkerMessage, WorkerPrimaryMessage, WorkerSynchronizeMessage,
+    WorkerToPrimary, WorkerToPrimaryServer, WorkerToWorker, WorkerToWorkerServer,
 };
 
 pub mod cluster;
@@ -132,16 +133,87 @@ pub fn random_key() -> KeyPair {
 pub fn make_consensus_store(store_path: &std::path::Path) -> Arc<ConsensusStore> {
     const LAST_COMMITTED_CF: &str = "last_committed";
     const SEQUENCE_CF: &str = "sequence";
+    const SUB_DAGS_CF: &str = "sub_dags";
+    const LEADER_REPUTATION_CF: &str = "leader_reputation";
+
+    let rocksdb = rocks::open_cf(
+        store_path,
+        None,
+        &[
+            LAST_COMMITTED_CF,
+            SEQUENCE_CF,
+            SUB_DAGS_CF,
+            LEADER_REPUTATION_CF,
+        ],
+    )
+    .expect("Failed creating database");
 
-    let rocksdb = rocks::open_cf(store_path, None, &[LAST_COMMITTED_CF, SEQUENCE_CF])
-        .expect("Failed creating database");
-
-    let (last_committed_map, sequence_map) = reopen!(&rocksdb,
+    let (last_committed_map, sequence_map, sub_dags_map, leader_reputation_map) = reopen!(&rocksdb,
         LAST_COMMITTED_CF;<PublicKey, Round>,
-        SEQUENCE_CF;<SequenceNumber, CertificateDigest>
+        SEQUENCE_CF;<SequenceNumber, CertificateDigest>,
+        SUB_DAGS_CF;<SequenceNumber, CommittedSubDagInfo>,
+        LEADER_REPUTATION_CF;<u8, LeaderReputation>
+    );
+
+    Arc::new(ConsensusStore::new(
+        last_committed_map,
+        sequence_map,
+        sub_dags_map,
+        leader_reputation_map,
+    ))
+}
+
+pub fn make_transaction_status_store(store_path: &std::path::Path) -> Arc<TransactionStatusStore> {
+    const TRANSACTION_BATCHES_CF: &str = "transaction_batches";
+    const TRANSACTION_BATCHES_BY_ROUND_CF: &str = "transaction_batches_by_round";
+    const BATCH_CERTIFICATES_CF: &str = "batch_certificates";
+    const BATCH_CERTIFICATES_BY_ROUND_CF: &str = "batch_certificates_by_round";
+    const TRANSACTION_COMMITS_CF: &str = "transaction_commits";
+    const TRANSACTION_COMMITS_BY_INDEX_CF: &str = "transaction_commits_by_index";
+    const DELIVERED_HEIGHT_CF: &str = "delivered_height";
+
+    let rocksdb = rocks::open_cf(
+        store_path,
+        None,
+        &[
+            TRANSACTION_BATCHES_CF,
+            TRANSACTION_BATCHES_BY_ROUND_CF,
+            BATCH_CERTIFICATES_CF,
+            BATCH_CERTIFICATES_BY_ROUND_CF,
+            TRANSACTION_COMMITS_CF,
+            TRANSACTION_COMMITS_BY_INDEX_CF,
+            DELIVERED_HEIGHT_CF,
+        ],
+    )
+    .expect("Failed creating database");
+
+    let (
+        transaction_batches_map,
+        transaction_batches_by_round_map,
+        batch_certificates_map,
+        batch_certificates_by_round_map,
+        transaction_commits_map,
+        transaction_commits_by_index_map,
+        delivered_height_map,
+    ) = reopen!(&rocksdb,
+        TRANSACTION_BATCHES_CF;<Vec<u8>, BatchDigest>,
+        TRANSACTION_BATCHES_BY_ROUND_CF;<(Round, Vec<u8>), ()>,
+        BATCH_CERTIFICATES_CF;<BatchDigest, BatchCertificate>,
+        BATCH_CERTIFICATES_BY_ROUND_CF;<(Round, BatchDigest), ()>,
+        TRANSACTION_COMMITS_CF;<Vec<u8>, TransactionCommit>,
+        TRANSACTION_COMMITS_BY_INDEX_CF;<(SequenceNumber, Vec<u8>), ()>,
+        DELIVERED_HEIGHT_CF;<u8, u64>
     );
 
-    Arc::new(ConsensusStore::new(last_committed_map, sequence_map))
+    Arc::new(TransactionStatusStore::new(
+        transaction_batches_map,
+        transaction_batches_by_round_map,
+        batch_certificates_map,
+        batch_certificates_by_round_map,
+        transaction_commits_map,
+        transaction_commits_by_index_map,
+        delivered_height_map,
+    ))
 }
 
 pub fn fixture_payload(number_of_batches: u8) -> IndexMap<BatchDigest, WorkerId> {
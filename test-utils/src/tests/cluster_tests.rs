@@ -1,7 +1,8 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
-use crate::cluster::Cluster;
+use crate::cluster::{proto_transaction, Cluster};
 use crate::ensure_test_environment;
+use config::Parameters;
 use std::time::Duration;
 use types::{PublicKeyProto, RoundsRequest};
 
@@ -65,3 +66,69 @@ async fn cluster_setup_with_consensus_disabled() {
     assert_eq!(0, r.oldest_round);
     assert_eq!(0, r.newest_round);
 }
+
+#[tokio::test]
+async fn cluster_delivers_identical_blocks() {
+    ensure_test_environment();
+    let mut parameters = Parameters {
+        batch_size: 200,
+        max_header_delay: Duration::from_millis(500),
+        ..Parameters::default()
+    };
+    parameters.block_policy.certificates_per_block = 4;
+    // Acknowledged delivery: the unacknowledged blocks are retransmitted after a restart.
+    parameters.uds_protocol.version = 2;
+    let mut cluster = Cluster::new_with_block_capture(Some(parameters), true);
+
+    cluster.start(Some(4), Some(1), None).await;
+    let mut nonce = 0;
+    submit_transactions(&cluster, &[0, 1, 2, 3], &mut nonce).await;
+    cluster.wait_for_block_height(2).await;
+
+    // Restart a primary with its store while it forms a block: it drops the certificates of
+    // the open block, sequences them again and resumes the chain where it left it.
+    cluster
+        .authority(1)
+        .primary()
+        .await
+        .wait_for_open_block()
+        .await;
+    cluster
+        .authority(1)
+        .restart(true, Duration::from_secs(10))
+        .await;
+    submit_transactions(&cluster, &[0, 1, 2, 3], &mut nonce).await;
+
+    // Kill a worker: the batches it sealed are fetched from the other workers.
+    cluster.authority(2).stop_worker(0).await;
+    submit_transactions(&cluster, &[0, 1, 3], &mut nonce).await;
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    cluster.authority(2).start_worker(0, true).await;
+    submit_transactions(&cluster, &[0, 1, 2, 3], &mut nonce).await;
+
+    cluster.wait_for_block_height(8).await;
+    assert!(cluster.assert_identical_blocks().await >= 9);
+
+    // The executor stand-ins rejected no block, even across the restart and the worker outage.
+    for id in 0..4 {
+        let violations = cluster.authority(id).primary().await.captured_violations();
+        assert_eq!(
+            violations,
+            Some(Vec::new()),
+            "Node {id} sent rejected blocks"
+        );
+    }
+}
+
+async fn submit_transactions(cluster: &Cluster, authorities: &[usize], nonce: &mut u64) {
+    for id in authorities {
+        let mut client = cluster.authority(*id).new_transactions_client(&0).await;
+        for _ in 0..10 {
+            client
+                .submit_transaction(proto_transaction(*nonce))
+                .await
+                .unwrap();
+            *nonce += 1;
+        }
+    }
+}
@@ -4,7 +4,11 @@
 
 use crate::{CertificateDigest, Round};
 use crypto::PublicKey;
-use std::{collections::HashMap, ops::RangeInclusive};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::RangeInclusive,
+};
 use store::{
     rocks::{DBMap, TypedStoreError},
     traits::Map,
@@ -20,12 +24,59 @@ pub type ShutdownToken = mpsc::Sender<()>;
 /// Convenience type to propagate store errors.
 pub type StoreResult<T> = Result<T, TypedStoreError>;
 
+/// The commit of a leader: the range of the global sequence made of the leader's sub-dag.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CommittedSubDagInfo {
+    /// The commit sequence number (the number of leaders committed before this one in the epoch).
+    pub sub_dag_index: SequenceNumber,
+    /// The digest of the committed leader.
+    pub leader: CertificateDigest,
+    /// The round of the committed leader.
+    pub leader_round: Round,
+    /// The consensus index of the first certificate of the sub-dag.
+    pub first_consensus_index: SequenceNumber,
+    /// The consensus index of the last certificate of the sub-dag (the leader).
+    pub last_consensus_index: SequenceNumber,
+    /// The time of the commit on this node (ms since UNIX epoch). It is not agreed upon by the
+    /// committee and should not be used where all nodes must produce the same output.
+    pub commit_timestamp: u64,
+}
+
+impl CommittedSubDagInfo {
+    /// Whether the certificate at `consensus_index` ends the sub-dag.
+    pub fn is_last(&self, consensus_index: SequenceNumber) -> bool {
+        consensus_index == self.last_consensus_index
+    }
+}
+
+/// The reputation of the authorities and the leader swaps it decided. It is derived only from the
+/// committed sub-dags, so every node computes the same leader schedule.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LeaderReputation {
+    /// The round of the committed leader that ended the previous window: the swaps apply to the
+    /// later rounds.
+    pub schedule_round: Round,
+    /// The score of the authorities in the current window: one point per committed certificate
+    /// and one per committed leader slot.
+    pub scores: BTreeMap<PublicKey, u64>,
+    /// The authorities with the lowest scores in the previous window, out of the leader schedule.
+    pub bad_nodes: BTreeSet<PublicKey>,
+    /// The authorities with the highest scores in the previous window, leading in their stead.
+    pub good_nodes: Vec<PublicKey>,
+}
+
 /// The persistent storage of the sequencer.
 pub struct ConsensusStore {
     /// The latest committed round of each validator.
     last_committed: DBMap<PublicKey, Round>,
-    /// The global consensus sequence.
+    /// The global consensus sequence. The certificate with consensus index i is stored at key
+    /// i + 1 (the number of certificates sequenced with it): the keys are private to the store,
+    /// its methods take consensus indices.
     sequence: DBMap<SequenceNumber, CertificateDigest>,
+    /// The committed sub-dags, by the consensus index of their leader.
+    sub_dags: DBMap<SequenceNumber, CommittedSubDagInfo>,
+    /// The leader reputation as of the last committed sub-dag (single entry).
+    leader_reputation: DBMap<u8, LeaderReputation>,
 }
 
 impl ConsensusStore {
@@ -33,33 +84,59 @@ impl ConsensusStore {
     pub fn new(
         last_committed: DBMap<PublicKey, Round>,
         sequence: DBMap<SequenceNumber, CertificateDigest>,
+        sub_dags: DBMap<SequenceNumber, CommittedSubDagInfo>,
+        leader_reputation: DBMap<u8, LeaderReputation>,
     ) -> Self {
         Self {
             last_committed,
             sequence,
+            sub_dags,
+            leader_reputation,
         }
     }
 
+    /// The single key of the leader reputation.
+    const LEADER_REPUTATION_KEY: u8 = 0;
+
     /// Clear the store.
     pub fn clear(&self) -> StoreResult<()> {
         self.last_committed.clear()?;
         self.sequence.clear()?;
+        self.sub_dags.clear()?;
+        self.leader_reputation.clear()?;
         Ok(())
     }
 
-    /// Persist the consensus state.
+    /// Persist the consensus state with the certificate sequenced at `consensus_index`. The
+    /// sub-dag is persisted with the certificate of its leader (the last certificate of the
+    /// sub-dag) so the commit boundaries survive a crash, and so is the leader reputation it
+    /// updated.
     pub fn write_consensus_state(
         &self,
         last_committed: &HashMap<PublicKey, Round>,
         consensus_index: &SequenceNumber,
         certificate_id: &CertificateDigest,
+        sub_dag: Option<&CommittedSubDagInfo>,
+        leader_reputation: Option<&LeaderReputation>,
     ) -> Result<(), TypedStoreError> {
         let mut write_batch = self.last_committed.batch();
         write_batch = write_batch.insert_batch(&self.last_committed, last_committed.iter())?;
         write_batch = write_batch.insert_batch(
             &self.sequence,
-            std::iter::once((consensus_index, certificate_id)),
+            std::iter::once((consensus_index + 1, certificate_id)),
         )?;
+        if let Some(sub_dag) = sub_dag {
+            write_batch = write_batch.insert_batch(
+                &self.sub_dags,
+                std::iter::once((sub_dag.last_consensus_index, sub_dag)),
+            )?;
+        }
+        if let Some(leader_reputation) = leader_reputation {
+            write_batch = write_batch.insert_batch(
+                &self.leader_reputation,
+                std::iter::once((Self::LEADER_REPUTATION_KEY, leader_reputation)),
+            )?;
+        }
         write_batch.write()
     }
 
@@ -68,22 +145,24 @@ impl ConsensusStore {
         self.last_committed.iter().collect()
     }
 
-    /// Load the certificate digests sequenced at a specific indices.
+    /// Load the certificate digests sequenced at the consensus indices of `range`, with their
+    /// consensus index. The indices missing from the store are skipped.
     pub fn read_sequenced_certificates(
         &self,
-        missing: &RangeInclusive<SequenceNumber>,
-    ) -> StoreResult<Vec<Option<CertificateDigest>>> {
+        range: &RangeInclusive<SequenceNumber>,
+    ) -> StoreResult<Vec<(SequenceNumber, CertificateDigest)>> {
         Ok(self
             .sequence
             .iter()
-            .skip_to(missing.start())?
-            .take_while(|(index, _)| index <= missing.end())
-            .map(|(_, digest)| Some(digest))
+            .skip_to(&(range.start() + 1))?
+            .take_while(|(key, _)| *key <= range.end() + 1)
+            .map(|(key, digest)| (key - 1, digest))
             .collect())
     }
 
-    /// Load the last (ie. the highest) consensus index associated to a certificate.
-    pub fn read_last_consensus_index(&self) -> StoreResult<SequenceNumber> {
+    /// Load the consensus index of the next certificate to sequence (ie. the number of
+    /// certificates sequenced in the epoch).
+    pub fn read_next_consensus_index(&self) -> StoreResult<SequenceNumber> {
         Ok(self
             .sequence
             .keys()
@@ -91,4 +170,33 @@ impl ConsensusStore {
             .next()
             .unwrap_or_default())
     }
+
+    /// Load the sub-dag that contains the certificate sequenced at `consensus_index`.
+    pub fn read_committed_sub_dag(
+        &self,
+        consensus_index: SequenceNumber,
+    ) -> StoreResult<Option<CommittedSubDagInfo>> {
+        Ok(self
+            .sub_dags
+            .iter()
+            .skip_to(&consensus_index)?
+            .next()
+            .map(|(_, sub_dag)| sub_dag)
+            .filter(|sub_dag| sub_dag.first_consensus_index <= consensus_index))
+    }
+
+    /// Load the leader reputation as of the last committed sub-dag.
+    pub fn read_leader_reputation(&self) -> StoreResult<Option<LeaderReputation>> {
+        self.leader_reputation.get(&Self::LEADER_REPUTATION_KEY)
+    }
+
+    /// Load the last committed sub-dag.
+    pub fn read_last_committed_sub_dag(&self) -> StoreResult<Option<CommittedSubDagInfo>> {
+        Ok(self
+            .sub_dags
+            .iter()
+            .skip_prior_to(&SequenceNumber::MAX)?
+            .next()
+            .map(|(_, sub_dag)| sub_dag))
+    }
 }
@@ -13,6 +13,9 @@ pub use primary::*;
 mod proto;
 pub use proto::*;
 
+mod transaction_status;
+pub use transaction_status::*;
+
 mod worker;
 pub use worker::*;
 
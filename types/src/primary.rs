@@ -712,6 +712,13 @@ pub enum PrimaryMessage {
         payload_availability: Vec<(CertificateDigest, bool)>,
         from: PublicKey,
     },
+
+    /// The (height, block hash) of the most recent blocks delivered to the executor by `from`,
+    /// compared by the other primaries to detect a fork of the executed chain.
+    BlockFingerprints {
+        fingerprints: Vec<(u64, Vec<u8>)>,
+        from: PublicKey,
+    },
 }
 
 /// Message to reconfigure worker tasks. This message must be sent by a trusted source.
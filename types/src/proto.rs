@@ -19,6 +19,8 @@ use crypto::PublicKey;
 pub use narwhal::{
     collection_error::CollectionErrorType,
     collection_retrieval_result::RetrievalResult,
+    committed_output_client::CommittedOutputClient,
+    committed_output_server::{CommittedOutput, CommittedOutputServer},
     configuration_client::ConfigurationClient,
     configuration_server::{Configuration, ConfigurationServer},
     primary_to_primary_client::PrimaryToPrimaryClient,
@@ -27,6 +29,8 @@ pub use narwhal::{
     primary_to_worker_server::{PrimaryToWorker, PrimaryToWorkerServer},
     proposer_client::ProposerClient,
     proposer_server::{Proposer, ProposerServer},
+    transaction_status_client::TransactionStatusClient,
+    transaction_status_server::{TransactionStatus, TransactionStatusServer},
     transactions_client::TransactionsClient,
     transactions_server::{Transactions, TransactionsServer},
     validator_client::ValidatorClient,
@@ -36,11 +40,12 @@ pub use narwhal::{
     worker_to_worker_client::WorkerToWorkerClient,
     worker_to_worker_server::{WorkerToWorker, WorkerToWorkerServer},
     CertificateDigest as CertificateDigestProto, Collection, CollectionError,
-    CollectionRetrievalResult, Empty, GetCollectionsRequest, GetCollectionsResponse,
-    GetPrimaryAddressResponse, MultiAddr as MultiAddrProto, NewEpochRequest, NewNetworkInfoRequest,
-    NodeReadCausalRequest, NodeReadCausalResponse, PublicKey as PublicKeyProto, ReadCausalRequest,
-    ReadCausalResponse, RemoveCollectionsRequest, RoundsRequest, RoundsResponse,
-    Transaction as TransactionProto, ValidatorData,
+    CollectionRetrievalResult, CommittedBatch, CommittedCertificate, Empty, GetCollectionsRequest,
+    GetCollectionsResponse, GetPrimaryAddressResponse, MultiAddr as MultiAddrProto,
+    NewEpochRequest, NewNetworkInfoRequest, NodeReadCausalRequest, NodeReadCausalResponse,
+    PublicKey as PublicKeyProto, ReadCausalRequest, ReadCausalResponse, RemoveCollectionsRequest,
+    RoundsRequest, RoundsResponse, SubscribeCommittedRequest, Transaction as TransactionProto,
+    TransactionStage, TransactionStatusRequest, TransactionStatusResponse, ValidatorData,
 };
 
 impl From<PublicKey> for PublicKeyProto {
@@ -0,0 +1,155 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    BatchCertificate, BatchDigest, CertificateDigest, Round, SequenceNumber, TransactionCommit,
+    TransactionStage, TransactionStatusResponse, TransactionStatusStore,
+};
+use store::{reopen, rocks};
+
+fn transaction_status_store() -> TransactionStatusStore {
+    const TRANSACTION_BATCHES_CF: &str = "transaction_batches";
+    const TRANSACTION_BATCHES_BY_ROUND_CF: &str = "transaction_batches_by_round";
+    const BATCH_CERTIFICATES_CF: &str = "batch_certificates";
+    const BATCH_CERTIFICATES_BY_ROUND_CF: &str = "batch_certificates_by_round";
+    const TRANSACTION_COMMITS_CF: &str = "transaction_commits";
+    const TRANSACTION_COMMITS_BY_INDEX_CF: &str = "transaction_commits_by_index";
+    const DELIVERED_HEIGHT_CF: &str = "delivered_height";
+
+    let rocksdb = rocks::open_cf(
+        test_utils::temp_dir(),
+        None,
+        &[
+            TRANSACTION_BATCHES_CF,
+            TRANSACTION_BATCHES_BY_ROUND_CF,
+            BATCH_CERTIFICATES_CF,
+            BATCH_CERTIFICATES_BY_ROUND_CF,
+            TRANSACTION_COMMITS_CF,
+            TRANSACTION_COMMITS_BY_INDEX_CF,
+            DELIVERED_HEIGHT_CF,
+        ],
+    )
+    .unwrap();
+    let (
+        transaction_batches_map,
+        transaction_batches_by_round_map,
+        batch_certificates_map,
+        batch_certificates_by_round_map,
+        transaction_commits_map,
+        transaction_commits_by_index_map,
+        delivered_height_map,
+    ) = reopen!(&rocksdb,
+        TRANSACTION_BATCHES_CF;<Vec<u8>, BatchDigest>,
+        TRANSACTION_BATCHES_BY_ROUND_CF;<(Round, Vec<u8>), ()>,
+        BATCH_CERTIFICATES_CF;<BatchDigest, BatchCertificate>,
+        BATCH_CERTIFICATES_BY_ROUND_CF;<(Round, BatchDigest), ()>,
+        TRANSACTION_COMMITS_CF;<Vec<u8>, TransactionCommit>,
+        TRANSACTION_COMMITS_BY_INDEX_CF;<(SequenceNumber, Vec<u8>), ()>,
+        DELIVERED_HEIGHT_CF;<u8, u64>
+    );
+    TransactionStatusStore::new(
+        transaction_batches_map,
+        transaction_batches_by_round_map,
+        batch_certificates_map,
+        batch_certificates_by_round_map,
+        transaction_commits_map,
+        transaction_commits_by_index_map,
+        delivered_height_map,
+    )
+}
+
+#[test]
+fn reports_the_furthest_stage() {
+    let store = transaction_status_store();
+    let transaction = vec![1u8; 32];
+    let batch = BatchDigest::new([2; 32]);
+    let certificate = CertificateDigest::new([3; 32]);
+    let stage = |batch| store.read_status(&transaction, batch).unwrap().stage();
+
+    assert_eq!(stage(None), TransactionStage::Unknown);
+
+    store
+        .write_batched(batch, 3, &[transaction.clone()])
+        .unwrap();
+    assert_eq!(stage(None), TransactionStage::Batched);
+
+    store.write_certified(certificate, 7, [batch]).unwrap();
+    assert_eq!(
+        store.read_status(&transaction, None).unwrap(),
+        TransactionStatusResponse {
+            stage: TransactionStage::Certified.into(),
+            batch_digest: batch.0.to_vec().into(),
+            certificate_digest: Some(certificate.into()),
+            round: 7,
+            ..TransactionStatusResponse::default()
+        }
+    );
+
+    store.write_committed(&transaction, 11, 5).unwrap();
+    assert_eq!(stage(None), TransactionStage::Committed);
+    store.write_delivered(5).unwrap();
+    // Blocks are delivered in order: a lower height does not move the delivered height back.
+    store.write_delivered(4).unwrap();
+    let status = store.read_status(&transaction, None).unwrap();
+    assert_eq!(status.stage(), TransactionStage::Delivered);
+    assert_eq!((status.consensus_index, status.block_height), (11, 5));
+}
+
+#[test]
+fn finds_the_certificate_of_a_given_batch() {
+    // A primary does not share its store with the worker that batched the transaction.
+    let store = transaction_status_store();
+    let transaction = vec![1u8; 32];
+    let batch = BatchDigest::new([2; 32]);
+
+    store
+        .write_certified(CertificateDigest::new([3; 32]), 7, [batch])
+        .unwrap();
+    assert_eq!(
+        store.read_status(&transaction, None).unwrap().stage(),
+        TransactionStage::Unknown
+    );
+    assert_eq!(
+        store
+            .read_status(&transaction, Some(batch))
+            .unwrap()
+            .stage(),
+        TransactionStage::Certified
+    );
+}
+
+#[test]
+fn stages_are_pruned_below_their_watermark() {
+    let store = transaction_status_store();
+    let stage = |transaction: &[u8]| store.read_status(transaction, None).unwrap().stage();
+    assert_eq!(store.read_batched_round().unwrap(), None);
+
+    for i in 1..=4u8 {
+        let transaction = vec![i; 32];
+        let batch = BatchDigest::new([i + 10; 32]);
+        store
+            .write_batched(batch, i as Round, &[transaction.clone()])
+            .unwrap();
+        store
+            .write_certified(CertificateDigest::new([i + 20; 32]), i as Round, [batch])
+            .unwrap();
+        store
+            .write_committed(&transaction, i as SequenceNumber, i as u64)
+            .unwrap();
+    }
+    assert_eq!(store.read_batched_round().unwrap(), Some(4));
+
+    // Only the transactions committed below the watermark are forgotten.
+    assert_eq!(store.prune_committed_below(3).unwrap(), 2);
+    assert_eq!(stage(&[2; 32]), TransactionStage::Certified);
+    assert_eq!(stage(&[3; 32]), TransactionStage::Committed);
+
+    assert_eq!(store.prune_certified_below(3).unwrap(), 2);
+    assert_eq!(stage(&[2; 32]), TransactionStage::Batched);
+
+    assert_eq!(store.prune_batched_below(3).unwrap(), 2);
+    assert_eq!(stage(&[2; 32]), TransactionStage::Unknown);
+    assert_eq!(store.prune_batched_below(3).unwrap(), 0);
+    assert_eq!(stage(&[3; 32]), TransactionStage::Committed);
+    assert_eq!(store.read_batched_round().unwrap(), Some(4));
+}
@@ -0,0 +1,282 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use crate::{
+    BatchDigest, CertificateDigest, Round, SequenceNumber, StoreResult, TransactionStage,
+    TransactionStatusResponse,
+};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use store::{rocks::DBMap, traits::Map};
+
+#[cfg(test)]
+#[path = "tests/transaction_status_tests.rs"]
+mod transaction_status_tests;
+
+/// The certificate whose header carries a batch.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BatchCertificate {
+    pub certificate: CertificateDigest,
+    pub round: Round,
+}
+
+/// Where a committed transaction was put for the executor.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TransactionCommit {
+    /// The consensus index of the certificate of its batch.
+    pub consensus_index: SequenceNumber,
+    /// The height of the block that carries it.
+    pub block_height: u64,
+}
+
+/// Index of the lifecycle of the transactions, by transaction hash (Keccak256).
+/// Each stage is written by the component that reaches it: the worker's `BatchMaker` (batched),
+/// the primary's `Core` (certified) and the execution state (committed, delivered).
+/// Each stage is indexed by round or consensus index so its writer prunes it with the same
+/// watermark as the rest of its state: the gc round for the batched and certified stages, the
+/// watermark of the processed batches for the committed stage.
+pub struct TransactionStatusStore {
+    /// Transaction hash -> digest of the batch that carries it.
+    transaction_batches: DBMap<Vec<u8>, BatchDigest>,
+    /// (committed round when sealed, transaction hash) of the batched transactions.
+    transaction_batches_by_round: DBMap<(Round, Vec<u8>), ()>,
+    /// Batch digest -> certificate of the header that carries it.
+    batch_certificates: DBMap<BatchDigest, BatchCertificate>,
+    /// (certificate round, batch digest) of the certified batches.
+    batch_certificates_by_round: DBMap<(Round, BatchDigest), ()>,
+    /// Transaction hash -> its block for the executor.
+    transaction_commits: DBMap<Vec<u8>, TransactionCommit>,
+    /// (consensus index, transaction hash) of the committed transactions.
+    transaction_commits_by_index: DBMap<(SequenceNumber, Vec<u8>), ()>,
+    /// The highest block height delivered to the executor (single entry).
+    delivered_height: DBMap<u8, u64>,
+}
+
+impl TransactionStatusStore {
+    const DELIVERED_HEIGHT_KEY: u8 = 0;
+
+    /// Create a new transaction status store by using already loaded maps.
+    pub fn new(
+        transaction_batches: DBMap<Vec<u8>, BatchDigest>,
+        transaction_batches_by_round: DBMap<(Round, Vec<u8>), ()>,
+        batch_certificates: DBMap<BatchDigest, BatchCertificate>,
+        batch_certificates_by_round: DBMap<(Round, BatchDigest), ()>,
+        transaction_commits: DBMap<Vec<u8>, TransactionCommit>,
+        transaction_commits_by_index: DBMap<(SequenceNumber, Vec<u8>), ()>,
+        delivered_height: DBMap<u8, u64>,
+    ) -> Self {
+        Self {
+            transaction_batches,
+            transaction_batches_by_round,
+            batch_certificates,
+            batch_certificates_by_round,
+            transaction_commits,
+            transaction_commits_by_index,
+            delivered_height,
+        }
+    }
+
+    /// Record the transactions sealed into a batch. `committed_round` is the last committed round
+    /// known to the worker when sealing, the batch cannot be certified much before it.
+    pub fn write_batched(
+        &self,
+        batch: BatchDigest,
+        committed_round: Round,
+        transaction_hashes: &[Vec<u8>],
+    ) -> StoreResult<()> {
+        self.transaction_batches
+            .batch()
+            .insert_batch(
+                &self.transaction_batches,
+                transaction_hashes
+                    .iter()
+                    .map(|transaction_hash| (transaction_hash.clone(), batch)),
+            )?
+            .insert_batch(
+                &self.transaction_batches_by_round,
+                transaction_hashes
+                    .iter()
+                    .map(|transaction_hash| ((committed_round, transaction_hash.clone()), ())),
+            )?
+            .write()
+    }
+
+    /// The highest committed round recorded with a batched transaction, to resume stamping the
+    /// transactions after a restart.
+    pub fn read_batched_round(&self) -> StoreResult<Option<Round>> {
+        Ok(self
+            .transaction_batches_by_round
+            .keys()
+            .skip_prior_to(&(Round::MAX, Vec::new()))?
+            .next()
+            .map(|(round, _)| round))
+    }
+
+    /// Delete the transactions batched when the committed round was below `gc_round`.
+    /// Returns the number of pruned transactions.
+    pub fn prune_batched_below(&self, gc_round: Round) -> StoreResult<usize> {
+        let keys: Vec<(Round, Vec<u8>)> = self
+            .transaction_batches_by_round
+            .keys()
+            .take_while(|(round, _)| *round < gc_round)
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let count = keys.len();
+        self.transaction_batches
+            .batch()
+            .delete_batch(
+                &self.transaction_batches,
+                keys.iter()
+                    .map(|(_, transaction_hash)| transaction_hash.clone()),
+            )?
+            .delete_batch(&self.transaction_batches_by_round, keys)?
+            .write()?;
+        Ok(count)
+    }
+
+    /// Record the batches in the payload of a certified header.
+    pub fn write_certified(
+        &self,
+        certificate: CertificateDigest,
+        round: Round,
+        batches: impl IntoIterator<Item = BatchDigest>,
+    ) -> StoreResult<()> {
+        let batch_certificate = BatchCertificate { certificate, round };
+        let batches: Vec<BatchDigest> = batches.into_iter().collect();
+        self.batch_certificates
+            .batch()
+            .insert_batch(
+                &self.batch_certificates,
+                batches.iter().map(|batch| (*batch, batch_certificate)),
+            )?
+            .insert_batch(
+                &self.batch_certificates_by_round,
+                batches.iter().map(|batch| ((round, *batch), ())),
+            )?
+            .write()
+    }
+
+    /// Delete the batches certified below `gc_round`. Returns the number of pruned batches.
+    pub fn prune_certified_below(&self, gc_round: Round) -> StoreResult<usize> {
+        let keys: Vec<(Round, BatchDigest)> = self
+            .batch_certificates_by_round
+            .keys()
+            .take_while(|(round, _)| *round < gc_round)
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let count = keys.len();
+        self.batch_certificates
+            .batch()
+            .delete_batch(
+                &self.batch_certificates,
+                keys.iter().map(|(_, batch)| *batch),
+            )?
+            .delete_batch(&self.batch_certificates_by_round, keys)?
+            .write()?;
+        Ok(count)
+    }
+
+    /// Record a transaction put into a block for the executor.
+    pub fn write_committed(
+        &self,
+        transaction_hash: &[u8],
+        consensus_index: SequenceNumber,
+        block_height: u64,
+    ) -> StoreResult<()> {
+        let transaction_hash = transaction_hash.to_vec();
+        self.transaction_commits
+            .batch()
+            .insert_batch(
+                &self.transaction_commits,
+                std::iter::once((
+                    transaction_hash.clone(),
+                    TransactionCommit {
+                        consensus_index,
+                        block_height,
+                    },
+                )),
+            )?
+            .insert_batch(
+                &self.transaction_commits_by_index,
+                std::iter::once(((consensus_index, transaction_hash), ())),
+            )?
+            .write()
+    }
+
+    /// Delete the transactions committed at a consensus index below `watermark`.
+    /// Returns the number of pruned transactions.
+    pub fn prune_committed_below(&self, watermark: SequenceNumber) -> StoreResult<usize> {
+        let keys: Vec<(SequenceNumber, Vec<u8>)> = self
+            .transaction_commits_by_index
+            .keys()
+            .take_while(|(consensus_index, _)| *consensus_index < watermark)
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let count = keys.len();
+        self.transaction_commits
+            .batch()
+            .delete_batch(
+                &self.transaction_commits,
+                keys.iter()
+                    .map(|(_, transaction_hash)| transaction_hash.clone()),
+            )?
+            .delete_batch(&self.transaction_commits_by_index, keys)?
+            .write()?;
+        Ok(count)
+    }
+
+    /// Record the delivery of the blocks up to `block_height` (blocks are delivered in order).
+    pub fn write_delivered(&self, block_height: u64) -> StoreResult<()> {
+        if self.read_delivered_height()? >= Some(block_height) {
+            return Ok(());
+        }
+        self.delivered_height
+            .insert(&Self::DELIVERED_HEIGHT_KEY, &block_height)
+    }
+
+    /// The highest block height delivered to the executor, if any.
+    pub fn read_delivered_height(&self) -> StoreResult<Option<u64>> {
+        self.delivered_height.get(&Self::DELIVERED_HEIGHT_KEY)
+    }
+
+    /// The furthest stage of a transaction known to this store. `batch` is the digest of the
+    /// batch of the transaction when the caller knows it (the worker may use another store).
+    pub fn read_status(
+        &self,
+        transaction_hash: &[u8],
+        batch: Option<BatchDigest>,
+    ) -> StoreResult<TransactionStatusResponse> {
+        let transaction_hash = transaction_hash.to_vec();
+        let mut status = TransactionStatusResponse::default();
+
+        let recorded_batch = self.transaction_batches.get(&transaction_hash)?;
+        if let Some(batch) = recorded_batch {
+            status.set_stage(TransactionStage::Batched);
+            status.batch_digest = Bytes::from(batch.0.to_vec());
+        }
+        if let Some(batch) = recorded_batch.or(batch) {
+            if let Some(batch_certificate) = self.batch_certificates.get(&batch)? {
+                status.set_stage(TransactionStage::Certified);
+                status.batch_digest = Bytes::from(batch.0.to_vec());
+                status.certificate_digest = Some(batch_certificate.certificate.into());
+                status.round = batch_certificate.round;
+            }
+        }
+        if let Some(commit) = self.transaction_commits.get(&transaction_hash)? {
+            let delivered = self.read_delivered_height()? >= Some(commit.block_height);
+            status.set_stage(if delivered {
+                TransactionStage::Delivered
+            } else {
+                TransactionStage::Committed
+            });
+            status.consensus_index = commit.consensus_index;
+            status.block_height = commit.block_height;
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Admission control of the client transactions received by the worker: maximum transaction
+//! size, maximum bytes waiting to be sealed into a batch, and token buckets per source IP and
+//! per `FromAddress`. Rejected transactions get `ResourceExhausted` with a retry hint.
+
+use crate::transaction_logger::transaction::{Transaction, Transactions};
+use config::AdmissionControlParameters;
+use prost::Message;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[cfg(test)]
+#[path = "tests/admission_control_tests.rs"]
+pub mod admission_control_tests;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AdmissionError {
+    #[error("Transaction of {size} B exceeds the maximum transaction size of {max} B")]
+    TransactionTooLarge { size: usize, max: usize },
+
+    #[error("Worker has {pending} B of transactions waiting to be batched (max {max} B)")]
+    MempoolFull {
+        pending: usize,
+        max: usize,
+        retry_after: Duration,
+    },
+
+    #[error("Too many transactions from {source_ip}")]
+    SourceRateLimited {
+        source_ip: IpAddr,
+        retry_after: Duration,
+    },
+
+    #[error("Too many transactions from sender 0x{sender}")]
+    SenderRateLimited {
+        sender: String,
+        retry_after: Duration,
+    },
+}
+
+impl AdmissionError {
+    /// When the client may try again (None: retrying the same transaction will not help).
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::TransactionTooLarge { .. } => None,
+            Self::MempoolFull { retry_after, .. }
+            | Self::SourceRateLimited { retry_after, .. }
+            | Self::SenderRateLimited { retry_after, .. } => Some(*retry_after),
+        }
+    }
+
+    /// Label of the rejection metric.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::TransactionTooLarge { .. } => "transaction_too_large",
+            Self::MempoolFull { .. } => "mempool_full",
+            Self::SourceRateLimited { .. } => "source_rate_limited",
+            Self::SenderRateLimited { .. } => "sender_rate_limited",
+        }
+    }
+}
+
+/// Bytes of client transactions accepted by the worker and not yet sealed into a batch.
+/// Shared between the `TxReceiverHandler` (reserves) and the `BatchMaker` (releases).
+#[derive(Clone, Default)]
+pub struct PendingBytes(Arc<AtomicUsize>);
+
+impl PendingBytes {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Reserve `size` bytes unless it would exceed `max` (zero means no limit).
+    /// Returns the pending bytes when the reservation is refused.
+    pub fn try_reserve(&self, size: usize, max: usize) -> Result<(), usize> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |pending| {
+                if max == 0 || pending.saturating_add(size) <= max {
+                    Some(pending.saturating_add(size))
+                } else {
+                    None
+                }
+            })
+            .map(|_| ())
+    }
+
+    pub fn release(&self, size: usize) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |pending| {
+                Some(pending.saturating_sub(size))
+            });
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token buckets of `rate` transactions per second and `burst` capacity, one per key.
+pub struct RateLimiter<K> {
+    rate: f64,
+    burst: f64,
+    max_keys: usize,
+    buckets: HashMap<K, TokenBucket>,
+}
+
+impl<K: Clone + Eq + Hash> RateLimiter<K> {
+    /// `burst` zero defaults to one second worth of tokens.
+    pub fn new(rate: u32, burst: u32, max_keys: usize) -> Self {
+        let burst = if burst == 0 { rate } else { burst };
+        Self {
+            rate: rate as f64,
+            burst: burst.max(1) as f64,
+            max_keys: max_keys.max(1),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// A rate limiter if `rate` is not zero (zero disables the limit).
+    fn enabled(rate: u32, burst: u32, max_keys: usize) -> Option<Mutex<Self>> {
+        (rate > 0).then(|| Mutex::new(Self::new(rate, burst, max_keys)))
+    }
+
+    /// Take the tokens of all the requests, or none of them. Returns the key of the first bucket
+    /// without enough tokens and when it will have them otherwise.
+    pub fn try_acquire(
+        &mut self,
+        requests: &[(K, u32)],
+        now: Instant,
+    ) -> Result<(), (K, Duration)> {
+        self.check(requests, now)?;
+        self.take(requests, now);
+        Ok(())
+    }
+
+    /// Whether all the requests have enough tokens, without taking them.
+    fn check(&mut self, requests: &[(K, u32)], now: Instant) -> Result<(), (K, Duration)> {
+        for (key, count) in requests {
+            let tokens = self.refill(key, now);
+            let needed = *count as f64;
+            if tokens < needed {
+                // A request larger than the burst waits for a full bucket.
+                let missing = needed.min(self.burst) - tokens;
+                let retry_after = Duration::from_secs_f64((missing / self.rate).max(0.001));
+                return Err((key.clone(), retry_after));
+            }
+        }
+        Ok(())
+    }
+
+    /// Take the tokens of requests that passed `check`.
+    fn take(&mut self, requests: &[(K, u32)], now: Instant) {
+        for (key, count) in requests {
+            if let Some(bucket) = self.buckets.get_mut(key) {
+                bucket.tokens -= *count as f64;
+            }
+        }
+        self.evict_idle(now);
+    }
+
+    fn refill(&mut self, key: &K, now: Instant) -> f64 {
+        let (rate, burst) = (self.rate, self.burst);
+        let bucket = self.buckets.entry(key.clone()).or_insert(TokenBucket {
+            tokens: burst,
+            last_refill: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        bucket.last_refill = now;
+        bucket.tokens
+    }
+
+    /// Forget the buckets that are full again: they behave as new ones.
+    fn evict_idle(&mut self, now: Instant) {
+        if self.buckets.len() <= self.max_keys {
+            return;
+        }
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.last_refill);
+            bucket.tokens + elapsed.as_secs_f64() * rate < burst
+        });
+    }
+}
+
+pub struct AdmissionControl {
+    max_transaction_size: usize,
+    max_pending_bytes: usize,
+    /// Retry hint when the pending bytes are at their maximum (time to seal a batch).
+    mempool_retry_after: Duration,
+    pending_bytes: PendingBytes,
+    per_source_ip: Option<Mutex<RateLimiter<IpAddr>>>,
+    per_sender: Option<Mutex<RateLimiter<Vec<u8>>>>,
+}
+
+impl AdmissionControl {
+    pub fn new(
+        parameters: &AdmissionControlParameters,
+        mempool_retry_after: Duration,
+        pending_bytes: PendingBytes,
+    ) -> Self {
+        Self {
+            max_transaction_size: parameters.max_transaction_size,
+            max_pending_bytes: parameters.max_pending_bytes,
+            mempool_retry_after,
+            pending_bytes,
+            per_source_ip: RateLimiter::enabled(
+                parameters.per_source_ip_rate,
+                parameters.per_source_ip_burst,
+                parameters.max_tracked_clients,
+            ),
+            per_sender: RateLimiter::enabled(
+                parameters.per_sender_rate,
+                parameters.per_sender_burst,
+                parameters.max_tracked_clients,
+            ),
+        }
+    }
+
+    /// Admit a client payload. On success its bytes are pending until the `BatchMaker` seals them
+    /// (or until `release` if the payload is not sent to the `BatchMaker` after all).
+    pub fn admit(&self, source: Option<IpAddr>, transaction: &[u8]) -> Result<(), AdmissionError> {
+        let size = transaction.len();
+        if self.max_transaction_size > 0 && size > self.max_transaction_size {
+            return Err(AdmissionError::TransactionTooLarge {
+                size,
+                max: self.max_transaction_size,
+            });
+        }
+
+        self.pending_bytes
+            .try_reserve(size, self.max_pending_bytes)
+            .map_err(|pending| AdmissionError::MempoolFull {
+                pending,
+                max: self.max_pending_bytes,
+                retry_after: self.mempool_retry_after,
+            })?;
+        if let Err(e) = self.check_rates(source, transaction) {
+            self.release(size);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn release(&self, size: usize) {
+        self.pending_bytes.release(size);
+    }
+
+    fn check_rates(
+        &self,
+        source: Option<IpAddr>,
+        transaction: &[u8],
+    ) -> Result<(), AdmissionError> {
+        let now = Instant::now();
+        // Check both limits before taking any token: a transaction rejected by one limit does
+        // not consume the tokens of the other.
+        let sources: Vec<_> = source.map(|source_ip| (source_ip, 1)).into_iter().collect();
+        let mut per_source_ip = self
+            .per_source_ip
+            .as_ref()
+            .filter(|_| !sources.is_empty())
+            .map(|limiter| limiter.lock().unwrap());
+        if let Some(limiter) = &mut per_source_ip {
+            limiter
+                .check(&sources, now)
+                .map_err(
+                    |(source_ip, retry_after)| AdmissionError::SourceRateLimited {
+                        source_ip,
+                        retry_after,
+                    },
+                )?;
+        }
+        let senders = match &self.per_sender {
+            Some(_) => senders(transaction),
+            None => Vec::new(),
+        };
+        let mut per_sender = self
+            .per_sender
+            .as_ref()
+            .filter(|_| !senders.is_empty())
+            .map(|limiter| limiter.lock().unwrap());
+        if let Some(limiter) = &mut per_sender {
+            limiter
+                .check(&senders, now)
+                .map_err(|(sender, retry_after)| AdmissionError::SenderRateLimited {
+                    sender: hex::encode(sender),
+                    retry_after,
+                })?;
+        }
+
+        if let Some(limiter) = &mut per_source_ip {
+            limiter.take(&sources, now);
+        }
+        if let Some(limiter) = &mut per_sender {
+            limiter.take(&senders, now);
+        }
+        Ok(())
+    }
+}
+
+/// The `FromAddress` of the transactions of a payload with their number of transactions.
+/// Payloads that are not `transaction.proto` have no sender.
+fn senders(payload: &[u8]) -> Vec<(Vec<u8>, u32)> {
+    let transactions = match Transactions::decode(payload) {
+        Ok(txs) if !txs.transactions.is_empty() => txs.transactions,
+        _ => match Transaction::decode(payload) {
+            Ok(tx) => vec![tx],
+            Err(_) => return Vec::new(),
+        },
+    };
+    let mut counts: Vec<(Vec<u8>, u32)> = Vec::new();
+    for tx in transactions {
+        match counts
+            .iter_mut()
+            .find(|(sender, _)| *sender == tx.from_address)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((tx.from_address, 1)),
+        }
+    }
+    counts
+}
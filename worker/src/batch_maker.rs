@@ -1,7 +1,10 @@
 // Copyright (c) 2021, Facebook, Inc. and its affiliates
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
-use crate::metrics::WorkerMetrics;
+use crate::{
+    admission_control::PendingBytes, metrics::WorkerMetrics,
+    transaction_deduplicator::transaction_hashes,
+};
 #[cfg(feature = "trace_transaction")]
 use byteorder::{BigEndian, ReadBytesExt};
 use config::Committee;
@@ -16,7 +19,7 @@ use tokio::{
 use types::{
     error::DagError,
     metered_channel::{Receiver, Sender},
-    Batch, ReconfigureNotification, Transaction,
+    Batch, ReconfigureNotification, Round, Transaction, TransactionStatusStore,
 };
 
 // Dependencies để tính transaction hash (fallback)
@@ -47,6 +50,12 @@ pub struct BatchMaker {
     current_batch_size: usize,
     /// Metrics handler
     node_metrics: Arc<WorkerMetrics>,
+    /// Bytes accepted by the admission control and not yet sealed, released when sealing.
+    pending_bytes: PendingBytes,
+    /// Records the batch of each sealed transaction (for the `TransactionStatus` service).
+    transaction_status_store: Option<Arc<TransactionStatusStore>>,
+    /// The last committed round, recorded with the sealed transactions to prune them.
+    rx_committed_round: watch::Receiver<Round>,
 }
 
 impl BatchMaker {
@@ -59,6 +68,9 @@ impl BatchMaker {
         rx_transaction: Receiver<Transaction>,
         tx_message: Sender<Batch>,
         node_metrics: Arc<WorkerMetrics>,
+        pending_bytes: PendingBytes,
+        transaction_status_store: Option<Arc<TransactionStatusStore>>,
+        rx_committed_round: watch::Receiver<Round>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
             Self {
@@ -71,6 +83,9 @@ impl BatchMaker {
                 current_batch: Batch(Vec::with_capacity(batch_size * 2)),
                 current_batch_size: 0,
                 node_metrics,
+                pending_bytes,
+                transaction_status_store,
+                rx_committed_round,
             }
             .run()
             .await;
@@ -279,6 +294,21 @@ impl BatchMaker {
             }
         }
 
+        if let Some(store) = &self.transaction_status_store {
+            if !batch.0.is_empty() {
+                use fastcrypto::hash::Hash;
+                let hashes: Vec<_> = batch
+                    .0
+                    .iter()
+                    .flat_map(|tx| transaction_hashes(tx))
+                    .collect();
+                let committed_round = *self.rx_committed_round.borrow();
+                if let Err(e) = store.write_batched(batch.digest(), committed_round, &hashes) {
+                    tracing::warn!("Failed to record the transactions of a sealed batch: {e}");
+                }
+            }
+        }
+
         #[cfg(feature = "benchmark")]
         {
             use fastcrypto::hash::Hash;
@@ -340,5 +370,7 @@ impl BatchMaker {
         if self.tx_message.send(batch).await.is_err() {
             tracing::debug!("{}", DagError::ShuttingDown);
         }
+        // The sealed transactions no longer count against the admission control.
+        self.pending_bytes.release(size);
     }
 }
@@ -8,6 +8,7 @@
     rust_2021_compatibility
 )]
 
+pub mod admission_control;
 mod batch_maker;
 mod handlers;
 pub mod metrics;
@@ -15,7 +16,15 @@ mod primary_connector;
 mod processor;
 mod quorum_waiter;
 mod synchronizer;
+pub mod transaction_deduplicator;
 pub mod transaction_logger;
+pub mod transaction_validator;
 mod worker;
 
-pub use crate::worker::{Worker, WorkerMessage};
+pub use crate::{
+    transaction_validator::{
+        ProtoTransactionValidator, TransactionValidationError, TransactionValidator,
+        TrivialTransactionValidator,
+    },
+    worker::{Worker, WorkerMessage},
+};
@@ -4,8 +4,9 @@ use mysten_network::metrics::MetricsCallbackProvider;
 use network::metrics::NetworkMetrics;
 use prometheus::{
     default_registry, register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
-    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, HistogramVec,
-    IntCounterVec, IntGauge, IntGaugeVec, Registry,
+    register_int_counter_with_registry, register_int_gauge_vec_with_registry,
+    register_int_gauge_with_registry, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Registry,
 };
 use std::time::Duration;
 use tonic::Code;
@@ -49,6 +50,10 @@ pub struct WorkerMetrics {
     pub pending_elements_worker_synchronizer: IntGaugeVec,
     /// Number of created batches from the batch_maker
     pub created_batch_size: HistogramVec,
+    /// Number of client transactions rejected because they were already submitted
+    pub duplicate_transactions_rejected: IntCounter,
+    /// Number of client transactions rejected by the admission control, by reason
+    pub admission_rejections: IntCounterVec,
 }
 
 impl WorkerMetrics {
@@ -68,6 +73,19 @@ impl WorkerMetrics {
                 registry
             )
             .unwrap(),
+            duplicate_transactions_rejected: register_int_counter_with_registry!(
+                "duplicate_transactions_rejected",
+                "Number of client transactions rejected because they were already submitted",
+                registry
+            )
+            .unwrap(),
+            admission_rejections: register_int_counter_vec_with_registry!(
+                "admission_rejections",
+                "Number of client transactions rejected by the admission control, by reason",
+                &["reason"],
+                registry
+            )
+            .unwrap(),
         }
     }
 }
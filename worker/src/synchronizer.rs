@@ -13,7 +13,8 @@ use tokio::{sync::watch, task::JoinHandle};
 use tracing::{error, warn};
 use types::{
     metered_channel::{Receiver, Sender},
-    Batch, BatchDigest, ReconfigureNotification, WorkerPrimaryError, WorkerPrimaryMessage,
+    Batch, BatchDigest, ReconfigureNotification, Round, TransactionStatusStore, WorkerPrimaryError,
+    WorkerPrimaryMessage,
 };
 
 #[cfg(test)]
@@ -36,6 +37,12 @@ pub struct Synchronizer {
     tx_reconfigure: watch::Sender<ReconfigureNotification>,
     /// Output channel to send out the batch requests.
     tx_primary: Sender<WorkerPrimaryMessage>,
+    /// The depth of the garbage collector of the primary.
+    gc_depth: Round,
+    /// Publish the last committed round to the `BatchMaker`.
+    tx_committed_round: watch::Sender<Round>,
+    /// The batched transactions, pruned below the gc round.
+    transaction_status_store: Option<Arc<TransactionStatusStore>>,
 }
 
 impl Synchronizer {
@@ -48,6 +55,9 @@ impl Synchronizer {
         tx_reconfigure: watch::Sender<ReconfigureNotification>,
         tx_primary: Sender<WorkerPrimaryMessage>,
         network: P2pNetwork,
+        gc_depth: Round,
+        tx_committed_round: watch::Sender<Round>,
+        transaction_status_store: Option<Arc<TransactionStatusStore>>,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
             Self {
@@ -58,6 +68,9 @@ impl Synchronizer {
                 network,
                 tx_reconfigure,
                 tx_primary,
+                gc_depth,
+                tx_committed_round,
+                transaction_status_store,
             }
             .run()
             .await;
@@ -70,8 +83,8 @@ impl Synchronizer {
             tokio::select! {
                 // Handle primary's messages.
                 Some(message) = self.rx_message.recv() => match message {
-                    PrimaryWorkerMessage::Cleanup(_) => {
-                        // TODO: this message does nothing, remove it.
+                    PrimaryWorkerMessage::Cleanup(round) => {
+                        self.handle_cleanup(round);
                     },
                     PrimaryWorkerMessage::Reconfigure(message) => {
                         // Reconfigure this task and update the shared committee.
@@ -79,6 +92,8 @@ impl Synchronizer {
                             ReconfigureNotification::NewEpoch(new_committee) => {
                                 self.network.cleanup(self.worker_cache.load().network_diff(new_committee.keys()));
                                 self.committee.swap(Arc::new(new_committee.clone()));
+                                // The rounds restart with the new epoch.
+                                self.tx_committed_round.send_replace(0);
 
                                 // Update the worker cache.
                                 self.worker_cache.swap(Arc::new(WorkerCache {
@@ -147,6 +162,27 @@ impl Synchronizer {
         }
     }
 
+    /// Publish the last committed round and prune the transactions batched when the committed
+    /// round was below the gc round: their batches are certified or dropped by now.
+    fn handle_cleanup(&mut self, round: Round) {
+        if round <= *self.tx_committed_round.borrow() {
+            return;
+        }
+        let gc_round = round.saturating_sub(self.gc_depth);
+        if let Some(store) = &self.transaction_status_store {
+            match store.prune_batched_below(gc_round) {
+                Ok(0) => (),
+                Ok(pruned) => {
+                    tracing::debug!("Pruned {pruned} batched transactions below round {gc_round}")
+                }
+                Err(e) => {
+                    warn!("Failed to prune the batched transactions below round {gc_round}: {e}")
+                }
+            }
+        }
+        self.tx_committed_round.send_replace(round);
+    }
+
     async fn handle_request_batch(&mut self, digest: BatchDigest) {
         let message = match self.store.read(digest).await {
             Ok(Some(batch)) => WorkerPrimaryMessage::RequestedBatch(digest, batch),
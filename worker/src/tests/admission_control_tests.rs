@@ -0,0 +1,123 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+use std::net::Ipv4Addr;
+
+fn parameters() -> AdmissionControlParameters {
+    AdmissionControlParameters {
+        max_transaction_size: 100,
+        max_pending_bytes: 250,
+        ..AdmissionControlParameters::default()
+    }
+}
+
+fn transaction(sender: u8, nonce: u8) -> Vec<u8> {
+    Transaction {
+        from_address: vec![sender; 20],
+        nonce: vec![nonce],
+        ..Transaction::default()
+    }
+    .encode_to_vec()
+}
+
+#[test]
+fn token_bucket_refills_at_rate() {
+    let mut limiter = RateLimiter::new(10, 2, 100);
+    let start = Instant::now();
+    assert!(limiter.try_acquire(&[("a", 2)], start).is_ok());
+    let (key, retry_after) = limiter.try_acquire(&[("a", 1)], start).unwrap_err();
+    assert_eq!(key, "a");
+    assert_eq!(retry_after, Duration::from_millis(100));
+    // Other keys have their own bucket.
+    assert!(limiter.try_acquire(&[("b", 1)], start).is_ok());
+    assert!(limiter
+        .try_acquire(&[("a", 1)], start + Duration::from_millis(100))
+        .is_ok());
+    // All or nothing: "b" keeps its token when "a" is limited.
+    assert!(limiter.try_acquire(&[("b", 1), ("a", 1)], start).is_err());
+    assert!(limiter.try_acquire(&[("b", 1)], start).is_ok());
+}
+
+#[test]
+fn limits_size_and_pending_bytes() {
+    let pending_bytes = PendingBytes::default();
+    let admission = AdmissionControl::new(
+        &parameters(),
+        Duration::from_millis(200),
+        pending_bytes.clone(),
+    );
+
+    assert_eq!(
+        admission.admit(None, &[0; 101]),
+        Err(AdmissionError::TransactionTooLarge {
+            size: 101,
+            max: 100
+        })
+    );
+    assert_eq!(admission.admit(None, &[0; 100]), Ok(()));
+    assert_eq!(admission.admit(None, &[0; 100]), Ok(()));
+    let error = admission.admit(None, &[0; 100]).unwrap_err();
+    assert_eq!(error.retry_after(), Some(Duration::from_millis(200)));
+    assert_eq!(pending_bytes.get(), 200);
+
+    // The batch maker sealed the transactions.
+    pending_bytes.release(200);
+    assert_eq!(admission.admit(None, &[0; 100]), Ok(()));
+}
+
+#[test]
+fn limits_sources_and_senders() {
+    let pending_bytes = PendingBytes::default();
+    let admission = AdmissionControl::new(
+        &AdmissionControlParameters {
+            per_source_ip_rate: 1,
+            per_sender_rate: 1,
+            ..AdmissionControlParameters::default()
+        },
+        Duration::from_millis(200),
+        pending_bytes.clone(),
+    );
+    let first_ip = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    let second_ip = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+
+    assert_eq!(admission.admit(first_ip, &transaction(1, 0)), Ok(()));
+    assert!(matches!(
+        admission.admit(first_ip, &transaction(2, 0)),
+        Err(AdmissionError::SourceRateLimited { .. })
+    ));
+    assert!(matches!(
+        admission.admit(second_ip, &transaction(1, 1)),
+        Err(AdmissionError::SenderRateLimited { .. })
+    ));
+    // Rejected transactions do not stay pending.
+    assert_eq!(pending_bytes.get(), transaction(1, 0).len());
+}
+
+#[test]
+fn rejected_transactions_keep_the_tokens_of_the_other_limit() {
+    let admission = AdmissionControl::new(
+        &AdmissionControlParameters {
+            per_source_ip_rate: 1,
+            per_sender_rate: 1,
+            ..AdmissionControlParameters::default()
+        },
+        Duration::from_millis(200),
+        PendingBytes::default(),
+    );
+    let first_ip = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    let second_ip = Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)));
+
+    assert_eq!(admission.admit(first_ip, &transaction(1, 0)), Ok(()));
+    // The sender is limited: the second IP keeps its token.
+    assert!(matches!(
+        admission.admit(second_ip, &transaction(1, 1)),
+        Err(AdmissionError::SenderRateLimited { .. })
+    ));
+    assert_eq!(admission.admit(second_ip, &transaction(2, 0)), Ok(()));
+    // The source is limited: the third sender keeps its token.
+    assert!(matches!(
+        admission.admit(first_ip, &transaction(3, 0)),
+        Err(AdmissionError::SourceRateLimited { .. })
+    ));
+    assert_eq!(admission.admit(None, &transaction(3, 1)), Ok(()));
+}
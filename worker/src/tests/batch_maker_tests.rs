@@ -2,8 +2,10 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 use super::*;
+use fastcrypto::hash::Hash;
 use prometheus::Registry;
-use test_utils::{transaction, CommitteeFixture};
+use test_utils::{make_transaction_status_store, temp_dir, transaction, CommitteeFixture};
+use types::{TransactionStage, TransactionStatusResponse};
 
 #[tokio::test]
 async fn make_batch() {
@@ -14,6 +16,7 @@ async fn make_batch() {
     let (tx_transaction, rx_transaction) = test_utils::test_channel!(1);
     let (tx_message, mut rx_message) = test_utils::test_channel!(1);
     let node_metrics = WorkerMetrics::new(&Registry::new());
+    let transaction_status_store = make_transaction_status_store(&temp_dir());
 
     // Spawn a `BatchMaker` instance.
     let _batch_maker_handle = BatchMaker::spawn(
@@ -25,6 +28,9 @@ async fn make_batch() {
         rx_transaction,
         tx_message,
         Arc::new(node_metrics),
+        PendingBytes::default(),
+        Some(transaction_status_store.clone()),
+        /* rx_committed_round */ watch::channel(0).1,
     );
 
     // Send enough transactions to seal a batch.
@@ -36,6 +42,19 @@ async fn make_batch() {
     let expected_batch = Batch(vec![tx.clone(), tx.clone()]);
     let batch = rx_message.recv().await.unwrap();
     assert_eq!(batch, expected_batch);
+
+    // The transactions are recorded in the batch.
+    let status = transaction_status_store
+        .read_status(&transaction_hashes(&tx)[0], None)
+        .unwrap();
+    assert_eq!(
+        status,
+        TransactionStatusResponse {
+            stage: TransactionStage::Batched.into(),
+            batch_digest: batch.digest().0.to_vec().into(),
+            ..TransactionStatusResponse::default()
+        }
+    );
 }
 
 #[tokio::test]
@@ -58,6 +77,9 @@ async fn batch_timeout() {
         rx_transaction,
         tx_message,
         Arc::new(node_metrics),
+        PendingBytes::default(),
+        /* transaction_status_store */ None,
+        /* rx_committed_round */ watch::channel(0).1,
     );
 
     // Do not send enough transactions to seal a batch.
@@ -5,8 +5,12 @@ use super::*;
 use arc_swap::ArcSwap;
 use fastcrypto::Hash;
 use std::time::Duration;
-use test_utils::{batch, batches, open_batch_store, test_network, CommitteeFixture};
+use test_utils::{
+    batch, batches, make_transaction_status_store, open_batch_store, temp_dir, test_network,
+    CommitteeFixture,
+};
 use tokio::time::timeout;
+use types::TransactionStage;
 
 #[tokio::test]
 async fn test_successful_request_batch() {
@@ -36,6 +40,9 @@ async fn test_successful_request_batch() {
         tx_reconfiguration,
         tx_primary,
         P2pNetwork::new(network),
+        /* gc_depth */ 50,
+        /* tx_committed_round */ watch::channel(0).0,
+        /* transaction_status_store */ None,
     );
 
     // Create a dummy batch and store
@@ -93,6 +100,9 @@ async fn test_request_batch_not_found() {
         tx_reconfiguration,
         tx_primary,
         P2pNetwork::new(network),
+        /* gc_depth */ 50,
+        /* tx_committed_round */ watch::channel(0).0,
+        /* transaction_status_store */ None,
     );
 
     // The non existing batch id
@@ -150,6 +160,9 @@ async fn test_successful_batch_delete() {
         tx_reconfiguration,
         tx_primary,
         P2pNetwork::new(network),
+        /* gc_depth */ 50,
+        /* tx_committed_round */ watch::channel(0).0,
+        /* transaction_status_store */ None,
     );
 
     // Create dummy batches and store them
@@ -193,3 +206,63 @@ async fn test_successful_batch_delete() {
         assert!(result.unwrap().is_none());
     }
 }
+
+#[tokio::test]
+async fn cleanup_prunes_the_batched_transactions() {
+    let (tx_message, rx_message) = test_utils::test_channel!(1);
+    let (tx_primary, _rx_primary) = test_utils::test_channel!(1);
+
+    let fixture = CommitteeFixture::builder().randomize_ports(true).build();
+    let committee = fixture.committee();
+    let worker_cache = fixture.shared_worker_cache();
+    let myself = fixture.authorities().next().unwrap().worker(0);
+
+    let (tx_reconfiguration, _rx_reconfiguration) =
+        watch::channel(ReconfigureNotification::NewEpoch(committee.clone()));
+    let (tx_committed_round, mut rx_committed_round) = watch::channel(0);
+    let transaction_status_store = make_transaction_status_store(&temp_dir());
+    let (old, recent) = (vec![1u8; 32], vec![2u8; 32]);
+    transaction_status_store
+        .write_batched(batch().digest(), 5, &[old.clone()])
+        .unwrap();
+    transaction_status_store
+        .write_batched(batch().digest(), 20, &[recent.clone()])
+        .unwrap();
+
+    let network = test_network(myself.keypair(), &myself.info().worker_address);
+    let _synchronizer_handle = Synchronizer::spawn(
+        Arc::new(ArcSwap::from_pointee(committee.clone())),
+        worker_cache,
+        open_batch_store(),
+        rx_message,
+        tx_reconfiguration,
+        tx_primary,
+        P2pNetwork::new(network),
+        /* gc_depth */ 50,
+        tx_committed_round,
+        Some(transaction_status_store.clone()),
+    );
+
+    // WHEN the primary commits round 60
+    tx_message
+        .send(PrimaryWorkerMessage::Cleanup(60))
+        .await
+        .expect("Should be able to send message");
+
+    // THEN the round is published to the batch maker
+    timeout(Duration::from_secs(5), rx_committed_round.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(*rx_committed_round.borrow(), 60);
+
+    // AND only the transactions batched below the gc round are forgotten
+    let stage = |transaction: &[u8]| {
+        transaction_status_store
+            .read_status(transaction, None)
+            .unwrap()
+            .stage()
+    };
+    assert_eq!(stage(&old), TransactionStage::Unknown);
+    assert_eq!(stage(&recent), TransactionStage::Batched);
+}
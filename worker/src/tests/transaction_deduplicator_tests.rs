@@ -0,0 +1,100 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+
+fn deduplicator(window: Duration, max_entries: usize) -> TransactionDeduplicator {
+    TransactionDeduplicator::new(&TransactionDeduplicationParameters {
+        enabled: true,
+        window,
+        max_entries,
+    })
+}
+
+fn hash(i: u8) -> Vec<u8> {
+    vec![i; 32]
+}
+
+#[test]
+fn rejects_resubmissions_within_window() {
+    let deduplicator = deduplicator(Duration::from_secs(10), 100);
+    let start = Instant::now();
+
+    assert_eq!(
+        deduplicator.check_and_insert_at(&[hash(1), hash(2)], start),
+        Ok(())
+    );
+    // One duplicate rejects the whole payload, and its new hashes are not remembered.
+    assert_eq!(
+        deduplicator.check_and_insert_at(&[hash(3), hash(2)], start),
+        Err(hash(2))
+    );
+    assert_eq!(deduplicator.len(), 2);
+    assert!(deduplicator.contains(&hash(1)));
+    assert!(!deduplicator.contains(&hash(3)));
+
+    // Once the window has passed, the transaction can be submitted again.
+    let later = start + Duration::from_secs(10);
+    assert_eq!(deduplicator.check_and_insert_at(&[hash(2)], later), Ok(()));
+    assert_eq!(deduplicator.len(), 1);
+}
+
+#[test]
+fn forgets_oldest_hashes_when_full() {
+    let deduplicator = deduplicator(Duration::from_secs(10), 2);
+    let now = Instant::now();
+    for i in 0..3 {
+        assert_eq!(deduplicator.check_and_insert_at(&[hash(i)], now), Ok(()));
+    }
+    assert_eq!(deduplicator.len(), 2);
+    assert_eq!(deduplicator.check_and_insert_at(&[hash(0)], now), Ok(()));
+    assert_eq!(
+        deduplicator.check_and_insert_at(&[hash(2)], now),
+        Err(hash(2))
+    );
+}
+
+#[test]
+fn removed_hashes_can_be_submitted_again() {
+    let deduplicator = deduplicator(Duration::from_secs(10), 100);
+    let now = Instant::now();
+    assert_eq!(
+        deduplicator.check_and_insert_at(&[hash(1), hash(2)], now),
+        Ok(())
+    );
+    assert_eq!(deduplicator.check_and_insert_at(&[hash(3)], now), Ok(()));
+
+    deduplicator.remove(&[hash(1), hash(2)]);
+    assert_eq!(deduplicator.len(), 1);
+    assert!(!deduplicator.contains(&hash(1)));
+    assert!(deduplicator.contains(&hash(3)));
+    assert_eq!(
+        deduplicator.check_and_insert_at(&[hash(1), hash(2)], now),
+        Ok(())
+    );
+}
+
+#[test]
+fn hashes_wrapped_transactions() {
+    let first = Transaction {
+        nonce: vec![1],
+        ..Transaction::default()
+    };
+    let second = Transaction {
+        nonce: vec![2],
+        ..Transaction::default()
+    };
+    let wrapped = Transactions {
+        transactions: vec![first.clone(), second.clone()],
+    };
+    assert_eq!(
+        transaction_hashes(&wrapped.encode_to_vec()),
+        vec![
+            calculate_transaction_hash(&first),
+            calculate_transaction_hash(&second)
+        ]
+    );
+    assert_eq!(
+        transaction_hashes(&[0xff; 8]),
+        vec![Keccak256::digest([0xff; 8]).to_vec()]
+    );
+}
@@ -0,0 +1,143 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+use prost::Message as _;
+use secp256k1::{PublicKey, SecretKey};
+
+const CHAIN_ID: u64 = 991;
+
+fn validator(require_signature: bool) -> ProtoTransactionValidator {
+    ProtoTransactionValidator::new(&TransactionValidationParameters {
+        enabled: true,
+        chain_id: CHAIN_ID,
+        require_signature,
+    })
+}
+
+fn address(secret_key: &SecretKey) -> Vec<u8> {
+    let public_key = PublicKey::from_secret_key_global(secret_key);
+    Keccak256::digest(&public_key.serialize_uncompressed()[1..])[12..].to_vec()
+}
+
+/// A transaction signed with EIP-155 (R, S, V).
+fn signed_transaction(chain_id: u64) -> Transaction {
+    let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+    let mut tx = Transaction {
+        from_address: address(&secret_key),
+        to_address: vec![0x11; 20],
+        amount: vec![1],
+        max_gas: 21_000,
+        nonce: vec![7],
+        chain_id,
+        ..Transaction::default()
+    };
+    let message = Message::from_slice(&signing_hash(&tx)).unwrap();
+    let (recovery_id, compact) = SECP256K1
+        .sign_ecdsa_recoverable(&message, &secret_key)
+        .serialize_compact();
+    tx.r = compact[..32].to_vec();
+    tx.s = compact[32..].to_vec();
+    tx.v = (chain_id * 2 + 35 + recovery_id.to_i32() as u64)
+        .to_be_bytes()
+        .iter()
+        .skip_while(|byte| **byte == 0)
+        .copied()
+        .collect();
+    tx
+}
+
+/// A transaction signed outside this crate, the way the clients sign them: secp256k1 (low S,
+/// EIP-155 V) over the Keccak256 hash of its `TransactionHashData` encoding. The signer is the
+/// go-ethereum test key `b71c71a6...f291`.
+const EXTERNALLY_SIGNED_TRANSACTION: &str = concat!(
+    "0a1411111111111111111111111111111111111111111a080de0b6b3a76400002088a401288094eb",
+    "dc033a04deadbeef6201036a1471562b71999873db5b286df957af199ec94617f778df078a0120ec",
+    "40a187ef02622508eb452893eceeaa96e15e46ea6a6a7a3fc4592bb56a07fe920120387e9e5b7d77",
+    "2257f251b3a1e58ac82cf7fb6004e08ae253a36b75e3e287d42c9a010207e2",
+);
+const EXTERNALLY_SIGNED_TRANSACTION_SENDER: &str = "71562b71999873db5b286df957af199ec94617f7";
+
+fn wrap(transactions: Vec<Transaction>) -> Vec<u8> {
+    Transactions { transactions }.encode_to_vec()
+}
+
+#[test]
+fn accepts_signed_transactions() {
+    let tx = signed_transaction(CHAIN_ID);
+    assert_eq!(validator(true).validate(&wrap(vec![tx.clone()])), Ok(()));
+    assert_eq!(validator(true).validate(&tx.encode_to_vec()), Ok(()));
+}
+
+#[test]
+fn accepts_externally_signed_transactions() {
+    let bytes = hex::decode(EXTERNALLY_SIGNED_TRANSACTION).unwrap();
+    let tx = Transaction::decode(bytes.as_slice()).unwrap();
+    // The encoding is the canonical one: the signed fields hash the same on both sides.
+    assert_eq!(tx.encode_to_vec(), bytes);
+    assert_eq!(
+        hex::encode(recover_sender(&tx).unwrap()),
+        EXTERNALLY_SIGNED_TRANSACTION_SENDER
+    );
+    assert_eq!(validator(true).validate(&bytes), Ok(()));
+    assert_eq!(validator(true).validate(&wrap(vec![tx])), Ok(()));
+}
+
+#[test]
+fn rejects_wrong_chain_id() {
+    let tx = signed_transaction(CHAIN_ID + 1);
+    assert_eq!(
+        validator(false).validate(&wrap(vec![tx])),
+        Err(TransactionValidationError::ChainIdMismatch {
+            index: 0,
+            expected: CHAIN_ID,
+            actual: CHAIN_ID + 1,
+        })
+    );
+}
+
+#[test]
+fn rejects_tampered_transactions() {
+    // Another sender: the signature recovers to the original signer.
+    let mut tx = signed_transaction(CHAIN_ID);
+    tx.from_address = vec![0x22; 20];
+    assert!(matches!(
+        validator(false).validate(&wrap(vec![signed_transaction(CHAIN_ID), tx])),
+        Err(TransactionValidationError::SenderMismatch { index: 1, .. })
+    ));
+
+    // V signed for another chain id.
+    let mut tx = signed_transaction(CHAIN_ID);
+    tx.v = vec![37];
+    assert!(matches!(
+        validator(false).validate(&wrap(vec![tx])),
+        Err(TransactionValidationError::InvalidSignature { index: 0, .. })
+    ));
+}
+
+#[test]
+fn signature_is_only_required_when_configured() {
+    let tx = Transaction {
+        chain_id: CHAIN_ID,
+        sign: vec![1; 96],
+        ..Transaction::default()
+    };
+    assert_eq!(validator(false).validate(&wrap(vec![tx.clone()])), Ok(()));
+    assert_eq!(
+        validator(true).validate(&wrap(vec![tx])),
+        Err(TransactionValidationError::MissingSignature(0))
+    );
+}
+
+#[test]
+fn rejects_malformed_payloads() {
+    assert_eq!(
+        validator(false).validate(&[]),
+        Err(TransactionValidationError::EmptyTransactions)
+    );
+    assert!(matches!(
+        validator(false).validate(&[0xff; 16]),
+        Err(TransactionValidationError::Undecodable(_))
+    ));
+    // The trivial validator accepts anything (benchmark payloads).
+    assert_eq!(TrivialTransactionValidator.validate(&[0xff; 16]), Ok(()));
+}
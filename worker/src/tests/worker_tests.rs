@@ -2,13 +2,15 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 use super::*;
+use crate::TrivialTransactionValidator;
 use arc_swap::ArcSwap;
 use bytes::Bytes;
 use fastcrypto::Hash;
 use prometheus::Registry;
 use store::rocks;
 use test_utils::{
-    batch, temp_dir, CommitteeFixture, WorkerToPrimaryMockServer, WorkerToWorkerMockServer,
+    batch, make_transaction_status_store, temp_dir, CommitteeFixture, WorkerToPrimaryMockServer,
+    WorkerToWorkerMockServer,
 };
 use types::{TransactionsClient, WorkerPrimaryMessage};
 
@@ -44,6 +46,8 @@ async fn handle_clients_transactions() {
         worker_cache.clone(),
         parameters,
         store,
+        Arc::new(TrivialTransactionValidator),
+        make_transaction_status_store(&temp_dir()),
         metrics,
     );
 
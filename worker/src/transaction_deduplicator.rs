@@ -0,0 +1,134 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Time-windowed set of the transaction hashes submitted to the worker, used to reject a
+//! transaction resubmitted to the same worker before it is batched a second time.
+//!
+//! Resubmissions through other workers or validators are not caught here: the execution state
+//! drops transactions already delivered in another batch.
+
+use crate::transaction_logger::{
+    calculate_transaction_hash,
+    transaction::{Transaction, Transactions},
+};
+use config::TransactionDeduplicationParameters;
+use prost::Message;
+use sha3::{Digest, Keccak256};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[cfg(test)]
+#[path = "tests/transaction_deduplicator_tests.rs"]
+pub mod transaction_deduplicator_tests;
+
+/// The hashes of the transactions of a client payload, as computed by the execution state:
+/// the transactions of a `Transactions` wrapper, a single `Transaction`, or the Keccak256 of the
+/// raw bytes when the payload is not `transaction.proto`.
+pub fn transaction_hashes(payload: &[u8]) -> Vec<Vec<u8>> {
+    if let Ok(txs) = Transactions::decode(payload) {
+        if !txs.transactions.is_empty() {
+            return txs
+                .transactions
+                .iter()
+                .map(calculate_transaction_hash)
+                .collect();
+        }
+    }
+    match Transaction::decode(payload) {
+        Ok(tx) => vec![calculate_transaction_hash(&tx)],
+        Err(_) => vec![Keccak256::digest(payload).to_vec()],
+    }
+}
+
+#[derive(Default)]
+struct SeenTransactions {
+    /// Transaction hash -> time it was accepted.
+    seen: HashMap<Vec<u8>, Instant>,
+    /// Accepted hashes, oldest first.
+    order: VecDeque<(Instant, Vec<u8>)>,
+}
+
+pub struct TransactionDeduplicator {
+    window: Duration,
+    max_entries: usize,
+    inner: Mutex<SeenTransactions>,
+}
+
+impl TransactionDeduplicator {
+    pub fn new(parameters: &TransactionDeduplicationParameters) -> Self {
+        Self {
+            window: parameters.window,
+            max_entries: parameters.max_entries.max(1),
+            inner: Mutex::new(SeenTransactions::default()),
+        }
+    }
+
+    /// Remember the hashes if none of them was accepted within the window.
+    /// Returns the first hash already seen otherwise, and remembers none of them.
+    pub fn check_and_insert(&self, hashes: &[Vec<u8>]) -> Result<(), Vec<u8>> {
+        self.check_and_insert_at(hashes, Instant::now())
+    }
+
+    fn check_and_insert_at(&self, hashes: &[Vec<u8>], now: Instant) -> Result<(), Vec<u8>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.expire(now, self.window);
+
+        if let Some(duplicate) = hashes.iter().find(|hash| inner.seen.contains_key(*hash)) {
+            return Err(duplicate.clone());
+        }
+
+        for hash in hashes {
+            if inner.seen.insert(hash.clone(), now).is_none() {
+                inner.order.push_back((now, hash.clone()));
+            }
+        }
+        while inner.order.len() > self.max_entries {
+            if let Some((_, hash)) = inner.order.pop_front() {
+                inner.seen.remove(&hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget the hashes of a payload that was accepted but not batched: it can be submitted again.
+    pub fn remove(&self, hashes: &[Vec<u8>]) {
+        let mut inner = self.inner.lock().unwrap();
+        for hash in hashes {
+            inner.seen.remove(hash);
+        }
+        inner.order.retain(|(_, hash)| !hashes.contains(hash));
+    }
+
+    /// Whether the hash was accepted within the window.
+    pub fn contains(&self, hash: &[u8]) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .seen
+            .get(hash)
+            .map_or(false, |accepted| accepted.elapsed() < self.window)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SeenTransactions {
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some((accepted, _)) = self.order.front() {
+            if now.saturating_duration_since(*accepted) < window {
+                break;
+            }
+            if let Some((_, hash)) = self.order.pop_front() {
+                self.seen.remove(&hash);
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright (c) 2022, Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Validation of the client transactions received by the worker, before they are batched.
+//!
+//! Invalid transactions are rejected at ingress with `InvalidArgument` instead of being sequenced
+//! and then dropped by the executor.
+
+use crate::transaction_logger::transaction::{
+    AccessTuple, Transaction, TransactionHashData, Transactions,
+};
+use config::TransactionValidationParameters;
+use prost::Message as _;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, SECP256K1,
+};
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+#[cfg(test)]
+#[path = "tests/transaction_validator_tests.rs"]
+pub mod transaction_validator_tests;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TransactionValidationError {
+    #[error("Transaction is neither a Transactions nor a Transaction message: {0}")]
+    Undecodable(String),
+
+    #[error("Transactions message contains no transaction")]
+    EmptyTransactions,
+
+    #[error("Transaction {index}: chain id {actual} does not match chain id {expected}")]
+    ChainIdMismatch {
+        index: usize,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Transaction {0}: missing (R, S, V) signature")]
+    MissingSignature(usize),
+
+    #[error("Transaction {index}: invalid signature: {reason}")]
+    InvalidSignature { index: usize, reason: String },
+
+    #[error("Transaction {index}: signed by 0x{recovered} but FromAddress is 0x{from}")]
+    SenderMismatch {
+        index: usize,
+        from: String,
+        recovered: String,
+    },
+}
+
+/// Checks a client transaction before it is sent to the batch maker.
+pub trait TransactionValidator: Send + Sync + 'static {
+    /// `transaction` is the payload of a `TransactionProto`, as submitted by the client.
+    fn validate(&self, transaction: &[u8]) -> Result<(), TransactionValidationError>;
+}
+
+/// Accepts every transaction (benchmarks, tests and payloads that are not `transaction.proto`).
+pub struct TrivialTransactionValidator;
+
+impl TransactionValidator for TrivialTransactionValidator {
+    fn validate(&self, _transaction: &[u8]) -> Result<(), TransactionValidationError> {
+        Ok(())
+    }
+}
+
+/// Validates `transaction.proto` payloads: a `Transactions` wrapper or a single `Transaction`.
+/// - the payload must decode, and a `Transactions` wrapper must not be empty;
+/// - the chain id must match the configured one (unless it is zero);
+/// - when (R, S, V) are set, the secp256k1 signature must recover to `FromAddress`.
+///
+/// Transactions without (R, S, V) are signed with `Sign` (native transactions): their signature
+/// is checked by the executor, they are only rejected when `require_signature` is set.
+pub struct ProtoTransactionValidator {
+    chain_id: u64,
+    require_signature: bool,
+}
+
+impl ProtoTransactionValidator {
+    pub fn new(parameters: &TransactionValidationParameters) -> Self {
+        Self {
+            chain_id: parameters.chain_id,
+            require_signature: parameters.require_signature,
+        }
+    }
+
+    fn validate_transaction(
+        &self,
+        index: usize,
+        tx: &Transaction,
+    ) -> Result<(), TransactionValidationError> {
+        if self.chain_id != 0 && tx.chain_id != self.chain_id {
+            return Err(TransactionValidationError::ChainIdMismatch {
+                index,
+                expected: self.chain_id,
+                actual: tx.chain_id,
+            });
+        }
+
+        if tx.r.is_empty() && tx.s.is_empty() && tx.v.is_empty() {
+            if self.require_signature {
+                return Err(TransactionValidationError::MissingSignature(index));
+            }
+            return Ok(());
+        }
+
+        let invalid =
+            |reason: String| TransactionValidationError::InvalidSignature { index, reason };
+        let recovered = recover_sender(tx).map_err(invalid)?;
+        if recovered.as_slice() != tx.from_address.as_slice() {
+            return Err(TransactionValidationError::SenderMismatch {
+                index,
+                from: hex::encode(&tx.from_address),
+                recovered: hex::encode(recovered),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TransactionValidator for ProtoTransactionValidator {
+    fn validate(&self, transaction: &[u8]) -> Result<(), TransactionValidationError> {
+        if transaction.is_empty() {
+            return Err(TransactionValidationError::EmptyTransactions);
+        }
+
+        // Same interpretation order as the logging in `TxReceiverHandler`: wrapper first. A single
+        // `Transaction` may also decode as a wrapper, so it is tried when the wrapper is rejected.
+        let as_wrapper = match Transactions::decode(transaction) {
+            Ok(txs) if !txs.transactions.is_empty() => Some(
+                txs.transactions
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(index, tx)| self.validate_transaction(index, tx)),
+            ),
+            _ => None,
+        };
+        if let Some(Ok(())) = as_wrapper {
+            return Ok(());
+        }
+
+        match (Transaction::decode(transaction), as_wrapper) {
+            (Ok(tx), as_wrapper) => match self.validate_transaction(0, &tx) {
+                Ok(()) => Ok(()),
+                Err(e) => Err(as_wrapper.and_then(Result::err).unwrap_or(e)),
+            },
+            (Err(_), Some(wrapper_result)) => wrapper_result,
+            (Err(e), None) => Err(TransactionValidationError::Undecodable(e.to_string())),
+        }
+    }
+}
+
+/// The message signed by (R, S, V): Keccak256 of the protobuf-encoded `TransactionHashData`
+/// with R, S and V cleared (the transaction hash covers the signature, the signed message cannot).
+pub fn signing_hash(tx: &Transaction) -> [u8; 32] {
+    let hash_data = TransactionHashData {
+        from_address: tx.from_address.clone(),
+        to_address: tx.to_address.clone(),
+        amount: tx.amount.clone(),
+        max_gas: tx.max_gas,
+        max_gas_price: tx.max_gas_price,
+        max_time_use: tx.max_time_use,
+        data: tx.data.clone(),
+        r#type: tx.r#type,
+        last_device_key: tx.last_device_key.clone(),
+        new_device_key: tx.new_device_key.clone(),
+        nonce: tx.nonce.clone(),
+        chain_id: tx.chain_id,
+        r: Vec::new(),
+        s: Vec::new(),
+        v: Vec::new(),
+        gas_tip_cap: tx.gas_tip_cap.clone(),
+        gas_fee_cap: tx.gas_fee_cap.clone(),
+        access_list: tx
+            .access_list
+            .iter()
+            .map(|at| AccessTuple {
+                address: at.address.clone(),
+                storage_keys: at.storage_keys.clone(),
+            })
+            .collect(),
+    };
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Keccak256::digest(hash_data.encode_to_vec()));
+    hash
+}
+
+/// The recovery id encoded in V: 0/1, 27/28 (legacy) or `chain_id * 2 + 35/36` (EIP-155).
+fn recovery_id(tx: &Transaction) -> Result<RecoveryId, String> {
+    if tx.v.len() > 8 {
+        return Err(format!("V is {} bytes long", tx.v.len()));
+    }
+    let mut be_bytes = [0u8; 8];
+    be_bytes[8 - tx.v.len()..].copy_from_slice(&tx.v);
+    let v = u64::from_be_bytes(be_bytes);
+    let id = match v {
+        0 | 1 => v,
+        27 | 28 => v - 27,
+        v if v >= 35 => {
+            let chain_id = (v - 35) / 2;
+            if chain_id != tx.chain_id {
+                return Err(format!(
+                    "V {} is signed for chain id {} but ChainID is {}",
+                    v, chain_id, tx.chain_id
+                ));
+            }
+            (v - 35) % 2
+        }
+        v => return Err(format!("V {} is not a valid recovery id", v)),
+    };
+    RecoveryId::from_i32(id as i32).map_err(|e| e.to_string())
+}
+
+/// Recover the address that signed the transaction: the last 20 bytes of the Keccak256 hash of
+/// the uncompressed public key.
+fn recover_sender(tx: &Transaction) -> Result<[u8; 20], String> {
+    if tx.r.len() > 32 || tx.s.len() > 32 {
+        return Err("R and S must be at most 32 bytes long".to_string());
+    }
+    // R and S are big-endian integers, possibly without their leading zeros.
+    let mut compact = [0u8; 64];
+    compact[32 - tx.r.len()..32].copy_from_slice(&tx.r);
+    compact[64 - tx.s.len()..].copy_from_slice(&tx.s);
+
+    let signature = RecoverableSignature::from_compact(&compact, recovery_id(tx)?)
+        .map_err(|e| e.to_string())?;
+    let message = Message::from_slice(&signing_hash(tx)).map_err(|e| e.to_string())?;
+    let public_key = SECP256K1
+        .recover_ecdsa(&message, &signature)
+        .map_err(|e| e.to_string())?;
+
+    let hash = Keccak256::digest(&public_key.serialize_uncompressed()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    Ok(address)
+}
@@ -2,6 +2,7 @@
 // Copyright (c) 2022, Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 use crate::{
+    admission_control::{AdmissionControl, AdmissionError, PendingBytes},
     batch_maker::BatchMaker,
     handlers::{ChildRpcSender, PrimaryReceiverHandler, WorkerReceiverHandler},
     metrics::WorkerChannelMetrics,
@@ -9,6 +10,8 @@ use crate::{
     processor::Processor,
     quorum_waiter::QuorumWaiter,
     synchronizer::Synchronizer,
+    transaction_deduplicator::{transaction_hashes, TransactionDeduplicator},
+    transaction_validator::TransactionValidator,
 };
 use anemo::{types::PeerInfo, PeerId};
 use anemo_tower::{callback::CallbackLayer, trace::TraceLayer};
@@ -20,10 +23,13 @@ use multiaddr::{Multiaddr, Protocol};
 use network::metrics::MetricsMakeCallbackHandler;
 use network::P2pNetwork;
 use primary::PrimaryWorkerMessage;
-use std::{net::Ipv4Addr, sync::Arc};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+};
 use store::Store;
 use tokio::{sync::watch, task::JoinHandle};
-use tonic::{Request, Response, Status};
+use tonic::{Code, Request, Response, Status};
 use tower::ServiceBuilder;
 use tracing::{info, warn};
 use hex;
@@ -31,8 +37,10 @@ use prost::Message;
 use types::{
     error::DagError,
     metered_channel::{channel, Receiver, Sender},
-    Batch, BatchDigest, Empty, PrimaryToWorkerServer, ReconfigureNotification, Transaction,
-    TransactionProto, Transactions, TransactionsServer, WorkerPrimaryMessage, WorkerToWorkerServer,
+    Batch, BatchDigest, Empty, PrimaryToWorkerServer, ReconfigureNotification, Round,
+    Transaction, TransactionProto, TransactionStage, TransactionStatus, TransactionStatusRequest,
+    TransactionStatusResponse, TransactionStatusServer, TransactionStatusStore, Transactions,
+    TransactionsServer, WorkerPrimaryMessage, WorkerToWorkerServer,
 };
 
 #[cfg(test)]
@@ -60,6 +68,10 @@ pub struct Worker {
     parameters: Parameters,
     /// The persistent storage.
     store: Store<BatchDigest, Batch>,
+    /// Checks the clients' transactions before they are batched.
+    validator: Arc<dyn TransactionValidator>,
+    /// The batch of each sealed transaction, served by the `TransactionStatus` service.
+    transaction_status_store: Arc<TransactionStatusStore>,
 }
 
 impl Worker {
@@ -71,6 +83,8 @@ impl Worker {
         worker_cache: SharedWorkerCache,
         parameters: Parameters,
         store: Store<BatchDigest, Batch>,
+        validator: Arc<dyn TransactionValidator>,
+        transaction_status_store: Arc<TransactionStatusStore>,
         metrics: Metrics,
     ) -> Vec<JoinHandle<()>> {
         // Define a worker instance.
@@ -82,6 +96,8 @@ impl Worker {
             worker_cache,
             parameters,
             store,
+            validator,
+            transaction_status_store,
         };
 
         let node_metrics = Arc::new(metrics.worker_metrics.unwrap());
@@ -96,6 +112,15 @@ impl Worker {
         let initial_committee = (*(*(*committee).load()).clone()).clone();
         let (tx_reconfigure, rx_reconfigure) =
             watch::channel(ReconfigureNotification::NewEpoch(initial_committee));
+        // Resume from the last committed round recorded with a batched transaction, so the
+        // transactions sealed before the first cleanup are not pruned early.
+        let (tx_committed_round, rx_committed_round) = watch::channel(
+            worker
+                .transaction_status_store
+                .read_batched_round()
+                .unwrap_or_default()
+                .unwrap_or_default(),
+        );
 
         let (tx_worker_processor, rx_worker_processor) =
             channel(CHANNEL_CAPACITY, &channel_metrics.tx_worker_processor);
@@ -215,6 +240,7 @@ impl Worker {
         );
         let client_flow_handles = worker.handle_clients_transactions(
             &tx_reconfigure,
+            rx_committed_round,
             tx_primary.clone(),
             node_metrics,
             channel_metrics,
@@ -226,8 +252,13 @@ impl Worker {
             tx_primary.clone(),
             rx_worker_processor,
         );
-        let primary_flow_handles =
-            worker.handle_primary_messages(rx_synchronizer, tx_reconfigure, tx_primary, network);
+        let primary_flow_handles = worker.handle_primary_messages(
+            rx_synchronizer,
+            tx_reconfigure,
+            tx_committed_round,
+            tx_primary,
+            network,
+        );
 
         // NOTE: This log entry is used to compute performance.
         info!(
@@ -253,6 +284,7 @@ impl Worker {
         &self,
         rx_synchronizer: Receiver<PrimaryWorkerMessage>,
         tx_reconfigure: watch::Sender<ReconfigureNotification>,
+        tx_committed_round: watch::Sender<Round>,
         tx_primary: Sender<WorkerPrimaryMessage>,
         network: anemo::Network,
     ) -> Vec<JoinHandle<()>> {
@@ -266,6 +298,9 @@ impl Worker {
             tx_reconfigure,
             tx_primary,
             P2pNetwork::new(network),
+            self.parameters.gc_depth,
+            tx_committed_round,
+            Some(self.transaction_status_store.clone()),
         );
 
         vec![handle]
@@ -275,6 +310,7 @@ impl Worker {
     fn handle_clients_transactions(
         &self,
         tx_reconfigure: &watch::Sender<ReconfigureNotification>,
+        rx_committed_round: watch::Receiver<Round>,
         tx_primary: Sender<WorkerPrimaryMessage>,
         node_metrics: Arc<WorkerMetrics>,
         channel_metrics: Arc<WorkerChannelMetrics>,
@@ -298,7 +334,26 @@ impl Worker {
         let address = address
             .replace(0, |_protocol| Some(Protocol::Ip4(Ipv4Addr::UNSPECIFIED)))
             .unwrap();
-        let tx_receiver_handle = TxReceiverHandler { tx_batch_maker }.spawn(
+        let deduplicator = self.parameters.transaction_deduplication.enabled.then(|| {
+            Arc::new(TransactionDeduplicator::new(
+                &self.parameters.transaction_deduplication,
+            ))
+        });
+        let pending_bytes = PendingBytes::default();
+        let admission_control = Arc::new(AdmissionControl::new(
+            &self.parameters.admission_control,
+            /* mempool_retry_after */ self.parameters.max_batch_delay,
+            pending_bytes.clone(),
+        ));
+        let tx_receiver_handle = TxReceiverHandler {
+            tx_batch_maker,
+            admission_control,
+            validator: self.validator.clone(),
+            deduplicator,
+            transaction_status_store: self.transaction_status_store.clone(),
+            node_metrics: node_metrics.clone(),
+        }
+        .spawn(
             address.clone(),
             tx_reconfigure.subscribe(),
             endpoint_metrics,
@@ -315,6 +370,9 @@ impl Worker {
             /* rx_transaction */ rx_batch_maker,
             /* tx_message */ tx_quorum_waiter,
             node_metrics,
+            pending_bytes,
+            Some(self.transaction_status_store.clone()),
+            rx_committed_round,
         );
 
         // The `QuorumWaiter` waits for 2f authorities to acknowledge reception of the batch. It then forwards
@@ -376,13 +434,85 @@ impl Worker {
     }
 }
 
+/// `ResourceExhausted` status of a rejected transaction, with the retry hint in the message and in
+/// the `retry-after-ms` metadata.
+fn resource_exhausted(error: AdmissionError) -> Status {
+    let retry_after = match error.retry_after() {
+        Some(retry_after) => retry_after.as_millis(),
+        None => return Status::resource_exhausted(error.to_string()),
+    };
+    let mut status =
+        Status::resource_exhausted(format!("{}, retry after {} ms", error, retry_after));
+    if let Ok(value) = retry_after.to_string().parse() {
+        status.metadata_mut().insert("retry-after-ms", value);
+    }
+    status
+}
+
 /// Defines how the network receiver handles incoming transactions.
 #[derive(Clone)]
 struct TxReceiverHandler {
     tx_batch_maker: Sender<Transaction>,
+    /// Size, pending bytes and rate limits of the clients' transactions.
+    admission_control: Arc<AdmissionControl>,
+    validator: Arc<dyn TransactionValidator>,
+    /// Rejects transactions already submitted to this worker (None = disabled).
+    deduplicator: Option<Arc<TransactionDeduplicator>>,
+    transaction_status_store: Arc<TransactionStatusStore>,
+    node_metrics: Arc<WorkerMetrics>,
 }
 
 impl TxReceiverHandler {
+    /// Admission control, validation and deduplication of a client payload. On success its bytes
+    /// are pending in the admission control until the `BatchMaker` seals them.
+    fn accept(&self, source: Option<IpAddr>, tx_bytes: &[u8]) -> Result<(), Status> {
+        self.admission_control
+            .admit(source, tx_bytes)
+            .map_err(|e| {
+                self.node_metrics
+                    .admission_rejections
+                    .with_label_values(&[e.reason()])
+                    .inc();
+                resource_exhausted(e)
+            })?;
+
+        let result = self
+            .validator
+            .validate(tx_bytes)
+            .map_err(|e| Status::invalid_argument(e.to_string()))
+            .and_then(|()| self.check_duplicate(tx_bytes));
+        if result.is_err() {
+            self.admission_control.release(tx_bytes.len());
+        }
+        result
+    }
+
+    /// Undo the `accept` of a payload that did not reach the `BatchMaker`: its bytes are no
+    /// longer pending and its transactions can be submitted again.
+    fn release(&self, tx_bytes: &[u8]) {
+        self.admission_control.release(tx_bytes.len());
+        if let Some(deduplicator) = &self.deduplicator {
+            deduplicator.remove(&transaction_hashes(tx_bytes));
+        }
+    }
+
+    /// Reject a payload whose transactions were already submitted within the deduplication window.
+    fn check_duplicate(&self, tx_bytes: &[u8]) -> Result<(), Status> {
+        let deduplicator = match &self.deduplicator {
+            Some(deduplicator) => deduplicator,
+            None => return Ok(()),
+        };
+        deduplicator
+            .check_and_insert(&transaction_hashes(tx_bytes))
+            .map_err(|hash| {
+                self.node_metrics.duplicate_transactions_rejected.inc();
+                Status::already_exists(format!(
+                    "Transaction {} was already submitted",
+                    hex::encode(hash)
+                ))
+            })
+    }
+
     async fn wait_for_shutdown(mut rx_reconfigure: watch::Receiver<ReconfigureNotification>) {
         loop {
             let result = rx_reconfigure.changed().await;
@@ -405,7 +535,8 @@ impl TxReceiverHandler {
             tokio::select! {
                 _result =  mysten_network::config::Config::new()
                     .server_builder_with_metrics(endpoint_metrics)
-                    .add_service(TransactionsServer::new(self))
+                    .add_service(TransactionsServer::new(self.clone()))
+                    .add_service(TransactionStatusServer::new(self))
                     .bind(&address)
                     .await
                     .unwrap()
@@ -417,12 +548,37 @@ impl TxReceiverHandler {
     }
 }
 
+#[async_trait]
+impl TransactionStatus for TxReceiverHandler {
+    /// The worker knows the batch of the transactions it sealed, and the transactions accepted
+    /// but not sealed yet when the deduplication is enabled.
+    async fn get_transaction_status(
+        &self,
+        request: Request<TransactionStatusRequest>,
+    ) -> Result<Response<TransactionStatusResponse>, Status> {
+        let transaction_hash = request.into_inner().transaction_hash;
+        let mut status = self
+            .transaction_status_store
+            .read_status(&transaction_hash, None)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        if status.stage() == TransactionStage::Unknown
+            && self.deduplicator.as_ref().map_or(false, |deduplicator| {
+                deduplicator.contains(&transaction_hash)
+            })
+        {
+            status.set_stage(TransactionStage::Submitted);
+        }
+        Ok(Response::new(status))
+    }
+}
+
 #[async_trait]
 impl Transactions for TxReceiverHandler {
     async fn submit_transaction(
         &self,
         request: Request<TransactionProto>,
     ) -> Result<Response<Empty>, Status> {
+        let source = request.remote_addr().map(|addr| addr.ip());
         let message = request.into_inner().transaction;
         let tx_bytes = message.to_vec();
         
@@ -489,13 +645,18 @@ impl Transactions for TxReceiverHandler {
                 tx_bytes.len()
             );
         }
+
+        // Reject the transactions over the limits, invalid or duplicate before they are batched.
+        if let Err(status) = self.accept(source, &tx_bytes) {
+            warn!("[WORKER RX] Rejected transaction: {}", status.message());
+            return Err(status);
+        }
         
         // Send the transaction to the batch maker.
-        self.tx_batch_maker
-            .send(tx_bytes)
-            .await
-            .map_err(|_| DagError::ShuttingDown)
-            .map_err(|e| Status::not_found(e.to_string()))?;
+        if let Err(error) = self.tx_batch_maker.send(tx_bytes).await {
+            self.release(&error.0);
+            return Err(Status::not_found(DagError::ShuttingDown.to_string()));
+        }
 
         Ok(Response::new(Empty {}))
     }
@@ -504,8 +665,10 @@ impl Transactions for TxReceiverHandler {
         &self,
         request: tonic::Request<tonic::Streaming<types::TransactionProto>>,
     ) -> Result<tonic::Response<types::Empty>, tonic::Status> {
+        let source = request.remote_addr().map(|addr| addr.ip());
         let mut transactions = request.into_inner();
         let mut tx_count = 0;
+        let mut duplicate_count = 0;
 
         while let Some(Ok(txn)) = transactions.next().await {
             let tx_bytes = txn.transaction.to_vec();
@@ -577,17 +740,47 @@ impl Transactions for TxReceiverHandler {
                     tx_bytes.len()
                 );
             }
+
+            // Reject the transactions over the limits, invalid or duplicate before they are batched.
+            // A duplicate is dropped on its own; any other rejection ends the stream, and the
+            // transactions of the stream received before it were already sent to the batch maker.
+            if let Err(status) = self.accept(source, &tx_bytes) {
+                if status.code() == Code::AlreadyExists {
+                    duplicate_count += 1;
+                    warn!(
+                        "[WORKER RX STREAM] Skipped duplicate transaction [Stream#{}]: {}",
+                        tx_count,
+                        status.message()
+                    );
+                    continue;
+                }
+                warn!(
+                    "[WORKER RX STREAM] Rejected transaction [Stream#{}]: {}",
+                    tx_count,
+                    status.message()
+                );
+                return Err(Status::with_metadata(
+                    status.code(),
+                    format!(
+                        "Transaction #{} of the stream: {}",
+                        tx_count,
+                        status.message()
+                    ),
+                    status.metadata().clone(),
+                ));
+            }
             
             // Send the transaction to the batch maker.
-            self.tx_batch_maker
-                .send(tx_bytes)
-                .await
-                .expect("Failed to send transaction");
+            if let Err(error) = self.tx_batch_maker.send(tx_bytes).await {
+                self.release(&error.0);
+                return Err(Status::not_found(DagError::ShuttingDown.to_string()));
+            }
         }
         
         info!(
-            "[WORKER RX STREAM] Stream completed: TotalTransactions={}",
-            tx_count
+            "[WORKER RX STREAM] Stream completed: TotalTransactions={}, SkippedDuplicates={}",
+            tx_count,
+            duplicate_count
         );
         
         Ok(Response::new(Empty {}))